@@ -0,0 +1,797 @@
+//! `agent.delegate`: hands a sub-task to a child turn engine with a fresh
+//! history, so the work behind it stays out of the parent's context.
+
+use anyhow::anyhow;
+use reqwest::Client;
+use serde_json::{Value, json};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, info, info_span};
+
+use super::events::{AgentEvent, AgentEventKind};
+use super::tools::{
+    CompositeRunner, ToolApprovalFuture, ToolApprover, ToolCall, ToolContext, ToolExecutionError,
+    ToolFuture, ToolOutput, ToolRunner, ToolSpec,
+};
+use super::{ModelCall, ModelFuture, TurnEngine, TurnState, build_system_messages, call_turn_id};
+use crate::config::{Config, DelegationLimits, ModelRoles};
+use crate::model::{Message, ModelUsage};
+
+pub const DELEGATE_TOOL_NAME: &str = "agent.delegate";
+/// A child engine runs exactly one turn.
+const CHILD_TURN_ID: u64 = 1;
+const SUB_AGENT_PROMPT: &str = "You are a sub-agent working on one task handed to you by another agent. Complete it with the tools you have and reply with the result only; the other agent sees nothing but your final answer.";
+
+/// Offers `agent.delegate`, which runs a sub-task as a single turn of a child
+/// engine. The child starts from a fresh history and may use the runner's
+/// tools, or a subset of them, and a different model. Nesting depth, and the
+/// runs and tokens spent by all delegations of one turn, are bounded by
+/// [`Config::delegation`].
+pub struct DelegateRunner {
+    client: Client,
+    cfg: Arc<Config>,
+    /// The tools a child may use, not including `agent.delegate`.
+    tools: Arc<dyn ToolRunner>,
+    /// How many delegations above this runner's owner; 0 for the top-level
+    /// agent.
+    depth: usize,
+    /// Shared by every delegation below one top-level call. `None` at the
+    /// top, where calls draw from `turn_budget` instead.
+    budget: Option<Arc<DelegationBudget>>,
+    /// The budget of the turn the top-level agent is on, keyed by turn id.
+    turn_budget: Mutex<Option<(u64, Arc<DelegationBudget>)>>,
+}
+
+impl DelegateRunner {
+    pub fn new(client: Client, cfg: Arc<Config>, tools: Arc<dyn ToolRunner>) -> Self {
+        Self {
+            client,
+            cfg,
+            tools,
+            depth: 0,
+            budget: None,
+            turn_budget: Mutex::new(None),
+        }
+    }
+
+    /// The budget `call` draws from: the parent's below the top level,
+    /// otherwise the one shared by every delegation of the call's turn.
+    fn budget_for(&self, call: &ToolCall) -> Arc<DelegationBudget> {
+        if let Some(budget) = &self.budget {
+            return Arc::clone(budget);
+        }
+        let fresh = || Arc::new(DelegationBudget::new(self.limits().clone()));
+        let Some(turn_id) = call_turn_id(&call.id) else {
+            return fresh();
+        };
+        let mut turn_budget = self
+            .turn_budget
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match &*turn_budget {
+            Some((id, budget)) if *id == turn_id => Arc::clone(budget),
+            _ => {
+                let budget = fresh();
+                *turn_budget = Some((turn_id, Arc::clone(&budget)));
+                budget
+            }
+        }
+    }
+
+    fn limits(&self) -> &DelegationLimits {
+        &self.cfg.delegation
+    }
+
+    fn available_tools(&self) -> Vec<String> {
+        self.tools
+            .tools()
+            .into_iter()
+            .map(|spec| spec.name)
+            .filter(|name| name != DELEGATE_TOOL_NAME)
+            .collect()
+    }
+
+    /// Validates the call and sets up the child. Only a valid call counts
+    /// against the budget.
+    fn plan(&self, call: &ToolCall) -> Result<ChildPlan, ToolExecutionError> {
+        if self.depth >= self.limits().max_depth {
+            return Err(ToolExecutionError::new(format!(
+                "{DELEGATE_TOOL_NAME} is not available at nesting depth {}",
+                self.depth
+            )));
+        }
+        let task = call.required_str("task")?.to_string();
+        let can_nest = self.depth + 1 < self.limits().max_depth;
+
+        let available = self.available_tools();
+        let (allowed, nest) = match call.arguments.get("tools") {
+            None | Some(Value::Null) => (available, can_nest),
+            Some(Value::Array(names)) => {
+                let mut allowed = Vec::new();
+                let mut nest = false;
+                for name in names {
+                    let Some(name) = name.as_str() else {
+                        return Err(ToolExecutionError::new(
+                            "'tools' must be an array of tool names",
+                        ));
+                    };
+                    if name == DELEGATE_TOOL_NAME && can_nest {
+                        nest = true;
+                    } else if available.iter().any(|known| known == name) {
+                        allowed.push(name.to_string());
+                    } else {
+                        return Err(ToolExecutionError::new(format!(
+                            "tool '{name}' is not available to sub-agents; available: {}",
+                            available.join(", ")
+                        )));
+                    }
+                }
+                (allowed, nest)
+            }
+            Some(_) => {
+                return Err(ToolExecutionError::new(
+                    "'tools' must be an array of tool names",
+                ));
+            }
+        };
+
+        let cfg = match call.arguments.get("model") {
+            None | Some(Value::Null) => Arc::clone(&self.cfg),
+            // An explicit model runs every phase, so role targets are dropped.
+            Some(Value::String(model)) if !model.trim().is_empty() => Arc::new(Config {
+                model: model.trim().to_string(),
+                model_roles: ModelRoles::default(),
+                ..(*self.cfg).clone()
+            }),
+            Some(_) => {
+                return Err(ToolExecutionError::new(
+                    "'model' must be a non-empty string",
+                ));
+            }
+        };
+
+        let budget = self.budget_for(call);
+        budget.start_run()?;
+
+        let tools: Arc<dyn ToolRunner> = Arc::new(AllowedTools {
+            inner: Arc::clone(&self.tools),
+            names: allowed,
+        });
+        let mut runner = CompositeRunner::new().with(Box::new(Arc::clone(&tools)));
+        if nest {
+            runner = runner.with(Box::new(DelegateRunner {
+                client: self.client.clone(),
+                cfg: Arc::clone(&cfg),
+                tools,
+                depth: self.depth + 1,
+                budget: Some(Arc::clone(&budget)),
+                turn_budget: Mutex::new(None),
+            }));
+        }
+        Ok(ChildPlan {
+            task,
+            cfg,
+            runner,
+            budget,
+            depth: self.depth + 1,
+        })
+    }
+}
+
+impl ToolRunner for DelegateRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        if self.depth >= self.limits().max_depth {
+            return Vec::new();
+        }
+        vec![
+            ToolSpec::new(
+                DELEGATE_TOOL_NAME,
+                "hands a self-contained sub-task to a sub-agent with a fresh conversation and returns its answer. Use it for multi-step work whose details this conversation does not need.",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "properties": {
+                    "task": {
+                        "type": "string",
+                        "description": "The sub-task, with everything the sub-agent needs to know."
+                    },
+                    "tools": {
+                        "type": "array",
+                        "items": {"type": "string"},
+                        "description": "Tools the sub-agent may use; defaults to all of yours."
+                    },
+                    "model": {
+                        "type": "string",
+                        "description": "Model to run the sub-agent on; defaults to yours."
+                    }
+                },
+                "required": ["task"]
+            })),
+        ]
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        Box::pin(async move { self.delegate(call, &ToolContext::default()).await })
+    }
+
+    fn execute_in<'a>(&'a self, call: &'a ToolCall, context: &'a ToolContext) -> ToolFuture<'a> {
+        Box::pin(self.delegate(call, context))
+    }
+}
+
+impl DelegateRunner {
+    async fn delegate(
+        &self,
+        call: &ToolCall,
+        context: &ToolContext,
+    ) -> Result<ToolOutput, ToolExecutionError> {
+        let plan = self.plan(call)?;
+        let engine = plan.engine(&call.id, context);
+        let chat = engine.live_chat(CHILD_TURN_ID, &self.client, &plan.cfg);
+        run_child(engine, plan, &call.id, context, chat).await
+    }
+}
+
+/// A validated delegation, ready to run.
+struct ChildPlan {
+    task: String,
+    cfg: Arc<Config>,
+    runner: CompositeRunner,
+    budget: Arc<DelegationBudget>,
+    depth: usize,
+}
+
+impl ChildPlan {
+    /// The child reviews its calls with the parent's approver, under ids
+    /// scoped to the parent's call so they cannot collide with the parent's
+    /// or another child's.
+    fn engine(&self, parent_call_id: &str, context: &ToolContext) -> TurnEngine {
+        let specs = self.runner.tools();
+        let mut engine = TurnEngine::new(&self.cfg, &specs);
+        engine.tool_approver = context.approver.as_ref().map(|approver| {
+            Arc::new(ScopedApprover {
+                inner: Arc::clone(approver),
+                parent_call_id: parent_call_id.to_string(),
+            }) as Arc<dyn ToolApprover>
+        });
+        let mut system_messages = build_system_messages(&self.cfg, &specs, &[]);
+        system_messages.push(Message::system(SUB_AGENT_PROMPT));
+        engine.state = TurnState::from_system_messages(system_messages);
+        engine
+    }
+}
+
+/// Runs the child's turn under an `agent.delegate` span, so its
+/// `agent.turn` span nests under the parent's `tool.call`, and returns the
+/// answer followed by a one-line trace. The child's approval requests, denials
+/// and tool results are re-sent to the parent's subscribers as they happen.
+async fn run_child<C>(
+    mut engine: TurnEngine,
+    plan: ChildPlan,
+    parent_call_id: &str,
+    context: &ToolContext,
+    mut chat: C,
+) -> Result<ToolOutput, ToolExecutionError>
+where
+    C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+{
+    let mut events = engine.subscribe();
+    let budget = Arc::clone(&plan.budget);
+    let budgeted_chat = move |messages, call| -> ModelFuture {
+        if let Err(err) = budget.check_tokens() {
+            return Box::pin(async move { Err(err) });
+        }
+        let reply = chat(messages, call);
+        let budget = Arc::clone(&budget);
+        Box::pin(async move {
+            let reply = reply.await?;
+            budget.record(&reply.usage);
+            Ok(reply)
+        })
+    };
+
+    let span = info_span!(
+        "agent.delegate",
+        depth = plan.depth,
+        parent_call_id,
+        model = %plan.cfg.model,
+        tool_count = plan.runner.tools().len()
+    );
+    let never_cancelled = CancellationToken::new();
+    let turn = engine
+        .run_turn_cancellable_with(
+            CHILD_TURN_ID,
+            &plan.task,
+            budgeted_chat,
+            &plan.runner,
+            &never_cancelled,
+        )
+        .instrument(span);
+    tokio::pin!(turn);
+    let mut tool_results = Vec::new();
+    let mut on_event = |event: AgentEvent| {
+        if let Some(summary) = forward_child_event(event.kind, parent_call_id, context) {
+            tool_results.push(summary);
+        }
+    };
+    let result = loop {
+        tokio::select! {
+            result = &mut turn => break result,
+            Some(event) = events.recv() => on_event(event),
+        }
+    };
+    while let Ok(event) = events.try_recv() {
+        on_event(event);
+    }
+    let result =
+        result.map_err(|err| ToolExecutionError::new(format!("sub-agent failed: {err:#}")))?;
+
+    info!(
+        depth = plan.depth,
+        tool_hops = result.tool_hops,
+        tokens = result.usage.usage.total_tokens(),
+        "sub-agent finished"
+    );
+    let tools = if tool_results.is_empty() {
+        "none".to_string()
+    } else {
+        tool_results.join(", ")
+    };
+    Ok(ToolOutput::new(format!(
+        "{}\n\n[sub-agent: model {}, {} tool hops, {} tokens; tools: {tools}]",
+        result.answer.trim(),
+        result.model_target.model,
+        result.tool_hops,
+        result.usage.usage.total_tokens()
+    )))
+}
+
+/// Re-sends a child's tool event to the parent's subscribers, with call ids
+/// scoped to `parent_call_id`, and returns its entry for the trace.
+fn forward_child_event(
+    mut kind: AgentEventKind,
+    parent_call_id: &str,
+    context: &ToolContext,
+) -> Option<String> {
+    let summary = match &mut kind {
+        AgentEventKind::ToolApprovalRequested { call_id, .. } => {
+            *call_id = scoped_call_id(parent_call_id, call_id);
+            None
+        }
+        AgentEventKind::ToolDenied { tool_name, .. } => Some(format!("{tool_name} denied")),
+        AgentEventKind::ToolResult {
+            tool_name,
+            is_error,
+            ..
+        } => Some(format!(
+            "{tool_name} {}",
+            if *is_error { "error" } else { "ok" }
+        )),
+        _ => return None,
+    };
+    context.events.emit(context.turn_id, kind);
+    summary
+}
+
+fn scoped_call_id(parent_call_id: &str, call_id: &str) -> String {
+    format!("{parent_call_id}.{call_id}")
+}
+
+/// Hands a child's calls to the parent's approver under scoped ids.
+struct ScopedApprover {
+    inner: Arc<dyn ToolApprover>,
+    parent_call_id: String,
+}
+
+impl ToolApprover for ScopedApprover {
+    fn review<'a>(&'a self, call: &'a ToolCall) -> ToolApprovalFuture<'a> {
+        let call = ToolCall {
+            id: scoped_call_id(&self.parent_call_id, &call.id),
+            ..call.clone()
+        };
+        Box::pin(async move { self.inner.review(&call).await })
+    }
+}
+
+/// What the delegations of one turn, and everything below them, may spend.
+struct DelegationBudget {
+    limits: DelegationLimits,
+    runs: AtomicUsize,
+    tokens: AtomicU64,
+}
+
+impl DelegationBudget {
+    fn new(limits: DelegationLimits) -> Self {
+        Self {
+            limits,
+            runs: AtomicUsize::new(0),
+            tokens: AtomicU64::new(0),
+        }
+    }
+
+    fn start_run(&self) -> Result<(), ToolExecutionError> {
+        let max_runs = self.limits.max_runs;
+        self.runs
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |runs| {
+                (runs < max_runs).then_some(runs + 1)
+            })
+            .map(|_| ())
+            .map_err(|_| {
+                ToolExecutionError::new(format!(
+                    "delegation budget of {max_runs} sub-agent runs is used up"
+                ))
+            })
+    }
+
+    /// Fails once the tokens spent reach the budget; checked before every
+    /// child model request, so the last request may overshoot it.
+    fn check_tokens(&self) -> anyhow::Result<()> {
+        if self.tokens.load(Ordering::SeqCst) >= self.limits.max_tokens {
+            return Err(anyhow!(
+                "delegation budget of {} tokens is used up",
+                self.limits.max_tokens
+            ));
+        }
+        Ok(())
+    }
+
+    fn record(&self, usage: &ModelUsage) {
+        self.tokens
+            .fetch_add(usage.total_tokens(), Ordering::SeqCst);
+    }
+}
+
+/// Offers only `names` out of `inner`'s tools.
+struct AllowedTools {
+    inner: Arc<dyn ToolRunner>,
+    names: Vec<String>,
+}
+
+impl ToolRunner for AllowedTools {
+    fn tools(&self) -> Vec<ToolSpec> {
+        self.inner
+            .tools()
+            .into_iter()
+            .filter(|spec| self.names.contains(&spec.name))
+            .collect()
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        if !self.names.contains(&call.name) {
+            let err = ToolExecutionError::new(format!("unknown tool '{}'", call.name));
+            return Box::pin(async move { Err(err) });
+        }
+        self.inner.execute(call)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    use super::{DELEGATE_TOOL_NAME, DelegateRunner, SUB_AGENT_PROMPT, run_child};
+    use crate::agent::events::{AgentEvent, AgentEventKind};
+    use crate::agent::tools::{
+        BuiltinRunner, CompositeRunner, ToolApproval, ToolApprovalFuture, ToolApprover, ToolCall,
+        ToolContext, ToolFuture, ToolOutput, ToolRunner, ToolSpec,
+    };
+    use crate::agent::{ModelCall, ModelFuture};
+    use crate::config::{Config, DelegationLimits, ModelTarget};
+    use crate::model::{Message, ModelReply, ModelUsage};
+
+    struct NotesRunner;
+
+    impl ToolRunner for NotesRunner {
+        fn tools(&self) -> Vec<ToolSpec> {
+            vec![ToolSpec::new("notes.read", "reads notes.")]
+        }
+
+        fn execute<'a>(&'a self, _call: &'a ToolCall) -> ToolFuture<'a> {
+            Box::pin(async { Ok(ToolOutput::new("the build is green")) })
+        }
+    }
+
+    fn runner(limits: DelegationLimits) -> DelegateRunner {
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.delegation = limits;
+        let tools = CompositeRunner::new()
+            .with(Box::new(BuiltinRunner))
+            .with(Box::new(NotesRunner));
+        DelegateRunner::new(reqwest::Client::new(), Arc::new(cfg), Arc::new(tools))
+    }
+
+    fn limits(max_depth: usize, max_runs: usize, max_tokens: u64) -> DelegationLimits {
+        DelegationLimits {
+            max_depth,
+            max_runs,
+            max_tokens,
+        }
+    }
+
+    fn call(arguments: Value) -> ToolCall {
+        let Value::Object(arguments) = arguments else {
+            panic!("arguments should be an object");
+        };
+        ToolCall::new(DELEGATE_TOOL_NAME).with_arguments(arguments)
+    }
+
+    fn tool_names(runner: &dyn ToolRunner) -> Vec<String> {
+        runner.tools().into_iter().map(|spec| spec.name).collect()
+    }
+
+    /// Answers with `responses` in order, recording each request, and
+    /// reports 100 tokens per reply.
+    fn stub_chat(
+        responses: Vec<&str>,
+        requests: Arc<Mutex<Vec<Vec<Message>>>>,
+    ) -> impl FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send {
+        let mut responses: VecDeque<String> = responses.into_iter().map(String::from).collect();
+        move |messages, _| {
+            requests.lock().expect("requests lock").push(messages);
+            let content = responses.pop_front().expect("stub ran out of responses");
+            Box::pin(async move {
+                Ok(ModelReply {
+                    content,
+                    target: ModelTarget {
+                        provider: "ollama".to_string(),
+                        model: "child-model".to_string(),
+                        base_url: "http://127.0.0.1:9".to_string(),
+                        timeout_secs: 5,
+                    },
+                    usage: ModelUsage {
+                        prompt_tokens: 80,
+                        completion_tokens: 20,
+                        ..ModelUsage::default()
+                    },
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn delegate_is_offered_only_below_the_depth_limit() {
+        assert_eq!(
+            tool_names(&runner(limits(1, 4, 1_000))),
+            [DELEGATE_TOOL_NAME]
+        );
+        assert!(runner(limits(0, 4, 1_000)).tools().is_empty());
+    }
+
+    #[test]
+    fn plan_restricts_the_child_to_the_requested_tools() {
+        let runner = runner(limits(2, 4, 1_000));
+
+        let plan = runner
+            .plan(&call(
+                json!({"task": "check the build", "tools": ["notes.read"]}),
+            ))
+            .expect("plan should succeed");
+        assert_eq!(tool_names(&plan.runner), ["notes.read"]);
+
+        let plan = runner
+            .plan(&call(json!({"task": "check the build"})))
+            .expect("plan should succeed");
+        assert_eq!(
+            tool_names(&plan.runner),
+            ["time.now", "notes.read", DELEGATE_TOOL_NAME]
+        );
+        assert_eq!(plan.depth, 1);
+
+        let err = runner
+            .plan(&call(json!({"task": "x", "tools": ["shell.run"]})))
+            .err()
+            .expect("unknown tools should fail");
+        assert_eq!(
+            err.to_string(),
+            "tool 'shell.run' is not available to sub-agents; available: time.now, notes.read"
+        );
+    }
+
+    #[test]
+    fn plan_stops_nesting_at_the_depth_limit() {
+        let runner = runner(limits(1, 4, 1_000));
+
+        let plan = runner
+            .plan(&call(json!({"task": "check the build"})))
+            .expect("plan should succeed");
+        assert_eq!(tool_names(&plan.runner), ["time.now", "notes.read"]);
+
+        let err = runner
+            .plan(&call(json!({"task": "x", "tools": [DELEGATE_TOOL_NAME]})))
+            .err()
+            .expect("nesting past the limit should fail");
+        assert!(
+            err.to_string()
+                .contains("'agent.delegate' is not available")
+        );
+    }
+
+    #[test]
+    fn plan_switches_the_model_and_shares_the_run_budget_with_nested_runners() {
+        let runner = runner(limits(3, 2, 1_000));
+
+        let plan = runner
+            .plan(&call(json!({"task": "summarize", "model": "llama3.1:8b"})))
+            .expect("plan should succeed");
+        assert_eq!(plan.cfg.model, "llama3.1:8b");
+
+        assert!(
+            tool_names(&plan.runner)
+                .iter()
+                .any(|name| name == DELEGATE_TOOL_NAME)
+        );
+        plan.budget.start_run().expect("second run fits the budget");
+        let err = plan
+            .budget
+            .start_run()
+            .expect_err("third run exceeds the budget");
+        assert_eq!(
+            err.to_string(),
+            "delegation budget of 2 sub-agent runs is used up"
+        );
+
+        assert!(
+            runner
+                .plan(&call(json!({"task": "x", "model": 7})))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn delegations_of_one_turn_share_the_budget() {
+        let runner = runner(limits(2, 2, 1_000));
+        let in_turn = |id: &str| {
+            let mut call = call(json!({"task": "summarize"}));
+            call.id = id.to_string();
+            call
+        };
+
+        let first = runner.plan(&in_turn("call_5_1_0")).expect("first run fits");
+        let second = runner
+            .plan(&in_turn("call_5_1_1"))
+            .expect("second run fits");
+        assert!(Arc::ptr_eq(&first.budget, &second.budget));
+        let err = runner
+            .plan(&in_turn("call_5_2_0"))
+            .err()
+            .expect("third run in the turn exceeds the budget");
+        assert_eq!(
+            err.to_string(),
+            "delegation budget of 2 sub-agent runs is used up"
+        );
+
+        let next_turn = runner
+            .plan(&in_turn("call_6_1_0"))
+            .expect("a new turn starts a fresh budget");
+        assert!(!Arc::ptr_eq(&first.budget, &next_turn.budget));
+    }
+
+    #[tokio::test]
+    async fn child_runs_with_a_fresh_history_and_returns_answer_with_trace() {
+        let runner = runner(limits(1, 4, 1_000));
+        let plan = runner
+            .plan(&call(json!({"task": "is the build green?"})))
+            .expect("plan should succeed");
+        let engine = plan.engine("call_3_1_0", &ToolContext::default());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let chat = stub_chat(
+            vec![
+                r#"{"tool_call":{"name":"notes.read"}}"#,
+                "Yes, it is green.",
+            ],
+            Arc::clone(&requests),
+        );
+
+        let output = run_child(engine, plan, "call_3_1_0", &ToolContext::default(), chat)
+            .await
+            .expect("child should finish");
+
+        assert_eq!(
+            output.content,
+            "Yes, it is green.\n\n[sub-agent: model child-model, 1 tool hops, 200 tokens; tools: notes.read ok]"
+        );
+        let requests = requests.lock().expect("requests lock");
+        let first = &requests[0];
+        assert!(
+            first
+                .iter()
+                .any(|message| message.content == SUB_AGENT_PROMPT)
+        );
+        assert_eq!(
+            first.last().expect("request has messages").content,
+            "is the build green?"
+        );
+    }
+
+    /// Denies every call, recording the ids it was asked about.
+    #[derive(Default)]
+    struct DenyingApprover {
+        reviewed: Mutex<Vec<String>>,
+    }
+
+    impl ToolApprover for DenyingApprover {
+        fn review<'a>(&'a self, call: &'a ToolCall) -> ToolApprovalFuture<'a> {
+            self.reviewed
+                .lock()
+                .expect("reviewed lock")
+                .push(call.id.clone());
+            Box::pin(async {
+                ToolApproval::Denied {
+                    reason: "not now".to_string(),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn child_calls_are_reviewed_by_the_parent_approver() {
+        let runner = runner(limits(1, 4, 1_000));
+        let plan = runner
+            .plan(&call(json!({"task": "is the build green?"})))
+            .expect("plan should succeed");
+        let approver = Arc::new(DenyingApprover::default());
+        let mut context = ToolContext {
+            turn_id: 4,
+            approver: Some(Arc::clone(&approver) as Arc<dyn ToolApprover>),
+            ..ToolContext::default()
+        };
+        let mut parent_events = context.events.subscribe();
+        let engine = plan.engine("call_4_1_0", &context);
+        let chat = stub_chat(
+            vec![
+                r#"{"tool_call":{"name":"notes.read"}}"#,
+                "I could not tell.",
+            ],
+            Arc::new(Mutex::new(Vec::new())),
+        );
+
+        let output = run_child(engine, plan, "call_4_1_0", &context, chat)
+            .await
+            .expect("child should finish");
+
+        assert!(output.content.ends_with("tools: notes.read denied]"));
+        assert_eq!(
+            *approver.reviewed.lock().expect("reviewed lock"),
+            ["call_4_1_0.call_1_1_0"]
+        );
+        let forwarded: Vec<AgentEvent> =
+            std::iter::from_fn(|| parent_events.try_recv().ok()).collect();
+        assert!(forwarded.iter().all(|event| event.turn_id == 4));
+        assert!(matches!(
+            &forwarded[0].kind,
+            AgentEventKind::ToolApprovalRequested { call_id, .. } if call_id == "call_4_1_0.call_1_1_0"
+        ));
+        assert!(matches!(
+            &forwarded[1].kind,
+            AgentEventKind::ToolDenied { tool_name, .. } if tool_name == "notes.read"
+        ));
+    }
+
+    #[tokio::test]
+    async fn child_stops_when_the_token_budget_is_used_up() {
+        let runner = runner(limits(1, 4, 150));
+        let plan = runner
+            .plan(&call(json!({"task": "read the notes twice"})))
+            .expect("plan should succeed");
+        let engine = plan.engine("call_3_1_0", &ToolContext::default());
+        let chat = stub_chat(
+            vec![
+                r#"{"tool_call":{"name":"notes.read"}}"#,
+                r#"{"tool_call":{"name":"notes.read"}}"#,
+                "done",
+            ],
+            Arc::new(Mutex::new(Vec::new())),
+        );
+
+        let err = run_child(engine, plan, "call_3_1_0", &ToolContext::default(), chat)
+            .await
+            .expect_err("child should run out of tokens");
+        assert_eq!(
+            err.to_string(),
+            "sub-agent failed: delegation budget of 150 tokens is used up"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::plan::{Plan, StepStatus};
+
+/// Serializes as `{"turn_id":1,"type":"turn_started",...}`, with `type` set to
+/// [`AgentEventKind::as_str`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AgentEvent {
+    pub turn_id: u64,
+    #[serde(flatten)]
+    pub kind: AgentEventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AgentEventKind {
+    TurnStarted {
+        user_input: String,
+    },
+    ModelRequestSent {
+        message_count: usize,
+    },
+    ModelDelta {
+        content: String,
+    },
+    /// Tool events carry the call's position within its model response, since
+    /// one response can request several calls that run concurrently.
+    ToolCallParsed {
+        tool_hop: usize,
+        call_index: usize,
+        call_id: String,
+        tool_name: String,
+    },
+    /// Sent when a [`ToolApprover`](super::tools::ToolApprover) is set; the
+    /// call waits until it is approved or denied.
+    ToolApprovalRequested {
+        call_index: usize,
+        call_id: String,
+        tool_name: String,
+        arguments: Map<String, Value>,
+    },
+    ToolApproved {
+        call_index: usize,
+        tool_name: String,
+    },
+    ToolDenied {
+        call_index: usize,
+        tool_name: String,
+        reason: String,
+    },
+    ToolResult {
+        call_index: usize,
+        tool_name: String,
+        output: String,
+        is_error: bool,
+    },
+    HistoryTrimmed {
+        dropped_messages: usize,
+        history_len: usize,
+    },
+    /// Plan events are only sent by plan-mode turns. Step indexes count from
+    /// 0 into the current plan's steps.
+    PlanCreated {
+        plan: Plan,
+    },
+    PlanStepStarted {
+        step_index: usize,
+        description: String,
+    },
+    PlanStepFinished {
+        step_index: usize,
+        status: StepStatus,
+        result: String,
+    },
+    /// Sent after a failed step, with the failed step kept and the remaining
+    /// steps replaced.
+    PlanRevised {
+        plan: Plan,
+    },
+    TurnFinished {
+        answer: String,
+        tool_hops: usize,
+    },
+    TurnCancelled,
+    /// The turn ended with an error; the user input stays in the history.
+    TurnFailed {
+        error: String,
+    },
+}
+
+impl AgentEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TurnStarted { .. } => "turn_started",
+            Self::ModelRequestSent { .. } => "model_request_sent",
+            Self::ModelDelta { .. } => "model_delta",
+            Self::ToolCallParsed { .. } => "tool_call_parsed",
+            Self::ToolApprovalRequested { .. } => "tool_approval_requested",
+            Self::ToolApproved { .. } => "tool_approved",
+            Self::ToolDenied { .. } => "tool_denied",
+            Self::ToolResult { .. } => "tool_result",
+            Self::HistoryTrimmed { .. } => "history_trimmed",
+            Self::PlanCreated { .. } => "plan_created",
+            Self::PlanStepStarted { .. } => "plan_step_started",
+            Self::PlanStepFinished { .. } => "plan_step_finished",
+            Self::PlanRevised { .. } => "plan_revised",
+            Self::TurnFinished { .. } => "turn_finished",
+            Self::TurnCancelled => "turn_cancelled",
+            Self::TurnFailed { .. } => "turn_failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventEmitter {
+    subscribers: Vec<UnboundedSender<AgentEvent>>,
+}
+
+impl EventEmitter {
+    pub(crate) fn subscribe(&mut self) -> UnboundedReceiver<AgentEvent> {
+        self.subscribers.retain(|sender| !sender.is_closed());
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    pub(crate) fn emit(&self, turn_id: u64, kind: AgentEventKind) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+
+        let event = AgentEvent { turn_id, kind };
+        for sender in &self.subscribers {
+            // A dropped receiver only means that consumer stopped listening.
+            let _ = sender.send(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AgentEvent, AgentEventKind, EventEmitter};
+
+    #[test]
+    fn emitter_delivers_events_to_every_subscriber() {
+        let mut emitter = EventEmitter::default();
+        let mut first = emitter.subscribe();
+        let mut second = emitter.subscribe();
+
+        emitter.emit(7, AgentEventKind::ModelRequestSent { message_count: 3 });
+
+        let expected = AgentEvent {
+            turn_id: 7,
+            kind: AgentEventKind::ModelRequestSent { message_count: 3 },
+        };
+        assert_eq!(first.try_recv().expect("first subscriber event"), expected);
+        assert_eq!(
+            second.try_recv().expect("second subscriber event"),
+            expected
+        );
+    }
+
+    #[test]
+    fn events_serialize_with_their_type_name() {
+        let event = AgentEvent {
+            turn_id: 2,
+            kind: AgentEventKind::ToolDenied {
+                call_index: 0,
+                tool_name: "time.now".to_string(),
+                reason: "not now".to_string(),
+            },
+        };
+
+        assert_eq!(
+            serde_json::to_value(&event).expect("event should serialize"),
+            serde_json::json!({
+                "turn_id": 2,
+                "type": event.kind.as_str(),
+                "call_index": 0,
+                "tool_name": "time.now",
+                "reason": "not now",
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(AgentEvent {
+                turn_id: 3,
+                kind: AgentEventKind::TurnCancelled,
+            })
+            .expect("event should serialize"),
+            serde_json::json!({"turn_id": 3, "type": "turn_cancelled"})
+        );
+    }
+
+    #[test]
+    fn emitter_ignores_dropped_subscribers() {
+        let mut emitter = EventEmitter::default();
+        drop(emitter.subscribe());
+        let mut live = emitter.subscribe();
+
+        emitter.emit(
+            1,
+            AgentEventKind::TurnFinished {
+                answer: "done".to_string(),
+                tool_hops: 0,
+            },
+        );
+
+        assert_eq!(emitter.subscribers.len(), 1);
+        assert_eq!(live.try_recv().expect("live subscriber event").turn_id, 1);
+    }
+}
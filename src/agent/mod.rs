@@ -1,37 +1,120 @@
-mod tools;
+pub mod delegate;
+pub mod events;
+pub mod plan;
+mod summary;
+pub mod tools;
 
 use anyhow::Result;
+use futures_util::{StreamExt, stream};
 use reqwest::Client;
+use std::error::Error;
+use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, debug, info, info_span, warn};
 
-use crate::config::Config;
-use crate::model::{self, Message};
-
+use crate::config::{
+    AgentMode, Config, GenerationOptions, ModelRole, ModelTarget, ToolCallExtraction,
+};
+use crate::memory::{self, MemoryEntry, MemoryStore};
+use crate::model::{
+    self, ImageAttachment, Message, MessageRole, ModelReply, RoleUsage, UsageTotals,
+};
+use crate::model_error::ModelError;
+use crate::output_schema::OutputFormat;
+use events::{AgentEvent, AgentEventKind, EventEmitter};
+use plan::{Plan, StepStatus};
+
+/// History budget, counted in messages; images count extra, see
+/// [`HISTORY_WEIGHT_PER_IMAGE`].
 const MAX_HISTORY_MESSAGES: usize = 40;
+/// Images take far more context than a typical message, so each one counts
+/// as this many messages toward [`MAX_HISTORY_MESSAGES`].
+const HISTORY_WEIGHT_PER_IMAGE: usize = 4;
 const MAX_TOOL_HOPS_PER_TURN: usize = 2;
 const INITIAL_TURN_ID: u64 = 1;
 
-type ModelFuture = Pin<Box<dyn Future<Output = Result<String>>>>;
+type ModelFuture = Pin<Box<dyn Future<Output = Result<ModelReply>> + Send>>;
+
+/// What the engine asks of one model request besides the messages.
+#[derive(Debug, Clone, PartialEq)]
+struct ModelCall {
+    role: ModelRole,
+    output_format: Option<OutputFormat>,
+    /// Whether the reply is sent as [`AgentEventKind::ModelDelta`] events;
+    /// off for replies that never reach the user as they are.
+    stream: bool,
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum HistoryMessageKind {
     System,
     UserInput,
+    /// A plan step's instructions: a user message that does not start a turn.
+    PlanStep,
     ToolResult,
     Assistant,
 }
 
+/// Outcome of a completed turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TurnResult {
+    pub answer: String,
+    pub tool_hops: usize,
+    /// The model target that produced the turn's last response, which may be
+    /// a fallback when the primary target failed.
+    pub model_target: ModelTarget,
+    /// Tokens and time spent on the turn's model calls.
+    pub usage: UsageTotals,
+    /// The same usage, split by the role each call ran as.
+    pub role_usage: RoleUsage,
+}
+
+/// Returned by [`Agent::run_turn_cancellable`] when the turn was cancelled
+/// before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TurnCancelled {
+    pub turn_id: u64,
+}
+
+impl fmt::Display for TurnCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "turn {} was cancelled", self.turn_id)
+    }
+}
+
+impl Error for TurnCancelled {}
+
+/// How one run of the tool loop ended.
+struct ToolLoopOutcome {
+    answer: String,
+    tool_hops: usize,
+    model_target: ModelTarget,
+    /// The answer is the hop-limit message rather than a model reply.
+    hit_hop_limit: bool,
+}
+
+#[derive(Clone)]
 struct TurnState {
     history: Vec<Message>,
     history_kinds: Vec<HistoryMessageKind>,
+    /// The system messages, ending with the summary message when there is
+    /// one.
     system_len: usize,
+    /// Images attached to the next user input.
+    pending_images: Vec<ImageAttachment>,
+    /// Condensed trimmed history, see [`summary`].
+    summary: Option<String>,
+    /// Messages trimmed since the summary was last brought up to date.
+    trimmed: Vec<Message>,
 }
 
 impl TurnState {
-    fn new(cfg: &Config) -> Self {
-        Self::from_system_messages(build_system_messages(cfg))
+    fn new(cfg: &Config, tools: &[tools::ToolSpec]) -> Self {
+        Self::from_system_messages(build_system_messages(cfg, tools, &[]))
     }
 
     fn from_system_messages(system_messages: Vec<Message>) -> Self {
@@ -42,57 +125,146 @@ impl TurnState {
             history,
             history_kinds,
             system_len,
+            pending_images: Vec::new(),
+            summary: None,
+            trimmed: Vec::new(),
+        }
+    }
+
+    /// Swaps the system prefix of the history, keeping the conversation and
+    /// its summary.
+    fn replace_system_messages(&mut self, mut system_messages: Vec<Message>) {
+        if let Some(summary) = &self.summary {
+            system_messages.push(summary::summary_message(summary));
         }
+        let system_len = system_messages.len();
+        self.history.splice(..self.system_len, system_messages);
+        self.history_kinds.splice(
+            ..self.system_len,
+            std::iter::repeat_n(HistoryMessageKind::System, system_len),
+        );
+        self.system_len = system_len;
     }
 
     fn reset(&mut self) {
+        if self.summary.take().is_some() {
+            self.system_len -= 1;
+        }
         self.history.truncate(self.system_len);
         self.history_kinds.truncate(self.system_len);
+        self.pending_images.clear();
+        self.trimmed.clear();
+    }
+
+    /// Keeps `summary` as the last system message, replacing the previous
+    /// one.
+    fn set_summary(&mut self, summary: String) {
+        let message = summary::summary_message(&summary);
+        if self.summary.is_some() {
+            self.history[self.system_len - 1] = message;
+        } else {
+            self.history.insert(self.system_len, message);
+            self.history_kinds
+                .insert(self.system_len, HistoryMessageKind::System);
+            self.system_len += 1;
+        }
+        self.summary = Some(summary);
     }
 
     fn history(&self) -> &[Message] {
         &self.history
     }
 
-    fn push_user_input(&mut self, content: impl Into<String>) {
-        self.push_message(Message::user(content), HistoryMessageKind::UserInput);
+    /// The `push_*` methods return how many messages were trimmed to make room.
+    /// Pending images are attached to the input and cleared.
+    fn push_user_input(&mut self, content: impl Into<String>) -> usize {
+        let images = std::mem::take(&mut self.pending_images);
+        self.push_message(
+            Message::user(content).with_images(images),
+            HistoryMessageKind::UserInput,
+        )
+    }
+
+    fn push_plan_step(&mut self, instructions: impl Into<String>) -> usize {
+        self.push_message(Message::user(instructions), HistoryMessageKind::PlanStep)
     }
 
-    fn push_tool_result(&mut self, tool_name: &str, tool_result: &str) {
+    fn push_tool_result(&mut self, tool_name: &str, call_id: &str, tool_result: &str) -> usize {
         self.push_message(
-            Message::user(format_tool_result_user_message(tool_name, tool_result)),
+            Message::tool(tool_name, call_id, tool_result),
             HistoryMessageKind::ToolResult,
-        );
+        )
     }
 
-    fn push_assistant(&mut self, content: impl Into<String>) {
-        self.push_message(Message::assistant(content), HistoryMessageKind::Assistant);
+    fn push_assistant(&mut self, content: impl Into<String>) -> usize {
+        self.push_message(Message::assistant(content), HistoryMessageKind::Assistant)
     }
 
-    fn push_message(&mut self, message: Message, kind: HistoryMessageKind) {
+    fn push_message(&mut self, message: Message, kind: HistoryMessageKind) -> usize {
         self.history.push(message);
         self.history_kinds.push(kind);
-        self.trim_history();
+        self.trim_history()
     }
 
-    fn trim_history(&mut self) {
-        trim_history_messages(&mut self.history, &mut self.history_kinds, self.system_len);
+    fn trim_history(&mut self) -> usize {
+        let dropped =
+            trim_history_messages(&mut self.history, &mut self.history_kinds, self.system_len);
+        let count = dropped.len();
+        self.trimmed.extend(dropped);
+        count
     }
 }
 
 struct TurnEngine {
     state: TurnState,
+    events: EventEmitter,
+    max_tool_concurrency: usize,
+    max_tool_calls_per_hop: usize,
+    tool_call_extraction: ToolCallExtraction,
+    tool_approver: Option<Arc<dyn tools::ToolApprover>>,
+    mode: AgentMode,
+    /// Roles whose text replies are asked again as
+    /// [`ModelRole::FinalAnswer`], because that role runs on another target.
+    reroute_answers_from: Vec<ModelRole>,
+    /// Whether trimmed history is condensed by [`ModelRole::Summarizer`],
+    /// which is only done when that role has a target of its own.
+    summarize_history: bool,
+    /// The latest plan-mode turn's plan, kept after the turn finishes.
+    plan: Option<Plan>,
+    /// Usage of every model call that returned, including calls from turns
+    /// that were later cancelled or failed.
+    session_usage: RoleUsage,
 }
 
 impl TurnEngine {
-    fn new(cfg: &Config) -> Self {
+    fn new(cfg: &Config, tools: &[tools::ToolSpec]) -> Self {
         Self {
-            state: TurnState::new(cfg),
+            state: TurnState::new(cfg, tools),
+            events: EventEmitter::default(),
+            max_tool_concurrency: cfg.tool_max_concurrency,
+            max_tool_calls_per_hop: cfg.tool_max_calls_per_hop,
+            tool_call_extraction: cfg.tool_call_extraction,
+            tool_approver: None,
+            mode: cfg.agent_mode,
+            reroute_answers_from: [ModelRole::Router, ModelRole::ToolStep]
+                .into_iter()
+                .filter(|role| {
+                    cfg.role_model_target(*role) != cfg.role_model_target(ModelRole::FinalAnswer)
+                })
+                .collect(),
+            summarize_history: cfg.model_roles.summarizer.is_some(),
+            plan: None,
+            session_usage: RoleUsage::default(),
         }
     }
 
+    fn subscribe(&mut self) -> UnboundedReceiver<AgentEvent> {
+        self.events.subscribe()
+    }
+
     fn reset(&mut self) {
         self.state.reset();
+        self.plan = None;
     }
 
     fn history(&self) -> &[Message] {
@@ -104,34 +276,116 @@ impl TurnEngine {
         turn_id: u64,
         user_input: &str,
         client: &Client,
-        cfg: &Config,
+        cfg: &Arc<Config>,
         tool_runner: &dyn tools::ToolRunner,
-    ) -> Result<String> {
+        cancel: &CancellationToken,
+    ) -> Result<TurnResult> {
+        let chat = self.live_chat(turn_id, client, cfg);
+        self.run_turn_cancellable_with(turn_id, user_input, chat, tool_runner, cancel)
+            .await
+    }
+
+    /// Sends model requests for turn `turn_id` to the targets of their role,
+    /// streaming deltas as [`AgentEventKind::ModelDelta`] events.
+    fn live_chat(
+        &self,
+        turn_id: u64,
+        client: &Client,
+        cfg: &Arc<Config>,
+    ) -> impl FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send + 'static {
         let client = client.clone();
-        let cfg = cfg.clone();
+        let cfg = Arc::clone(cfg);
+        let events = self.events.clone();
+        move |messages, call: ModelCall| {
+            let client = client.clone();
+            let cfg = Arc::new(cfg.for_role(call.role));
+            let events = events.clone();
+            let message_count = messages.len();
+            let model_span = info_span!(
+                "model.chat",
+                turn_id,
+                role = call.role.as_str(),
+                provider = tracing::field::Empty,
+                model = tracing::field::Empty,
+                message_count,
+                attempts = tracing::field::Empty,
+                model_target = tracing::field::Empty,
+                prompt_tokens = tracing::field::Empty,
+                completion_tokens = tracing::field::Empty,
+                latency_ms = tracing::field::Empty
+            );
+            let options = GenerationOptions {
+                output_format: call.output_format,
+                ..cfg.generation.clone()
+            };
+            let stream = call.stream;
+            Box::pin(
+                async move {
+                    let mut on_delta = |delta: &str| {
+                        if !stream {
+                            return;
+                        }
+                        events.emit(
+                            turn_id,
+                            AgentEventKind::ModelDelta {
+                                content: delta.to_string(),
+                            },
+                        );
+                    };
+                    model::chat_stream(&client, &cfg, &messages, &options, &mut on_delta)
+                        .await
+                        .map_err(anyhow::Error::from)
+                }
+                .instrument(model_span),
+            )
+        }
+    }
 
-        self.run_turn_with(
-            turn_id,
-            user_input,
-            move |messages| {
-                let client = client.clone();
-                let cfg = cfg.clone();
-                let message_count = messages.len();
-                let model_span = info_span!(
-                    "model.chat",
+    /// Runs a turn until it finishes or `cancel` fires. Cancelling drops the
+    /// in-flight model and tool futures and restores the pre-turn history and
+    /// plan.
+    async fn run_turn_cancellable_with<C>(
+        &mut self,
+        turn_id: u64,
+        user_input: &str,
+        chat: C,
+        tool_runner: &dyn tools::ToolRunner,
+        cancel: &CancellationToken,
+    ) -> Result<TurnResult>
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
+        let snapshot = self.state.clone();
+        let plan_snapshot = self.plan.clone();
+        let outcome = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => None,
+            result = self.run_turn_with(turn_id, user_input, chat, tool_runner) => Some(result),
+        };
+
+        match outcome {
+            Some(Err(err)) => {
+                self.events.emit(
                     turn_id,
-                    provider = %cfg.model_provider,
-                    model = %cfg.model,
-                    message_count
+                    AgentEventKind::TurnFailed {
+                        error: format!("{err:#}"),
+                    },
                 );
-                Box::pin(
-                    async move { model::chat(&client, &cfg, &messages).await }
-                        .instrument(model_span),
-                )
-            },
-            tool_runner,
-        )
-        .await
+                Err(err)
+            }
+            Some(result) => result,
+            None => {
+                self.state = snapshot;
+                self.plan = plan_snapshot;
+                info!(
+                    turn_id,
+                    history_len = self.state.history().len(),
+                    "cancelled turn and restored history"
+                );
+                self.events.emit(turn_id, AgentEventKind::TurnCancelled);
+                Err(TurnCancelled { turn_id }.into())
+            }
+        }
     }
 
     #[tracing::instrument(
@@ -145,107 +399,678 @@ impl TurnEngine {
         user_input: &str,
         mut chat: C,
         tool_runner: &dyn tools::ToolRunner,
-    ) -> Result<String>
+    ) -> Result<TurnResult>
     where
-        C: FnMut(Vec<Message>) -> ModelFuture,
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
     {
-        self.state.push_user_input(user_input);
+        self.events.emit(
+            turn_id,
+            AgentEventKind::TurnStarted {
+                user_input: user_input.to_string(),
+            },
+        );
+        let dropped = self.state.push_user_input(user_input);
+        self.report_trim(turn_id, dropped);
         debug!(
             user_input_len = user_input.len(),
             history_len = self.state.history().len(),
             "started turn"
         );
 
+        let mut usage = RoleUsage::default();
+        let (answer, tool_hops, model_target) = match self.mode {
+            AgentMode::React => {
+                let outcome = self
+                    .run_tool_loop(turn_id, 0, false, &mut usage, &mut chat, tool_runner)
+                    .await?;
+                (outcome.answer, outcome.tool_hops, outcome.model_target)
+            }
+            AgentMode::Plan => {
+                self.run_plan(turn_id, &mut usage, &mut chat, tool_runner)
+                    .await?
+            }
+        };
+        let totals = usage.total();
+        info!(
+            tool_hops,
+            response_len = answer.len(),
+            history_len = self.state.history().len(),
+            model_target = %model_target,
+            model_calls = totals.model_calls,
+            prompt_tokens = totals.usage.prompt_tokens,
+            completion_tokens = totals.usage.completion_tokens,
+            "completed turn"
+        );
+        Ok(self.finish_turn(turn_id, answer, tool_hops, model_target, usage))
+    }
+
+    /// Asks the model and runs the tools it calls until it answers or hits
+    /// [`MAX_TOOL_HOPS_PER_TURN`], then adds the answer to the history.
+    /// `hop_base` counts the hops the turn already made, keeping call ids
+    /// unique across the steps of a plan. Plan steps run as
+    /// [`ModelRole::ToolStep`] throughout, since their replies are not the
+    /// turn's answer.
+    async fn run_tool_loop<C>(
+        &mut self,
+        turn_id: u64,
+        hop_base: usize,
+        plan_step: bool,
+        usage: &mut RoleUsage,
+        chat: &mut C,
+        tool_runner: &dyn tools::ToolRunner,
+    ) -> Result<ToolLoopOutcome>
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
         let mut tool_hops = 0usize;
-        let mut response = chat(self.state.history().to_vec()).await?;
+        let mut role = loop_role(plan_step, tool_hops);
+        let mut reply = self
+            .request_model(turn_id, tool_hops, role, !plan_step, usage, chat)
+            .await?;
 
         loop {
-            let Some(tool_call) = tools::parse_tool_call(&response) else {
-                self.state.push_assistant(response.clone());
-                info!(
+            let Some((mut tool_calls, tool_call_match)) =
+                tools::extract_tool_calls(&reply.content, self.tool_call_extraction)
+            else {
+                if !plan_step && self.reroute_answers_from.contains(&role) {
+                    debug!(
+                        from_role = role.as_str(),
+                        "asking the final-answer model for the answer"
+                    );
+                    role = ModelRole::FinalAnswer;
+                    reply = self
+                        .request_model(turn_id, tool_hops, role, true, usage, chat)
+                        .await?;
+                    continue;
+                }
+                let answer = tools::final_answer(reply.content, self.tool_call_extraction);
+                self.push_assistant(turn_id, answer.clone());
+                return Ok(ToolLoopOutcome {
+                    answer,
                     tool_hops,
-                    response_len = response.len(),
-                    history_len = self.state.history().len(),
-                    "completed turn"
-                );
-                return Ok(response);
+                    model_target: reply.target,
+                    hit_hop_limit: false,
+                });
             };
 
+            let tool_hop = hop_base + tool_hops + 1;
+            for (call_index, tool_call) in tool_calls.iter_mut().enumerate() {
+                tool_call.id = tool_call_id(turn_id, tool_hop, call_index);
+                self.events.emit(
+                    turn_id,
+                    AgentEventKind::ToolCallParsed {
+                        tool_hop,
+                        call_index,
+                        call_id: tool_call.id.clone(),
+                        tool_name: tool_call.name.clone(),
+                    },
+                );
+            }
+
             if tool_hops >= MAX_TOOL_HOPS_PER_TURN {
                 warn!(
                     max_tool_hops = MAX_TOOL_HOPS_PER_TURN,
                     tool_hops, "tool hop limit reached"
                 );
+                for (call_index, tool_call) in tool_calls.iter().enumerate() {
+                    self.events.emit(
+                        turn_id,
+                        AgentEventKind::ToolDenied {
+                            call_index,
+                            tool_name: tool_call.name.clone(),
+                            reason: format!("tool hop limit of {} reached", MAX_TOOL_HOPS_PER_TURN),
+                        },
+                    );
+                }
                 let limit_msg = format!(
                     "I stopped after {} tool calls in one turn. Please try a simpler request.",
                     MAX_TOOL_HOPS_PER_TURN
                 );
-                self.state.push_assistant(limit_msg.clone());
-                return Ok(limit_msg);
+                self.push_assistant(turn_id, limit_msg.clone());
+                return Ok(ToolLoopOutcome {
+                    answer: limit_msg,
+                    tool_hops,
+                    model_target: reply.target,
+                    hit_hop_limit: true,
+                });
             }
 
             tool_hops += 1;
-            info!(tool_name = %tool_call.name, tool_hop = tool_hops, "executing tool call");
-            self.state.push_assistant(response);
-
-            let tool_span = info_span!(
-                "tool.call",
-                turn_id,
-                tool_hop = tool_hops,
-                tool_name = %tool_call.name
+            info!(
+                tool_hop,
+                tool_call_count = tool_calls.len(),
+                extraction_mode = self.tool_call_extraction.as_str(),
+                tool_call_match = tool_call_match.as_str(),
+                "executing tool calls"
             );
-            let tool_result = match tool_runner.execute(&tool_call).instrument(tool_span).await {
-                Ok(output) => {
-                    debug!(
-                        tool_name = %tool_call.name,
-                        output_len = output.content.len(),
-                        "tool call succeeded"
-                    );
-                    output.content
-                }
-                Err(err) => {
-                    warn!(tool_name = %tool_call.name, error = %err, "tool call failed");
-                    format!("ERROR: {err}")
-                }
-            };
-            self.state.push_tool_result(&tool_call.name, &tool_result);
+            self.push_assistant(turn_id, reply.content);
+
+            let tool_results = self
+                .execute_tool_calls(turn_id, tool_hop, &tool_calls, tool_runner)
+                .await;
+            for (tool_call, tool_result) in tool_calls.iter().zip(&tool_results) {
+                let dropped =
+                    self.state
+                        .push_tool_result(&tool_call.name, &tool_call.id, tool_result);
+                self.report_trim(turn_id, dropped);
+            }
             debug!(
                 history_len = self.state.history().len(),
                 "requesting follow-up model response"
             );
 
-            response = chat(self.state.history().to_vec()).await?;
+            role = loop_role(plan_step, tool_hops);
+            reply = self
+                .request_model(turn_id, tool_hops, role, !plan_step, usage, chat)
+                .await?;
+        }
+    }
+
+    /// Runs a plan-mode turn: asks for a plan, runs each step as a tool loop
+    /// and revises the plan after a failed step, then asks for the answer.
+    /// Returns the answer, the tool hops of all steps and the target of the
+    /// last reply.
+    async fn run_plan<C>(
+        &mut self,
+        turn_id: u64,
+        usage: &mut RoleUsage,
+        chat: &mut C,
+        tool_runner: &dyn tools::ToolRunner,
+    ) -> Result<(String, usize, ModelTarget)>
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
+        let Some(steps) = self
+            .request_plan(turn_id, plan::planning_instructions(), usage, chat)
+            .await?
+        else {
+            warn!("falling back to a single tool loop without a plan");
+            let outcome = self
+                .run_tool_loop(turn_id, 0, false, usage, chat, tool_runner)
+                .await?;
+            return Ok((outcome.answer, outcome.tool_hops, outcome.model_target));
+        };
+        let mut current = Plan::new(steps);
+        info!(step_count = current.steps.len(), "created plan");
+        self.events.emit(
+            turn_id,
+            AgentEventKind::PlanCreated {
+                plan: current.clone(),
+            },
+        );
+        self.plan = Some(current.clone());
+
+        let mut tool_hops = 0usize;
+        let mut revisions = 0usize;
+        while let Some(step_index) = current.next_pending() {
+            current.steps[step_index].status = StepStatus::Running;
+            self.plan = Some(current.clone());
+            self.events.emit(
+                turn_id,
+                AgentEventKind::PlanStepStarted {
+                    step_index,
+                    description: current.steps[step_index].description.clone(),
+                },
+            );
+            let dropped = self
+                .state
+                .push_plan_step(plan::step_instructions(&current, step_index));
+            self.report_trim(turn_id, dropped);
+
+            let outcome = self
+                .run_tool_loop(turn_id, tool_hops, true, usage, chat, tool_runner)
+                .await?;
+            tool_hops += outcome.tool_hops;
+            let failure = if outcome.hit_hop_limit {
+                Some(outcome.answer.as_str())
+            } else {
+                plan::step_failure(&outcome.answer)
+            };
+            let (status, result) = match failure {
+                Some(reason) => (StepStatus::Failed, reason.to_string()),
+                None => (StepStatus::Done, outcome.answer.trim().to_string()),
+            };
+            info!(
+                step_index,
+                status = status.as_str(),
+                tool_hops = outcome.tool_hops,
+                "finished plan step"
+            );
+            let step = &mut current.steps[step_index];
+            step.status = status;
+            step.result = Some(result.clone());
+            self.plan = Some(current.clone());
+            self.events.emit(
+                turn_id,
+                AgentEventKind::PlanStepFinished {
+                    step_index,
+                    status,
+                    result,
+                },
+            );
+
+            if status != StepStatus::Failed {
+                continue;
+            }
+            if revisions >= plan::MAX_PLAN_REVISIONS {
+                warn!(
+                    max_revisions = plan::MAX_PLAN_REVISIONS,
+                    "plan revision limit reached; skipping remaining steps"
+                );
+                self.skip_remaining_steps(turn_id, &mut current, "plan revision limit reached");
+                break;
+            }
+            revisions += 1;
+            let Some(steps) = self
+                .request_plan(
+                    turn_id,
+                    plan::revision_instructions(&current, step_index),
+                    usage,
+                    chat,
+                )
+                .await?
+            else {
+                self.skip_remaining_steps(turn_id, &mut current, "plan revision was invalid");
+                break;
+            };
+            current.revise(steps);
+            info!(
+                revision = current.revision,
+                step_count = current.steps.len(),
+                "revised plan"
+            );
+            self.plan = Some(current.clone());
+            self.events.emit(
+                turn_id,
+                AgentEventKind::PlanRevised {
+                    plan: current.clone(),
+                },
+            );
+        }
+
+        self.summarize_trimmed(turn_id, usage, chat).await;
+        let mut messages = self.state.history().to_vec();
+        messages.push(Message::system(plan::final_instructions(&current)));
+        let call = ModelCall {
+            role: ModelRole::FinalAnswer,
+            output_format: None,
+            stream: true,
+        };
+        let reply = self
+            .request_model_with(turn_id, messages, call, usage, chat)
+            .await?;
+        let answer = tools::final_answer(reply.content, self.tool_call_extraction);
+        self.push_assistant(turn_id, answer.clone());
+        Ok((answer, tool_hops, reply.target))
+    }
+
+    /// Skips the plan's pending steps, reporting each as finished.
+    fn skip_remaining_steps(&mut self, turn_id: u64, current: &mut Plan, reason: &str) {
+        for step_index in current.skip_pending() {
+            self.events.emit(
+                turn_id,
+                AgentEventKind::PlanStepFinished {
+                    step_index,
+                    status: StepStatus::Skipped,
+                    result: reason.to_string(),
+                },
+            );
+        }
+        self.plan = Some(current.clone());
+    }
+
+    /// Asks for a step list with `instructions` appended to the history; the
+    /// request and reply are not kept in the history. `None` when the model
+    /// did not return a valid step list.
+    async fn request_plan<C>(
+        &mut self,
+        turn_id: u64,
+        instructions: String,
+        usage: &mut RoleUsage,
+        chat: &mut C,
+    ) -> Result<Option<Vec<String>>>
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
+        self.summarize_trimmed(turn_id, usage, chat).await;
+        let mut messages = self.state.history().to_vec();
+        messages.push(Message::system(instructions));
+        let call = ModelCall {
+            role: ModelRole::Router,
+            output_format: Some(plan::plan_output_format()),
+            stream: false,
+        };
+        let reply = match self
+            .request_model_with(turn_id, messages, call, usage, chat)
+            .await
+        {
+            Ok(reply) => reply,
+            Err(err) => {
+                return match err.downcast_ref::<ModelError>() {
+                    Some(ModelError::InvalidOutput { message }) => {
+                        warn!(error = %message, "model returned an invalid plan");
+                        Ok(None)
+                    }
+                    _ => Err(err),
+                };
+            }
+        };
+        match plan::parse_steps(&reply.content) {
+            Ok(steps) => Ok(Some(steps)),
+            Err(err) => {
+                warn!(error = %err, "model returned an invalid plan");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Runs one hop's tool calls concurrently, at most `max_tool_concurrency`
+    /// at a time, and returns their results in call order. Calls past
+    /// `max_tool_calls_per_hop` are denied without running.
+    async fn execute_tool_calls(
+        &self,
+        turn_id: u64,
+        tool_hop: usize,
+        tool_calls: &[tools::ToolCall],
+        tool_runner: &dyn tools::ToolRunner,
+    ) -> Vec<String> {
+        let context = tools::ToolContext {
+            turn_id,
+            approver: self.tool_approver.clone(),
+            events: self.events.clone(),
+        };
+        let context = &context;
+        // Boxing each call up front keeps the turn future `Send`; a `map`
+        // closure over borrowed calls trips higher-ranked lifetime inference.
+        let calls: Vec<Pin<Box<dyn Future<Output = String> + Send + '_>>> = tool_calls
+            .iter()
+            .enumerate()
+            .map(|(call_index, tool_call)| {
+                let tool_span = info_span!(
+                    "tool.call",
+                    turn_id,
+                    tool_hop,
+                    call_index,
+                    call_id = %tool_call.id,
+                    tool_name = %tool_call.name
+                );
+                let call = async move {
+                    if call_index >= self.max_tool_calls_per_hop {
+                        let reason = format!(
+                            "at most {} tool calls run per response",
+                            self.max_tool_calls_per_hop
+                        );
+                        info!(tool_name = %tool_call.name, %reason, "tool call denied");
+                        self.events.emit(
+                            turn_id,
+                            AgentEventKind::ToolDenied {
+                                call_index,
+                                tool_name: tool_call.name.clone(),
+                                reason: reason.clone(),
+                            },
+                        );
+                        return format!("DENIED: {reason}");
+                    }
+                    if let Some(approver) = &self.tool_approver {
+                        self.events.emit(
+                            turn_id,
+                            AgentEventKind::ToolApprovalRequested {
+                                call_index,
+                                call_id: tool_call.id.clone(),
+                                tool_name: tool_call.name.clone(),
+                                arguments: tool_call.arguments.clone(),
+                            },
+                        );
+                        if let tools::ToolApproval::Denied { reason } =
+                            approver.review(tool_call).await
+                        {
+                            info!(tool_name = %tool_call.name, %reason, "tool call denied");
+                            self.events.emit(
+                                turn_id,
+                                AgentEventKind::ToolDenied {
+                                    call_index,
+                                    tool_name: tool_call.name.clone(),
+                                    reason: reason.clone(),
+                                },
+                            );
+                            return format!("DENIED: {reason}");
+                        }
+                    }
+                    self.events.emit(
+                        turn_id,
+                        AgentEventKind::ToolApproved {
+                            call_index,
+                            tool_name: tool_call.name.clone(),
+                        },
+                    );
+                    let (tool_result, is_error) = match tool_runner
+                        .execute_in(tool_call, context)
+                        .await
+                    {
+                        Ok(output) => {
+                            debug!(
+                                tool_name = %tool_call.name,
+                                output_len = output.content.len(),
+                                "tool call succeeded"
+                            );
+                            (output.content, false)
+                        }
+                        Err(err) => {
+                            warn!(tool_name = %tool_call.name, error = %err, "tool call failed");
+                            (format!("ERROR: {err}"), true)
+                        }
+                    };
+                    self.events.emit(
+                        turn_id,
+                        AgentEventKind::ToolResult {
+                            call_index,
+                            tool_name: tool_call.name.clone(),
+                            output: tool_result.clone(),
+                            is_error,
+                        },
+                    );
+                    tool_result
+                }
+                .instrument(tool_span);
+                Box::pin(call) as Pin<Box<dyn Future<Output = String> + Send + '_>>
+            })
+            .collect();
+
+        stream::iter(calls)
+            .buffered(self.max_tool_concurrency)
+            .collect()
+            .await
+    }
+
+    /// In schema mode, requests that may still lead to a tool call ask for
+    /// [`tools::turn_output_format`]; once the hop limit is reached the reply
+    /// is free text. `stream` is dropped for roles whose text replies are
+    /// asked again as [`ModelRole::FinalAnswer`].
+    async fn request_model<C>(
+        &mut self,
+        turn_id: u64,
+        tool_hops: usize,
+        role: ModelRole,
+        stream: bool,
+        turn_usage: &mut RoleUsage,
+        chat: &mut C,
+    ) -> Result<ModelReply>
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
+        self.summarize_trimmed(turn_id, turn_usage, chat).await;
+        let messages = self.state.history().to_vec();
+        let output_format = (self.tool_call_extraction == ToolCallExtraction::Schema
+            && tool_hops < MAX_TOOL_HOPS_PER_TURN)
+            .then(tools::turn_output_format);
+        let call = ModelCall {
+            role,
+            output_format,
+            stream: stream && !self.reroute_answers_from.contains(&role),
+        };
+        self.request_model_with(turn_id, messages, call, turn_usage, chat)
+            .await
+    }
+
+    async fn request_model_with<C>(
+        &mut self,
+        turn_id: u64,
+        messages: Vec<Message>,
+        call: ModelCall,
+        turn_usage: &mut RoleUsage,
+        chat: &mut C,
+    ) -> Result<ModelReply>
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
+        self.events.emit(
+            turn_id,
+            AgentEventKind::ModelRequestSent {
+                message_count: messages.len(),
+            },
+        );
+        let role = call.role;
+        let reply = chat(messages, call).await?;
+        turn_usage.record(role, &reply.usage);
+        self.session_usage.record(role, &reply.usage);
+        Ok(reply)
+    }
+
+    /// Folds the messages trimmed since the last request into the history
+    /// summary. A failed or empty reply leaves the summary as it was; the
+    /// trimmed messages are gone either way.
+    async fn summarize_trimmed<C>(&mut self, turn_id: u64, turn_usage: &mut RoleUsage, chat: &mut C)
+    where
+        C: FnMut(Vec<Message>, ModelCall) -> ModelFuture + Send,
+    {
+        let trimmed = std::mem::take(&mut self.state.trimmed);
+        if trimmed.is_empty() || !self.summarize_history {
+            return;
+        }
+        let messages = summary::request_messages(self.state.summary.as_deref(), &trimmed);
+        let call = ModelCall {
+            role: ModelRole::Summarizer,
+            output_format: None,
+            stream: false,
+        };
+        match self
+            .request_model_with(turn_id, messages, call, turn_usage, chat)
+            .await
+        {
+            Ok(reply) if !reply.content.trim().is_empty() => {
+                debug!(
+                    summarized_messages = trimmed.len(),
+                    "updated history summary"
+                );
+                self.state.set_summary(reply.content.trim().to_string());
+                let dropped = self.state.trim_history();
+                self.report_trim(turn_id, dropped);
+            }
+            Ok(_) => warn!("summarizer returned an empty summary"),
+            Err(err) => warn!(error = %format!("{err:#}"), "failed to summarize trimmed history"),
+        }
+    }
+
+    fn push_assistant(&mut self, turn_id: u64, content: impl Into<String>) {
+        let dropped = self.state.push_assistant(content);
+        self.report_trim(turn_id, dropped);
+    }
+
+    fn report_trim(&self, turn_id: u64, dropped_messages: usize) {
+        if dropped_messages == 0 {
+            return;
+        }
+
+        debug!(dropped_messages, "trimmed conversation history");
+        self.events.emit(
+            turn_id,
+            AgentEventKind::HistoryTrimmed {
+                dropped_messages,
+                history_len: self.state.history().len(),
+            },
+        );
+    }
+
+    fn finish_turn(
+        &self,
+        turn_id: u64,
+        answer: String,
+        tool_hops: usize,
+        model_target: ModelTarget,
+        role_usage: RoleUsage,
+    ) -> TurnResult {
+        self.events.emit(
+            turn_id,
+            AgentEventKind::TurnFinished {
+                answer: answer.clone(),
+                tool_hops,
+            },
+        );
+        TurnResult {
+            answer,
+            tool_hops,
+            model_target,
+            usage: role_usage.total(),
+            role_usage,
         }
     }
 }
 
-pub struct Agent<'a> {
-    client: &'a Client,
-    cfg: &'a Config,
+/// Owns everything a turn needs, so agents are `Send + 'static` and can run on
+/// spawned tasks or live in shared server state.
+pub struct Agent {
+    client: Client,
+    cfg: Arc<Config>,
     tool_runner: Box<dyn tools::ToolRunner>,
     turn_engine: TurnEngine,
     next_turn_id: u64,
+    memory: Option<Arc<MemoryStore>>,
+    /// System messages supplied with a loaded conversation, kept after the
+    /// agent's own.
+    extra_system_messages: Vec<Message>,
 }
 
-impl<'a> Agent<'a> {
-    pub fn new(client: &'a Client, cfg: &'a Config) -> Self {
+impl Agent {
+    pub fn new(client: Client, cfg: Arc<Config>) -> Self {
         Self::with_tool_runner(client, cfg, Box::new(tools::BuiltinRunner))
     }
 
     pub fn with_tool_runner(
-        client: &'a Client,
-        cfg: &'a Config,
+        client: Client,
+        cfg: Arc<Config>,
         tool_runner: Box<dyn tools::ToolRunner>,
     ) -> Self {
+        let turn_engine = TurnEngine::new(&cfg, &tool_runner.tools());
         Self {
             client,
             cfg,
             tool_runner,
-            turn_engine: TurnEngine::new(cfg),
+            turn_engine,
             next_turn_id: INITIAL_TURN_ID,
+            memory: None,
+            extra_system_messages: Vec::new(),
         }
     }
 
+    /// Recalls memories from `store` at the start of every turn and adds the
+    /// most relevant ones to the system messages. Pair it with a
+    /// [`memory::MemoryRunner`] so the model can save and forget memories.
+    pub fn with_memory(mut self, store: Arc<MemoryStore>) -> Self {
+        self.memory = Some(store);
+        self
+    }
+
+    /// Asks `approver` before every tool call. Denied calls do not run; the
+    /// model gets the denial reason as the call's result.
+    pub fn with_tool_approver(mut self, approver: Arc<dyn tools::ToolApprover>) -> Self {
+        self.turn_engine.tool_approver = Some(approver);
+        self
+    }
+
+    pub fn memory(&self) -> Option<&Arc<MemoryStore>> {
+        self.memory.as_ref()
+    }
+
     pub fn reset(&mut self) {
         self.turn_engine.reset();
     }
@@ -254,19 +1079,104 @@ impl<'a> Agent<'a> {
         self.turn_engine.history()
     }
 
-    pub async fn run_turn(&mut self, user_input: &str) -> Result<String> {
+    /// The plan of the latest plan-mode turn, with each step's status.
+    pub fn plan(&self) -> Option<&Plan> {
+        self.turn_engine.plan.as_ref()
+    }
+
+    /// Replaces the conversation with `messages`, such as a transcript sent
+    /// by an API client. System messages are kept after the agent's own;
+    /// the rest become the history the next turn continues from.
+    pub fn load_conversation(&mut self, messages: Vec<Message>) {
+        self.turn_engine.reset();
+        let (system, conversation): (Vec<Message>, Vec<Message>) = messages
+            .into_iter()
+            .partition(|message| message.role == MessageRole::System);
+        self.extra_system_messages = system;
+        let system_messages = self.system_messages(&[]);
+        let state = &mut self.turn_engine.state;
+        state.replace_system_messages(system_messages);
+        for message in conversation {
+            let kind = match message.role {
+                MessageRole::User => HistoryMessageKind::UserInput,
+                MessageRole::Tool { .. } => HistoryMessageKind::ToolResult,
+                MessageRole::Assistant | MessageRole::System => HistoryMessageKind::Assistant,
+            };
+            state.push_message(message, kind);
+        }
+    }
+
+    /// Queues an image for the next turn's user input.
+    pub fn attach_image(&mut self, image: ImageAttachment) {
+        self.turn_engine.state.pending_images.push(image);
+    }
+
+    /// Images queued for the next turn.
+    pub fn pending_images(&self) -> &[ImageAttachment] {
+        &self.turn_engine.state.pending_images
+    }
+
+    /// Usage summed over every model call since the agent was created;
+    /// [`Agent::reset`] clears the history but not these totals.
+    pub fn session_usage(&self) -> UsageTotals {
+        self.turn_engine.session_usage.total()
+    }
+
+    /// [`Agent::session_usage`] split by the role each call ran as.
+    pub fn session_role_usage(&self) -> &RoleUsage {
+        &self.turn_engine.session_usage
+    }
+
+    /// Returns a receiver for the typed events emitted while turns run.
+    ///
+    /// Every subscriber sees every event; dropping the receiver unsubscribes.
+    pub fn subscribe(&mut self) -> UnboundedReceiver<AgentEvent> {
+        self.turn_engine.subscribe()
+    }
+
+    pub async fn run_turn(&mut self, user_input: &str) -> Result<TurnResult> {
+        self.run_turn_cancellable(user_input, &CancellationToken::new())
+            .await
+    }
+
+    /// Runs a turn that stops early when `cancel` fires.
+    ///
+    /// A cancelled turn aborts pending model requests and tool calls, rolls the
+    /// history back to its pre-turn state and fails with [`TurnCancelled`].
+    pub async fn run_turn_cancellable(
+        &mut self,
+        user_input: &str,
+        cancel: &CancellationToken,
+    ) -> Result<TurnResult> {
         let turn_id = self.next_turn_id();
+        if let Some(store) = &self.memory {
+            let memories = store
+                .relevant(user_input, self.cfg.memory_inject_limit)
+                .await;
+            debug!(turn_id, memory_count = memories.len(), "recalled memories");
+            let system_messages = self.system_messages(&memories);
+            self.turn_engine
+                .state
+                .replace_system_messages(system_messages);
+        }
         self.turn_engine
             .run_turn_live(
                 turn_id,
                 user_input,
-                self.client,
-                self.cfg,
+                &self.client,
+                &self.cfg,
                 self.tool_runner.as_ref(),
+                cancel,
             )
             .await
     }
 
+    fn system_messages(&self, memories: &[MemoryEntry]) -> Vec<Message> {
+        let mut messages = build_system_messages(&self.cfg, &self.tool_runner.tools(), memories);
+        messages.extend(self.extra_system_messages.iter().cloned());
+        messages
+    }
+
     fn next_turn_id(&mut self) -> u64 {
         let turn_id = self.next_turn_id;
         self.next_turn_id = self.next_turn_id.saturating_add(1);
@@ -274,64 +1184,161 @@ impl<'a> Agent<'a> {
     }
 }
 
-fn format_tool_result_user_message(tool_name: &str, tool_result: &str) -> String {
-    format!("Tool '{}' result: {}", tool_name, tool_result)
+/// The role of a tool-loop request made after `tool_hops` hops.
+fn loop_role(plan_step: bool, tool_hops: usize) -> ModelRole {
+    if plan_step {
+        ModelRole::ToolStep
+    } else if tool_hops >= MAX_TOOL_HOPS_PER_TURN {
+        ModelRole::FinalAnswer
+    } else if tool_hops == 0 {
+        ModelRole::Router
+    } else {
+        ModelRole::ToolStep
+    }
+}
+
+/// Builds an id that is unique within the conversation, because turn ids and
+/// hops never repeat.
+fn tool_call_id(turn_id: u64, tool_hop: usize, call_index: usize) -> String {
+    format!("call_{turn_id}_{tool_hop}_{call_index}")
+}
+
+/// The turn a [`tool_call_id`] was issued in.
+fn call_turn_id(call_id: &str) -> Option<u64> {
+    call_id
+        .strip_prefix("call_")?
+        .split('_')
+        .next()?
+        .parse()
+        .ok()
 }
 
 fn is_user_turn_start(kind: HistoryMessageKind) -> bool {
     matches!(kind, HistoryMessageKind::UserInput)
 }
 
+/// Drops messages until the history fits [`MAX_HISTORY_MESSAGES`] and
+/// returns them in order.
 fn trim_history_messages(
     history: &mut Vec<Message>,
     history_kinds: &mut Vec<HistoryMessageKind>,
     system_len: usize,
-) {
+) -> Vec<Message> {
     debug_assert_eq!(history.len(), history_kinds.len());
 
-    if history.len() <= MAX_HISTORY_MESSAGES {
-        return;
+    if history.iter().map(history_weight).sum::<usize>() <= MAX_HISTORY_MESSAGES {
+        return Vec::new();
     }
 
-    let keep_tail = MAX_HISTORY_MESSAGES.saturating_sub(system_len);
-    let min_start = history.len().saturating_sub(keep_tail).max(system_len);
-    let aligned_start =
-        (min_start..history.len()).find(|&idx| is_user_turn_start(history_kinds[idx]));
+    let system_weight: usize = history[..system_len].iter().map(history_weight).sum();
+    let budget = MAX_HISTORY_MESSAGES.saturating_sub(system_weight);
+    let min_start = fitting_start(history, system_len, budget);
+
+    let mut kept: Vec<usize> = (0..system_len).collect();
+    if let Some(start) =
+        (min_start..history.len()).find(|&idx| is_user_turn_start(history_kinds[idx]))
+    {
+        kept.extend(start..history.len());
+    } else if let Some(question) = (system_len..history.len())
+        .rev()
+        .find(|&idx| is_user_turn_start(history_kinds[idx]))
+    {
+        // The current turn alone is over budget. Its question stays, so the
+        // model never loses what it is answering, and its oldest tool calls,
+        // results and plan steps go.
+        kept.push(question);
+        let budget = budget.saturating_sub(history_weight(&history[question]));
+        let tail_min = fitting_start(history, question + 1, budget);
+        if let Some(tail) = (tail_min..history.len()).find(|&idx| {
+            matches!(
+                history_kinds[idx],
+                HistoryMessageKind::Assistant | HistoryMessageKind::PlanStep
+            )
+        }) {
+            kept.extend(tail..history.len());
+        }
+    }
 
-    let mut trimmed_history = history[..system_len].to_vec();
-    let mut trimmed_kinds = history_kinds[..system_len].to_vec();
+    *history_kinds = kept.iter().map(|&idx| history_kinds[idx]).collect();
+    let (kept_history, dropped): (Vec<_>, Vec<_>) = std::mem::take(history)
+        .into_iter()
+        .enumerate()
+        .partition(|(idx, _)| kept.binary_search(idx).is_ok());
+    *history = kept_history
+        .into_iter()
+        .map(|(_, message)| message)
+        .collect();
+    dropped.into_iter().map(|(_, message)| message).collect()
+}
 
-    if let Some(start) = aligned_start {
-        trimmed_history.extend_from_slice(&history[start..]);
-        trimmed_kinds.extend_from_slice(&history_kinds[start..]);
+/// The first index at or after `from` whose suffix fits in `budget`.
+fn fitting_start(history: &[Message], from: usize, mut budget: usize) -> usize {
+    let mut start = history.len();
+    while start > from {
+        let weight = history_weight(&history[start - 1]);
+        if weight > budget {
+            break;
+        }
+        budget -= weight;
+        start -= 1;
     }
+    start
+}
 
-    *history = trimmed_history;
-    *history_kinds = trimmed_kinds;
+fn history_weight(message: &Message) -> usize {
+    1 + message.images.len() * HISTORY_WEIGHT_PER_IMAGE
 }
 
-fn build_system_messages(cfg: &Config) -> Vec<Message> {
+fn build_system_messages(
+    cfg: &Config,
+    tools: &[tools::ToolSpec],
+    memories: &[MemoryEntry],
+) -> Vec<Message> {
     let mut messages = Vec::new();
 
     if !cfg.system_prompt.trim().is_empty() {
         messages.push(Message::system(cfg.system_prompt.clone()));
     }
 
-    messages.push(Message::system(tools::usage_instructions()));
+    messages.push(Message::system(tools::usage_instructions(tools)));
+    if cfg.tool_call_extraction == ToolCallExtraction::Schema {
+        messages.push(Message::system(tools::structured_output_instructions()));
+    }
+    if !memories.is_empty() {
+        messages.push(Message::system(format!(
+            "Facts remembered from earlier sessions; use them when relevant:\n{}",
+            memory::format_memories(memories)
+        )));
+    }
     messages
 }
 
 #[cfg(test)]
 mod tests {
-    use std::cell::RefCell;
     use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::time::Duration;
+
+    use tokio_util::sync::CancellationToken;
 
     use super::{
-        HistoryMessageKind, MAX_HISTORY_MESSAGES, MAX_TOOL_HOPS_PER_TURN, ModelFuture, TurnEngine,
-        TurnState,
+        Agent, HISTORY_WEIGHT_PER_IMAGE, HistoryMessageKind, MAX_HISTORY_MESSAGES,
+        MAX_TOOL_HOPS_PER_TURN, ModelCall, ModelFuture, TurnCancelled, TurnEngine, TurnState,
+        build_system_messages, history_weight,
+    };
+    use crate::agent::events::{AgentEventKind, EventEmitter};
+    use crate::agent::plan::StepStatus;
+    use crate::agent::tools::{
+        ToolApproval, ToolApprovalFuture, ToolApprover, ToolCall, ToolFuture, ToolOutput,
+        ToolRunner, ToolSpec, turn_output_format,
     };
-    use crate::agent::tools::{ToolCall, ToolFuture, ToolOutput, ToolRunner};
-    use crate::model::Message;
+    use crate::config::{AgentMode, Config, ModelRole, ModelTarget, ToolCallExtraction};
+    use crate::memory::MemoryEntry;
+    use crate::model::{
+        ImageAttachment, Message, MessageRole, ModelReply, ModelUsage, RoleUsage, UsageTotals,
+    };
+    use crate::model_error::ModelError;
 
     struct StubModel {
         responses: VecDeque<String>,
@@ -352,40 +1359,140 @@ mod tests {
                 .responses
                 .pop_front()
                 .expect("stub model missing queued response");
-            Box::pin(async move { Ok(response) })
+            Box::pin(async move {
+                Ok(ModelReply {
+                    content: response,
+                    target: test_model_target(),
+                    usage: ModelUsage {
+                        prompt_tokens: 10,
+                        completion_tokens: 5,
+                        latency: Duration::from_millis(20),
+                        ..ModelUsage::default()
+                    },
+                })
+            })
         }
     }
 
     #[derive(Default)]
     struct StubToolRunner {
-        calls: RefCell<Vec<String>>,
+        calls: Mutex<Vec<String>>,
     }
 
     impl StubToolRunner {
-        fn calls(&self) -> std::cell::Ref<'_, Vec<String>> {
-            self.calls.borrow()
+        fn calls(&self) -> MutexGuard<'_, Vec<String>> {
+            self.calls
+                .lock()
+                .expect("calls lock should not be poisoned")
         }
     }
 
     impl ToolRunner for StubToolRunner {
+        fn tools(&self) -> Vec<ToolSpec> {
+            Vec::new()
+        }
+
         fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
-            self.calls.borrow_mut().push(call.name.clone());
+            self.calls().push(call.name.clone());
             let output = ToolOutput::new(format!("stub-result-for-{}", call.name));
             Box::pin(async move { Ok(output) })
         }
     }
 
-    fn test_system_messages() -> Vec<Message> {
-        vec![Message::system("sys"), Message::system("tools")]
+    /// Denies every call whose name starts with `deny.`.
+    struct PrefixApprover;
+
+    impl ToolApprover for PrefixApprover {
+        fn review<'a>(&'a self, call: &'a ToolCall) -> ToolApprovalFuture<'a> {
+            let approval = if call.name.starts_with("deny.") {
+                ToolApproval::Denied {
+                    reason: "not allowed".to_string(),
+                }
+            } else {
+                ToolApproval::Approved
+            };
+            Box::pin(async move { approval })
+        }
     }
 
-    fn test_state() -> TurnState {
-        TurnState::from_system_messages(test_system_messages())
+    /// Sleeps for the number of milliseconds given in the tool name's suffix,
+    /// e.g. `sleep.30`, and records the peak number of concurrent calls.
+    #[derive(Default)]
+    struct SleepingToolRunner {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl ToolRunner for SleepingToolRunner {
+        fn tools(&self) -> Vec<ToolSpec> {
+            Vec::new()
+        }
+
+        fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+            Box::pin(async move {
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+                let millis = call
+                    .name
+                    .rsplit('.')
+                    .next()
+                    .and_then(|suffix| suffix.parse().ok())
+                    .unwrap_or(0);
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(ToolOutput::new(format!("slept-{millis}")))
+            })
+        }
+    }
+
+    struct PendingToolRunner {
+        cancel_on_call: CancellationToken,
+    }
+
+    impl ToolRunner for PendingToolRunner {
+        fn tools(&self) -> Vec<ToolSpec> {
+            Vec::new()
+        }
+
+        fn execute<'a>(&'a self, _call: &'a ToolCall) -> ToolFuture<'a> {
+            self.cancel_on_call.cancel();
+            Box::pin(std::future::pending())
+        }
+    }
+
+    fn test_config(model_base_url: String) -> Arc<Config> {
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.model_base_url = model_base_url;
+        cfg.model_timeout_secs = 1;
+        cfg.model_retry.max_attempts = 1;
+        Arc::new(cfg)
+    }
+
+    fn test_model_target() -> ModelTarget {
+        Config::from_env_with(|_| None).primary_model_target()
+    }
+
+    fn test_system_messages() -> Vec<Message> {
+        vec![Message::system("sys"), Message::system("tools")]
+    }
+
+    fn test_state() -> TurnState {
+        TurnState::from_system_messages(test_system_messages())
     }
 
     fn test_engine() -> TurnEngine {
         TurnEngine {
             state: test_state(),
+            events: EventEmitter::default(),
+            max_tool_concurrency: 4,
+            max_tool_calls_per_hop: 8,
+            tool_call_extraction: ToolCallExtraction::Strict,
+            tool_approver: None,
+            mode: AgentMode::React,
+            reroute_answers_from: Vec::new(),
+            summarize_history: false,
+            plan: None,
+            session_usage: RoleUsage::default(),
         }
     }
 
@@ -410,9 +1517,9 @@ mod tests {
 
         state.push_user_input("q0");
         state.push_assistant(r#"{"tool_call":{"name":"time.now"}}"#);
-        state.push_tool_result("time.now", "one");
+        state.push_tool_result("time.now", "call_1_1_0", "one");
         state.push_assistant(r#"{"tool_call":{"name":"time.now"}}"#);
-        state.push_tool_result("time.now", "two");
+        state.push_tool_result("time.now", "call_1_2_0", "two");
         state.push_assistant("done");
 
         for i in 1..=17 {
@@ -426,13 +1533,15 @@ mod tests {
     }
 
     #[test]
-    fn trim_history_drops_non_system_when_no_complete_turn_fits() {
+    fn trim_history_drops_the_oldest_hops_when_the_current_turn_is_over_budget() {
         let mut state = test_state();
 
         state.push_user_input("q0");
+        state.push_assistant("a0");
+        state.push_user_input("q1");
         for i in 0..25 {
             state.push_assistant(r#"{"tool_call":{"name":"time.now"}}"#);
-            state.push_tool_result("time.now", &i.to_string());
+            state.push_tool_result("time.now", &format!("call_2_{i}_0"), &i.to_string());
         }
 
         state.trim_history();
@@ -440,25 +1549,137 @@ mod tests {
         assert!(state.history.len() <= MAX_HISTORY_MESSAGES);
         assert_eq!(state.history[0].content, "sys");
         assert_eq!(state.history[1].content, "tools");
+        assert_eq!(state.history[2].content, "q1");
+        assert_eq!(state.history_kinds[2], HistoryMessageKind::UserInput);
+        assert_eq!(state.history_kinds[3], HistoryMessageKind::Assistant);
+        assert_eq!(state.history.last().expect("latest result").content, "24");
         assert!(
-            state.history_kinds[state.system_len..]
+            state.history_kinds[3..]
                 .iter()
                 .all(|kind| *kind != HistoryMessageKind::UserInput)
         );
     }
 
+    #[test]
+    fn trim_history_keeps_the_question_through_a_long_plan() {
+        let mut state = test_state();
+        state.push_user_input("earlier");
+        state.push_assistant("reply");
+        state.push_user_input("the question");
+        for i in 0..25 {
+            state.push_plan_step(format!("step {i}"));
+            state.push_assistant(format!("result {i}"));
+        }
+
+        assert!(state.history.len() <= MAX_HISTORY_MESSAGES);
+        assert_eq!(state.history[2].content, "the question");
+        assert_eq!(state.history_kinds[2], HistoryMessageKind::UserInput);
+        assert!(
+            state.history_kinds[3..]
+                .iter()
+                .all(|kind| *kind != HistoryMessageKind::UserInput)
+        );
+        assert_eq!(
+            state.history.last().expect("latest result").content,
+            "result 24"
+        );
+    }
+
+    #[test]
+    fn trim_history_keeps_a_single_user_message_that_is_over_budget() {
+        let mut state = test_state();
+        state.push_user_input("earlier");
+        state.push_assistant("reply");
+
+        state.pending_images = vec![ImageAttachment::new("image/png", b"png"); 10];
+        let dropped = state.push_user_input("what is in these?");
+
+        assert!(history_weight(&state.history[2]) > MAX_HISTORY_MESSAGES);
+        assert_eq!(dropped, 2);
+        assert_eq!(state.history.len(), state.system_len + 1);
+        assert_eq!(state.history[2].content, "what is in these?");
+        assert_eq!(state.history[2].images.len(), 10);
+    }
+
+    #[test]
+    fn trim_history_counts_images_toward_the_budget() {
+        let mut state = test_state();
+        for i in 0..4 {
+            state.pending_images = vec![ImageAttachment::new("image/png", b"png"); 2];
+            state.push_user_input(format!("look-{i}"));
+            state.push_assistant(format!("saw-{i}"));
+        }
+
+        let weight: usize = state.history.iter().map(history_weight).sum();
+        assert!(weight <= MAX_HISTORY_MESSAGES);
+        assert!(state.history.len() < 10);
+        assert_eq!(
+            history_weight(&state.history[2]),
+            1 + 2 * HISTORY_WEIGHT_PER_IMAGE
+        );
+        assert_eq!(state.history_kinds[2], HistoryMessageKind::UserInput);
+        assert_eq!(state.history.last().expect("history").content, "saw-3");
+    }
+
+    #[test]
+    fn replace_system_messages_keeps_the_conversation() {
+        let mut state = test_state();
+        state.push_user_input("hi");
+        state.push_assistant("hello");
+        let memories = vec![MemoryEntry {
+            id: 7,
+            text: "User prefers metric units".to_string(),
+            created_at: "2026-01-01T00:00:00Z".to_string(),
+            embedding: None,
+        }];
+
+        state.replace_system_messages(build_system_messages(
+            &Config::from_env_with(|_| None),
+            &[],
+            &memories,
+        ));
+
+        assert_eq!(state.system_len, 3);
+        assert_eq!(
+            state.history[2].content,
+            "Facts remembered from earlier sessions; use them when relevant:\n[#7] User prefers metric units"
+        );
+        assert_eq!(state.history[3].content, "hi");
+        assert_eq!(state.history_kinds[3], HistoryMessageKind::UserInput);
+        state.reset();
+        assert_eq!(state.history.len(), 3);
+    }
+
+    #[test]
+    fn pending_images_attach_to_next_user_input_only() {
+        let mut state = test_state();
+        state
+            .pending_images
+            .push(ImageAttachment::new("image/png", b"png"));
+
+        state.push_user_input("what is this?");
+        state.push_user_input("and now?");
+
+        assert_eq!(state.history[2].images.len(), 1);
+        assert!(state.history[3].images.is_empty());
+        assert!(state.pending_images.is_empty());
+    }
+
     #[tokio::test]
     async fn turn_engine_handles_plain_assistant_reply() {
         let mut engine = test_engine();
         let mut model = StubModel::new(vec!["plain answer"]);
         let tool_runner = StubToolRunner::default();
 
-        let answer = engine
-            .run_turn_with(1, "hello", |messages| model.chat(messages), &tool_runner)
+        let result = engine
+            .run_turn_with(1, "hello", |messages, _| model.chat(messages), &tool_runner)
             .await
             .expect("turn should succeed");
+        let answer = result.answer;
 
         assert_eq!(answer, "plain answer");
+        assert_eq!(result.tool_hops, 0);
+        assert_eq!(result.model_target, test_model_target());
         assert_eq!(model.call_count, 1);
         assert!(tool_runner.calls().is_empty());
         assert_eq!(
@@ -480,26 +1701,106 @@ mod tests {
         ]);
         let tool_runner = StubToolRunner::default();
 
-        let answer = engine
+        let result = engine
             .run_turn_with(
                 2,
                 "what time?",
-                |messages| model.chat(messages),
+                |messages, _| model.chat(messages),
                 &tool_runner,
             )
             .await
             .expect("turn should succeed");
+        let answer = result.answer;
 
         assert_eq!(answer, "Here is the final answer.");
+        assert_eq!(
+            result.usage,
+            UsageTotals {
+                model_calls: 2,
+                usage: ModelUsage {
+                    prompt_tokens: 20,
+                    completion_tokens: 10,
+                    latency: Duration::from_millis(40),
+                    ..ModelUsage::default()
+                },
+            }
+        );
         assert_eq!(model.call_count, 2);
         assert_eq!(tool_runner.calls().as_slice(), &["time.now".to_string()]);
-        assert!(
-            engine
-                .history()
-                .iter()
-                .any(|msg| msg.content.starts_with("Tool 'time.now' result:")),
-            "tool result should be recorded in history"
+        let tool_message = engine
+            .history()
+            .iter()
+            .find(|msg| matches!(msg.role, MessageRole::Tool { .. }))
+            .expect("tool result should be recorded in history");
+        assert_eq!(
+            tool_message.role,
+            MessageRole::Tool {
+                tool_name: "time.now".to_string(),
+                call_id: "call_2_1_0".to_string(),
+            }
         );
+        assert_eq!(tool_message.content, "stub-result-for-time.now");
+    }
+
+    #[tokio::test]
+    async fn turn_engine_accumulates_session_usage_across_turns() {
+        let mut engine = test_engine();
+        let mut model = StubModel::new(vec![
+            r#"{"tool_call":{"name":"time.now"}}"#,
+            "It is noon.",
+            "You're welcome.",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        for (turn_id, input) in [(1, "what time?"), (2, "thanks")] {
+            engine
+                .run_turn_with(
+                    turn_id,
+                    input,
+                    |messages, _| model.chat(messages),
+                    &tool_runner,
+                )
+                .await
+                .expect("turn should succeed");
+        }
+
+        assert_eq!(engine.session_usage.total().model_calls, 3);
+        assert_eq!(engine.session_usage.total().usage.total_tokens(), 45);
+    }
+
+    #[tokio::test]
+    async fn turn_engine_requests_structured_output_in_schema_mode() {
+        let mut engine = test_engine();
+        engine.tool_call_extraction = ToolCallExtraction::Schema;
+        let mut model = StubModel::new(vec![
+            r#"{"tool_call":{"name":"time.now"}}"#,
+            r#"{"answer":"It is noon."}"#,
+        ]);
+        let mut formats = Vec::new();
+        let tool_runner = StubToolRunner::default();
+
+        let answer = engine
+            .run_turn_with(
+                3,
+                "what time?",
+                |messages, call: ModelCall| {
+                    formats.push(call.output_format);
+                    model.chat(messages)
+                },
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed")
+            .answer;
+
+        assert_eq!(answer, "It is noon.");
+        assert_eq!(tool_runner.calls().as_slice(), &["time.now".to_string()]);
+        assert_eq!(formats, vec![Some(turn_output_format()); 2]);
+        let last = engine
+            .history()
+            .last()
+            .expect("history should not be empty");
+        assert_eq!(last.content, "It is noon.");
     }
 
     #[tokio::test]
@@ -513,17 +1814,44 @@ mod tests {
             .run_turn_with(
                 3,
                 "what time now?",
-                |messages| model.chat(messages),
+                |messages, _| model.chat(messages),
                 &tool_runner,
             )
             .await
-            .expect("turn should succeed");
+            .expect("turn should succeed")
+            .answer;
 
         assert_eq!(answer, mixed_output);
         assert_eq!(model.call_count, 1);
         assert!(tool_runner.calls().is_empty());
     }
 
+    #[tokio::test]
+    async fn turn_engine_runs_tool_from_mixed_output_in_lenient_mode() {
+        let mut engine = test_engine();
+        engine.tool_call_extraction = ToolCallExtraction::Lenient;
+        let mut model = StubModel::new(vec![
+            "Let me check.\n```json\n{\"tool_call\":{\"name\":\"time.now\"}}\n```",
+            "It is noon.",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        let answer = engine
+            .run_turn_with(
+                12,
+                "what time now?",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed")
+            .answer;
+
+        assert_eq!(answer, "It is noon.");
+        assert_eq!(model.call_count, 2);
+        assert_eq!(tool_runner.calls().as_slice(), &["time.now".to_string()]);
+    }
+
     #[tokio::test]
     async fn turn_engine_stops_when_tool_hop_limit_is_reached() {
         let mut engine = test_engine();
@@ -538,11 +1866,12 @@ mod tests {
             .run_turn_with(
                 4,
                 "keep checking",
-                |messages| model.chat(messages),
+                |messages, _| model.chat(messages),
                 &tool_runner,
             )
             .await
-            .expect("turn should succeed");
+            .expect("turn should succeed")
+            .answer;
 
         assert!(
             answer.contains(&format!(
@@ -562,4 +1891,751 @@ mod tests {
             answer
         );
     }
+
+    #[tokio::test]
+    async fn turn_engine_emits_events_for_tool_turn() {
+        let mut engine = test_engine();
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec![r#"{"tool_call":{"name":"time.now"}}"#, "It is noon."]);
+        let tool_runner = StubToolRunner::default();
+
+        engine
+            .run_turn_with(
+                5,
+                "what time?",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        let mut kinds = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            assert_eq!(event.turn_id, 5);
+            kinds.push(event.kind);
+        }
+        assert_eq!(
+            kinds,
+            vec![
+                AgentEventKind::TurnStarted {
+                    user_input: "what time?".to_string(),
+                },
+                AgentEventKind::ModelRequestSent { message_count: 3 },
+                AgentEventKind::ToolCallParsed {
+                    tool_hop: 1,
+                    call_index: 0,
+                    call_id: "call_5_1_0".to_string(),
+                    tool_name: "time.now".to_string(),
+                },
+                AgentEventKind::ToolApproved {
+                    call_index: 0,
+                    tool_name: "time.now".to_string(),
+                },
+                AgentEventKind::ToolResult {
+                    call_index: 0,
+                    tool_name: "time.now".to_string(),
+                    output: "stub-result-for-time.now".to_string(),
+                    is_error: false,
+                },
+                AgentEventKind::ModelRequestSent { message_count: 5 },
+                AgentEventKind::TurnFinished {
+                    answer: "It is noon.".to_string(),
+                    tool_hops: 1,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn turn_engine_skips_calls_the_approver_denies() {
+        let mut engine = test_engine();
+        engine.tool_approver = Some(Arc::new(PrefixApprover));
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec![
+            r#"{"tool_calls":[{"name":"deny.rm","arguments":{"path":"/"}},{"name":"time.now"}]}"#,
+            "Done.",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        engine
+            .run_turn_with(
+                12,
+                "clean up",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(*tool_runner.calls(), vec!["time.now".to_string()]);
+        let tool_results: Vec<&str> = engine
+            .history()
+            .iter()
+            .filter(|msg| matches!(msg.role, MessageRole::Tool { .. }))
+            .map(|msg| msg.content.as_str())
+            .collect();
+        assert_eq!(
+            tool_results,
+            vec!["DENIED: not allowed", "stub-result-for-time.now"]
+        );
+        let kinds: Vec<AgentEventKind> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| event.kind)
+            .collect();
+        assert!(
+            kinds.contains(&AgentEventKind::ToolApprovalRequested {
+                call_index: 0,
+                call_id: "call_12_1_0".to_string(),
+                tool_name: "deny.rm".to_string(),
+                arguments: serde_json::json!({"path": "/"})
+                    .as_object()
+                    .cloned()
+                    .expect("arguments are an object"),
+            })
+        );
+        assert!(kinds.contains(&AgentEventKind::ToolDenied {
+            call_index: 0,
+            tool_name: "deny.rm".to_string(),
+            reason: "not allowed".to_string(),
+        }));
+    }
+
+    #[tokio::test]
+    async fn turn_engine_emits_denial_when_tool_hop_limit_is_reached() {
+        let mut engine = test_engine();
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec![r#"{"tool_call":{"name":"time.now"}}"#; 3]);
+        let tool_runner = StubToolRunner::default();
+
+        engine
+            .run_turn_with(
+                6,
+                "keep checking",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        let kinds: Vec<AgentEventKind> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| event.kind)
+            .collect();
+        let denials = kinds
+            .iter()
+            .filter(|kind| matches!(kind, AgentEventKind::ToolDenied { .. }))
+            .count();
+        let approvals = kinds
+            .iter()
+            .filter(|kind| matches!(kind, AgentEventKind::ToolApproved { .. }))
+            .count();
+        assert_eq!(denials, 1);
+        assert_eq!(approvals, MAX_TOOL_HOPS_PER_TURN);
+        assert!(matches!(
+            kinds.last(),
+            Some(AgentEventKind::TurnFinished { tool_hops, .. })
+                if *tool_hops == MAX_TOOL_HOPS_PER_TURN
+        ));
+    }
+
+    #[tokio::test]
+    async fn routed_turn_asks_the_final_answer_model_for_the_answer() {
+        let cfg = Config::from_env_with(|key| {
+            (key == "MODEL_FINAL_ANSWER").then(|| "ollama|llama3.1:70b".to_string())
+        });
+        let mut engine = TurnEngine::new(&cfg, &[]);
+        assert_eq!(
+            engine.reroute_answers_from,
+            [ModelRole::Router, ModelRole::ToolStep]
+        );
+        let mut model = StubModel::new(vec![
+            r#"{"tool_call":{"name":"time.now"}}"#,
+            "draft answer",
+            "It is noon.",
+        ]);
+        let mut calls = Vec::new();
+        let tool_runner = StubToolRunner::default();
+
+        let result = engine
+            .run_turn_with(
+                9,
+                "what time?",
+                |messages, call: ModelCall| {
+                    calls.push((call.role, call.stream));
+                    model.chat(messages)
+                },
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(result.answer, "It is noon.");
+        assert_eq!(
+            calls,
+            [
+                (ModelRole::Router, false),
+                (ModelRole::ToolStep, false),
+                (ModelRole::FinalAnswer, true),
+            ]
+        );
+        assert!(
+            engine
+                .history()
+                .iter()
+                .all(|message| message.content != "draft answer")
+        );
+        assert_eq!(result.usage.model_calls, 3);
+        for role in [
+            ModelRole::Router,
+            ModelRole::ToolStep,
+            ModelRole::FinalAnswer,
+        ] {
+            assert_eq!(
+                result.role_usage.get(role).map(|totals| totals.model_calls),
+                Some(1),
+                "{}",
+                role.as_str()
+            );
+        }
+        assert_eq!(result.role_usage.get(ModelRole::Summarizer), None);
+    }
+
+    #[tokio::test]
+    async fn trimmed_history_is_condensed_by_the_summarizer() {
+        let cfg = Config::from_env_with(|key| {
+            (key == "MODEL_SUMMARIZER").then(|| "ollama|qwen2.5:0.5b".to_string())
+        });
+        let mut engine = TurnEngine::new(&cfg, &[]);
+        engine.state = test_state();
+        for i in 0..19 {
+            engine.state.push_user_input(format!("q{i}"));
+            engine.state.push_assistant(format!("a{i}"));
+        }
+        let mut model = StubModel::new(vec!["summary 1", "answer 1", "summary 2", "answer 2"]);
+        let mut calls = Vec::new();
+        let tool_runner = StubToolRunner::default();
+
+        let mut results = Vec::new();
+        for (turn_id, input) in [(1, "next question"), (2, "last question")] {
+            let result = engine
+                .run_turn_with(
+                    turn_id,
+                    input,
+                    |messages: Vec<Message>, call: ModelCall| {
+                        calls.push((call.role, messages.last().cloned()));
+                        model.chat(messages)
+                    },
+                    &tool_runner,
+                )
+                .await
+                .expect("turn should succeed");
+            results.push(result);
+        }
+
+        let summarizer_requests: Vec<String> = calls
+            .iter()
+            .filter(|(role, _)| *role == ModelRole::Summarizer)
+            .map(|(_, last)| last.clone().expect("request has messages").content)
+            .collect();
+        assert_eq!(summarizer_requests.len(), 2);
+        assert!(summarizer_requests[0].starts_with("user: q0\nassistant: a0\n"));
+        assert!(summarizer_requests[1].starts_with("Summary so far:\nsummary 1\n"));
+        let summaries: Vec<&Message> = engine
+            .history()
+            .iter()
+            .filter(|message| {
+                message
+                    .content
+                    .starts_with("Summary of the earlier conversation")
+            })
+            .collect();
+        assert_eq!(summaries.len(), 1);
+        assert!(summaries[0].content.ends_with("summary 2"));
+        assert_eq!(engine.state.system_len, test_system_messages().len() + 1);
+        assert!(engine.history().len() <= MAX_HISTORY_MESSAGES);
+        assert_eq!(
+            results[0]
+                .role_usage
+                .get(ModelRole::Summarizer)
+                .map(|totals| totals.model_calls),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn unrouted_turn_streams_the_first_reply_as_the_answer() {
+        let mut engine = TurnEngine::new(&Config::from_env_with(|_| None), &[]);
+        let mut model = StubModel::new(vec!["plain answer"]);
+        let mut calls = Vec::new();
+        let tool_runner = StubToolRunner::default();
+
+        let result = engine
+            .run_turn_with(
+                10,
+                "hello",
+                |messages, call: ModelCall| {
+                    calls.push((call.role, call.stream));
+                    model.chat(messages)
+                },
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(result.answer, "plain answer");
+        assert_eq!(calls, [(ModelRole::Router, true)]);
+        assert_eq!(result.usage, result.role_usage.total());
+    }
+
+    #[tokio::test]
+    async fn plan_turn_runs_steps_and_revises_after_a_failed_step() {
+        let mut engine = test_engine();
+        engine.mode = AgentMode::Plan;
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec![
+            r#"{"steps":["Check the time","Check the calendar"]}"#,
+            r#"{"tool_call":{"name":"time.now"}}"#,
+            "It is noon.",
+            "FAILED: no calendar tool",
+            r#"{"steps":["Say the time"]}"#,
+            "Noon.",
+            "It is noon.",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        let result = engine
+            .run_turn_with(
+                7,
+                "what time is it?",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(result.answer, "It is noon.");
+        assert_eq!(result.tool_hops, 1);
+        assert_eq!(result.usage.model_calls, 7);
+        assert_eq!(tool_runner.calls().as_slice(), &["time.now".to_string()]);
+        let plan = engine.plan.clone().expect("plan should be kept");
+        assert_eq!(plan.revision, 1);
+        assert_eq!(
+            plan.steps
+                .iter()
+                .map(|step| step.status)
+                .collect::<Vec<_>>(),
+            [StepStatus::Done, StepStatus::Failed, StepStatus::Done]
+        );
+
+        let mut plan_events = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            match event.kind {
+                AgentEventKind::PlanCreated { .. } => plan_events.push("created".to_string()),
+                AgentEventKind::PlanRevised { .. } => plan_events.push("revised".to_string()),
+                AgentEventKind::PlanStepStarted { step_index, .. } => {
+                    plan_events.push(format!("started {step_index}"))
+                }
+                AgentEventKind::PlanStepFinished {
+                    step_index, status, ..
+                } => plan_events.push(format!("{} {step_index}", status.as_str())),
+                AgentEventKind::ToolCallParsed { call_id, .. } => plan_events.push(call_id),
+                _ => {}
+            }
+        }
+        assert_eq!(
+            plan_events,
+            [
+                "created",
+                "started 0",
+                "call_7_1_0",
+                "done 0",
+                "started 1",
+                "failed 1",
+                "revised",
+                "started 2",
+                "done 2",
+            ]
+        );
+        assert_eq!(
+            engine
+                .history()
+                .last()
+                .expect("history should have the answer")
+                .content,
+            "It is noon."
+        );
+    }
+
+    #[tokio::test]
+    async fn plan_turn_falls_back_to_a_tool_loop_on_an_invalid_plan() {
+        let mut engine = test_engine();
+        engine.mode = AgentMode::Plan;
+        let mut model = StubModel::new(vec![
+            "1. Check the time",
+            r#"{"tool_call":{"name":"time.now"}}"#,
+            "It is noon.",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        let result = engine
+            .run_turn_with(
+                8,
+                "what time?",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should fall back to a tool loop");
+
+        assert_eq!(result.answer, "It is noon.");
+        assert_eq!(result.tool_hops, 1);
+        assert_eq!(tool_runner.calls().as_slice(), &["time.now".to_string()]);
+        assert!(engine.plan.is_none());
+    }
+
+    #[tokio::test]
+    async fn plan_turn_reports_steps_skipped_at_the_revision_limit() {
+        let mut engine = test_engine();
+        engine.mode = AgentMode::Plan;
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec![
+            r#"{"steps":["Find the file"]}"#,
+            "FAILED: not in src",
+            r#"{"steps":["Search docs"]}"#,
+            "FAILED: not in docs",
+            r#"{"steps":["Search tests","Read it"]}"#,
+            "FAILED: not in tests",
+            "I could not find it.",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        let result = engine
+            .run_turn_with(
+                9,
+                "where is the file?",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(result.answer, "I could not find it.");
+        let plan = engine.plan.clone().expect("plan should be kept");
+        assert_eq!(plan.steps[3].status, StepStatus::Skipped);
+        let skipped: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .filter_map(|event| match event.kind {
+                AgentEventKind::PlanStepFinished {
+                    step_index,
+                    status: StepStatus::Skipped,
+                    result,
+                } => Some((step_index, result)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(skipped, [(3, "plan revision limit reached".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_plan_turn_restores_the_previous_plan() {
+        let mut engine = test_engine();
+        engine.mode = AgentMode::Plan;
+        let cancel = CancellationToken::new();
+        let mut model = StubModel::new(vec![r#"{"steps":["Check the time"]}"#]);
+        let tool_runner = StubToolRunner::default();
+
+        let err = engine
+            .run_turn_cancellable_with(
+                10,
+                "what time?",
+                |messages, _| {
+                    if model.call_count == 0 {
+                        return model.chat(messages);
+                    }
+                    cancel.cancel();
+                    Box::pin(std::future::pending()) as ModelFuture
+                },
+                &tool_runner,
+                &cancel,
+            )
+            .await
+            .expect_err("turn should be cancelled");
+
+        assert!(err.is::<TurnCancelled>());
+        assert!(engine.plan.is_none());
+    }
+
+    #[tokio::test]
+    async fn turn_engine_reports_history_trimming() {
+        let mut engine = test_engine();
+        for i in 0..(MAX_HISTORY_MESSAGES / 2) {
+            engine.state.push_user_input(format!("q{i}"));
+            engine.state.push_assistant(format!("a{i}"));
+        }
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec!["latest answer"]);
+        let tool_runner = StubToolRunner::default();
+
+        engine
+            .run_turn_with(
+                7,
+                "one more",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        let trimmed =
+            std::iter::from_fn(|| events.try_recv().ok()).find_map(|event| match event.kind {
+                AgentEventKind::HistoryTrimmed {
+                    dropped_messages,
+                    history_len,
+                } => Some((dropped_messages, history_len)),
+                _ => None,
+            });
+        let (dropped_messages, history_len) = trimmed.expect("trim event should be emitted");
+        assert!(dropped_messages > 0);
+        assert!(history_len <= MAX_HISTORY_MESSAGES);
+    }
+
+    #[tokio::test]
+    async fn cancelled_turn_restores_history_while_model_is_pending() {
+        let mut engine = test_engine();
+        engine.state.push_user_input("earlier question");
+        engine.state.push_assistant("earlier answer");
+        let before: Vec<String> = engine
+            .history()
+            .iter()
+            .map(|msg| msg.content.clone())
+            .collect();
+        let mut events = engine.subscribe();
+        let cancel = CancellationToken::new();
+        let tool_runner = StubToolRunner::default();
+
+        let err = engine
+            .run_turn_cancellable_with(
+                8,
+                "slow question",
+                |_messages, _| {
+                    cancel.cancel();
+                    Box::pin(std::future::pending()) as ModelFuture
+                },
+                &tool_runner,
+                &cancel,
+            )
+            .await
+            .expect_err("turn should be cancelled");
+
+        assert_eq!(
+            err.downcast_ref::<TurnCancelled>(),
+            Some(&TurnCancelled { turn_id: 8 })
+        );
+        let after: Vec<String> = engine
+            .history()
+            .iter()
+            .map(|msg| msg.content.clone())
+            .collect();
+        assert_eq!(after, before);
+        let last_event = std::iter::from_fn(|| events.try_recv().ok())
+            .last()
+            .expect("events should be emitted");
+        assert_eq!(last_event.kind, AgentEventKind::TurnCancelled);
+    }
+
+    #[tokio::test]
+    async fn cancelled_turn_drops_pending_tool_call() {
+        let mut engine = test_engine();
+        let cancel = CancellationToken::new();
+        let mut model = StubModel::new(vec![r#"{"tool_call":{"name":"time.now"}}"#]);
+        let tool_runner = PendingToolRunner {
+            cancel_on_call: cancel.clone(),
+        };
+
+        let err = engine
+            .run_turn_cancellable_with(
+                9,
+                "what time?",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+                &cancel,
+            )
+            .await
+            .expect_err("turn should be cancelled");
+
+        assert!(err.is::<TurnCancelled>(), "unexpected error: {err:#}");
+        assert_eq!(engine.history().len(), test_system_messages().len());
+        assert_eq!(model.call_count, 1);
+    }
+
+    #[tokio::test]
+    async fn agent_turns_can_run_on_spawned_tasks() {
+        let cfg = test_config(crate::test_http::unused_url());
+        let mut agent = Agent::new(reqwest::Client::new(), cfg);
+        let mut events = agent.subscribe();
+
+        let (agent, result) = tokio::spawn(async move {
+            let result = agent.run_turn("hello").await;
+            (agent, result)
+        })
+        .await
+        .expect("spawned turn should not panic");
+
+        let err = result.expect_err("turn should fail without a model server");
+        assert!(
+            matches!(
+                err.downcast_ref::<ModelError>(),
+                Some(ModelError::ConnectionRefused { .. })
+            ),
+            "unexpected error: {err:#}"
+        );
+        let last_event = std::iter::from_fn(|| events.try_recv().ok())
+            .last()
+            .expect("turn should emit events");
+        assert!(
+            matches!(last_event.kind, AgentEventKind::TurnFailed { .. }),
+            "unexpected last event: {last_event:?}"
+        );
+        assert_eq!(
+            agent
+                .history()
+                .last()
+                .expect("history should keep the user input")
+                .content,
+            "hello"
+        );
+    }
+
+    #[test]
+    fn load_conversation_keeps_client_system_messages_after_the_agents_own() {
+        let cfg = test_config("http://127.0.0.1:9".to_string());
+        let mut agent = Agent::new(reqwest::Client::new(), cfg);
+        let own_system_len = agent.history().len();
+
+        agent.load_conversation(vec![
+            Message::system("Answer in French."),
+            Message::user("hi"),
+            Message::assistant("bonjour"),
+        ]);
+
+        let history = agent.history();
+        assert_eq!(history.len(), own_system_len + 3);
+        assert_eq!(history[own_system_len].content, "Answer in French.");
+        assert_eq!(agent.turn_engine.state.system_len, own_system_len + 1);
+        assert_eq!(
+            agent.turn_engine.state.history_kinds[own_system_len + 1..],
+            [HistoryMessageKind::UserInput, HistoryMessageKind::Assistant]
+        );
+
+        agent.reset();
+        assert_eq!(agent.history().len(), own_system_len + 1);
+    }
+
+    #[tokio::test]
+    async fn turn_engine_runs_batched_tool_calls_in_parallel_and_keeps_call_order() {
+        let mut engine = test_engine();
+        let mut model = StubModel::new(vec![
+            r#"{"tool_calls":[{"name":"sleep.60"},{"name":"sleep.1"},{"name":"sleep.20"}]}"#,
+            "All done.",
+        ]);
+        let tool_runner = SleepingToolRunner::default();
+
+        let answer = engine
+            .run_turn_with(
+                10,
+                "look up three things",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed")
+            .answer;
+
+        assert_eq!(answer, "All done.");
+        assert_eq!(model.call_count, 2);
+        assert_eq!(tool_runner.max_in_flight.load(Ordering::SeqCst), 3);
+        let tool_results: Vec<(&str, &str, &str)> = engine
+            .history()
+            .iter()
+            .filter_map(|msg| match &msg.role {
+                MessageRole::Tool { tool_name, call_id } => {
+                    Some((tool_name.as_str(), call_id.as_str(), msg.content.as_str()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            tool_results,
+            vec![
+                ("sleep.60", "call_10_1_0", "slept-60"),
+                ("sleep.1", "call_10_1_1", "slept-1"),
+                ("sleep.20", "call_10_1_2", "slept-20"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn turn_engine_bounds_tool_call_concurrency() {
+        let mut engine = test_engine();
+        engine.max_tool_concurrency = 2;
+        let mut model = StubModel::new(vec![
+            r#"{"tool_calls":[{"name":"sleep.10"},{"name":"sleep.10"},{"name":"sleep.10"},{"name":"sleep.10"}]}"#,
+            "done",
+        ]);
+        let tool_runner = SleepingToolRunner::default();
+
+        engine
+            .run_turn_with(
+                11,
+                "batch",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(tool_runner.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn turn_engine_denies_calls_past_the_per_hop_limit() {
+        let mut engine = test_engine();
+        engine.max_tool_calls_per_hop = 2;
+        let mut events = engine.subscribe();
+        let mut model = StubModel::new(vec![
+            r#"{"tool_calls":[{"name":"a"},{"name":"b"},{"name":"c"}]}"#,
+            "done",
+        ]);
+        let tool_runner = StubToolRunner::default();
+
+        engine
+            .run_turn_with(
+                12,
+                "fan out",
+                |messages, _| model.chat(messages),
+                &tool_runner,
+            )
+            .await
+            .expect("turn should succeed");
+
+        assert_eq!(tool_runner.calls().as_slice(), &["a", "b"]);
+        let denied = engine
+            .history()
+            .iter()
+            .find(|msg| {
+                msg.role
+                    == MessageRole::Tool {
+                        tool_name: "c".to_string(),
+                        call_id: "call_12_1_2".to_string(),
+                    }
+            })
+            .expect("denied call should still get a result");
+        assert_eq!(
+            denied.content,
+            "DENIED: at most 2 tool calls run per response"
+        );
+        assert!(
+            std::iter::from_fn(|| events.try_recv().ok()).any(|event| matches!(
+                event.kind,
+                AgentEventKind::ToolDenied { call_index: 2, .. }
+            ))
+        );
+    }
 }
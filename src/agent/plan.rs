@@ -0,0 +1,263 @@
+//! Plans for [`AgentMode::Plan`](crate::config::AgentMode::Plan) turns: the
+//! model writes a step list, each step runs as its own tool loop, and a
+//! failed step leads to a revised plan.
+
+use serde::Serialize;
+use serde_json::json;
+use std::fmt;
+
+use crate::output_schema::{OutputFormat, SchemaViolation};
+
+/// Most steps in one plan, and in each revision.
+pub const MAX_PLAN_STEPS: usize = 8;
+/// How often one turn may revise its plan after a failed step; later
+/// failures skip the remaining steps.
+pub const MAX_PLAN_REVISIONS: usize = 2;
+/// Step replies starting with this mark the step as failed.
+const FAILED_PREFIX: &str = "FAILED:";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Plan {
+    /// 0 for the first plan, counted up by each revision.
+    pub revision: usize,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlanStep {
+    pub description: String,
+    pub status: StepStatus,
+    /// The step's reply, or why it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    /// Left over when the plan could not be revised any more.
+    Skipped,
+}
+
+impl StepStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Done => "done",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+impl Plan {
+    pub(crate) fn new(steps: Vec<String>) -> Self {
+        Self {
+            revision: 0,
+            steps: steps.into_iter().map(PlanStep::pending).collect(),
+        }
+    }
+
+    /// Replaces the steps that have not run yet with `steps`; finished and
+    /// failed steps stay as a record.
+    pub(crate) fn revise(&mut self, steps: Vec<String>) {
+        self.steps
+            .retain(|step| !matches!(step.status, StepStatus::Pending));
+        self.steps.extend(steps.into_iter().map(PlanStep::pending));
+        self.revision += 1;
+    }
+
+    pub(crate) fn next_pending(&self) -> Option<usize> {
+        self.steps
+            .iter()
+            .position(|step| step.status == StepStatus::Pending)
+    }
+
+    /// Marks every pending step skipped and returns their indices.
+    pub(crate) fn skip_pending(&mut self) -> Vec<usize> {
+        let mut skipped = Vec::new();
+        for (index, step) in self.steps.iter_mut().enumerate() {
+            if step.status == StepStatus::Pending {
+                step.status = StepStatus::Skipped;
+                skipped.push(index);
+            }
+        }
+        skipped
+    }
+}
+
+impl PlanStep {
+    fn pending(description: String) -> Self {
+        Self {
+            description,
+            status: StepStatus::Pending,
+            result: None,
+        }
+    }
+}
+
+/// One line per step, e.g. `2. [failed] Run the tests: no test command`.
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, step) in self.steps.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{}. [{}] {}",
+                index + 1,
+                step.status.as_str(),
+                step.description
+            )?;
+            if let Some(result) = &step.result {
+                write!(f, ": {}", first_line(result))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn first_line(text: &str) -> &str {
+    text.trim().lines().next().unwrap_or_default()
+}
+
+/// `{"steps": ["...", ...]}` with 1 to [`MAX_PLAN_STEPS`] steps.
+pub(crate) fn plan_output_format() -> OutputFormat {
+    OutputFormat::Schema(json!({
+        "type": "object",
+        "required": ["steps"],
+        "properties": {
+            "steps": {
+                "type": "array",
+                "items": {"type": "string", "minLength": 1},
+                "minItems": 1,
+                "maxItems": MAX_PLAN_STEPS
+            }
+        },
+        "additionalProperties": false
+    }))
+}
+
+pub(crate) fn parse_steps(content: &str) -> Result<Vec<String>, SchemaViolation> {
+    let value = plan_output_format().check(content)?;
+    Ok(value["steps"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|step| step.as_str())
+        .map(|step| step.trim().to_string())
+        .collect())
+}
+
+pub(crate) fn planning_instructions() -> String {
+    format!(
+        "Before answering, plan the work for the user's last message. Reply with JSON of the form {{\"steps\": [\"...\"]}}: at most {MAX_PLAN_STEPS} short steps, in order, each one a piece of work you can do with your tools or by reasoning. Do not do the work yet."
+    )
+}
+
+pub(crate) fn revision_instructions(plan: &Plan, failed_step: usize) -> String {
+    format!(
+        "Step {} of the plan failed. The plan so far:\n{plan}\n\nReply with JSON of the form {{\"steps\": [\"...\"]}} listing at most {MAX_PLAN_STEPS} steps that replace the remaining ones and still get the user's request done.",
+        failed_step + 1
+    )
+}
+
+/// Sent as a user message so the step and its tool calls read as one
+/// exchange in the history.
+pub(crate) fn step_instructions(plan: &Plan, step: usize) -> String {
+    format!(
+        "Plan step {} of {}: {}\nWork on this step only, using tools if needed, then reply with its result. If the step cannot be done, reply with \"{FAILED_PREFIX}\" and the reason.",
+        step + 1,
+        plan.steps.len(),
+        plan.steps[step].description
+    )
+}
+
+pub(crate) fn final_instructions(plan: &Plan) -> String {
+    format!(
+        "The plan is finished:\n{plan}\n\nNow answer the user's request using the step results. Do not call tools."
+    )
+}
+
+/// Returns the reason when a step reply reports failure.
+pub(crate) fn step_failure(reply: &str) -> Option<&str> {
+    reply
+        .trim()
+        .strip_prefix(FAILED_PREFIX)
+        .map(|reason| reason.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_PLAN_STEPS, Plan, StepStatus, parse_steps, step_failure};
+
+    fn plan(steps: &[&str]) -> Plan {
+        Plan::new(steps.iter().map(|step| step.to_string()).collect())
+    }
+
+    #[test]
+    fn parse_steps_accepts_plans_within_the_step_limit() {
+        assert_eq!(
+            parse_steps(r#"{"steps":[" Read the docs ","Answer"]}"#).expect("plan should parse"),
+            ["Read the docs", "Answer"]
+        );
+
+        let too_long = serde_json::json!({"steps": vec!["step"; MAX_PLAN_STEPS + 1]});
+        for reply in [
+            r#"{"steps":[]}"#,
+            r#"{"steps":["ok"],"notes":"x"}"#,
+            "1. Read the docs",
+            &too_long.to_string(),
+        ] {
+            assert!(parse_steps(reply).is_err(), "{reply}");
+        }
+    }
+
+    #[test]
+    fn revise_keeps_finished_steps_and_replaces_pending_ones() {
+        let mut plan = plan(&["Find the config", "Edit it", "Restart"]);
+        plan.steps[0].status = StepStatus::Done;
+        plan.steps[1].status = StepStatus::Failed;
+        plan.steps[1].result = Some("file is read-only".to_string());
+
+        plan.revise(vec!["Copy the config".to_string(), "Restart".to_string()]);
+
+        assert_eq!(plan.revision, 1);
+        assert_eq!(plan.next_pending(), Some(2));
+        assert_eq!(
+            plan.to_string(),
+            "1. [done] Find the config\n2. [failed] Edit it: file is read-only\n3. [pending] Copy the config\n4. [pending] Restart"
+        );
+
+        assert_eq!(plan.skip_pending(), [2, 3]);
+        assert_eq!(plan.next_pending(), None);
+        assert_eq!(plan.steps[3].status, StepStatus::Skipped);
+    }
+
+    #[test]
+    fn plans_serialize_with_step_status_names() {
+        let mut plan = plan(&["Check the time"]);
+        plan.steps[0].status = StepStatus::Done;
+        plan.steps[0].result = Some("noon".to_string());
+
+        assert_eq!(
+            serde_json::to_value(&plan).expect("plan should serialize"),
+            serde_json::json!({
+                "revision": 0,
+                "steps": [{"description": "Check the time", "status": "done", "result": "noon"}]
+            })
+        );
+    }
+
+    #[test]
+    fn step_failure_reads_the_failed_prefix() {
+        assert_eq!(step_failure("  FAILED: no network\n"), Some("no network"));
+        assert_eq!(step_failure("It is noon."), None);
+    }
+}
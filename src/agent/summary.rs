@@ -0,0 +1,62 @@
+//! History summaries: when trimming drops messages and `MODEL_SUMMARIZER` is
+//! set, the [`ModelRole::Summarizer`](crate::config::ModelRole::Summarizer)
+//! target condenses them into one system message that stays in the history.
+
+use crate::model::{Message, MessageRole};
+
+const SUMMARIZER_PROMPT: &str = "You condense conversations. Summarize the transcript you are given in a few short paragraphs: the user's goals, the facts and decisions established, tool results that still matter, and open questions. Reply with the summary only.";
+const SUMMARY_HEADER: &str = "Summary of the earlier conversation, which is no longer shown:";
+
+/// The request that folds `dropped` into `previous`, the summary so far.
+pub(crate) fn request_messages(previous: Option<&str>, dropped: &[Message]) -> Vec<Message> {
+    let mut transcript = String::new();
+    if let Some(previous) = previous {
+        transcript.push_str("Summary so far:\n");
+        transcript.push_str(previous);
+        transcript.push_str("\n\nConversation since:\n");
+    }
+    for message in dropped {
+        let speaker = match &message.role {
+            MessageRole::Tool { tool_name, .. } => format!("tool {tool_name}"),
+            role => role.as_str().to_string(),
+        };
+        transcript.push_str(&format!("{speaker}: {}", message.content.trim()));
+        if !message.images.is_empty() {
+            transcript.push_str(&format!(" [{} images]", message.images.len()));
+        }
+        transcript.push('\n');
+    }
+    vec![
+        Message::system(SUMMARIZER_PROMPT),
+        Message::user(transcript),
+    ]
+}
+
+/// The system message that carries `summary` in the history.
+pub(crate) fn summary_message(summary: &str) -> Message {
+    Message::system(format!("{SUMMARY_HEADER}\n{}", summary.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SUMMARIZER_PROMPT, request_messages};
+    use crate::model::{ImageAttachment, Message};
+
+    #[test]
+    fn request_messages_fold_the_dropped_messages_into_the_previous_summary() {
+        let dropped = vec![
+            Message::user("what time is it?")
+                .with_images(vec![ImageAttachment::new("image/png", b"png")]),
+            Message::tool("time.now", "call_1_1_0", "12:00"),
+            Message::assistant("It is noon."),
+        ];
+
+        let messages = request_messages(Some("The user is in Lisbon."), &dropped);
+
+        assert_eq!(messages[0].content, SUMMARIZER_PROMPT);
+        assert_eq!(
+            messages[1].content,
+            "Summary so far:\nThe user is in Lisbon.\n\nConversation since:\nuser: what time is it? [1 images]\ntool time.now: 12:00\nassistant: It is noon.\n"
+        );
+    }
+}
@@ -1,15 +1,102 @@
 use chrono::{DateTime, SecondsFormat, Utc};
 use serde::Deserialize;
+use serde_json::{Map, Value, json};
 use std::error::Error;
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::{debug, warn};
 
+use super::events::EventEmitter;
+use crate::config::ToolCallExtraction;
+use crate::output_schema::OutputFormat;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ToolCall {
+    /// Identifies the call within a conversation. Parsed calls start with an
+    /// empty id; the turn engine assigns one before execution.
+    pub id: String,
     pub name: String,
+    /// The `arguments` object from the model's request; empty when omitted.
+    pub arguments: Map<String, Value>,
+}
+
+impl ToolCall {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            id: String::new(),
+            name: name.into(),
+            arguments: Map::new(),
+        }
+    }
+
+    pub fn with_arguments(mut self, arguments: Map<String, Value>) -> Self {
+        self.arguments = arguments;
+        self
+    }
+
+    /// Returns the string argument `key`, trimmed, or an error naming it.
+    pub fn required_str(&self, key: &str) -> Result<&str, ToolExecutionError> {
+        self.arguments
+            .get(key)
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| {
+                ToolExecutionError::new(format!(
+                    "{} requires a non-empty string argument '{key}'",
+                    self.name
+                ))
+            })
+    }
+}
+
+/// A tool a runner offers to the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    /// JSON Schema of the `arguments` object; `None` for tools without
+    /// arguments.
+    pub parameters: Option<Value>,
+}
+
+impl ToolSpec {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters: None,
+        }
+    }
+
+    pub fn with_parameters(mut self, parameters: Value) -> Self {
+        self.parameters = Some(parameters);
+        self
+    }
+}
+
+/// Where in a model reply the tool-call object was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallMatch {
+    /// The whole reply was the tool-call object.
+    Exact,
+    /// The object was the content of a fenced code block.
+    Fenced,
+    /// The object was surrounded by other text.
+    Embedded,
+}
+
+impl ToolCallMatch {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Fenced => "fenced",
+            Self::Embedded => "embedded",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -47,48 +134,337 @@ impl fmt::Display for ToolExecutionError {
 impl Error for ToolExecutionError {}
 
 pub type ToolExecutionResult = std::result::Result<ToolOutput, ToolExecutionError>;
-pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = ToolExecutionResult> + 'a>>;
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = ToolExecutionResult> + Send + 'a>>;
+
+pub trait ToolRunner: Send + Sync {
+    /// The tools this runner can execute, as described to the model.
+    fn tools(&self) -> Vec<ToolSpec>;
 
-pub trait ToolRunner {
     fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a>;
+
+    /// Runs `call` on behalf of the turn described by `context`. Runners that
+    /// start turns of their own, like `agent.delegate`, use it to put those
+    /// turns under the same review; the rest just [`execute`](Self::execute).
+    fn execute_in<'a>(&'a self, call: &'a ToolCall, _context: &'a ToolContext) -> ToolFuture<'a> {
+        self.execute(call)
+    }
+}
+
+/// The turn a tool call runs for: its id, its approver and where its events
+/// go.
+#[derive(Clone, Default)]
+pub struct ToolContext {
+    pub(crate) turn_id: u64,
+    pub(crate) approver: Option<Arc<dyn ToolApprover>>,
+    pub(crate) events: EventEmitter,
+}
+
+/// Lets one runner, e.g. a set of connected servers, be shared by every
+/// agent.
+impl<T: ToolRunner + ?Sized> ToolRunner for Arc<T> {
+    fn tools(&self) -> Vec<ToolSpec> {
+        (**self).tools()
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        (**self).execute(call)
+    }
+
+    fn execute_in<'a>(&'a self, call: &'a ToolCall, context: &'a ToolContext) -> ToolFuture<'a> {
+        (**self).execute_in(call, context)
+    }
+}
+
+/// A decision on whether a tool call may run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolApproval {
+    Approved,
+    /// The call is skipped and the model sees `reason` as its result.
+    Denied {
+        reason: String,
+    },
+}
+
+pub type ToolApprovalFuture<'a> = Pin<Box<dyn Future<Output = ToolApproval> + Send + 'a>>;
+
+/// Reviews tool calls before they run, e.g. by asking a user. Without an
+/// approver every call runs.
+pub trait ToolApprover: Send + Sync {
+    fn review<'a>(&'a self, call: &'a ToolCall) -> ToolApprovalFuture<'a>;
 }
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct BuiltinRunner;
 
+/// Routes each call to the first runner that lists the tool's name.
+#[derive(Default)]
+pub struct CompositeRunner {
+    runners: Vec<Box<dyn ToolRunner>>,
+}
+
+impl CompositeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, runner: Box<dyn ToolRunner>) -> Self {
+        self.runners.push(runner);
+        self
+    }
+
+    /// The first runner that lists the tool's name.
+    fn runner_for(&self, call: &ToolCall) -> Option<&dyn ToolRunner> {
+        self.runners
+            .iter()
+            .find(|runner| runner.tools().iter().any(|spec| spec.name == call.name))
+            .map(|runner| runner.as_ref())
+    }
+}
+
+impl ToolRunner for CompositeRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        let mut specs: Vec<ToolSpec> = Vec::new();
+        for spec in self.runners.iter().flat_map(|runner| runner.tools()) {
+            if !specs.iter().any(|known| known.name == spec.name) {
+                specs.push(spec);
+            }
+        }
+        specs
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        match self.runner_for(call) {
+            Some(runner) => runner.execute(call),
+            None => unknown_tool(call),
+        }
+    }
+
+    fn execute_in<'a>(&'a self, call: &'a ToolCall, context: &'a ToolContext) -> ToolFuture<'a> {
+        match self.runner_for(call) {
+            Some(runner) => runner.execute_in(call, context),
+            None => unknown_tool(call),
+        }
+    }
+}
+
+fn unknown_tool(call: &ToolCall) -> ToolFuture<'static> {
+    warn!(tool_name = %call.name, "no runner offers tool");
+    let err = ToolExecutionError::new(format!("unknown tool '{}'", call.name));
+    Box::pin(async move { Err(err) })
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ToolCallEnvelope {
+    Single(SingleToolCallEnvelope),
+    Batch(BatchToolCallEnvelope),
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
-struct ToolCallEnvelope {
+struct SingleToolCallEnvelope {
     tool_call: ToolCallPayload,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchToolCallEnvelope {
+    tool_calls: Vec<ToolCallPayload>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 struct ToolCallPayload {
     name: String,
+    #[serde(default)]
+    arguments: Map<String, Value>,
 }
 
-pub fn usage_instructions() -> &'static str {
-    "Tools are available.
-Available tools:
-- time.now: returns current UTC time and unix time in seconds.
-If a tool is needed, reply with exactly this JSON object and nothing else:
-{\"tool_call\":{\"name\":\"time.now\"}}
+/// Describes `tools` and the tool-call format for the system prompt.
+pub fn usage_instructions(tools: &[ToolSpec]) -> String {
+    let mut text = String::from("Tools are available.\nAvailable tools:\n");
+    for tool in tools {
+        text.push_str(&format!("- {}: {}", tool.name, tool.description));
+        if let Some(parameters) = &tool.parameters {
+            text.push_str(&format!(" Arguments schema: {parameters}"));
+        }
+        text.push('\n');
+    }
+    let example = tools
+        .first()
+        .map_or("<tool name>", |tool| tool.name.as_str());
+    text.push_str(&format!(
+        "If a tool is needed, reply with exactly this JSON object and nothing else:
+{{\"tool_call\":{{\"name\":\"{example}\"}}}}
+Pass arguments as an object: {{\"tool_call\":{{\"name\":\"<tool name>\",\"arguments\":{{...}}}}}}
+To call several tools at once, reply with exactly one JSON object listing them:
+{{\"tool_calls\":[{{\"name\":\"{example}\"}},{{\"name\":\"{example}\"}}]}}
 After receiving tool results, respond normally to the user."
+    ));
+    text
+}
+
+/// Extra instructions for [`ToolCallExtraction::Schema`], where every reply is
+/// a JSON object.
+pub fn structured_output_instructions() -> &'static str {
+    "Every reply must be exactly one JSON object.
+To answer the user, reply with {\"answer\":\"<your reply>\"}."
 }
 
-pub fn parse_tool_call(text: &str) -> Option<ToolCall> {
-    let parsed: ToolCallEnvelope = serde_json::from_str(text.trim()).ok()?;
-    let name = parsed.tool_call.name.trim();
-    if name.is_empty() {
+/// Output format for model requests that may lead to a tool call in
+/// [`ToolCallExtraction::Schema`] mode: a single call, a batch of calls, or a
+/// final answer.
+pub fn turn_output_format() -> OutputFormat {
+    let call = json!({
+        "type": "object",
+        "required": ["name"],
+        "properties": {
+            "name": {"type": "string", "minLength": 1},
+            "arguments": {"type": "object"}
+        },
+        "additionalProperties": false
+    });
+    OutputFormat::Schema(json!({
+        "oneOf": [
+            {
+                "type": "object",
+                "required": ["tool_call"],
+                "properties": {"tool_call": call},
+                "additionalProperties": false
+            },
+            {
+                "type": "object",
+                "required": ["tool_calls"],
+                "properties": {"tool_calls": {"type": "array", "items": call, "minItems": 1}},
+                "additionalProperties": false
+            },
+            {
+                "type": "object",
+                "required": ["answer"],
+                "properties": {"answer": {"type": "string"}},
+                "additionalProperties": false
+            }
+        ]
+    }))
+}
+
+/// Returns the text to show the user for a reply without tool calls. In
+/// schema mode that is the `answer` field; other replies are used as-is.
+pub fn final_answer(text: String, mode: ToolCallExtraction) -> String {
+    if mode != ToolCallExtraction::Schema {
+        return text;
+    }
+
+    match serde_json::from_str::<Value>(text.trim()) {
+        Ok(Value::Object(mut object)) => match object.remove("answer") {
+            Some(Value::String(answer)) => answer,
+            _ => text,
+        },
+        _ => text,
+    }
+}
+
+/// Parses a reply that consists of exactly one `{"tool_call":...}` object or
+/// one `{"tool_calls":[...]}` object. Returns the calls in request order.
+pub fn parse_tool_calls(text: &str) -> Option<Vec<ToolCall>> {
+    let payloads = match serde_json::from_str(text.trim()).ok()? {
+        ToolCallEnvelope::Single(envelope) => vec![envelope.tool_call],
+        ToolCallEnvelope::Batch(envelope) => envelope.tool_calls,
+    };
+    if payloads.is_empty() {
         return None;
     }
-    Some(ToolCall {
-        name: name.to_string(),
-    })
+
+    payloads
+        .into_iter()
+        .map(|payload| {
+            let name = payload.name.trim();
+            (!name.is_empty()).then(|| ToolCall::new(name).with_arguments(payload.arguments))
+        })
+        .collect()
+}
+
+/// Finds tool calls in a model reply according to `mode`.
+///
+/// Strict and schema modes only accept a reply that is exactly one tool-call
+/// object. Lenient mode also accepts exactly one tool-call object inside a
+/// fenced code block or surrounded by other text; replies with several
+/// candidate objects are ambiguous and treated as plain text.
+pub fn extract_tool_calls(
+    text: &str,
+    mode: ToolCallExtraction,
+) -> Option<(Vec<ToolCall>, ToolCallMatch)> {
+    if let Some(calls) = parse_tool_calls(text) {
+        return Some((calls, ToolCallMatch::Exact));
+    }
+    if mode != ToolCallExtraction::Lenient {
+        return None;
+    }
+
+    let mut fenced: Vec<Vec<ToolCall>> = fenced_blocks(text)
+        .into_iter()
+        .filter_map(parse_tool_calls)
+        .collect();
+    match fenced.len() {
+        0 => {}
+        1 => return fenced.pop().map(|calls| (calls, ToolCallMatch::Fenced)),
+        _ => return None,
+    }
+
+    let mut embedded: Vec<Vec<ToolCall>> = embedded_json_objects(text)
+        .into_iter()
+        .filter_map(parse_tool_calls)
+        .collect();
+    if embedded.len() == 1 {
+        return embedded.pop().map(|calls| (calls, ToolCallMatch::Embedded));
+    }
+    None
+}
+
+/// Returns the contents of closed ``` fences, without the info string.
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    let parts: Vec<&str> = text.split("```").collect();
+    parts
+        .iter()
+        .enumerate()
+        .skip(1)
+        .step_by(2)
+        .filter(|(idx, _)| idx + 1 < parts.len())
+        .map(|(_, block)| match block.split_once('\n') {
+            Some((info, body)) if !info.trim_start().starts_with('{') => body,
+            _ => block,
+        })
+        .collect()
+}
+
+/// Returns every top-level JSON object that appears in `text`.
+fn embedded_json_objects(text: &str) -> Vec<&str> {
+    let mut objects = Vec::new();
+    let mut offset = 0;
+    while let Some(pos) = text[offset..].find('{') {
+        let start = offset + pos;
+        let mut values =
+            serde_json::Deserializer::from_str(&text[start..]).into_iter::<serde_json::Value>();
+        match values.next() {
+            Some(Ok(_)) => {
+                let end = start + values.byte_offset();
+                objects.push(&text[start..end]);
+                offset = end;
+            }
+            _ => offset = start + 1,
+        }
+    }
+    objects
 }
 
 impl ToolRunner for BuiltinRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        vec![ToolSpec::new(
+            "time.now",
+            "returns current UTC time and unix time in seconds.",
+        )]
+    }
+
     fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
         Box::pin(async move {
             debug!(tool_name = %call.name, "running built-in tool");
@@ -118,46 +494,202 @@ impl ToolRunner for BuiltinRunner {
 
 #[cfg(test)]
 mod tests {
-    use super::{BuiltinRunner, ToolCall, ToolRunner, parse_tool_call};
+    use serde_json::json;
+
+    use super::{
+        BuiltinRunner, CompositeRunner, ToolCall, ToolCallMatch, ToolFuture, ToolOutput,
+        ToolRunner, ToolSpec, extract_tool_calls, final_answer, parse_tool_calls,
+        turn_output_format, usage_instructions,
+    };
+    use crate::config::ToolCallExtraction;
+
+    fn call_names(calls: Vec<ToolCall>) -> Vec<String> {
+        calls.into_iter().map(|call| call.name).collect()
+    }
 
     #[test]
-    fn parse_tool_call_reads_name() {
-        let call = parse_tool_call(r#"{"tool_call":{"name":"time.now"}}"#)
+    fn parse_tool_calls_reads_single_call_name() {
+        let calls = parse_tool_calls(r#"{"tool_call":{"name":"time.now"}}"#)
             .expect("tool call should parse");
-        assert_eq!(call.name, "time.now");
+        assert_eq!(call_names(calls), vec!["time.now".to_string()]);
+    }
+
+    #[test]
+    fn parse_tool_calls_reads_batch_in_order() {
+        let calls = parse_tool_calls(
+            r#"{"tool_calls":[{"name":"time.now"},{"name":" fs.read "},{"name":"time.now"}]}"#,
+        )
+        .expect("tool calls should parse");
+        assert_eq!(
+            call_names(calls),
+            vec![
+                "time.now".to_string(),
+                "fs.read".to_string(),
+                "time.now".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_tool_calls_reads_arguments_object() {
+        let calls = parse_tool_calls(
+            r#"{"tool_call":{"name":"docs.search","arguments":{"query":"retry policy"}}}"#,
+        )
+        .expect("tool call should parse");
+        assert_eq!(calls[0].arguments["query"], "retry policy");
+        assert_eq!(calls[0].required_str("query"), Ok("retry policy"));
+        assert!(calls[0].required_str("limit").is_err());
+        assert!(
+            parse_tool_calls(r#"{"tool_call":{"name":"docs.search","arguments":"x"}}"#).is_none()
+        );
+    }
+
+    #[test]
+    fn parse_tool_calls_rejects_empty_batch() {
+        assert!(parse_tool_calls(r#"{"tool_calls":[]}"#).is_none());
+    }
+
+    #[test]
+    fn parse_tool_calls_rejects_batch_with_empty_name() {
+        assert!(parse_tool_calls(r#"{"tool_calls":[{"name":"time.now"},{"name":""}]}"#).is_none());
+    }
+
+    #[test]
+    fn parse_tool_calls_rejects_mixed_envelopes() {
+        assert!(
+            parse_tool_calls(
+                r#"{"tool_call":{"name":"time.now"},"tool_calls":[{"name":"time.now"}]}"#
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn parse_tool_calls_rejects_other_text() {
+        assert!(parse_tool_calls("hello").is_none());
+    }
+
+    #[test]
+    fn parse_tool_calls_rejects_legacy_format() {
+        assert!(parse_tool_calls("TOOL_CALL:time.now").is_none());
+    }
+
+    #[test]
+    fn parse_tool_calls_rejects_invalid_json() {
+        assert!(parse_tool_calls(r#"{"tool_call":{"name":}}"#).is_none());
     }
 
     #[test]
-    fn parse_tool_call_rejects_other_text() {
-        assert!(parse_tool_call("hello").is_none());
+    fn parse_tool_calls_rejects_unknown_shape() {
+        assert!(parse_tool_calls(r#"{"name":"time.now"}"#).is_none());
     }
 
     #[test]
-    fn parse_tool_call_rejects_legacy_format() {
-        assert!(parse_tool_call("TOOL_CALL:time.now").is_none());
+    fn parse_tool_calls_rejects_empty_name() {
+        assert!(parse_tool_calls(r#"{"tool_call":{"name":"   "}}"#).is_none());
+    }
+
+    fn extract_lenient(text: &str) -> Option<(Vec<String>, ToolCallMatch)> {
+        extract_tool_calls(text, ToolCallExtraction::Lenient)
+            .map(|(calls, matched)| (call_names(calls), matched))
+    }
+
+    #[test]
+    fn extract_tool_calls_strict_rejects_surrounding_text() {
+        let text = "Let me check.\n{\"tool_call\":{\"name\":\"time.now\"}}";
+        assert!(extract_tool_calls(text, ToolCallExtraction::Strict).is_none());
+    }
+
+    #[test]
+    fn extract_tool_calls_reports_exact_match_in_both_modes() {
+        let text = r#"{"tool_call":{"name":"time.now"}}"#;
+        for mode in [ToolCallExtraction::Strict, ToolCallExtraction::Lenient] {
+            let (_, matched) = extract_tool_calls(text, mode).expect("tool call should parse");
+            assert_eq!(matched, ToolCallMatch::Exact);
+        }
+    }
+
+    #[test]
+    fn extract_tool_calls_lenient_reads_fenced_block() {
+        let text = "Sure.\n```json\n{\"tool_call\":{\"name\":\"time.now\"}}\n```\n";
+        assert_eq!(
+            extract_lenient(text),
+            Some((vec!["time.now".to_string()], ToolCallMatch::Fenced))
+        );
     }
 
     #[test]
-    fn parse_tool_call_rejects_invalid_json() {
-        assert!(parse_tool_call(r#"{"tool_call":{"name":}}"#).is_none());
+    fn extract_tool_calls_lenient_reads_object_after_prose() {
+        let text = "Let me check.\n{\"tool_call\":{\"name\":\"time.now\"}}";
+        assert_eq!(
+            extract_lenient(text),
+            Some((vec!["time.now".to_string()], ToolCallMatch::Embedded))
+        );
     }
 
     #[test]
-    fn parse_tool_call_rejects_unknown_shape() {
-        assert!(parse_tool_call(r#"{"name":"time.now"}"#).is_none());
+    fn extract_tool_calls_lenient_rejects_multiple_candidates() {
+        let text =
+            "{\"tool_call\":{\"name\":\"time.now\"}} or {\"tool_call\":{\"name\":\"fs.read\"}}";
+        assert!(extract_lenient(text).is_none());
     }
 
     #[test]
-    fn parse_tool_call_rejects_empty_name() {
-        assert!(parse_tool_call(r#"{"tool_call":{"name":"   "}}"#).is_none());
+    fn extract_tool_calls_lenient_ignores_nested_tool_call_objects() {
+        let text = "Example: {\"example\":{\"tool_call\":{\"name\":\"time.now\"}}}";
+        assert!(extract_lenient(text).is_none());
+    }
+
+    #[test]
+    fn extract_tool_calls_lenient_rejects_unclosed_fence_and_bad_json() {
+        assert!(extract_lenient("```json\n{\"tool_call\":{\"name\":}}").is_none());
+        assert!(extract_lenient("no tools { here").is_none());
+    }
+
+    #[test]
+    fn turn_output_format_accepts_tool_calls_and_answers_only() {
+        let format = turn_output_format();
+
+        for reply in [
+            r#"{"tool_call":{"name":"time.now"}}"#,
+            r#"{"tool_call":{"name":"docs.search","arguments":{"query":"x"}}}"#,
+            r#"{"tool_calls":[{"name":"time.now"},{"name":"time.now"}]}"#,
+            r#"{"answer":"It is noon."}"#,
+        ] {
+            assert!(format.check(reply).is_ok(), "{reply}");
+        }
+        for reply in [
+            r#"{"tool_calls":[]}"#,
+            r#"{"answer":"hi","tool_call":{"name":"time.now"}}"#,
+            r#"{"tool_call":{"name":""}}"#,
+            "It is noon.",
+        ] {
+            assert!(format.check(reply).is_err(), "{reply}");
+        }
+    }
+
+    #[test]
+    fn final_answer_unwraps_answer_object_in_schema_mode_only() {
+        let reply = r#"{"answer":"It is noon."}"#.to_string();
+
+        assert_eq!(
+            final_answer(reply.clone(), ToolCallExtraction::Schema),
+            "It is noon."
+        );
+        assert_eq!(
+            final_answer(reply.clone(), ToolCallExtraction::Strict),
+            reply
+        );
+        assert_eq!(
+            final_answer("plain".to_string(), ToolCallExtraction::Schema),
+            "plain"
+        );
     }
 
     #[tokio::test]
     async fn execute_time_now_returns_readable_and_unix() {
         let output = BuiltinRunner
-            .execute(&ToolCall {
-                name: "time.now".to_string(),
-            })
+            .execute(&ToolCall::new("time.now"))
             .await
             .expect("time.now should work")
             .content;
@@ -167,13 +699,67 @@ mod tests {
         assert!(output.ends_with(')'));
     }
 
+    #[test]
+    fn usage_instructions_list_tools_and_argument_schemas() {
+        let text = usage_instructions(&[
+            ToolSpec::new("time.now", "returns the time."),
+            ToolSpec::new("docs.search", "searches docs.")
+                .with_parameters(json!({"type": "object"})),
+        ]);
+
+        assert!(text.contains("- time.now: returns the time.\n"));
+        assert!(text.contains(r#"{"tool_call":{"name":"time.now"}}"#));
+        assert!(
+            text.contains(
+                "- docs.search: searches docs. Arguments schema: {\"type\":\"object\"}\n"
+            )
+        );
+    }
+
+    struct EchoRunner;
+
+    impl ToolRunner for EchoRunner {
+        fn tools(&self) -> Vec<ToolSpec> {
+            vec![
+                ToolSpec::new("echo", "echoes."),
+                ToolSpec::new("time.now", "shadowed."),
+            ]
+        }
+
+        fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+            Box::pin(async move { Ok(ToolOutput::new(format!("echo:{}", call.name))) })
+        }
+    }
+
+    #[tokio::test]
+    async fn composite_runner_routes_by_tool_name_in_runner_order() {
+        let runner = CompositeRunner::new()
+            .with(Box::new(BuiltinRunner))
+            .with(Box::new(EchoRunner));
+
+        let names: Vec<String> = runner.tools().into_iter().map(|spec| spec.name).collect();
+        assert_eq!(names, vec!["time.now", "echo"]);
+
+        let echo = runner
+            .execute(&ToolCall::new("echo"))
+            .await
+            .expect("echo should run");
+        assert_eq!(echo.content, "echo:echo");
+        let time = runner
+            .execute(&ToolCall::new("time.now"))
+            .await
+            .expect("time.now should run");
+        assert!(time.content.contains("(unix: "));
+        let missing = runner
+            .execute(&ToolCall::new("missing.tool"))
+            .await
+            .expect_err("unknown tools should fail");
+        assert_eq!(missing.to_string(), "unknown tool 'missing.tool'");
+    }
+
     #[tokio::test]
     async fn execute_unknown_tool_returns_error() {
-        let result = BuiltinRunner
-            .execute(&ToolCall {
-                name: "missing.tool".to_string(),
-            })
-            .await;
+        let result = BuiltinRunner.execute(&ToolCall::new("missing.tool")).await;
         assert!(result.is_err());
     }
 }
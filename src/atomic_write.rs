@@ -0,0 +1,32 @@
+//! Whole-file writes that readers never see half done.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A failed write, with the path it failed on.
+#[derive(Debug)]
+pub(crate) struct WriteError {
+    pub(crate) path: PathBuf,
+    pub(crate) source: io::Error,
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it into
+/// place, creating missing parent directories first.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), WriteError> {
+    let error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| WriteError { path, source }
+    };
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(error(parent))?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    fs::write(&tmp_path, bytes).map_err(error(&tmp_path))?;
+    fs::rename(&tmp_path, path).map_err(error(path))
+}
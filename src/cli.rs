@@ -0,0 +1,119 @@
+use anyhow::{Result, bail};
+use std::path::PathBuf;
+
+/// What the binary was asked to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Command {
+    Repl,
+    /// Answer a single prompt and exit.
+    Prompt {
+        prompt: String,
+        images: Vec<PathBuf>,
+    },
+    /// Serve the OpenAI-compatible HTTP API until interrupted.
+    Serve,
+    /// Serve the local tools over MCP on stdio until stdin closes.
+    McpServe,
+}
+
+/// Parses the arguments after the program name. A lone `serve` starts the
+/// HTTP server and a lone `mcp-serve` the MCP server. Otherwise words that
+/// are not options form the prompt; `--image <path>` (or `--image=<path>`)
+/// attaches an image and may be repeated.
+pub(crate) fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command> {
+    let args: Vec<String> = args.into_iter().collect();
+    if args == ["serve"] {
+        return Ok(Command::Serve);
+    }
+    if args == ["mcp-serve"] {
+        return Ok(Command::McpServe);
+    }
+
+    let mut words = Vec::new();
+    let mut images = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--image" {
+            let Some(path) = args.next() else {
+                bail!("--image requires a path");
+            };
+            images.push(PathBuf::from(path));
+        } else if let Some(path) = arg.strip_prefix("--image=") {
+            images.push(PathBuf::from(path));
+        } else {
+            words.push(arg);
+        }
+    }
+
+    if words.is_empty() {
+        if !images.is_empty() {
+            bail!("--image requires a prompt");
+        }
+        return Ok(Command::Repl);
+    }
+    Ok(Command::Prompt {
+        prompt: words.join(" "),
+        images,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{Command, parse_args};
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_starts_repl_without_arguments() {
+        assert_eq!(parse_args(args(&[])).expect("empty args"), Command::Repl);
+    }
+
+    #[test]
+    fn parse_args_serves_only_for_a_lone_serve() {
+        assert_eq!(parse_args(args(&["serve"])).expect("serve"), Command::Serve);
+        assert_eq!(
+            parse_args(args(&["mcp-serve"])).expect("mcp-serve"),
+            Command::McpServe
+        );
+        assert_eq!(
+            parse_args(args(&["serve", "dinner", "ideas"])).expect("prompt"),
+            Command::Prompt {
+                prompt: "serve dinner ideas".to_string(),
+                images: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_args_collects_images_and_prompt_words() {
+        assert_eq!(
+            parse_args(args(&[
+                "what",
+                "--image",
+                "a.png",
+                "is",
+                "--image=b.jpg",
+                "this?"
+            ]))
+            .expect("args should parse"),
+            Command::Prompt {
+                prompt: "what is this?".to_string(),
+                images: vec![PathBuf::from("a.png"), PathBuf::from("b.jpg")],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_args_rejects_image_without_path_or_prompt() {
+        let missing_path = parse_args(args(&["hi", "--image"])).expect_err("path is required");
+        assert_eq!(missing_path.to_string(), "--image requires a path");
+
+        let missing_prompt =
+            parse_args(args(&["--image", "a.png"])).expect_err("prompt is required");
+        assert_eq!(missing_prompt.to_string(), "--image requires a prompt");
+    }
+}
@@ -1,13 +1,43 @@
 use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::model::TargetHealth;
+use crate::output_schema::OutputFormat;
 
 const DEFAULT_MODEL_PROVIDER: &str = "ollama";
 const DEFAULT_MODEL: &str = "qwen2.5:3b";
 const DEFAULT_MODEL_BASE_URL: &str = "http://localhost:11434";
 const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant.";
 const DEFAULT_MODEL_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_MODEL_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_MODEL_RETRY_BASE_DELAY_MS: u64 = 500;
+const DEFAULT_MODEL_RETRY_MAX_DELAY_MS: u64 = 8_000;
+const DEFAULT_MODEL_TARGET_COOLDOWN_SECS: u64 = 30;
+const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+const DEFAULT_EMBEDDING_BATCH_SIZE: usize = 32;
+const DEFAULT_DOCS_INDEX_PATH: &str = ".fizz/docs-index.json";
+const DEFAULT_DOCS_CHUNK_CHARS: usize = 1_500;
+const DEFAULT_MEMORY_PATH: &str = ".fizz/memory.json";
+const DEFAULT_MEMORY_INJECT_LIMIT: usize = 5;
+const DEFAULT_MEMORY_EMBEDDINGS: bool = false;
+const DEFAULT_MCP_CONFIG_PATH: &str = ".fizz/mcp.json";
+const DEFAULT_PLUGIN_DIR: &str = ".fizz/plugins";
+const DEFAULT_SERVER_HOST: &str = "127.0.0.1";
+const DEFAULT_SERVER_PORT: u16 = 8080;
 const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 30;
 const DEFAULT_TOOL_MEMORY_MB: u64 = 256;
+const DEFAULT_TOOL_MAX_OUTPUT_BYTES: u64 = 1_048_576;
 const DEFAULT_TOOL_ALLOW_DIRECT_NETWORK: bool = false;
+const DEFAULT_TOOL_MAX_CONCURRENCY: usize = 4;
+const DEFAULT_TOOL_MAX_CALLS_PER_HOP: usize = 8;
+const DEFAULT_DELEGATE_MAX_DEPTH: usize = 2;
+const DEFAULT_DELEGATE_MAX_RUNS: usize = 4;
+const DEFAULT_DELEGATE_MAX_TOKENS: u64 = 32_000;
+const DEFAULT_SESSION_MAX: usize = 64;
+const DEFAULT_SESSION_IDLE_TTL_SECS: u64 = 3_600;
+const DEFAULT_SESSION_APPROVAL_TIMEOUT_SECS: u64 = 300;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ToolRuntime {
@@ -24,6 +54,48 @@ impl ToolRuntime {
     }
 }
 
+/// How tool calls are recognized in model replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolCallExtraction {
+    /// The whole reply must be a single tool-call JSON object.
+    Strict,
+    /// A single tool-call object may also sit in a fenced block or among prose.
+    Lenient,
+    /// Model requests that may lead to a tool call ask the provider for JSON
+    /// matching the tool-call schema; final answers arrive as
+    /// `{"answer": "..."}`.
+    Schema,
+}
+
+impl ToolCallExtraction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Lenient => "lenient",
+            Self::Schema => "schema",
+        }
+    }
+}
+
+/// How a turn is run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AgentMode {
+    /// The model calls tools until it answers.
+    React,
+    /// The model first writes a step list, then each step runs with its own
+    /// tool hops and failed steps lead to a revised plan.
+    Plan,
+}
+
+impl AgentMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::React => "react",
+            Self::Plan => "plan",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WorkspaceFsMode {
     Host,
@@ -41,10 +113,175 @@ impl WorkspaceFsMode {
     }
 }
 
+/// Retry behavior for transient model provider failures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelRetryPolicy {
+    /// Total attempts per request, including the first one.
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for ModelRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MODEL_MAX_ATTEMPTS,
+            base_delay_ms: DEFAULT_MODEL_RETRY_BASE_DELAY_MS,
+            max_delay_ms: DEFAULT_MODEL_RETRY_MAX_DELAY_MS,
+        }
+    }
+}
+
+/// One place a chat request can be sent: a provider, a model on it, and the
+/// endpoint serving it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModelTarget {
+    pub provider: String,
+    pub model: String,
+    pub base_url: String,
+    pub timeout_secs: u64,
+}
+
+impl fmt::Display for ModelTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}@{}", self.provider, self.model, self.base_url)
+    }
+}
+
+/// A credential sent to a provider; kept out of `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ApiKey(***)")
+    }
+}
+
+/// A phase of a turn that can run on its own model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ModelRole {
+    /// The first request of a turn, which decides between calling tools and
+    /// answering, and plan-mode planning requests.
+    Router,
+    /// Requests after tool results, and plan-mode step requests.
+    ToolStep,
+    /// The request whose reply is shown to the user.
+    FinalAnswer,
+    /// Requests that condense trimmed history into a summary. They are only
+    /// sent when this role has a target of its own.
+    Summarizer,
+}
+
+impl ModelRole {
+    pub const ALL: [ModelRole; 4] = [
+        Self::Router,
+        Self::ToolStep,
+        Self::FinalAnswer,
+        Self::Summarizer,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Router => "router",
+            Self::ToolStep => "tool_step",
+            Self::FinalAnswer => "final_answer",
+            Self::Summarizer => "summarizer",
+        }
+    }
+}
+
+/// Targets assigned to [`ModelRole`]s; unset roles run on the primary target.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ModelRoles {
+    pub router: Option<ModelTarget>,
+    pub tool_step: Option<ModelTarget>,
+    pub final_answer: Option<ModelTarget>,
+    pub summarizer: Option<ModelTarget>,
+}
+
+impl ModelRoles {
+    pub fn target(&self, role: ModelRole) -> Option<&ModelTarget> {
+        match role {
+            ModelRole::Router => self.router.as_ref(),
+            ModelRole::ToolStep => self.tool_step.as_ref(),
+            ModelRole::FinalAnswer => self.final_answer.as_ref(),
+            ModelRole::Summarizer => self.summarizer.as_ref(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        ModelRole::ALL
+            .iter()
+            .all(|role| self.target(*role).is_none())
+    }
+}
+
+/// Sampling and runtime options sent with chat requests. Unset fields leave
+/// the provider's defaults in place.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GenerationOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub seed: Option<u64>,
+    /// Upper bound on generated tokens.
+    pub max_tokens: Option<u32>,
+    pub stop: Vec<String>,
+    /// Context window size in tokens.
+    pub num_ctx: Option<u32>,
+    /// How long the provider keeps the model loaded after the request, e.g.
+    /// `5m`, or a number of seconds.
+    pub keep_alive: Option<String>,
+    /// Asks the provider for JSON output; responses are validated against it
+    /// before they are returned. Set per request, never from the environment.
+    pub output_format: Option<OutputFormat>,
+}
+
+impl GenerationOptions {
+    /// Returns these options with every field set in `overrides` replacing the
+    /// corresponding value here.
+    pub fn merged_with(&self, overrides: &GenerationOptions) -> GenerationOptions {
+        GenerationOptions {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            top_k: overrides.top_k.or(self.top_k),
+            seed: overrides.seed.or(self.seed),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            stop: if overrides.stop.is_empty() {
+                self.stop.clone()
+            } else {
+                overrides.stop.clone()
+            },
+            num_ctx: overrides.num_ctx.or(self.num_ctx),
+            keep_alive: overrides
+                .keep_alive
+                .clone()
+                .or_else(|| self.keep_alive.clone()),
+            output_format: overrides
+                .output_format
+                .clone()
+                .or_else(|| self.output_format.clone()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ToolResourceLimits {
     pub timeout_secs: u64,
     pub memory_mb: u64,
+    /// Most bytes a tool may write as its output.
+    pub max_output_bytes: u64,
 }
 
 impl Default for ToolResourceLimits {
@@ -52,6 +289,7 @@ impl Default for ToolResourceLimits {
         Self {
             timeout_secs: DEFAULT_TOOL_TIMEOUT_SECS,
             memory_mb: DEFAULT_TOOL_MEMORY_MB,
+            max_output_bytes: DEFAULT_TOOL_MAX_OUTPUT_BYTES,
         }
     }
 }
@@ -71,6 +309,47 @@ impl Default for ToolPolicy {
     }
 }
 
+/// Bounds on `agent.delegate`. Runs and tokens are counted across every
+/// delegation of one turn, including nested sub-agents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelegationLimits {
+    /// How deep sub-agents may nest; 0 disables delegation.
+    pub max_depth: usize,
+    pub max_runs: usize,
+    pub max_tokens: u64,
+}
+
+impl Default for DelegationLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_DELEGATE_MAX_DEPTH,
+            max_runs: DEFAULT_DELEGATE_MAX_RUNS,
+            max_tokens: DEFAULT_DELEGATE_MAX_TOKENS,
+        }
+    }
+}
+
+/// Bounds on the server's native sessions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionLimits {
+    /// Most sessions kept at once; creating one more fails.
+    pub max_sessions: usize,
+    /// Sessions without a request or a running turn for this long are removed.
+    pub idle_ttl_secs: u64,
+    /// How long a tool call waits for approval before it is denied.
+    pub approval_timeout_secs: u64,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            max_sessions: DEFAULT_SESSION_MAX,
+            idle_ttl_secs: DEFAULT_SESSION_IDLE_TTL_SECS,
+            approval_timeout_secs: DEFAULT_SESSION_APPROVAL_TIMEOUT_SECS,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub model_provider: String,
@@ -78,9 +357,55 @@ pub struct Config {
     pub model_base_url: String,
     pub system_prompt: String,
     pub model_timeout_secs: u64,
+    pub model_retry: ModelRetryPolicy,
+    /// Targets tried in order after the primary one fails.
+    pub model_fallbacks: Vec<ModelTarget>,
+    /// How long a failed target is skipped before it is tried first again.
+    pub model_target_cooldown_secs: u64,
+    /// When each target last failed; clones of this config share it.
+    pub model_target_health: Arc<TargetHealth>,
+    /// Targets for the phases of a turn.
+    pub model_roles: ModelRoles,
+    /// Default options for every chat request.
+    pub generation: GenerationOptions,
+    /// Model used for embeddings.
+    pub embedding_model: String,
+    /// Provider serving `embedding_model`; `ollama` or `openai`.
+    pub embedding_provider: String,
+    pub embedding_base_url: String,
+    /// Sent as a bearer token with `openai` embedding requests.
+    pub embedding_api_key: Option<ApiKey>,
+    /// Upper bound on texts sent in one embedding request.
+    pub embedding_batch_size: usize,
+    /// File holding the document retrieval index.
+    pub docs_index_path: PathBuf,
+    /// Target size of an indexed chunk, in characters.
+    pub docs_chunk_chars: usize,
+    /// File holding long-term memories.
+    pub memory_path: PathBuf,
+    /// How many memories are added to the system messages each turn; 0
+    /// disables injection.
+    pub memory_inject_limit: usize,
+    /// Rank memories by embedding similarity instead of keyword overlap.
+    pub memory_embeddings: bool,
+    /// JSON file listing the MCP servers whose tools the agent can use.
+    pub mcp_config_path: PathBuf,
+    /// Directory searched for plugin manifests.
+    pub plugin_dir: PathBuf,
+    /// Address `fizz serve` binds to.
+    pub server_host: String,
+    pub server_port: u16,
+    pub sessions: SessionLimits,
     pub tool_runtime: ToolRuntime,
     pub workspace_fs_mode: WorkspaceFsMode,
     pub tool_policy: ToolPolicy,
+    /// Upper bound on tool calls from one model response that run at once.
+    pub tool_max_concurrency: usize,
+    /// Most tool calls run from one model response; later calls are denied.
+    pub tool_max_calls_per_hop: usize,
+    pub tool_call_extraction: ToolCallExtraction,
+    pub delegation: DelegationLimits,
+    pub agent_mode: AgentMode,
 }
 
 impl Config {
@@ -88,40 +413,251 @@ impl Config {
         Self::from_env_with(|key| env::var(key).ok())
     }
 
-    fn from_env_with(mut get_var: impl FnMut(&str) -> Option<String>) -> Self {
+    pub(crate) fn from_env_with(mut get_var: impl FnMut(&str) -> Option<String>) -> Self {
         let model_base_url =
             get_var("MODEL_BASE_URL").unwrap_or_else(|| DEFAULT_MODEL_BASE_URL.to_string());
         let model_timeout_secs = parse_model_timeout_secs(get_var("MODEL_TIMEOUT_SECS").as_deref());
+        let model_retry = parse_model_retry_policy(
+            get_var("MODEL_MAX_ATTEMPTS").as_deref(),
+            get_var("MODEL_RETRY_BASE_DELAY_MS").as_deref(),
+            get_var("MODEL_RETRY_MAX_DELAY_MS").as_deref(),
+        );
+        let model_target_cooldown_secs =
+            parse_model_target_cooldown_secs(get_var("MODEL_TARGET_COOLDOWN_SECS").as_deref());
+        let generation = GenerationOptions {
+            temperature: parse_non_negative_f32(get_var("MODEL_TEMPERATURE").as_deref()),
+            top_p: parse_non_negative_f32(get_var("MODEL_TOP_P").as_deref()),
+            top_k: parse_optional_positive(get_var("MODEL_TOP_K").as_deref()),
+            seed: get_var("MODEL_SEED").and_then(|value| value.trim().parse().ok()),
+            max_tokens: parse_optional_positive(get_var("MODEL_MAX_TOKENS").as_deref()),
+            stop: parse_stop_sequences(get_var("MODEL_STOP").as_deref()),
+            num_ctx: parse_optional_positive(get_var("MODEL_NUM_CTX").as_deref()),
+            keep_alive: get_var("MODEL_KEEP_ALIVE")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty()),
+            output_format: None,
+        };
         let tool_runtime = parse_tool_runtime(get_var("TOOL_RUNTIME").as_deref());
         let tool_timeout_secs = parse_tool_timeout_secs(get_var("TOOL_TIMEOUT_SECS").as_deref());
         let tool_memory_mb = parse_tool_memory_mb(get_var("TOOL_MEMORY_MB").as_deref());
+        let tool_max_output_bytes =
+            parse_tool_max_output_bytes(get_var("TOOL_MAX_OUTPUT_BYTES").as_deref());
         let tool_allow_direct_network = parse_bool(
             get_var("TOOL_ALLOW_DIRECT_NETWORK").as_deref(),
             DEFAULT_TOOL_ALLOW_DIRECT_NETWORK,
         );
         let workspace_fs_mode = parse_workspace_fs_mode(get_var("WORKSPACE_FS_MODE").as_deref());
+        let tool_max_concurrency =
+            parse_tool_max_concurrency(get_var("TOOL_MAX_CONCURRENCY").as_deref());
+        let tool_max_calls_per_hop =
+            parse_tool_max_calls_per_hop(get_var("TOOL_MAX_CALLS_PER_HOP").as_deref());
+        let tool_call_extraction =
+            parse_tool_call_extraction(get_var("TOOL_CALL_EXTRACTION").as_deref());
+        let agent_mode = parse_agent_mode(get_var("AGENT_MODE").as_deref());
+        let delegation = DelegationLimits {
+            max_depth: parse_usize(
+                get_var("AGENT_DELEGATE_MAX_DEPTH").as_deref(),
+                DEFAULT_DELEGATE_MAX_DEPTH,
+            ),
+            max_runs: parse_delegate_max_runs(get_var("AGENT_DELEGATE_MAX_RUNS").as_deref()),
+            max_tokens: parse_positive_u64(
+                get_var("AGENT_DELEGATE_MAX_TOKENS").as_deref(),
+                DEFAULT_DELEGATE_MAX_TOKENS,
+            ),
+        };
+        let sessions = SessionLimits {
+            max_sessions: parse_positive_usize(
+                get_var("SESSION_MAX").as_deref(),
+                DEFAULT_SESSION_MAX,
+            ),
+            idle_ttl_secs: parse_positive_u64(
+                get_var("SESSION_IDLE_TTL_SECS").as_deref(),
+                DEFAULT_SESSION_IDLE_TTL_SECS,
+            ),
+            approval_timeout_secs: parse_positive_u64(
+                get_var("SESSION_APPROVAL_TIMEOUT_SECS").as_deref(),
+                DEFAULT_SESSION_APPROVAL_TIMEOUT_SECS,
+            ),
+        };
         let tool_policy = ToolPolicy {
             allow_direct_network: tool_allow_direct_network,
             resource_limits: ToolResourceLimits {
                 timeout_secs: tool_timeout_secs,
                 memory_mb: tool_memory_mb,
+                max_output_bytes: tool_max_output_bytes,
             },
         };
 
+        let model_fallbacks = parse_model_fallbacks(
+            get_var("MODEL_FALLBACKS").as_deref(),
+            &model_base_url,
+            model_timeout_secs,
+        );
+
+        let mut role_target = |key: &str| {
+            parse_model_fallbacks(get_var(key).as_deref(), &model_base_url, model_timeout_secs)
+                .into_iter()
+                .next()
+        };
+        let model_roles = ModelRoles {
+            router: role_target("MODEL_ROUTER"),
+            tool_step: role_target("MODEL_TOOL_STEP"),
+            final_answer: role_target("MODEL_FINAL_ANSWER"),
+            summarizer: role_target("MODEL_SUMMARIZER"),
+        };
+
+        let model_provider =
+            get_var("MODEL_PROVIDER").unwrap_or_else(|| DEFAULT_MODEL_PROVIDER.to_string());
+        let mut embedding_var = |key: &str| {
+            get_var(key)
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let embedding_provider =
+            embedding_var("EMBEDDING_PROVIDER").unwrap_or_else(|| model_provider.clone());
+        let embedding_base_url =
+            embedding_var("EMBEDDING_BASE_URL").unwrap_or_else(|| model_base_url.clone());
+        let embedding_api_key = embedding_var("EMBEDDING_API_KEY").map(ApiKey::new);
+
         Self {
-            model_provider: get_var("MODEL_PROVIDER")
-                .unwrap_or_else(|| DEFAULT_MODEL_PROVIDER.to_string()),
+            model_provider,
             model: get_var("MODEL").unwrap_or_else(|| DEFAULT_MODEL.to_string()),
             model_base_url,
             system_prompt: get_var("SYSTEM_PROMPT")
                 .unwrap_or_else(|| DEFAULT_SYSTEM_PROMPT.to_string()),
             model_timeout_secs,
+            model_retry,
+            model_fallbacks,
+            model_target_cooldown_secs,
+            model_target_health: Arc::default(),
+            model_roles,
+            generation,
+            embedding_model: get_var("EMBEDDING_MODEL")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| DEFAULT_EMBEDDING_MODEL.to_string()),
+            embedding_provider,
+            embedding_base_url,
+            embedding_api_key,
+            embedding_batch_size: parse_embedding_batch_size(
+                get_var("EMBEDDING_BATCH_SIZE").as_deref(),
+            ),
+            docs_index_path: get_var("DOCS_INDEX_PATH")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| DEFAULT_DOCS_INDEX_PATH.to_string())
+                .into(),
+            docs_chunk_chars: parse_docs_chunk_chars(get_var("DOCS_CHUNK_CHARS").as_deref()),
+            memory_path: get_var("MEMORY_PATH")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| DEFAULT_MEMORY_PATH.to_string())
+                .into(),
+            memory_inject_limit: parse_usize(
+                get_var("MEMORY_INJECT_LIMIT").as_deref(),
+                DEFAULT_MEMORY_INJECT_LIMIT,
+            ),
+            memory_embeddings: parse_bool(
+                get_var("MEMORY_EMBEDDINGS").as_deref(),
+                DEFAULT_MEMORY_EMBEDDINGS,
+            ),
+            mcp_config_path: get_var("MCP_CONFIG")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| DEFAULT_MCP_CONFIG_PATH.to_string())
+                .into(),
+            plugin_dir: get_var("PLUGIN_DIR")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| DEFAULT_PLUGIN_DIR.to_string())
+                .into(),
+            server_host: get_var("SERVER_HOST")
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .unwrap_or_else(|| DEFAULT_SERVER_HOST.to_string()),
+            server_port: parse_server_port(get_var("SERVER_PORT").as_deref()),
+            sessions,
             tool_runtime,
             workspace_fs_mode,
             tool_policy,
+            tool_max_concurrency,
+            tool_max_calls_per_hop,
+            tool_call_extraction,
+            delegation,
+            agent_mode,
         }
     }
 
+    /// The target built from `MODEL_PROVIDER`, `MODEL`, `MODEL_BASE_URL` and
+    /// `MODEL_TIMEOUT_SECS`.
+    pub fn primary_model_target(&self) -> ModelTarget {
+        ModelTarget {
+            provider: self.model_provider.clone(),
+            model: self.model.clone(),
+            base_url: self.model_base_url.clone(),
+            timeout_secs: self.model_timeout_secs,
+        }
+    }
+
+    /// The target `role` runs on first.
+    pub fn role_model_target(&self, role: ModelRole) -> ModelTarget {
+        self.model_roles
+            .target(role)
+            .cloned()
+            .unwrap_or_else(|| self.primary_model_target())
+    }
+
+    /// This config with `role`'s target as the primary one. The configured
+    /// targets stay behind it as fallbacks, so a failing role model falls
+    /// back to the main one.
+    pub fn for_role(&self, role: ModelRole) -> Config {
+        let Some(target) = self.model_roles.target(role) else {
+            return self.clone();
+        };
+        let model_fallbacks = self
+            .model_targets()
+            .into_iter()
+            .filter(|fallback| fallback != target)
+            .collect();
+        Config {
+            model_provider: target.provider.clone(),
+            model: target.model.clone(),
+            model_base_url: target.base_url.clone(),
+            model_timeout_secs: target.timeout_secs,
+            model_fallbacks,
+            ..self.clone()
+        }
+    }
+
+    /// The target built from `EMBEDDING_PROVIDER`, `EMBEDDING_MODEL` and
+    /// `EMBEDDING_BASE_URL`, with the primary target's timeout.
+    pub fn embedding_model_target(&self) -> ModelTarget {
+        ModelTarget {
+            provider: self.embedding_provider.clone(),
+            model: self.embedding_model.clone(),
+            base_url: self.embedding_base_url.clone(),
+            timeout_secs: self.model_timeout_secs,
+        }
+    }
+
+    /// `SERVER_HOST` and `SERVER_PORT` as a socket address string; IPv6 hosts
+    /// are bracketed.
+    pub fn server_addr(&self) -> String {
+        if self.server_host.contains(':') && !self.server_host.starts_with('[') {
+            format!("[{}]:{}", self.server_host, self.server_port)
+        } else {
+            format!("{}:{}", self.server_host, self.server_port)
+        }
+    }
+
+    /// The primary target followed by the fallbacks, in the order they are tried.
+    pub fn model_targets(&self) -> Vec<ModelTarget> {
+        let mut targets = Vec::with_capacity(1 + self.model_fallbacks.len());
+        targets.push(self.primary_model_target());
+        targets.extend(self.model_fallbacks.iter().cloned());
+        targets
+    }
+
     pub fn tool_timeout_secs(&self) -> u64 {
         self.tool_policy.resource_limits.timeout_secs
     }
@@ -130,6 +666,10 @@ impl Config {
         self.tool_policy.resource_limits.memory_mb
     }
 
+    pub fn tool_max_output_bytes(&self) -> u64 {
+        self.tool_policy.resource_limits.max_output_bytes
+    }
+
     pub fn tool_allow_direct_network(&self) -> bool {
         self.tool_policy.allow_direct_network
     }
@@ -141,10 +681,100 @@ fn parse_positive_u64(raw: Option<&str>, default: u64) -> u64 {
         .unwrap_or(default)
 }
 
+fn parse_positive_usize(raw: Option<&str>, default: usize) -> usize {
+    raw.and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(default)
+}
+
+/// Like [`parse_positive_usize`], for settings where `0` turns a feature off.
+fn parse_usize(raw: Option<&str>, default: usize) -> usize {
+    raw.and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
 fn parse_model_timeout_secs(raw: Option<&str>) -> u64 {
     parse_positive_u64(raw, DEFAULT_MODEL_TIMEOUT_SECS)
 }
 
+fn parse_model_retry_policy(
+    max_attempts: Option<&str>,
+    base_delay_ms: Option<&str>,
+    max_delay_ms: Option<&str>,
+) -> ModelRetryPolicy {
+    let max_attempts = max_attempts
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_MODEL_MAX_ATTEMPTS);
+    let base_delay_ms = parse_positive_u64(base_delay_ms, DEFAULT_MODEL_RETRY_BASE_DELAY_MS);
+    let max_delay_ms =
+        parse_positive_u64(max_delay_ms, DEFAULT_MODEL_RETRY_MAX_DELAY_MS).max(base_delay_ms);
+
+    ModelRetryPolicy {
+        max_attempts,
+        base_delay_ms,
+        max_delay_ms,
+    }
+}
+
+fn parse_non_negative_f32(raw: Option<&str>) -> Option<f32> {
+    raw.and_then(|value| value.trim().parse::<f32>().ok())
+        .filter(|value| value.is_finite() && *value >= 0.0)
+}
+
+fn parse_optional_positive(raw: Option<&str>) -> Option<u32> {
+    raw.and_then(|value| value.trim().parse::<u32>().ok())
+        .filter(|value| *value > 0)
+}
+
+fn parse_stop_sequences(raw: Option<&str>) -> Vec<String> {
+    raw.map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|sequence| !sequence.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn parse_model_target_cooldown_secs(raw: Option<&str>) -> u64 {
+    parse_positive_u64(raw, DEFAULT_MODEL_TARGET_COOLDOWN_SECS)
+}
+
+/// Parses comma-separated `provider|model[|base_url[|timeout_secs]]` entries.
+/// Missing endpoint fields fall back to the primary target's values; entries
+/// without a provider or model are skipped.
+fn parse_model_fallbacks(
+    raw: Option<&str>,
+    default_base_url: &str,
+    default_timeout_secs: u64,
+) -> Vec<ModelTarget> {
+    let Some(raw) = raw else {
+        return Vec::new();
+    };
+
+    raw.split(',')
+        .filter_map(|entry| {
+            let mut fields = entry.split('|').map(str::trim);
+            let provider = fields.next().filter(|value| !value.is_empty())?;
+            let model = fields.next().filter(|value| !value.is_empty())?;
+            let base_url = fields
+                .next()
+                .filter(|value| !value.is_empty())
+                .unwrap_or(default_base_url);
+            let timeout_secs = parse_positive_u64(fields.next(), default_timeout_secs);
+            Some(ModelTarget {
+                provider: provider.to_string(),
+                model: model.to_string(),
+                base_url: base_url.to_string(),
+                timeout_secs,
+            })
+        })
+        .collect()
+}
+
 fn parse_tool_timeout_secs(raw: Option<&str>) -> u64 {
     parse_positive_u64(raw, DEFAULT_TOOL_TIMEOUT_SECS)
 }
@@ -153,6 +783,36 @@ fn parse_tool_memory_mb(raw: Option<&str>) -> u64 {
     parse_positive_u64(raw, DEFAULT_TOOL_MEMORY_MB)
 }
 
+fn parse_tool_max_output_bytes(raw: Option<&str>) -> u64 {
+    parse_positive_u64(raw, DEFAULT_TOOL_MAX_OUTPUT_BYTES)
+}
+
+fn parse_tool_max_concurrency(raw: Option<&str>) -> usize {
+    parse_positive_usize(raw, DEFAULT_TOOL_MAX_CONCURRENCY)
+}
+
+fn parse_tool_max_calls_per_hop(raw: Option<&str>) -> usize {
+    parse_positive_usize(raw, DEFAULT_TOOL_MAX_CALLS_PER_HOP)
+}
+
+fn parse_delegate_max_runs(raw: Option<&str>) -> usize {
+    parse_positive_usize(raw, DEFAULT_DELEGATE_MAX_RUNS)
+}
+
+fn parse_embedding_batch_size(raw: Option<&str>) -> usize {
+    parse_positive_usize(raw, DEFAULT_EMBEDDING_BATCH_SIZE)
+}
+
+fn parse_docs_chunk_chars(raw: Option<&str>) -> usize {
+    parse_positive_usize(raw, DEFAULT_DOCS_CHUNK_CHARS)
+}
+
+fn parse_server_port(raw: Option<&str>) -> u16 {
+    raw.and_then(|value| value.trim().parse::<u16>().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(DEFAULT_SERVER_PORT)
+}
+
 fn parse_bool(raw: Option<&str>, default: bool) -> bool {
     match raw.map(str::trim).map(str::to_ascii_lowercase).as_deref() {
         Some("1" | "true" | "yes" | "on") => true,
@@ -173,6 +833,21 @@ fn parse_tool_runtime(raw: Option<&str>) -> ToolRuntime {
     }
 }
 
+fn parse_tool_call_extraction(raw: Option<&str>) -> ToolCallExtraction {
+    match raw.unwrap_or("strict").trim().to_ascii_lowercase().as_str() {
+        "lenient" => ToolCallExtraction::Lenient,
+        "schema" => ToolCallExtraction::Schema,
+        _ => ToolCallExtraction::Strict,
+    }
+}
+
+fn parse_agent_mode(raw: Option<&str>) -> AgentMode {
+    match raw.unwrap_or("react").trim().to_ascii_lowercase().as_str() {
+        "plan" => AgentMode::Plan,
+        _ => AgentMode::React,
+    }
+}
+
 fn parse_workspace_fs_mode(raw: Option<&str>) -> WorkspaceFsMode {
     match raw.unwrap_or("host").trim().to_ascii_lowercase().as_str() {
         "overlay" => WorkspaceFsMode::Overlay,
@@ -186,12 +861,24 @@ mod tests {
     use std::collections::HashMap;
 
     use super::{
-        Config, DEFAULT_MODEL, DEFAULT_MODEL_BASE_URL, DEFAULT_MODEL_PROVIDER,
-        DEFAULT_MODEL_TIMEOUT_SECS, DEFAULT_SYSTEM_PROMPT, DEFAULT_TOOL_ALLOW_DIRECT_NETWORK,
-        DEFAULT_TOOL_MEMORY_MB, DEFAULT_TOOL_TIMEOUT_SECS, ToolPolicy, ToolResourceLimits,
-        ToolRuntime, WorkspaceFsMode, parse_bool, parse_model_timeout_secs, parse_tool_memory_mb,
-        parse_tool_runtime, parse_tool_timeout_secs, parse_workspace_fs_mode,
+        AgentMode, ApiKey, Config, DEFAULT_DOCS_CHUNK_CHARS, DEFAULT_DOCS_INDEX_PATH,
+        DEFAULT_EMBEDDING_BATCH_SIZE, DEFAULT_EMBEDDING_MODEL, DEFAULT_MCP_CONFIG_PATH,
+        DEFAULT_MEMORY_EMBEDDINGS, DEFAULT_MEMORY_INJECT_LIMIT, DEFAULT_MEMORY_PATH, DEFAULT_MODEL,
+        DEFAULT_MODEL_BASE_URL, DEFAULT_MODEL_PROVIDER, DEFAULT_MODEL_TARGET_COOLDOWN_SECS,
+        DEFAULT_MODEL_TIMEOUT_SECS, DEFAULT_PLUGIN_DIR, DEFAULT_SERVER_HOST, DEFAULT_SERVER_PORT,
+        DEFAULT_SESSION_MAX, DEFAULT_SYSTEM_PROMPT, DEFAULT_TOOL_ALLOW_DIRECT_NETWORK,
+        DEFAULT_TOOL_MAX_CALLS_PER_HOP, DEFAULT_TOOL_MAX_CONCURRENCY,
+        DEFAULT_TOOL_MAX_OUTPUT_BYTES, DEFAULT_TOOL_MEMORY_MB, DEFAULT_TOOL_TIMEOUT_SECS,
+        DelegationLimits, GenerationOptions, ModelRetryPolicy, ModelRole, ModelRoles, ModelTarget,
+        SessionLimits, ToolCallExtraction, ToolPolicy, ToolResourceLimits, ToolRuntime,
+        WorkspaceFsMode, parse_agent_mode, parse_bool, parse_docs_chunk_chars,
+        parse_embedding_batch_size, parse_model_fallbacks, parse_model_retry_policy,
+        parse_model_timeout_secs, parse_non_negative_f32, parse_positive_usize, parse_server_port,
+        parse_stop_sequences, parse_tool_call_extraction, parse_tool_max_calls_per_hop,
+        parse_tool_max_concurrency, parse_tool_max_output_bytes, parse_tool_memory_mb,
+        parse_tool_runtime, parse_tool_timeout_secs, parse_usize, parse_workspace_fs_mode,
     };
+    use crate::output_schema::OutputFormat;
 
     fn config_from_pairs(pairs: &[(&str, &str)]) -> Config {
         let vars: HashMap<String, String> = pairs
@@ -209,15 +896,54 @@ mod tests {
         assert_eq!(cfg.model_base_url, DEFAULT_MODEL_BASE_URL);
         assert_eq!(cfg.system_prompt, DEFAULT_SYSTEM_PROMPT);
         assert_eq!(cfg.model_timeout_secs, DEFAULT_MODEL_TIMEOUT_SECS);
+        assert_eq!(cfg.model_retry, ModelRetryPolicy::default());
+        assert!(cfg.model_fallbacks.is_empty());
+        assert_eq!(cfg.model_roles, ModelRoles::default());
+        assert_eq!(cfg.generation, GenerationOptions::default());
+        assert_eq!(
+            cfg.model_target_cooldown_secs,
+            DEFAULT_MODEL_TARGET_COOLDOWN_SECS
+        );
         assert_eq!(cfg.tool_runtime, ToolRuntime::Builtin);
         assert_eq!(cfg.tool_timeout_secs(), DEFAULT_TOOL_TIMEOUT_SECS);
         assert_eq!(cfg.tool_memory_mb(), DEFAULT_TOOL_MEMORY_MB);
+        assert_eq!(cfg.tool_max_output_bytes(), DEFAULT_TOOL_MAX_OUTPUT_BYTES);
         assert_eq!(
             cfg.tool_allow_direct_network(),
             DEFAULT_TOOL_ALLOW_DIRECT_NETWORK
         );
         assert_eq!(cfg.workspace_fs_mode, WorkspaceFsMode::Host);
         assert_eq!(cfg.tool_policy, ToolPolicy::default());
+        assert_eq!(cfg.tool_max_concurrency, DEFAULT_TOOL_MAX_CONCURRENCY);
+        assert_eq!(cfg.tool_max_calls_per_hop, DEFAULT_TOOL_MAX_CALLS_PER_HOP);
+        assert_eq!(cfg.tool_call_extraction, ToolCallExtraction::Strict);
+        assert_eq!(cfg.delegation, DelegationLimits::default());
+        assert_eq!(cfg.sessions, SessionLimits::default());
+        assert_eq!(cfg.agent_mode, AgentMode::React);
+        assert_eq!(cfg.embedding_model, DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(cfg.embedding_model_target().provider, cfg.model_provider);
+        assert_eq!(cfg.embedding_model_target().base_url, cfg.model_base_url);
+        assert_eq!(cfg.embedding_api_key, None);
+        assert_eq!(cfg.embedding_batch_size, DEFAULT_EMBEDDING_BATCH_SIZE);
+        assert_eq!(
+            cfg.docs_index_path,
+            std::path::PathBuf::from(DEFAULT_DOCS_INDEX_PATH)
+        );
+        assert_eq!(cfg.docs_chunk_chars, DEFAULT_DOCS_CHUNK_CHARS);
+        assert_eq!(
+            cfg.memory_path,
+            std::path::PathBuf::from(DEFAULT_MEMORY_PATH)
+        );
+        assert_eq!(cfg.memory_inject_limit, DEFAULT_MEMORY_INJECT_LIMIT);
+        assert_eq!(cfg.memory_embeddings, DEFAULT_MEMORY_EMBEDDINGS);
+        assert_eq!(
+            cfg.mcp_config_path,
+            std::path::PathBuf::from(DEFAULT_MCP_CONFIG_PATH)
+        );
+        assert_eq!(cfg.plugin_dir, std::path::PathBuf::from(DEFAULT_PLUGIN_DIR));
+        assert_eq!(cfg.server_host, DEFAULT_SERVER_HOST);
+        assert_eq!(cfg.server_port, DEFAULT_SERVER_PORT);
+        assert_eq!(cfg.server_addr(), "127.0.0.1:8080");
     }
 
     #[test]
@@ -228,11 +954,57 @@ mod tests {
             ("MODEL_BASE_URL", "http://localhost:9999"),
             ("SYSTEM_PROMPT", "Be concise."),
             ("MODEL_TIMEOUT_SECS", "15"),
+            ("MODEL_MAX_ATTEMPTS", "5"),
+            ("MODEL_RETRY_BASE_DELAY_MS", "100"),
+            ("MODEL_RETRY_MAX_DELAY_MS", "2000"),
+            (
+                "MODEL_FALLBACKS",
+                "ollama|llama3.1:70b|http://gpu-box:11434|120",
+            ),
+            ("MODEL_TARGET_COOLDOWN_SECS", "10"),
+            ("MODEL_ROUTER", "ollama|qwen2.5:0.5b"),
+            (
+                "MODEL_FINAL_ANSWER",
+                "ollama|llama3.1:70b|http://gpu-box:11434|120",
+            ),
+            ("MODEL_TEMPERATURE", "0.2"),
+            ("MODEL_TOP_P", "0.9"),
+            ("MODEL_TOP_K", "40"),
+            ("MODEL_SEED", "42"),
+            ("MODEL_MAX_TOKENS", "512"),
+            ("MODEL_STOP", "</answer>, END"),
+            ("MODEL_NUM_CTX", "8192"),
+            ("MODEL_KEEP_ALIVE", "10m"),
+            ("EMBEDDING_MODEL", "mxbai-embed-large"),
+            ("EMBEDDING_PROVIDER", "openai"),
+            ("EMBEDDING_BASE_URL", "https://api.openai.com"),
+            ("EMBEDDING_API_KEY", " sk-test "),
+            ("EMBEDDING_BATCH_SIZE", "8"),
+            ("DOCS_INDEX_PATH", "/var/lib/fizz/docs.json"),
+            ("DOCS_CHUNK_CHARS", "800"),
+            ("MEMORY_PATH", "/var/lib/fizz/memory.json"),
+            ("MEMORY_INJECT_LIMIT", "0"),
+            ("MEMORY_EMBEDDINGS", "true"),
+            ("MCP_CONFIG", "/etc/fizz/mcp.json"),
+            ("PLUGIN_DIR", "/usr/lib/fizz/plugins"),
+            ("SERVER_HOST", "::1"),
+            ("SERVER_PORT", "9090"),
+            ("SESSION_MAX", "4"),
+            ("SESSION_IDLE_TTL_SECS", "60"),
+            ("SESSION_APPROVAL_TIMEOUT_SECS", "30"),
             ("TOOL_RUNTIME", "wasm"),
             ("TOOL_TIMEOUT_SECS", "9"),
             ("TOOL_MEMORY_MB", "512"),
+            ("TOOL_MAX_OUTPUT_BYTES", "4096"),
             ("TOOL_ALLOW_DIRECT_NETWORK", "true"),
             ("WORKSPACE_FS_MODE", "overlay"),
+            ("TOOL_MAX_CONCURRENCY", "8"),
+            ("TOOL_MAX_CALLS_PER_HOP", "3"),
+            ("TOOL_CALL_EXTRACTION", "lenient"),
+            ("AGENT_DELEGATE_MAX_DEPTH", "0"),
+            ("AGENT_DELEGATE_MAX_RUNS", "2"),
+            ("AGENT_DELEGATE_MAX_TOKENS", "5000"),
+            ("AGENT_MODE", "plan"),
         ]);
 
         assert_eq!(cfg.model_provider, "custom");
@@ -240,11 +1012,125 @@ mod tests {
         assert_eq!(cfg.model_base_url, "http://localhost:9999");
         assert_eq!(cfg.system_prompt, "Be concise.");
         assert_eq!(cfg.model_timeout_secs, 15);
+        assert_eq!(
+            cfg.model_retry,
+            ModelRetryPolicy {
+                max_attempts: 5,
+                base_delay_ms: 100,
+                max_delay_ms: 2000,
+            }
+        );
+        assert_eq!(cfg.model_target_cooldown_secs, 10);
+        assert_eq!(
+            cfg.model_roles,
+            ModelRoles {
+                router: Some(ModelTarget {
+                    provider: "ollama".to_string(),
+                    model: "qwen2.5:0.5b".to_string(),
+                    base_url: "http://localhost:9999".to_string(),
+                    timeout_secs: 15,
+                }),
+                final_answer: Some(ModelTarget {
+                    provider: "ollama".to_string(),
+                    model: "llama3.1:70b".to_string(),
+                    base_url: "http://gpu-box:11434".to_string(),
+                    timeout_secs: 120,
+                }),
+                ..ModelRoles::default()
+            }
+        );
+        assert_eq!(
+            cfg.embedding_api_key.as_ref().map(ApiKey::expose),
+            Some("sk-test")
+        );
+        assert_eq!(format!("{:?}", cfg.embedding_api_key), "Some(ApiKey(***))");
+        assert_eq!(cfg.embedding_batch_size, 8);
+        assert_eq!(
+            cfg.docs_index_path,
+            std::path::PathBuf::from("/var/lib/fizz/docs.json")
+        );
+        assert_eq!(cfg.docs_chunk_chars, 800);
+        assert_eq!(
+            cfg.memory_path,
+            std::path::PathBuf::from("/var/lib/fizz/memory.json")
+        );
+        assert_eq!(cfg.memory_inject_limit, 0);
+        assert!(cfg.memory_embeddings);
+        assert_eq!(
+            cfg.mcp_config_path,
+            std::path::PathBuf::from("/etc/fizz/mcp.json")
+        );
+        assert_eq!(
+            cfg.plugin_dir,
+            std::path::PathBuf::from("/usr/lib/fizz/plugins")
+        );
+        assert_eq!(cfg.server_addr(), "[::1]:9090");
+        assert_eq!(
+            cfg.sessions,
+            SessionLimits {
+                max_sessions: 4,
+                idle_ttl_secs: 60,
+                approval_timeout_secs: 30,
+            }
+        );
+        assert_eq!(
+            cfg.embedding_model_target(),
+            ModelTarget {
+                provider: "openai".to_string(),
+                model: "mxbai-embed-large".to_string(),
+                base_url: "https://api.openai.com".to_string(),
+                timeout_secs: 15,
+            }
+        );
+        assert_eq!(
+            cfg.generation,
+            GenerationOptions {
+                temperature: Some(0.2),
+                top_p: Some(0.9),
+                top_k: Some(40),
+                seed: Some(42),
+                max_tokens: Some(512),
+                stop: vec!["</answer>".to_string(), "END".to_string()],
+                num_ctx: Some(8192),
+                keep_alive: Some("10m".to_string()),
+                output_format: None,
+            }
+        );
+        assert_eq!(
+            cfg.model_targets(),
+            vec![
+                ModelTarget {
+                    provider: "custom".to_string(),
+                    model: "some-model:1".to_string(),
+                    base_url: "http://localhost:9999".to_string(),
+                    timeout_secs: 15,
+                },
+                ModelTarget {
+                    provider: "ollama".to_string(),
+                    model: "llama3.1:70b".to_string(),
+                    base_url: "http://gpu-box:11434".to_string(),
+                    timeout_secs: 120,
+                },
+            ]
+        );
         assert_eq!(cfg.tool_runtime, ToolRuntime::Wasm);
         assert_eq!(cfg.tool_timeout_secs(), 9);
         assert_eq!(cfg.tool_memory_mb(), 512);
+        assert_eq!(cfg.tool_max_output_bytes(), 4096);
         assert!(cfg.tool_allow_direct_network());
         assert_eq!(cfg.workspace_fs_mode, WorkspaceFsMode::Overlay);
+        assert_eq!(cfg.tool_max_concurrency, 8);
+        assert_eq!(cfg.tool_max_calls_per_hop, 3);
+        assert_eq!(cfg.tool_call_extraction, ToolCallExtraction::Lenient);
+        assert_eq!(
+            cfg.delegation,
+            DelegationLimits {
+                max_depth: 0,
+                max_runs: 2,
+                max_tokens: 5000,
+            }
+        );
+        assert_eq!(cfg.agent_mode, AgentMode::Plan);
         assert_eq!(
             cfg.tool_policy,
             ToolPolicy {
@@ -252,6 +1138,7 @@ mod tests {
                 resource_limits: ToolResourceLimits {
                     timeout_secs: 9,
                     memory_mb: 512,
+                    max_output_bytes: 4096,
                 },
             }
         );
@@ -280,12 +1167,174 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_usize_accepts_zero_unlike_parse_positive_usize() {
+        assert_eq!(parse_usize(Some(" 0 "), 5), 0);
+        assert_eq!(parse_usize(Some("-1"), 5), 5);
+        assert_eq!(parse_positive_usize(Some("0"), 5), 5);
+        assert_eq!(parse_positive_usize(Some("7"), 5), 7);
+        assert_eq!(
+            config_from_pairs(&[("SESSION_MAX", "0")])
+                .sessions
+                .max_sessions,
+            DEFAULT_SESSION_MAX
+        );
+    }
+
+    #[test]
+    fn parse_embedding_batch_size_requires_positive_integer() {
+        assert_eq!(
+            parse_embedding_batch_size(None),
+            DEFAULT_EMBEDDING_BATCH_SIZE
+        );
+        assert_eq!(
+            parse_embedding_batch_size(Some("0")),
+            DEFAULT_EMBEDDING_BATCH_SIZE
+        );
+        assert_eq!(parse_embedding_batch_size(Some(" 64 ")), 64);
+        assert_eq!(parse_docs_chunk_chars(Some("x")), DEFAULT_DOCS_CHUNK_CHARS);
+        assert_eq!(parse_docs_chunk_chars(Some("600")), 600);
+    }
+
+    #[test]
+    fn parse_server_port_requires_nonzero_port() {
+        assert_eq!(parse_server_port(None), DEFAULT_SERVER_PORT);
+        assert_eq!(parse_server_port(Some("0")), DEFAULT_SERVER_PORT);
+        assert_eq!(parse_server_port(Some("70000")), DEFAULT_SERVER_PORT);
+        assert_eq!(parse_server_port(Some(" 3000 ")), 3000);
+    }
+
     #[test]
     fn parse_model_timeout_secs_accepts_positive_integer() {
         assert_eq!(parse_model_timeout_secs(Some("45")), 45);
         assert_eq!(parse_model_timeout_secs(Some("  90  ")), 90);
     }
 
+    #[test]
+    fn parse_model_retry_policy_uses_defaults_for_missing_or_invalid_values() {
+        assert_eq!(
+            parse_model_retry_policy(None, None, None),
+            ModelRetryPolicy::default()
+        );
+        assert_eq!(
+            parse_model_retry_policy(Some("0"), Some("nope"), Some("-1")),
+            ModelRetryPolicy::default()
+        );
+    }
+
+    #[test]
+    fn parse_model_retry_policy_keeps_max_delay_at_least_base_delay() {
+        let policy = parse_model_retry_policy(Some("1"), Some("3000"), Some("1000"));
+        assert_eq!(policy.max_attempts, 1);
+        assert_eq!(policy.base_delay_ms, 3000);
+        assert_eq!(policy.max_delay_ms, 3000);
+    }
+
+    #[test]
+    fn parse_model_fallbacks_fills_missing_fields_from_primary_target() {
+        let targets = parse_model_fallbacks(
+            Some(
+                " ollama | qwen2.5:7b , |missing-provider, ollama|llama3|http://remote:11434|bad ",
+            ),
+            "http://localhost:11434",
+            60,
+        );
+
+        assert_eq!(
+            targets,
+            vec![
+                ModelTarget {
+                    provider: "ollama".to_string(),
+                    model: "qwen2.5:7b".to_string(),
+                    base_url: "http://localhost:11434".to_string(),
+                    timeout_secs: 60,
+                },
+                ModelTarget {
+                    provider: "ollama".to_string(),
+                    model: "llama3".to_string(),
+                    base_url: "http://remote:11434".to_string(),
+                    timeout_secs: 60,
+                },
+            ]
+        );
+        assert!(parse_model_fallbacks(None, "http://localhost:11434", 60).is_empty());
+    }
+
+    #[test]
+    fn for_role_puts_the_role_target_first_and_keeps_the_chain_behind_it() {
+        let cfg = config_from_pairs(&[
+            ("MODEL", "qwen2.5:7b"),
+            ("MODEL_FALLBACKS", "ollama|llama3.1:8b"),
+            ("MODEL_TOOL_STEP", "ollama|llama3.1:8b"),
+        ]);
+
+        let tool_step = cfg.for_role(ModelRole::ToolStep);
+        assert_eq!(
+            tool_step
+                .model_targets()
+                .iter()
+                .map(|target| target.model.as_str())
+                .collect::<Vec<_>>(),
+            ["llama3.1:8b", "qwen2.5:7b"]
+        );
+        assert_eq!(
+            cfg.role_model_target(ModelRole::ToolStep),
+            tool_step.primary_model_target()
+        );
+        assert_eq!(
+            cfg.for_role(ModelRole::FinalAnswer).model_targets(),
+            cfg.model_targets()
+        );
+        assert_eq!(
+            cfg.role_model_target(ModelRole::Router),
+            cfg.primary_model_target()
+        );
+    }
+
+    #[test]
+    fn parse_generation_values_ignore_invalid_input() {
+        assert_eq!(parse_non_negative_f32(Some("-0.5")), None);
+        assert_eq!(parse_non_negative_f32(Some("NaN")), None);
+        assert_eq!(parse_non_negative_f32(Some(" 0 ")), Some(0.0));
+        assert!(parse_stop_sequences(Some(" , ")).is_empty());
+        assert!(parse_stop_sequences(None).is_empty());
+    }
+
+    #[test]
+    fn generation_options_merge_prefers_overrides() {
+        let defaults = GenerationOptions {
+            temperature: Some(0.7),
+            seed: Some(1),
+            stop: vec!["END".to_string()],
+            keep_alive: Some("5m".to_string()),
+            ..GenerationOptions::default()
+        };
+        let overrides = GenerationOptions {
+            temperature: Some(0.0),
+            num_ctx: Some(4096),
+            output_format: Some(OutputFormat::Json),
+            ..GenerationOptions::default()
+        };
+
+        let merged = defaults.merged_with(&overrides);
+
+        assert_eq!(merged.temperature, Some(0.0));
+        assert_eq!(merged.seed, Some(1));
+        assert_eq!(merged.num_ctx, Some(4096));
+        assert_eq!(merged.stop, vec!["END".to_string()]);
+        assert_eq!(merged.keep_alive.as_deref(), Some("5m"));
+        assert_eq!(merged.output_format, Some(OutputFormat::Json));
+    }
+
+    #[test]
+    fn model_target_display_names_provider_model_and_endpoint() {
+        let cfg = config_from_pairs(&[]);
+        assert_eq!(
+            cfg.primary_model_target().to_string(),
+            "ollama/qwen2.5:3b@http://localhost:11434"
+        );
+    }
+
     #[test]
     fn parse_tool_timeout_secs_uses_default_for_missing_or_invalid_values() {
         assert_eq!(parse_tool_timeout_secs(None), DEFAULT_TOOL_TIMEOUT_SECS);
@@ -319,6 +1368,59 @@ mod tests {
         assert_eq!(parse_tool_memory_mb(Some("1024")), 1024);
     }
 
+    #[test]
+    fn from_env_uses_default_delegation_limits_for_invalid_values() {
+        let cfg = config_from_pairs(&[
+            ("AGENT_DELEGATE_MAX_DEPTH", "-1"),
+            ("AGENT_DELEGATE_MAX_RUNS", "0"),
+            ("AGENT_DELEGATE_MAX_TOKENS", "lots"),
+        ]);
+        assert_eq!(cfg.delegation, DelegationLimits::default());
+    }
+
+    #[test]
+    fn parse_tool_max_output_bytes_uses_default_for_missing_or_invalid_values() {
+        assert_eq!(
+            parse_tool_max_output_bytes(None),
+            DEFAULT_TOOL_MAX_OUTPUT_BYTES
+        );
+        assert_eq!(
+            parse_tool_max_output_bytes(Some("0")),
+            DEFAULT_TOOL_MAX_OUTPUT_BYTES
+        );
+        assert_eq!(parse_tool_max_output_bytes(Some(" 2048 ")), 2048);
+    }
+
+    #[test]
+    fn parse_tool_max_calls_per_hop_uses_default_for_missing_or_invalid_values() {
+        assert_eq!(
+            parse_tool_max_calls_per_hop(None),
+            DEFAULT_TOOL_MAX_CALLS_PER_HOP
+        );
+        assert_eq!(
+            parse_tool_max_calls_per_hop(Some("0")),
+            DEFAULT_TOOL_MAX_CALLS_PER_HOP
+        );
+        assert_eq!(parse_tool_max_calls_per_hop(Some(" 2 ")), 2);
+    }
+
+    #[test]
+    fn parse_tool_max_concurrency_uses_default_for_missing_or_invalid_values() {
+        assert_eq!(
+            parse_tool_max_concurrency(None),
+            DEFAULT_TOOL_MAX_CONCURRENCY
+        );
+        assert_eq!(
+            parse_tool_max_concurrency(Some("0")),
+            DEFAULT_TOOL_MAX_CONCURRENCY
+        );
+        assert_eq!(
+            parse_tool_max_concurrency(Some("-2")),
+            DEFAULT_TOOL_MAX_CONCURRENCY
+        );
+        assert_eq!(parse_tool_max_concurrency(Some(" 3 ")), 3);
+    }
+
     #[test]
     fn parse_bool_respects_truthy_and_falsy_values() {
         assert!(parse_bool(Some("true"), false));
@@ -341,6 +1443,30 @@ mod tests {
         assert_eq!(parse_tool_runtime(Some(" WASM ")), ToolRuntime::Wasm);
     }
 
+    #[test]
+    fn parse_agent_mode_defaults_to_react() {
+        assert_eq!(parse_agent_mode(None), AgentMode::React);
+        assert_eq!(parse_agent_mode(Some("plan-and-execute")), AgentMode::React);
+        assert_eq!(parse_agent_mode(Some(" Plan ")), AgentMode::Plan);
+    }
+
+    #[test]
+    fn parse_tool_call_extraction_defaults_to_strict_and_accepts_known_modes() {
+        assert_eq!(parse_tool_call_extraction(None), ToolCallExtraction::Strict);
+        assert_eq!(
+            parse_tool_call_extraction(Some("loose")),
+            ToolCallExtraction::Strict
+        );
+        assert_eq!(
+            parse_tool_call_extraction(Some(" LENIENT ")),
+            ToolCallExtraction::Lenient
+        );
+        assert_eq!(
+            parse_tool_call_extraction(Some("schema")),
+            ToolCallExtraction::Schema
+        );
+    }
+
     #[test]
     fn parse_workspace_fs_mode_defaults_to_host_and_accepts_known_values() {
         assert_eq!(parse_workspace_fs_mode(None), WorkspaceFsMode::Host);
@@ -1,31 +1,58 @@
 pub mod agent;
+mod atomic_write;
+mod cli;
 pub mod config;
 mod logging;
+pub mod mcp;
+pub mod memory;
 pub mod model;
+pub mod model_error;
 pub mod model_gateway;
+pub mod output_schema;
+pub mod plugins;
 pub mod providers;
 pub mod repl;
+pub mod retrieval;
+pub mod server;
+// Some helpers are only used by the integration tests.
+#[cfg(test)]
+mod test_fs;
+#[cfg(test)]
+#[allow(dead_code)]
+mod test_http;
 
 use anyhow::{Context, Result};
 use reqwest::Client;
 use std::env;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::info;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
 
 use agent::Agent;
+use agent::delegate::DelegateRunner;
+use agent::tools::{BuiltinRunner, CompositeRunner, ToolRunner};
+use cli::Command;
 use config::Config;
+use mcp::McpRunner;
+use memory::{MemoryRunner, MemoryStore};
+use model::ImageAttachment;
+use model_gateway::{HostModelGateway, ModelGateway};
+use plugins::ProcessRunner;
 use repl::run_repl;
+use retrieval::DocsSearchRunner;
 
 pub async fn run() -> Result<()> {
     dotenvy::dotenv().ok();
     logging::init();
 
-    let cfg = Config::from_env();
+    let cfg = Arc::new(Config::from_env());
     info!(
         model_provider = %cfg.model_provider,
         model = %cfg.model,
         model_base_url = %cfg.model_base_url,
         model_timeout_secs = cfg.model_timeout_secs,
+        model_fallbacks = cfg.model_fallbacks.len(),
         "loaded runtime configuration"
     );
 
@@ -34,16 +61,153 @@ pub async fn run() -> Result<()> {
         .build()
         .context("Failed to initialize HTTP client")?;
 
-    let args: Vec<String> = env::args().skip(1).collect();
-    if args.is_empty() {
-        info!("starting repl mode");
-        run_repl(&client, &cfg).await
-    } else {
-        let mut agent = Agent::new(&client, &cfg);
-        let prompt = args.join(" ");
-        info!(prompt_len = prompt.len(), "starting single-turn mode");
-        let answer = agent.run_turn(&prompt).await?;
-        println!("{}", answer.trim());
-        Ok(())
+    match cli::parse_args(env::args().skip(1))? {
+        Command::Repl => {
+            info!("starting repl mode");
+            run_repl(client, cfg).await
+        }
+        Command::Prompt { prompt, images } => {
+            let mut agent = AgentFactory::new(client, cfg).await?.build();
+            for path in &images {
+                let image = ImageAttachment::from_path(path)
+                    .with_context(|| format!("Failed to attach image '{}'", path.display()))?;
+                agent.attach_image(image);
+            }
+            info!(
+                prompt_len = prompt.len(),
+                image_count = images.len(),
+                "starting single-turn mode"
+            );
+            let result = agent.run_turn(&prompt).await?;
+            println!("{}", result.answer.trim());
+            Ok(())
+        }
+        Command::Serve => {
+            let shutdown = CancellationToken::new();
+            let signal = shutdown.clone();
+            tokio::spawn(async move {
+                if let Err(err) = tokio::signal::ctrl_c().await {
+                    warn!(error = %err, "failed to listen for ctrl-c");
+                }
+                signal.cancel();
+            });
+            server::serve(client, cfg, shutdown).await
+        }
+        Command::McpServe => {
+            let gateway: Arc<dyn ModelGateway> =
+                Arc::new(HostModelGateway::new(client, Arc::clone(&cfg)));
+            let memory = open_memory(&cfg, &gateway)?;
+            let plugins = discover_plugins(&cfg);
+            let tools = local_tools(&gateway, &cfg, &memory, &plugins);
+            info!(
+                tool_count = tools.tools().len(),
+                "serving tools over mcp stdio"
+            );
+            mcp::serve_stdio(
+                Arc::new(tools),
+                Duration::from_secs(cfg.tool_timeout_secs()),
+            )
+            .await
+            .context("MCP server failed")
+        }
     }
 }
+
+/// Builds the agents every mode runs: the built-in tools, `docs.search`,
+/// the memory tools over the store at `MEMORY_PATH`, the plugins under
+/// `PLUGIN_DIR`, and the tools of the MCP servers listed at `MCP_CONFIG`.
+/// The memory store, plugins and MCP servers are opened once and shared by
+/// every agent the factory builds.
+pub(crate) struct AgentFactory {
+    client: Client,
+    cfg: Arc<Config>,
+    gateway: Arc<dyn ModelGateway>,
+    memory: Arc<MemoryStore>,
+    plugins: Arc<ProcessRunner>,
+    mcp: Arc<McpRunner>,
+}
+
+impl AgentFactory {
+    pub(crate) async fn new(client: Client, cfg: Arc<Config>) -> Result<Self> {
+        let gateway: Arc<dyn ModelGateway> =
+            Arc::new(HostModelGateway::new(client.clone(), Arc::clone(&cfg)));
+        let memory = open_memory(&cfg, &gateway)?;
+        let plugins = discover_plugins(&cfg);
+        let mcp_servers =
+            mcp::load_server_configs(&cfg.mcp_config_path).context("Failed to load MCP config")?;
+        // Servers that fail to start are logged and left out.
+        let (mcp, _) =
+            McpRunner::connect(&mcp_servers, Duration::from_secs(cfg.tool_timeout_secs())).await;
+        Ok(Self {
+            client,
+            cfg,
+            gateway,
+            memory,
+            plugins,
+            mcp: Arc::new(mcp),
+        })
+    }
+
+    pub(crate) fn config(&self) -> &Arc<Config> {
+        &self.cfg
+    }
+
+    pub(crate) fn build(&self) -> Agent {
+        self.build_with_config(Arc::clone(&self.cfg))
+    }
+
+    /// Builds an agent whose turns use `cfg`, e.g. the factory's config with
+    /// per-request generation options.
+    pub(crate) fn build_with_config(&self, cfg: Arc<Config>) -> Agent {
+        let tools: Arc<dyn ToolRunner> = Arc::new(
+            local_tools(&self.gateway, &cfg, &self.memory, &self.plugins)
+                .with(Box::new(Arc::clone(&self.mcp))),
+        );
+        // Sub-agents get the same tools; the delegate runner offers
+        // nothing when AGENT_DELEGATE_MAX_DEPTH is 0.
+        let tool_runner = CompositeRunner::new()
+            .with(Box::new(Arc::clone(&tools)))
+            .with(Box::new(DelegateRunner::new(
+                self.client.clone(),
+                Arc::clone(&cfg),
+                tools,
+            )));
+        Agent::with_tool_runner(self.client.clone(), cfg, Box::new(tool_runner))
+            .with_memory(Arc::clone(&self.memory))
+    }
+}
+
+fn open_memory(cfg: &Config, gateway: &Arc<dyn ModelGateway>) -> Result<Arc<MemoryStore>> {
+    let mut memory = MemoryStore::open(&cfg.memory_path).context("Failed to open memory store")?;
+    if cfg.memory_embeddings {
+        memory = memory.with_embeddings(Arc::clone(gateway));
+    }
+    Ok(Arc::new(memory))
+}
+
+/// Broken plugins are logged and left out.
+fn discover_plugins(cfg: &Config) -> Arc<ProcessRunner> {
+    let (plugins, _) =
+        ProcessRunner::discover(&cfg.plugin_dir, cfg.tool_policy.resource_limits.clone());
+    Arc::new(plugins)
+}
+
+/// The tools fizz runs itself: the built-in tools, `docs.search`, the memory
+/// tools and the plugins. `fizz mcp-serve` offers exactly these, never the
+/// tools of other MCP servers, so fizz instances cannot end up serving each
+/// other.
+fn local_tools(
+    gateway: &Arc<dyn ModelGateway>,
+    cfg: &Arc<Config>,
+    memory: &Arc<MemoryStore>,
+    plugins: &Arc<ProcessRunner>,
+) -> CompositeRunner {
+    CompositeRunner::new()
+        .with(Box::new(BuiltinRunner))
+        .with(Box::new(DocsSearchRunner::new(
+            Arc::clone(gateway),
+            Arc::clone(cfg),
+        )))
+        .with(Box::new(MemoryRunner::new(Arc::clone(memory))))
+        .with(Box::new(Arc::clone(plugins)))
+}
@@ -0,0 +1,494 @@
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::{McpError, McpServerConfig};
+
+/// Protocol revision sent in `initialize`; servers answer with the revision
+/// they will speak.
+pub const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// JSON-RPC "method not found", returned for server requests fizz does not
+/// handle.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+type Writer = Arc<tokio::sync::Mutex<ChildStdin>>;
+
+/// Requests waiting for a response, by JSON-RPC id.
+#[derive(Default)]
+struct Pending {
+    senders: HashMap<u64, oneshot::Sender<Result<Value, McpError>>>,
+    /// Set once the server's stdout closes; later requests fail at once.
+    closed: bool,
+}
+
+type SharedPending = Arc<Mutex<Pending>>;
+
+fn lock(pending: &SharedPending) -> MutexGuard<'_, Pending> {
+    pending
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// A tool as listed by an MCP server.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpTool {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(rename = "inputSchema", default)]
+    pub input_schema: Option<Value>,
+}
+
+/// The result of `tools/call`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct McpToolResult {
+    #[serde(default)]
+    pub content: Vec<Value>,
+    #[serde(rename = "isError", default)]
+    pub is_error: bool,
+    #[serde(rename = "structuredContent", default)]
+    pub structured_content: Option<Value>,
+}
+
+impl McpToolResult {
+    /// Renders the content blocks as text for the model: text blocks as is,
+    /// embedded text resources by their text, and other blocks as short
+    /// placeholders. Falls back to the structured content when there are no
+    /// blocks.
+    pub fn text(&self) -> String {
+        let blocks: Vec<String> = self.content.iter().map(content_block_text).collect();
+        if blocks.is_empty()
+            && let Some(structured) = &self.structured_content
+        {
+            return structured.to_string();
+        }
+        blocks.join("\n")
+    }
+}
+
+fn content_block_text(block: &Value) -> String {
+    let field =
+        |value: &Value, key: &str| value.get(key).and_then(Value::as_str).map(str::to_string);
+    match block.get("type").and_then(Value::as_str) {
+        Some("text") => field(block, "text").unwrap_or_default(),
+        Some("image") | Some("audio") => format!(
+            "[{} content: {}]",
+            field(block, "type").unwrap_or_default(),
+            field(block, "mimeType").unwrap_or_else(|| "unknown type".to_string())
+        ),
+        Some("resource") => {
+            let resource = block.get("resource").unwrap_or(&Value::Null);
+            field(resource, "text").unwrap_or_else(|| {
+                format!("[resource: {}]", field(resource, "uri").unwrap_or_default())
+            })
+        }
+        Some("resource_link") => format!("[resource: {}]", field(block, "uri").unwrap_or_default()),
+        _ => block.to_string(),
+    }
+}
+
+/// A connection to one MCP server running as a child process, speaking
+/// newline-delimited JSON-RPC over its stdin and stdout.
+///
+/// Dropping the client kills the process.
+pub struct McpClient {
+    server: String,
+    writer: Writer,
+    pending: SharedPending,
+    next_id: AtomicU64,
+    request_timeout: Duration,
+    reader: JoinHandle<()>,
+    _child: Child,
+}
+
+impl McpClient {
+    /// Starts the server and performs the `initialize` handshake.
+    pub async fn connect(
+        config: &McpServerConfig,
+        request_timeout: Duration,
+    ) -> Result<Self, McpError> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|source| McpError::Spawn {
+                server: config.name.clone(),
+                command: config.command.clone(),
+                source,
+            })?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        if let Some(stderr) = child.stderr.take() {
+            let server = config.name.clone();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!(mcp_server = %server, "{line}");
+                }
+            });
+        }
+
+        let writer: Writer = Arc::new(tokio::sync::Mutex::new(stdin));
+        let pending = SharedPending::default();
+        let reader = tokio::spawn(read_messages(
+            config.name.clone(),
+            stdout,
+            Arc::clone(&writer),
+            Arc::clone(&pending),
+        ));
+        let client = Self {
+            server: config.name.clone(),
+            writer,
+            pending,
+            next_id: AtomicU64::new(1),
+            request_timeout,
+            reader,
+            _child: child,
+        };
+        client.initialize().await?;
+        Ok(client)
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    async fn initialize(&self) -> Result<(), McpError> {
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {"name": "fizz", "version": env!("CARGO_PKG_VERSION")},
+                }),
+            )
+            .await?;
+        // Computed outside the macro, which brings tracing's own `Value` into
+        // scope.
+        let protocol_version = result
+            .get("protocolVersion")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown");
+        let server_info = result.get("serverInfo").unwrap_or(&Value::Null);
+        debug!(
+            mcp_server = %self.server,
+            protocol_version,
+            %server_info,
+            "initialized mcp server"
+        );
+        self.notify("notifications/initialized", json!({})).await
+    }
+
+    /// Lists every tool the server offers, following pagination cursors.
+    pub async fn list_tools(&self) -> Result<Vec<McpTool>, McpError> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request("tools/list", params).await?;
+            let page: Vec<McpTool> = serde_json::from_value(
+                result
+                    .get("tools")
+                    .cloned()
+                    .unwrap_or(Value::Array(Vec::new())),
+            )
+            .map_err(|err| self.invalid_response(format!("bad tools/list result: {err}")))?;
+            tools.extend(page);
+            cursor = result
+                .get("nextCursor")
+                .and_then(Value::as_str)
+                .map(str::to_string);
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        arguments: &Map<String, Value>,
+    ) -> Result<McpToolResult, McpError> {
+        let result = self
+            .request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await?;
+        serde_json::from_value(result)
+            .map_err(|err| self.invalid_response(format!("bad tools/call result: {err}")))
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, McpError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut pending = lock(&self.pending);
+            if pending.closed {
+                return Err(self.closed());
+            }
+            pending.senders.insert(id, sender);
+        }
+        // Removes the entry when the response never comes, e.g. on timeout or
+        // when the caller is cancelled.
+        let _guard = PendingGuard {
+            pending: &self.pending,
+            id,
+        };
+
+        let message = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+        write_message(&self.writer, &message)
+            .await
+            .map_err(|source| McpError::Io {
+                server: self.server.clone(),
+                source,
+            })?;
+
+        match tokio::time::timeout(self.request_timeout, receiver).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(self.closed()),
+            Err(_) => {
+                let _ = self
+                    .notify(
+                        "notifications/cancelled",
+                        json!({"requestId": id, "reason": "request timed out"}),
+                    )
+                    .await;
+                Err(McpError::Timeout {
+                    server: self.server.clone(),
+                    method: method.to_string(),
+                    timeout_secs: self.request_timeout.as_secs(),
+                })
+            }
+        }
+    }
+
+    async fn notify(&self, method: &str, params: Value) -> Result<(), McpError> {
+        let message = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        write_message(&self.writer, &message)
+            .await
+            .map_err(|source| McpError::Io {
+                server: self.server.clone(),
+                source,
+            })
+    }
+
+    fn closed(&self) -> McpError {
+        McpError::Closed {
+            server: self.server.clone(),
+        }
+    }
+
+    fn invalid_response(&self, message: String) -> McpError {
+        McpError::InvalidResponse {
+            server: self.server.clone(),
+            message,
+        }
+    }
+}
+
+impl Drop for McpClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+struct PendingGuard<'a> {
+    pending: &'a SharedPending,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        lock(self.pending).senders.remove(&self.id);
+    }
+}
+
+async fn write_message(writer: &Writer, message: &Value) -> std::io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut writer = writer.lock().await;
+    writer.write_all(line.as_bytes()).await?;
+    writer.flush().await
+}
+
+/// Routes everything the server writes: responses to their waiting requests,
+/// server requests to [`answer_server_request`], notifications to the log.
+/// When stdout closes, pending and later requests fail with
+/// [`McpError::Closed`].
+async fn read_messages(
+    server: String,
+    stdout: ChildStdout,
+    writer: Writer,
+    pending: SharedPending,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                warn!(mcp_server = %server, error = %err, "failed to read from mcp server");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!(mcp_server = %server, error = %err, "mcp server wrote invalid JSON");
+                continue;
+            }
+        };
+
+        match (
+            message.get("method").and_then(Value::as_str),
+            message.get("id"),
+        ) {
+            (Some(method), Some(id)) => {
+                let reply = answer_server_request(method, id);
+                if let Err(err) = write_message(&writer, &reply).await {
+                    warn!(mcp_server = %server, error = %err, "failed to answer mcp server request");
+                }
+            }
+            (Some(method), None) => debug!(mcp_server = %server, method, "mcp notification"),
+            (None, Some(id)) => {
+                let Some(id) = id.as_u64() else {
+                    warn!(mcp_server = %server, %id, "mcp response has an unknown id");
+                    continue;
+                };
+                let result = response_result(&server, &message);
+                if let Some(sender) = lock(&pending).senders.remove(&id) {
+                    let _ = sender.send(result);
+                }
+            }
+            (None, None) => {
+                warn!(mcp_server = %server, "mcp server wrote a message without id or method")
+            }
+        }
+    }
+
+    debug!(mcp_server = %server, "mcp server closed its output");
+    let mut pending = lock(&pending);
+    pending.closed = true;
+    for (_, sender) in pending.senders.drain() {
+        let _ = sender.send(Err(McpError::Closed {
+            server: server.clone(),
+        }));
+    }
+}
+
+/// fizz offers no client capabilities, so it only answers `ping`.
+fn answer_server_request(method: &str, id: &Value) -> Value {
+    if method == "ping" {
+        return json!({"jsonrpc": "2.0", "id": id, "result": {}});
+    }
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": METHOD_NOT_FOUND, "message": format!("method '{method}' is not supported")},
+    })
+}
+
+fn response_result(server: &str, message: &Value) -> Result<Value, McpError> {
+    if let Some(error) = message.get("error") {
+        return Err(McpError::Rpc {
+            server: server.to_string(),
+            code: error
+                .get("code")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error")
+                .to_string(),
+        });
+    }
+    Ok(message.get("result").cloned().unwrap_or(Value::Null))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{McpToolResult, answer_server_request, response_result};
+    use crate::mcp::McpError;
+
+    #[test]
+    fn tool_result_text_renders_each_content_block() {
+        let result: McpToolResult = serde_json::from_value(json!({
+            "content": [
+                {"type": "text", "text": "ticket FIZZ-1 created"},
+                {"type": "image", "data": "iVBORw==", "mimeType": "image/png"},
+                {"type": "resource", "resource": {"uri": "db://rows/1", "text": "id=1"}},
+                {"type": "resource_link", "uri": "https://tickets/FIZZ-1", "name": "FIZZ-1"}
+            ]
+        }))
+        .expect("result should deserialize");
+
+        assert_eq!(
+            result.text(),
+            "ticket FIZZ-1 created\n[image content: image/png]\nid=1\n[resource: https://tickets/FIZZ-1]"
+        );
+        assert!(!result.is_error);
+    }
+
+    #[test]
+    fn tool_result_text_falls_back_to_structured_content() {
+        let result: McpToolResult = serde_json::from_value(json!({
+            "content": [],
+            "structuredContent": {"rows": 2}
+        }))
+        .expect("result should deserialize");
+
+        assert_eq!(result.text(), r#"{"rows":2}"#);
+    }
+
+    #[test]
+    fn server_requests_get_ping_answers_or_method_not_found() {
+        assert_eq!(
+            answer_server_request("ping", &json!(7)),
+            json!({"jsonrpc": "2.0", "id": 7, "result": {}})
+        );
+        assert_eq!(
+            answer_server_request("sampling/createMessage", &json!("a"))["error"]["code"],
+            -32601
+        );
+    }
+
+    #[test]
+    fn response_result_maps_json_rpc_errors() {
+        let err = response_result(
+            "tickets",
+            &json!({"jsonrpc": "2.0", "id": 1, "error": {"code": -32602, "message": "bad params"}}),
+        )
+        .expect_err("error responses should fail");
+
+        assert!(matches!(err, McpError::Rpc { code: -32602, .. }));
+        assert_eq!(
+            err.to_string(),
+            "MCP server 'tickets' returned error -32602: bad params"
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Tools from Model Context Protocol servers.
+//!
+//! Servers are listed in the JSON file at `MCP_CONFIG`, in the
+//! `{"mcpServers": {"<name>": {"command": ..., "args": [...], "env": {...}}}}`
+//! format other MCP clients use. Each server runs as a child process spoken
+//! to over stdio; its tools are offered to the model as `<name>.<tool>`.
+//!
+//! In the other direction, [`serve`] offers a runner's tools to MCP clients,
+//! which is what `fizz mcp-serve` does.
+
+mod client;
+mod server;
+
+pub use client::{McpClient, McpTool, McpToolResult, PROTOCOL_VERSION};
+pub use server::{serve, serve_stdio};
+
+use futures_util::future::join_all;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::agent::tools::{
+    ToolCall, ToolExecutionError, ToolFuture, ToolOutput, ToolRunner, ToolSpec,
+};
+
+/// Why an MCP server could not be configured, started or used.
+#[derive(Debug)]
+pub enum McpError {
+    Config {
+        path: PathBuf,
+        message: String,
+    },
+    Spawn {
+        server: String,
+        command: String,
+        source: io::Error,
+    },
+    Io {
+        server: String,
+        source: io::Error,
+    },
+    /// The server exited or closed its output.
+    Closed {
+        server: String,
+    },
+    Timeout {
+        server: String,
+        method: String,
+        timeout_secs: u64,
+    },
+    /// The server answered with a JSON-RPC error.
+    Rpc {
+        server: String,
+        code: i64,
+        message: String,
+    },
+    InvalidResponse {
+        server: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for McpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config { path, message } => {
+                write!(f, "Invalid MCP config '{}': {message}", path.display())
+            }
+            Self::Spawn {
+                server,
+                command,
+                source,
+            } => write!(
+                f,
+                "Failed to start MCP server '{server}' ({command}): {source}"
+            ),
+            Self::Io { server, source } => {
+                write!(f, "Failed to write to MCP server '{server}': {source}")
+            }
+            Self::Closed { server } => write!(f, "MCP server '{server}' closed the connection"),
+            Self::Timeout {
+                server,
+                method,
+                timeout_secs,
+            } => write!(
+                f,
+                "MCP server '{server}' did not answer {method} within {timeout_secs}s"
+            ),
+            Self::Rpc {
+                server,
+                code,
+                message,
+            } => write!(f, "MCP server '{server}' returned error {code}: {message}"),
+            Self::InvalidResponse { server, message } => {
+                write!(
+                    f,
+                    "MCP server '{server}' sent an invalid response: {message}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for McpError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Spawn { source, .. } | Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// How to start one MCP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McpServerConfig {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    /// Set on top of fizz's own environment.
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct McpConfigFile {
+    #[serde(default)]
+    mcp_servers: BTreeMap<String, McpServerEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct McpServerEntry {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+/// Reads the servers listed at `path`, ordered by name. A missing file means
+/// no servers.
+pub fn load_server_configs(path: &Path) -> Result<Vec<McpServerConfig>, McpError> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(McpError::Config {
+                path: path.to_path_buf(),
+                message: err.to_string(),
+            });
+        }
+    };
+    parse_server_configs(&raw).map_err(|message| McpError::Config {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+fn parse_server_configs(raw: &str) -> Result<Vec<McpServerConfig>, String> {
+    let file: McpConfigFile = serde_json::from_str(raw).map_err(|err| err.to_string())?;
+    file.mcp_servers
+        .into_iter()
+        .map(|(name, entry)| {
+            if name.trim().is_empty() || name.contains('.') {
+                return Err(format!(
+                    "server name '{name}' must be non-empty and contain no '.'"
+                ));
+            }
+            if entry.command.trim().is_empty() {
+                return Err(format!("server '{name}' has an empty command"));
+            }
+            Ok(McpServerConfig {
+                name,
+                command: entry.command,
+                args: entry.args,
+                env: entry.env,
+            })
+        })
+        .collect()
+}
+
+/// Offers the tools of every connected MCP server and forwards calls to
+/// them as `tools/call`.
+#[derive(Default)]
+pub struct McpRunner {
+    clients: Vec<McpClient>,
+    specs: Vec<ToolSpec>,
+    /// Exposed tool name to (index into `clients`, the server's tool name).
+    routes: HashMap<String, (usize, String)>,
+}
+
+impl McpRunner {
+    /// Starts and initializes every server concurrently and lists its tools.
+    /// Servers that fail are logged, returned and left out, so one broken
+    /// server does not take the others down.
+    pub async fn connect(
+        configs: &[McpServerConfig],
+        request_timeout: Duration,
+    ) -> (Self, Vec<McpError>) {
+        let results = join_all(configs.iter().map(|config| async move {
+            let client = McpClient::connect(config, request_timeout).await?;
+            let tools = client.list_tools().await?;
+            Ok::<_, McpError>((client, tools))
+        }))
+        .await;
+
+        let mut runner = Self::default();
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok((client, tools)) => {
+                    info!(
+                        mcp_server = client.server(),
+                        tool_count = tools.len(),
+                        "connected mcp server"
+                    );
+                    runner.add(client, tools);
+                }
+                Err(err) => {
+                    warn!(error = %err, "skipping mcp server");
+                    errors.push(err);
+                }
+            }
+        }
+        (runner, errors)
+    }
+
+    fn add(&mut self, client: McpClient, tools: Vec<McpTool>) {
+        let index = self.clients.len();
+        for tool in tools {
+            let name = format!("{}.{}", client.server(), tool.name);
+            let description = tool
+                .description
+                .filter(|description| !description.trim().is_empty())
+                .unwrap_or_else(|| {
+                    format!(
+                        "'{}' tool from MCP server '{}'.",
+                        tool.name,
+                        client.server()
+                    )
+                });
+            let mut spec = ToolSpec::new(&name, description);
+            if let Some(schema) = tool.input_schema.filter(has_properties) {
+                spec = spec.with_parameters(schema);
+            }
+            self.specs.push(spec);
+            self.routes.insert(name, (index, tool.name));
+        }
+        self.clients.push(client);
+    }
+}
+
+/// Schemas without properties describe tools without arguments, which
+/// [`ToolSpec`] expresses as no schema.
+fn has_properties(schema: &Value) -> bool {
+    schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .is_some_and(|properties| !properties.is_empty())
+}
+
+impl ToolRunner for McpRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        self.specs.clone()
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let (index, tool) = self
+                .routes
+                .get(&call.name)
+                .ok_or_else(|| ToolExecutionError::new(format!("unknown tool '{}'", call.name)))?;
+            let result = self.clients[*index]
+                .call_tool(tool, &call.arguments)
+                .await
+                .map_err(|err| ToolExecutionError::new(err.to_string()))?;
+            if result.is_error {
+                return Err(ToolExecutionError::new(format!(
+                    "{} failed: {}",
+                    call.name,
+                    result.text()
+                )));
+            }
+            Ok(ToolOutput::new(result.text()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::path::Path;
+
+    use super::{has_properties, load_server_configs, parse_server_configs};
+
+    #[test]
+    fn parse_server_configs_reads_the_mcp_servers_object() {
+        let configs = parse_server_configs(
+            r#"{"mcpServers": {
+                "tickets": {"command": "tickets-mcp", "args": ["--stdio"], "env": {"TOKEN": "t"}},
+                "db": {"command": "db-mcp"}
+            }}"#,
+        )
+        .expect("config should parse");
+
+        assert_eq!(configs.len(), 2);
+        assert_eq!(configs[0].name, "db");
+        assert!(configs[0].args.is_empty());
+        assert_eq!(configs[1].name, "tickets");
+        assert_eq!(configs[1].args, vec!["--stdio".to_string()]);
+        assert_eq!(configs[1].env.get("TOKEN").map(String::as_str), Some("t"));
+    }
+
+    #[test]
+    fn parse_server_configs_rejects_dotted_names_and_empty_commands() {
+        let err = parse_server_configs(r#"{"mcpServers": {"a.b": {"command": "x"}}}"#)
+            .expect_err("dotted names should fail");
+        assert!(err.contains("'a.b'"));
+
+        let err = parse_server_configs(r#"{"mcpServers": {"db": {"command": " "}}}"#)
+            .expect_err("empty commands should fail");
+        assert!(err.contains("empty command"));
+
+        assert!(parse_server_configs(r#"{"mcpServers": {"db": {}}}"#).is_err());
+    }
+
+    #[test]
+    fn load_server_configs_treats_a_missing_file_as_no_servers() {
+        let configs = load_server_configs(Path::new("/nonexistent/fizz/mcp.json"))
+            .expect("missing file should not fail");
+        assert!(configs.is_empty());
+    }
+
+    #[test]
+    fn has_properties_ignores_empty_schemas() {
+        assert!(!has_properties(&json!({"type": "object"})));
+        assert!(!has_properties(
+            &json!({"type": "object", "properties": {}})
+        ));
+        assert!(has_properties(
+            &json!({"type": "object", "properties": {"q": {"type": "string"}}})
+        ));
+    }
+}
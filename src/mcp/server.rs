@@ -0,0 +1,457 @@
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::{debug, info, warn};
+
+use super::PROTOCOL_VERSION;
+use crate::agent::tools::{ToolCall, ToolRunner, ToolSpec};
+
+/// Revisions a client may ask for; any other request gets
+/// [`PROTOCOL_VERSION`].
+const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &[PROTOCOL_VERSION, "2025-03-26", "2024-11-05"];
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves `runner`'s tools over MCP on stdin and stdout until stdin closes.
+pub async fn serve_stdio(runner: Arc<dyn ToolRunner>, tool_timeout: Duration) -> io::Result<()> {
+    serve(
+        runner,
+        tool_timeout,
+        tokio::io::BufReader::new(tokio::io::stdin()),
+        tokio::io::stdout(),
+    )
+    .await
+}
+
+/// Serves `runner`'s tools as an MCP server speaking newline-delimited
+/// JSON-RPC, until `input` closes. Tool calls run concurrently, each limited
+/// to `tool_timeout`; `notifications/cancelled` aborts one. Calls still
+/// running when `input` closes are aborted.
+pub async fn serve<R, W>(
+    runner: Arc<dyn ToolRunner>,
+    tool_timeout: Duration,
+    input: R,
+    output: W,
+) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Send + Unpin + 'static,
+{
+    let output = Arc::new(tokio::sync::Mutex::new(output));
+    let mut lines = input.lines();
+    let mut calls = JoinSet::new();
+    // Running calls by request id, for cancellation.
+    let mut running: HashMap<String, AbortHandle> = HashMap::new();
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            Some(finished) = calls.join_next(), if !calls.is_empty() => {
+                if let Ok(id) = finished {
+                    running.remove(&id);
+                }
+                continue;
+            }
+        };
+        let Some(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                write_message(
+                    &output,
+                    &error_response(&Value::Null, PARSE_ERROR, &format!("invalid JSON: {err}")),
+                )
+                .await?;
+                continue;
+            }
+        };
+        let id = message.get("id").cloned();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // fizz sends no requests, so responses from the client are stray.
+            if message.get("result").is_none() && message.get("error").is_none() {
+                let id = id.unwrap_or(Value::Null);
+                write_message(
+                    &output,
+                    &error_response(&id, INVALID_REQUEST, "message has no method"),
+                )
+                .await?;
+            }
+            continue;
+        };
+
+        let Some(id) = id else {
+            if method == "notifications/cancelled"
+                && let Some(request_id) = params.get("requestId")
+                && let Some(call) = running.remove(&request_id.to_string())
+            {
+                debug!(request_id = %request_id, "cancelling mcp tool call");
+                call.abort();
+            }
+            continue;
+        };
+
+        if method == "tools/call" {
+            match tool_call(runner.as_ref(), &params) {
+                Ok(call) => {
+                    let key = id.to_string();
+                    let runner = Arc::clone(&runner);
+                    let output = Arc::clone(&output);
+                    let handle = calls.spawn(async move {
+                        let result = run_tool_call(runner.as_ref(), &call, tool_timeout).await;
+                        let response = json!({"jsonrpc": "2.0", "id": id, "result": result});
+                        if let Err(err) = write_message(&output, &response).await {
+                            warn!(error = %err, "failed to write mcp tool result");
+                        }
+                        id.to_string()
+                    });
+                    running.insert(key, handle);
+                }
+                Err(message) => {
+                    write_message(&output, &error_response(&id, INVALID_PARAMS, &message)).await?;
+                }
+            }
+            continue;
+        }
+
+        let response = match answer_request(runner.as_ref(), method, &params) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err((code, message)) => error_response(&id, code, &message),
+        };
+        write_message(&output, &response).await?;
+    }
+
+    debug!(running_calls = calls.len(), "mcp client closed its input");
+    Ok(())
+}
+
+/// Answers every request except `tools/call`.
+fn answer_request(
+    runner: &dyn ToolRunner,
+    method: &str,
+    params: &Value,
+) -> Result<Value, (i64, String)> {
+    match method {
+        "initialize" => {
+            let requested = params
+                .get("protocolVersion")
+                .and_then(Value::as_str)
+                .unwrap_or(PROTOCOL_VERSION);
+            let version = if SUPPORTED_PROTOCOL_VERSIONS.contains(&requested) {
+                requested
+            } else {
+                PROTOCOL_VERSION
+            };
+            let client = params.get("clientInfo").unwrap_or(&Value::Null);
+            info!(
+                %client,
+                protocol_version = version,
+                "mcp client connected"
+            );
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": {"tools": {"listChanged": false}},
+                "serverInfo": {"name": "fizz", "version": env!("CARGO_PKG_VERSION")},
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => {
+            let tools: Vec<Value> = runner.tools().iter().map(tool_json).collect();
+            Ok(json!({ "tools": tools }))
+        }
+        _ => Err((
+            METHOD_NOT_FOUND,
+            format!("method '{method}' is not supported"),
+        )),
+    }
+}
+
+fn tool_json(spec: &ToolSpec) -> Value {
+    json!({
+        "name": spec.name,
+        "description": spec.description,
+        "inputSchema": spec
+            .parameters
+            .clone()
+            .unwrap_or_else(|| json!({"type": "object", "properties": {}})),
+    })
+}
+
+/// Validates `tools/call` params against the runner's tools.
+fn tool_call(runner: &dyn ToolRunner, params: &Value) -> Result<ToolCall, String> {
+    let Some(name) = params.get("name").and_then(Value::as_str) else {
+        return Err("tools/call requires a string 'name'".to_string());
+    };
+    let arguments = match params.get("arguments") {
+        None | Some(Value::Null) => Map::new(),
+        Some(Value::Object(arguments)) => arguments.clone(),
+        Some(_) => return Err("tools/call 'arguments' must be an object".to_string()),
+    };
+    if !runner.tools().iter().any(|spec| spec.name == name) {
+        return Err(format!("unknown tool '{name}'"));
+    }
+    Ok(ToolCall::new(name).with_arguments(arguments))
+}
+
+/// Runs a call and shapes the outcome as a `tools/call` result. Tool
+/// failures are results with `isError` set, so the calling model sees them.
+async fn run_tool_call(runner: &dyn ToolRunner, call: &ToolCall, timeout: Duration) -> Value {
+    debug!(tool_name = %call.name, "running tool for mcp client");
+    let (text, is_error) = match tokio::time::timeout(timeout, runner.execute(call)).await {
+        Ok(Ok(output)) => (output.content, false),
+        Ok(Err(err)) => (err.to_string(), true),
+        Err(_) => (
+            format!("{} timed out after {}s", call.name, timeout.as_secs()),
+            true,
+        ),
+    };
+    json!({
+        "content": [{"type": "text", "text": text}],
+        "isError": is_error,
+    })
+}
+
+fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}})
+}
+
+async fn write_message<W>(output: &tokio::sync::Mutex<W>, message: &Value) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut output = output.lock().await;
+    output.write_all(line.as_bytes()).await?;
+    output.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+
+    use super::serve;
+    use crate::agent::tools::{
+        BuiltinRunner, CompositeRunner, ToolCall, ToolExecutionError, ToolFuture, ToolOutput,
+        ToolRunner, ToolSpec,
+    };
+
+    /// `test.echo` echoes `text`, `test.fail` fails and `test.wait` never
+    /// finishes.
+    struct TestRunner;
+
+    impl ToolRunner for TestRunner {
+        fn tools(&self) -> Vec<ToolSpec> {
+            vec![
+                ToolSpec::new("test.echo", "echoes.").with_parameters(json!({
+                    "type": "object",
+                    "properties": {"text": {"type": "string"}}
+                })),
+                ToolSpec::new("test.fail", "fails."),
+                ToolSpec::new("test.wait", "waits."),
+            ]
+        }
+
+        fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+            Box::pin(async move {
+                match call.name.as_str() {
+                    "test.echo" => Ok(ToolOutput::new(call.required_str("text")?)),
+                    "test.wait" => std::future::pending().await,
+                    _ => Err(ToolExecutionError::new("test.fail failed")),
+                }
+            })
+        }
+    }
+
+    struct Client {
+        input: DuplexStream,
+        output: Lines<BufReader<DuplexStream>>,
+    }
+
+    impl Client {
+        async fn send(&mut self, message: Value) {
+            let mut line = message.to_string();
+            line.push('\n');
+            self.input
+                .write_all(line.as_bytes())
+                .await
+                .expect("write should succeed");
+        }
+
+        async fn receive(&mut self) -> Value {
+            let line = tokio::time::timeout(Duration::from_secs(5), self.output.next_line())
+                .await
+                .expect("server should answer")
+                .expect("read should succeed")
+                .expect("server should not close");
+            serde_json::from_str(&line).expect("server should write JSON")
+        }
+
+        async fn request(&mut self, id: u64, method: &str, params: Value) -> Value {
+            self.send(json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))
+                .await;
+            self.receive().await
+        }
+    }
+
+    fn start(tool_timeout: Duration) -> (Client, tokio::task::JoinHandle<std::io::Result<()>>) {
+        let (client_input, server_input) = tokio::io::duplex(4096);
+        let (server_output, client_output) = tokio::io::duplex(4096);
+        let runner = CompositeRunner::new()
+            .with(Box::new(BuiltinRunner))
+            .with(Box::new(TestRunner));
+        let server = tokio::spawn(serve(
+            Arc::new(runner),
+            tool_timeout,
+            BufReader::new(server_input),
+            server_output,
+        ));
+        let client = Client {
+            input: client_input,
+            output: BufReader::new(client_output).lines(),
+        };
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn serve_initializes_and_lists_the_runner_tools() {
+        let (mut client, server) = start(Duration::from_secs(5));
+
+        let response = client
+            .request(
+                1,
+                "initialize",
+                json!({"protocolVersion": "2024-11-05", "capabilities": {}}),
+            )
+            .await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+        assert_eq!(response["result"]["serverInfo"]["name"], "fizz");
+        client
+            .send(json!({"jsonrpc": "2.0", "method": "notifications/initialized"}))
+            .await;
+
+        let response = client.request(2, "tools/list", json!({})).await;
+        let tools = response["result"]["tools"]
+            .as_array()
+            .expect("tools should be a list");
+        let names: Vec<&str> = tools
+            .iter()
+            .map(|tool| tool["name"].as_str().expect("tool has a name"))
+            .collect();
+        assert_eq!(names, ["time.now", "test.echo", "test.fail", "test.wait"]);
+        assert_eq!(
+            tools[0]["inputSchema"],
+            json!({"type": "object", "properties": {}})
+        );
+        assert_eq!(
+            tools[1]["inputSchema"]["properties"]["text"]["type"],
+            "string"
+        );
+
+        let response = client.request(3, "ping", json!({})).await;
+        assert_eq!(response["result"], json!({}));
+
+        drop(client);
+        server
+            .await
+            .expect("server task should finish")
+            .expect("server should stop cleanly");
+    }
+
+    #[tokio::test]
+    async fn serve_maps_tool_outcomes_to_results_and_errors() {
+        let (mut client, _server) = start(Duration::from_secs(1));
+
+        let response = client
+            .request(
+                1,
+                "tools/call",
+                json!({"name": "test.echo", "arguments": {"text": "hi"}}),
+            )
+            .await;
+        assert_eq!(
+            response["result"],
+            json!({"content": [{"type": "text", "text": "hi"}], "isError": false})
+        );
+
+        let response = client
+            .request(2, "tools/call", json!({"name": "test.fail"}))
+            .await;
+        assert_eq!(response["result"]["isError"], true);
+        assert_eq!(response["result"]["content"][0]["text"], "test.fail failed");
+
+        let response = client
+            .request(3, "tools/call", json!({"name": "test.wait"}))
+            .await;
+        assert_eq!(response["result"]["isError"], true);
+        assert_eq!(
+            response["result"]["content"][0]["text"],
+            "test.wait timed out after 1s"
+        );
+
+        let response = client
+            .request(4, "tools/call", json!({"name": "shell.run"}))
+            .await;
+        assert_eq!(response["error"]["code"], -32602);
+        assert_eq!(response["error"]["message"], "unknown tool 'shell.run'");
+
+        let response = client.request(5, "resources/list", json!({})).await;
+        assert_eq!(response["error"]["code"], -32601);
+    }
+
+    #[tokio::test]
+    async fn serve_keeps_answering_while_a_call_runs_and_cancels_it_on_request() {
+        let (mut client, _server) = start(Duration::from_secs(60));
+
+        client
+            .send(json!({"jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": {"name": "test.wait"}}))
+            .await;
+        let response = client.request(2, "ping", json!({})).await;
+        assert_eq!(response["id"], 2);
+
+        client
+            .send(json!({"jsonrpc": "2.0", "method": "notifications/cancelled", "params": {"requestId": 1}}))
+            .await;
+        let response = client
+            .request(
+                3,
+                "tools/call",
+                json!({"name": "test.echo", "arguments": {"text": "after"}}),
+            )
+            .await;
+        // The cancelled call never answers, so the next response is call 3's.
+        assert_eq!(response["id"], 3);
+        assert_eq!(response["result"]["content"][0]["text"], "after");
+    }
+
+    #[tokio::test]
+    async fn serve_reports_invalid_json() {
+        let (mut client, _server) = start(Duration::from_secs(5));
+
+        client
+            .input
+            .write_all(b"{not json\n")
+            .await
+            .expect("write should succeed");
+        let response = client.receive().await;
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], -32700);
+    }
+}
@@ -0,0 +1,591 @@
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, warn};
+
+use crate::agent::tools::{
+    ToolCall, ToolExecutionError, ToolFuture, ToolOutput, ToolRunner, ToolSpec,
+};
+use crate::atomic_write::{WriteError, write_atomic};
+use crate::model_gateway::ModelGateway;
+use crate::retrieval::cosine_similarity;
+
+const DEFAULT_SEARCH_LIMIT: usize = 5;
+const MAX_SEARCH_LIMIT: usize = 20;
+/// Query words shorter than this are ignored by keyword ranking.
+const MIN_KEYWORD_LEN: usize = 3;
+
+/// Why the memory store could not be read or written.
+#[derive(Debug)]
+pub enum MemoryError {
+    Io { path: PathBuf, source: io::Error },
+    Corrupt { path: PathBuf, message: String },
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(
+                    f,
+                    "Failed to access memory store '{}': {source}",
+                    path.display()
+                )
+            }
+            Self::Corrupt { path, message } => write!(
+                f,
+                "Memory store '{}' is unreadable ({message}); fix or delete it",
+                path.display()
+            ),
+        }
+    }
+}
+
+impl Error for MemoryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Corrupt { .. } => None,
+        }
+    }
+}
+
+/// A remembered fact.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    pub id: u64,
+    pub text: String,
+    /// RFC 3339 UTC timestamp.
+    pub created_at: String,
+    /// Present when the memory was saved with embeddings enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MemoryFile {
+    next_id: u64,
+    memories: Vec<MemoryEntry>,
+}
+
+/// Long-term memories kept in a JSON file, shared by the memory tools, the
+/// turn engine and the REPL. Every change is written through to disk.
+pub struct MemoryStore {
+    path: PathBuf,
+    file: Mutex<MemoryFile>,
+    /// Embeds memories and queries when embedding search is enabled.
+    gateway: Option<Arc<dyn ModelGateway>>,
+}
+
+impl MemoryStore {
+    /// Opens the store at `path`; a missing file is an empty store.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, MemoryError> {
+        let path = path.into();
+        let file = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map_err(|err| MemoryError::Corrupt {
+                path: path.clone(),
+                message: err.to_string(),
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => MemoryFile::default(),
+            Err(err) => return Err(MemoryError::Io { path, source: err }),
+        };
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+            gateway: None,
+        })
+    }
+
+    /// Ranks memories by embedding similarity, using `gateway` to embed them.
+    pub fn with_embeddings(mut self, gateway: Arc<dyn ModelGateway>) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every memory, oldest first.
+    pub fn list(&self) -> Vec<MemoryEntry> {
+        self.lock().memories.clone()
+    }
+
+    pub async fn save(&self, text: &str) -> Result<MemoryEntry, MemoryError> {
+        let text = text.trim().to_string();
+        let embedding = self.embed(&text).await;
+
+        let mut file = self.lock();
+        file.next_id = file.next_id.max(1);
+        let entry = MemoryEntry {
+            id: file.next_id,
+            text,
+            created_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            embedding,
+        };
+        file.next_id += 1;
+        file.memories.push(entry.clone());
+        self.write(&file)?;
+        debug!(memory_id = entry.id, "saved memory");
+        Ok(entry)
+    }
+
+    /// Removes the memory with `id`; returns whether it existed.
+    pub fn forget(&self, id: u64) -> Result<bool, MemoryError> {
+        let mut file = self.lock();
+        let before = file.memories.len();
+        file.memories.retain(|entry| entry.id != id);
+        if file.memories.len() == before {
+            return Ok(false);
+        }
+        self.write(&file)?;
+        debug!(memory_id = id, "forgot memory");
+        Ok(true)
+    }
+
+    /// Removes every memory; returns how many there were.
+    pub fn clear(&self) -> Result<usize, MemoryError> {
+        let mut file = self.lock();
+        let count = file.memories.len();
+        file.memories.clear();
+        self.write(&file)?;
+        Ok(count)
+    }
+
+    /// Returns up to `limit` memories related to `query`, best first.
+    pub async fn search(&self, query: &str, limit: usize) -> Vec<MemoryEntry> {
+        self.ranked(query)
+            .await
+            .into_iter()
+            .filter(|(score, _)| *score > 0.0)
+            .take(limit)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// Returns up to `limit` memories for the system messages: those related
+    /// to `input` first, then the most recent ones.
+    pub async fn relevant(&self, input: &str, limit: usize) -> Vec<MemoryEntry> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        self.ranked(input)
+            .await
+            .into_iter()
+            .take(limit)
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    /// Scores every memory against `query`, sorted by score and then by
+    /// recency. A query is scored one way throughout: by embedding
+    /// similarity when embeddings are enabled and the request succeeds,
+    /// otherwise by keyword overlap.
+    async fn ranked(&self, query: &str) -> Vec<(f32, MemoryEntry)> {
+        let memories = self.list();
+        if memories.is_empty() {
+            return Vec::new();
+        }
+        let scores = match self.similarity_scores(query, &memories).await {
+            Some(scores) => scores,
+            None => {
+                let query_words = keywords(query);
+                memories
+                    .iter()
+                    .map(|entry| keyword_score(&query_words, &entry.text))
+                    .collect()
+            }
+        };
+
+        let mut scored: Vec<(f32, MemoryEntry)> = scores.into_iter().zip(memories).collect();
+        scored
+            .sort_by(|(a_score, a), (b_score, b)| b_score.total_cmp(a_score).then(b.id.cmp(&a.id)));
+        scored
+    }
+
+    /// Cosine similarity of `query` to each memory. The query is embedded in
+    /// one request with the memories saved without an embedding. `None` when
+    /// embeddings are disabled or the request fails.
+    async fn similarity_scores(&self, query: &str, memories: &[MemoryEntry]) -> Option<Vec<f32>> {
+        let gateway = self.gateway.as_ref()?;
+        let mut texts = vec![query.to_string()];
+        texts.extend(
+            memories
+                .iter()
+                .filter(|entry| entry.embedding.is_none())
+                .map(|entry| entry.text.clone()),
+        );
+        let mut vectors = match gateway.embed(texts).await {
+            Ok(vectors) => vectors.into_iter(),
+            Err(err) => {
+                warn!(error = %err, "could not embed memory query; using keyword ranking");
+                return None;
+            }
+        };
+        let query_vector = vectors.next()?;
+        memories
+            .iter()
+            .map(|entry| match &entry.embedding {
+                Some(embedding) => Some(cosine_similarity(&query_vector, embedding)),
+                None => Some(cosine_similarity(&query_vector, &vectors.next()?)),
+            })
+            .collect()
+    }
+
+    /// Embeds `text` when embeddings are enabled. A failed request is logged
+    /// and the memory is saved without an embedding.
+    async fn embed(&self, text: &str) -> Option<Vec<f32>> {
+        let gateway = self.gateway.as_ref()?;
+        match gateway.embed(vec![text.to_string()]).await {
+            Ok(mut vectors) => vectors.pop(),
+            Err(err) => {
+                warn!(error = %err, "could not embed memory text; saving it without one");
+                None
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryFile> {
+        self.file
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self, file: &MemoryFile) -> Result<(), MemoryError> {
+        let json = serde_json::to_vec_pretty(file).map_err(|err| MemoryError::Corrupt {
+            path: self.path.clone(),
+            message: err.to_string(),
+        })?;
+        write_atomic(&self.path, &json)
+            .map_err(|WriteError { path, source }| MemoryError::Io { path, source })
+    }
+}
+
+fn keywords(text: &str) -> HashSet<String> {
+    text.split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_KEYWORD_LEN)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// The share of `query_words` that appear in `text`.
+fn keyword_score(query_words: &HashSet<String>, text: &str) -> f32 {
+    if query_words.is_empty() {
+        return 0.0;
+    }
+    let memory_words = keywords(text);
+    let matched = query_words
+        .iter()
+        .filter(|word| memory_words.contains(*word))
+        .count();
+    matched as f32 / query_words.len() as f32
+}
+
+/// Renders memories as `[#id] text` lines.
+pub fn format_memories(memories: &[MemoryEntry]) -> String {
+    memories
+        .iter()
+        .map(|entry| format!("[#{}] {}", entry.id, entry.text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Offers `memory.save`, `memory.search` and `memory.forget` over a shared
+/// [`MemoryStore`].
+pub struct MemoryRunner {
+    store: Arc<MemoryStore>,
+}
+
+impl MemoryRunner {
+    pub fn new(store: Arc<MemoryStore>) -> Self {
+        Self { store }
+    }
+
+    async fn run(&self, call: &ToolCall) -> Result<String, ToolExecutionError> {
+        let store_error = |err: MemoryError| ToolExecutionError::new(err.to_string());
+        match call.name.as_str() {
+            "memory.save" => {
+                let entry = self
+                    .store
+                    .save(call.required_str("text")?)
+                    .await
+                    .map_err(store_error)?;
+                Ok(format!("Saved memory #{}.", entry.id))
+            }
+            "memory.search" => {
+                let limit = call
+                    .arguments
+                    .get("limit")
+                    .and_then(|limit| limit.as_u64())
+                    .map_or(DEFAULT_SEARCH_LIMIT, |limit| limit as usize)
+                    .clamp(1, MAX_SEARCH_LIMIT);
+                let memories = self.store.search(call.required_str("query")?, limit).await;
+                if memories.is_empty() {
+                    return Ok("No matching memories.".to_string());
+                }
+                Ok(format_memories(&memories))
+            }
+            "memory.forget" => {
+                let id = call
+                    .arguments
+                    .get("id")
+                    .and_then(|id| id.as_u64())
+                    .ok_or_else(|| {
+                        ToolExecutionError::new("memory.forget requires an integer argument 'id'")
+                    })?;
+                if self.store.forget(id).map_err(store_error)? {
+                    Ok(format!("Forgot memory #{id}."))
+                } else {
+                    Err(ToolExecutionError::new(format!("no memory with id {id}")))
+                }
+            }
+            _ => Err(ToolExecutionError::new(format!(
+                "unknown tool '{}'",
+                call.name
+            ))),
+        }
+    }
+}
+
+impl ToolRunner for MemoryRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        vec![
+            ToolSpec::new(
+                "memory.save",
+                "remembers a fact for later sessions, such as a user preference or a project convention.",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "required": ["text"],
+                "properties": {"text": {"type": "string"}}
+            })),
+            ToolSpec::new(
+                "memory.search",
+                "finds remembered facts related to a query, with their ids.",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "required": ["query"],
+                "properties": {
+                    "query": {"type": "string"},
+                    "limit": {"type": "integer", "minimum": 1, "maximum": MAX_SEARCH_LIMIT}
+                }
+            })),
+            ToolSpec::new(
+                "memory.forget",
+                "deletes a remembered fact that is wrong or outdated, by id.",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "required": ["id"],
+                "properties": {"id": {"type": "integer"}}
+            })),
+        ]
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        Box::pin(async move { self.run(call).await.map(ToolOutput::new) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::{MemoryError, MemoryRunner, MemoryStore, format_memories};
+    use crate::agent::tools::{ToolCall, ToolRunner};
+    use crate::model_error::{ModelError, RequestKind};
+    use crate::model_gateway::{
+        ModelGateway, ModelGatewayEmbedFuture, ModelGatewayFuture, ModelGatewayRequest,
+    };
+
+    fn unique_temp_path(suffix: &str) -> PathBuf {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock should be after unix epoch")
+            .as_nanos();
+        std::env::temp_dir()
+            .join(format!(
+                "fizz-memory-{suffix}-{stamp}-{}",
+                std::process::id()
+            ))
+            .join("memory.json")
+    }
+
+    fn call(name: &str, arguments: serde_json::Value) -> ToolCall {
+        ToolCall::new(name).with_arguments(
+            arguments
+                .as_object()
+                .cloned()
+                .expect("arguments should be an object"),
+        )
+    }
+
+    #[tokio::test]
+    async fn store_persists_memories_across_opens() {
+        let path = unique_temp_path("persist");
+        let store = MemoryStore::open(&path).expect("open empty store");
+        let first = store.save(" Prefers metric units ").await.expect("save");
+        store.save("Uses tabs").await.expect("save");
+        assert_eq!(first.id, 1);
+        assert_eq!(first.text, "Prefers metric units");
+        assert!(store.forget(2).expect("forget"));
+        assert!(!store.forget(2).expect("forget again"));
+
+        let reopened = MemoryStore::open(&path).expect("reopen store");
+        assert_eq!(
+            format_memories(&reopened.list()),
+            "[#1] Prefers metric units"
+        );
+        assert_eq!(reopened.save("Third").await.expect("save").id, 3);
+
+        fs::remove_dir_all(path.parent().expect("temp dir")).ok();
+    }
+
+    #[tokio::test]
+    async fn store_reports_corrupt_file() {
+        let path = unique_temp_path("corrupt");
+        fs::create_dir_all(path.parent().expect("temp dir")).expect("create dir");
+        fs::write(&path, "not json").expect("write file");
+
+        let err = MemoryStore::open(&path)
+            .err()
+            .expect("corrupt store should not open");
+
+        assert!(matches!(err, MemoryError::Corrupt { .. }));
+        fs::remove_dir_all(path.parent().expect("temp dir")).ok();
+    }
+
+    #[tokio::test]
+    async fn relevant_ranks_keyword_matches_before_recent_memories() {
+        let path = unique_temp_path("rank");
+        let store = MemoryStore::open(&path).expect("open store");
+        for text in [
+            "Project uses rustfmt defaults",
+            "User prefers metric units",
+            "User likes short answers",
+        ] {
+            store.save(text).await.expect("save");
+        }
+
+        let relevant = store.relevant("Convert 5 miles to metric", 2).await;
+        let ids: Vec<u64> = relevant.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert!(store.relevant("anything", 0).await.is_empty());
+        assert_eq!(store.search("rustfmt config", 5).await.len(), 1);
+        assert!(store.search("weather", 5).await.is_empty());
+
+        fs::remove_dir_all(path.parent().expect("temp dir")).ok();
+    }
+
+    /// Embeds "units" and "answers" texts on separate axes.
+    struct AxisGateway;
+
+    impl ModelGateway for AxisGateway {
+        fn chat<'a>(&'a self, _request: ModelGatewayRequest) -> ModelGatewayFuture<'a> {
+            Box::pin(async {
+                Err(ModelError::Unsupported {
+                    provider: "axis".to_string(),
+                    kind: RequestKind::Chat,
+                })
+            })
+        }
+
+        fn embed<'a>(&'a self, texts: Vec<String>) -> ModelGatewayEmbedFuture<'a> {
+            let vectors = texts
+                .iter()
+                .map(|text| {
+                    if text.contains("units") || text.contains("kilometres") {
+                        vec![1.0, 0.0]
+                    } else {
+                        vec![0.0, 1.0]
+                    }
+                })
+                .collect();
+            Box::pin(async move { Ok(vectors) })
+        }
+    }
+
+    #[tokio::test]
+    async fn search_uses_embeddings_when_enabled() {
+        let path = unique_temp_path("embed");
+        let store = MemoryStore::open(&path)
+            .expect("open store")
+            .with_embeddings(Arc::new(AxisGateway));
+        store.save("User likes short answers").await.expect("save");
+        let units = store.save("User prefers metric units").await.expect("save");
+        assert_eq!(units.embedding, Some(vec![1.0, 0.0]));
+
+        let hits = store.search("distance in kilometres", 1).await;
+
+        assert_eq!(hits[0].id, units.id);
+        fs::remove_dir_all(path.parent().expect("temp dir")).ok();
+    }
+
+    #[tokio::test]
+    async fn search_embeds_memories_saved_without_an_embedding() {
+        let path = unique_temp_path("backfill");
+        let plain = MemoryStore::open(&path).expect("open store");
+        let units = plain.save("User prefers metric units").await.expect("save");
+        assert_eq!(units.embedding, None);
+        let store = MemoryStore::open(&path)
+            .expect("reopen store")
+            .with_embeddings(Arc::new(AxisGateway));
+        store.save("User likes short answers").await.expect("save");
+
+        let hits = store.search("distance in kilometres", 1).await;
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].id, units.id);
+        fs::remove_dir_all(path.parent().expect("temp dir")).ok();
+    }
+
+    #[tokio::test]
+    async fn memory_tools_save_search_and_forget() {
+        let path = unique_temp_path("tools");
+        let store = Arc::new(MemoryStore::open(&path).expect("open store"));
+        let runner = MemoryRunner::new(Arc::clone(&store));
+
+        let saved = runner
+            .execute(&call(
+                "memory.save",
+                serde_json::json!({"text": "Deploys run on Fridays"}),
+            ))
+            .await
+            .expect("save should succeed");
+        assert_eq!(saved.content, "Saved memory #1.");
+
+        let found = runner
+            .execute(&call(
+                "memory.search",
+                serde_json::json!({"query": "when do deploys run"}),
+            ))
+            .await
+            .expect("search should succeed");
+        assert_eq!(found.content, "[#1] Deploys run on Fridays");
+
+        let forgotten = runner
+            .execute(&call("memory.forget", serde_json::json!({"id": 1})))
+            .await
+            .expect("forget should succeed");
+        assert_eq!(forgotten.content, "Forgot memory #1.");
+        let missing = runner
+            .execute(&call("memory.forget", serde_json::json!({"id": 1})))
+            .await
+            .expect_err("memory is gone");
+        assert_eq!(missing.to_string(), "no memory with id 1");
+        assert!(store.list().is_empty());
+
+        fs::remove_dir_all(path.parent().expect("temp dir")).ok();
+    }
+}
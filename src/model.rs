@@ -1,15 +1,32 @@
-use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 use reqwest::Client;
-use tracing::{debug, warn};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::{Span, debug, info, warn};
 
-use crate::config::Config;
+use crate::config::{Config, GenerationOptions, ModelRole, ModelTarget};
+use crate::model_error::{ModelError, RequestKind};
 use crate::providers;
+use crate::providers::ollama_admin::{ModelDetails, OllamaAdmin};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MessageRole {
     System,
     User,
     Assistant,
+    /// Result of a tool call, kept apart from user text so models (and
+    /// injection-minded tool output) cannot pass for the user. Every provider
+    /// maps it to its native form (Ollama: a `tool` message with
+    /// `tool_name`); a new provider must do the same rather than fold it
+    /// into user text.
+    Tool {
+        tool_name: String,
+        call_id: String,
+    },
 }
 
 impl MessageRole {
@@ -18,58 +35,800 @@ impl MessageRole {
             Self::System => "system",
             Self::User => "user",
             Self::Assistant => "assistant",
+            Self::Tool { .. } => "tool",
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// An image sent along with a message, base64-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageAttachment {
+    /// MIME type, e.g. `image/png`.
+    pub media_type: String,
+    /// Base64-encoded image bytes, without a `data:` prefix.
+    pub data: String,
+}
+
+impl ImageAttachment {
+    pub fn new(media_type: impl Into<String>, bytes: &[u8]) -> Self {
+        Self {
+            media_type: media_type.into(),
+            data: BASE64.encode(bytes),
+        }
+    }
+
+    /// Reads an image file; the media type is taken from the extension
+    /// (`png`, `jpg`/`jpeg`, `gif` or `webp`).
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let media_type = image_media_type(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unsupported image type '{}': expected png, jpg, jpeg, gif or webp",
+                    path.display()
+                ),
+            )
+        })?;
+        Ok(Self::new(media_type, &std::fs::read(path)?))
+    }
+
+    /// Size of the decoded image in bytes.
+    pub fn byte_len(&self) -> usize {
+        self.data.len() / 4 * 3 - self.data.bytes().rev().take_while(|b| *b == b'=').count()
+    }
+}
+
+fn image_media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Message {
     pub role: MessageRole,
     pub content: String,
+    /// Images for vision models; providers without image support ignore them.
+    pub images: Vec<ImageAttachment>,
 }
 
 impl Message {
     pub fn system(content: impl Into<String>) -> Self {
-        Self {
-            role: MessageRole::System,
-            content: content.into(),
-        }
+        Self::with_role(MessageRole::System, content)
     }
 
     pub fn user(content: impl Into<String>) -> Self {
-        Self {
-            role: MessageRole::User,
-            content: content.into(),
-        }
+        Self::with_role(MessageRole::User, content)
     }
 
     pub fn assistant(content: impl Into<String>) -> Self {
+        Self::with_role(MessageRole::Assistant, content)
+    }
+
+    fn with_role(role: MessageRole, content: impl Into<String>) -> Self {
         Self {
-            role: MessageRole::Assistant,
+            role,
             content: content.into(),
+            images: Vec::new(),
         }
     }
+
+    pub fn with_images(mut self, images: Vec<ImageAttachment>) -> Self {
+        self.images = images;
+        self
+    }
+
+    pub fn tool(
+        tool_name: impl Into<String>,
+        call_id: impl Into<String>,
+        content: impl Into<String>,
+    ) -> Self {
+        Self::with_role(
+            MessageRole::Tool {
+                tool_name: tool_name.into(),
+                call_id: call_id.into(),
+            },
+            content,
+        )
+    }
 }
 
-pub async fn chat(client: &Client, cfg: &Config, messages: &[Message]) -> Result<String> {
-    let provider = cfg.model_provider.to_ascii_lowercase();
+/// Token counts and timings of a model call, or a sum of several.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Time the provider spent loading the model into memory.
+    pub load_duration: Duration,
+    /// Time the provider spent evaluating the prompt.
+    pub prompt_duration: Duration,
+    /// Time the provider spent generating the completion.
+    pub completion_duration: Duration,
+    /// Wall-clock time from sending the request to receiving the whole
+    /// response, including retries.
+    pub latency: Duration,
+}
+
+impl ModelUsage {
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Generation speed, when the provider reported how long generation took.
+    pub fn completion_tokens_per_sec(&self) -> Option<f64> {
+        let secs = self.completion_duration.as_secs_f64();
+        (secs > 0.0).then(|| self.completion_tokens as f64 / secs)
+    }
+
+    pub fn add(&mut self, other: &ModelUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.load_duration += other.load_duration;
+        self.prompt_duration += other.prompt_duration;
+        self.completion_duration += other.completion_duration;
+        self.latency += other.latency;
+    }
+}
+
+/// Usage summed over the model calls of a turn or a session.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UsageTotals {
+    pub model_calls: u64,
+    pub usage: ModelUsage,
+}
+
+impl UsageTotals {
+    pub fn record(&mut self, usage: &ModelUsage) {
+        self.model_calls += 1;
+        self.usage.add(usage);
+    }
+}
+
+/// Usage split by the [`ModelRole`] each model call ran as.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoleUsage {
+    by_role: BTreeMap<ModelRole, UsageTotals>,
+}
+
+impl RoleUsage {
+    pub fn record(&mut self, role: ModelRole, usage: &ModelUsage) {
+        self.by_role.entry(role).or_default().record(usage);
+    }
+
+    pub fn get(&self, role: ModelRole) -> Option<&UsageTotals> {
+        self.by_role.get(&role)
+    }
+
+    /// Roles with at least one call, in [`ModelRole::ALL`] order.
+    pub fn iter(&self) -> impl Iterator<Item = (ModelRole, &UsageTotals)> {
+        self.by_role.iter().map(|(role, totals)| (*role, totals))
+    }
+
+    /// Usage summed over every role.
+    pub fn total(&self) -> UsageTotals {
+        self.by_role
+            .values()
+            .fold(UsageTotals::default(), |mut total, totals| {
+                total.model_calls += totals.model_calls;
+                total.usage.add(&totals.usage);
+                total
+            })
+    }
+}
+
+/// What a provider returned for one chat request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProviderReply {
+    pub content: String,
+    pub usage: ModelUsage,
+}
+
+/// A model response and the target that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelReply {
+    pub content: String,
+    pub target: ModelTarget,
+    pub usage: ModelUsage,
+}
+
+/// Sends the request to the first target that answers, walking
+/// [`Config::model_targets`] in order and skipping targets that failed within
+/// the cooldown period.
+///
+/// `options` are sent to every target; callers usually pass
+/// `cfg.generation`, possibly merged with per-request overrides. When they
+/// request an output format, the response is validated against it.
+pub async fn chat(
+    client: &Client,
+    cfg: &Config,
+    messages: &[Message],
+    options: &GenerationOptions,
+) -> Result<ModelReply, ModelError> {
+    let mut chain = TargetChain::new(cfg, &cfg.model_target_health);
+    while let Some(target) = chain.next_target() {
+        let started = Instant::now();
+        match chat_target(client, cfg, &target, messages, options).await {
+            Ok(reply) => {
+                return check_output(chain.answered(target, reply, started.elapsed()), options);
+            }
+            Err(err) => chain.failed(&target, err),
+        }
+    }
+    Err(chain.into_error())
+}
+
+/// Streams a chat response, passing each content delta to `on_delta` as it
+/// arrives, and returns the full response content.
+///
+/// Targets are walked like [`chat`], except that a stream failing after it
+/// produced output is not retried elsewhere, since the output was already
+/// delivered.
+pub async fn chat_stream(
+    client: &Client,
+    cfg: &Config,
+    messages: &[Message],
+    options: &GenerationOptions,
+    on_delta: &mut (dyn FnMut(&str) + Send),
+) -> Result<ModelReply, ModelError> {
+    let mut chain = TargetChain::new(cfg, &cfg.model_target_health);
+    while let Some(target) = chain.next_target() {
+        let started = Instant::now();
+        let mut streamed = false;
+        let mut forward = |delta: &str| {
+            streamed = true;
+            on_delta(delta);
+        };
+        match chat_stream_target(client, cfg, &target, messages, options, &mut forward).await {
+            Ok(reply) => {
+                return check_output(chain.answered(target, reply, started.elapsed()), options);
+            }
+            Err(err) if streamed => {
+                chain.failed(&target, err.clone());
+                return Err(err);
+            }
+            Err(err) => chain.failed(&target, err),
+        }
+    }
+    Err(chain.into_error())
+}
+
+async fn chat_target(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    messages: &[Message],
+    options: &GenerationOptions,
+) -> Result<ProviderReply, ModelError> {
+    let provider = target.provider.to_ascii_lowercase();
 
     match provider.as_str() {
         "ollama" => {
             debug!(
                 provider = "ollama",
-                model = %cfg.model,
+                model = %target.model,
                 message_count = messages.len(),
                 "dispatching model chat request"
             );
-            providers::ollama::chat(client, cfg, messages).await
+            providers::ollama::chat(client, cfg, target, messages, options).await
+        }
+        other => Err(unsupported_provider_error(other, RequestKind::Chat)),
+    }
+}
+
+async fn chat_stream_target(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    messages: &[Message],
+    options: &GenerationOptions,
+    on_delta: &mut (dyn FnMut(&str) + Send),
+) -> Result<ProviderReply, ModelError> {
+    let provider = target.provider.to_ascii_lowercase();
+
+    match provider.as_str() {
+        "ollama" => {
+            debug!(
+                provider = "ollama",
+                model = %target.model,
+                message_count = messages.len(),
+                "dispatching streaming model chat request"
+            );
+            providers::ollama::chat_stream(client, cfg, target, messages, options, on_delta).await
+        }
+        other => Err(unsupported_provider_error(other, RequestKind::Chat)),
+    }
+}
+
+/// Rejects a reply that does not match the requested output format.
+fn check_output(reply: ModelReply, options: &GenerationOptions) -> Result<ModelReply, ModelError> {
+    if let Some(format) = &options.output_format
+        && let Err(violation) = format.check(&reply.content)
+    {
+        warn!(
+            model_target = %reply.target,
+            error = %violation,
+            "model response did not match output format"
+        );
+        return Err(ModelError::InvalidOutput {
+            message: violation.to_string(),
+        });
+    }
+    Ok(reply)
+}
+
+/// Embeds `texts` with [`Config::embedding_model_target`], sending at most
+/// `embedding_batch_size` texts per request, and returns one vector per text
+/// in input order. Embedding requests are retried like chat requests but do
+/// not fall back to the chat fallback targets.
+pub async fn embed(
+    client: &Client,
+    cfg: &Config,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, ModelError> {
+    let target = cfg.embedding_model_target();
+    let provider = target.provider.to_ascii_lowercase();
+    let mut vectors = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(cfg.embedding_batch_size.max(1)) {
+        let batch_vectors = match provider.as_str() {
+            "ollama" => providers::ollama::embed(client, cfg, &target, batch).await?,
+            "openai" => providers::openai::embed(client, cfg, &target, batch).await?,
+            other => return Err(unsupported_provider_error(other, RequestKind::Embedding)),
+        };
+        vectors.extend(batch_vectors);
+    }
+    debug!(
+        model_target = %target,
+        text_count = texts.len(),
+        "embedded texts"
+    );
+    Ok(vectors)
+}
+
+/// Checks whether the target's provider has its model installed.
+pub async fn is_model_installed(client: &Client, target: &ModelTarget) -> Result<bool, ModelError> {
+    match target.provider.to_ascii_lowercase().as_str() {
+        "ollama" => {
+            OllamaAdmin::new(client.clone(), &target.base_url, target.timeout_secs)
+                .has_model(&target.model)
+                .await
+        }
+        other => Err(unsupported_provider_error(other, RequestKind::Chat)),
+    }
+}
+
+/// Fetches what the target's provider reports about its model.
+pub async fn model_details(
+    client: &Client,
+    target: &ModelTarget,
+) -> Result<ModelDetails, ModelError> {
+    match target.provider.to_ascii_lowercase().as_str() {
+        "ollama" => {
+            OllamaAdmin::new(client.clone(), &target.base_url, target.timeout_secs)
+                .show_model(&target.model)
+                .await
+        }
+        other => Err(unsupported_provider_error(other, RequestKind::Chat)),
+    }
+}
+
+/// Finds the target whose model was missing when a request failed with
+/// [`ModelError::ModelNotFound`], directly or for one of several targets.
+pub fn missing_model_target(err: &ModelError, cfg: &Config) -> Option<ModelTarget> {
+    match err {
+        ModelError::ModelNotFound { model, .. } => Some(configured_target(cfg, model)),
+        ModelError::AllTargetsFailed { failures } => failures
+            .iter()
+            .find(|(_, err)| matches!(err, ModelError::ModelNotFound { .. }))
+            .map(|(target, _)| target.clone()),
+        _ => None,
+    }
+}
+
+/// The configured target serving `model`: a chat target, a role target or the
+/// embedding target, in that order. Unknown models are taken to be served
+/// where the primary target is.
+fn configured_target(cfg: &Config, model: &str) -> ModelTarget {
+    cfg.model_targets()
+        .into_iter()
+        .chain(ModelRole::ALL.map(|role| cfg.role_model_target(role)))
+        .chain([cfg.embedding_model_target()])
+        .find(|target| target.model == model)
+        .unwrap_or_else(|| ModelTarget {
+            model: model.to_string(),
+            ..cfg.primary_model_target()
+        })
+}
+
+/// Remembers when each target last failed, so later requests can skip it for
+/// the cooldown period. Held by [`Config::model_target_health`].
+#[derive(Debug, Default)]
+pub struct TargetHealth {
+    failed_at: Mutex<HashMap<ModelTarget, Instant>>,
+}
+
+impl TargetHealth {
+    fn is_cooling_down(&self, target: &ModelTarget, cooldown: Duration, now: Instant) -> bool {
+        self.failures()
+            .get(target)
+            .is_some_and(|failed_at| now.saturating_duration_since(*failed_at) < cooldown)
+    }
+
+    fn mark_failed(&self, target: &ModelTarget, now: Instant) {
+        self.failures().insert(target.clone(), now);
+    }
+
+    fn mark_healthy(&self, target: &ModelTarget) {
+        self.failures().remove(target);
+    }
+
+    fn failures(&self) -> MutexGuard<'_, HashMap<ModelTarget, Instant>> {
+        // The map holds plain timestamps, so a poisoned lock is still usable.
+        self.failed_at
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// One request's walk over the configured targets.
+///
+/// Healthy targets are tried first, in configured order; targets in cooldown
+/// are only tried once every healthy one has failed, so a single-target setup
+/// never refuses a request outright.
+struct TargetChain<'a> {
+    health: &'a TargetHealth,
+    cooldown: Duration,
+    pending: VecDeque<ModelTarget>,
+    failures: Vec<(ModelTarget, ModelError)>,
+}
+
+impl<'a> TargetChain<'a> {
+    fn new(cfg: &Config, health: &'a TargetHealth) -> Self {
+        let cooldown = Duration::from_secs(cfg.model_target_cooldown_secs);
+        let now = Instant::now();
+        let (healthy, cooling): (Vec<_>, Vec<_>) = cfg
+            .model_targets()
+            .into_iter()
+            .partition(|target| !health.is_cooling_down(target, cooldown, now));
+        if !cooling.is_empty() {
+            debug!(
+                cooling_targets = cooling.len(),
+                "deferring model targets in cooldown"
+            );
+        }
+
+        Self {
+            health,
+            cooldown,
+            pending: healthy.into_iter().chain(cooling).collect(),
+            failures: Vec::new(),
+        }
+    }
+
+    fn next_target(&mut self) -> Option<ModelTarget> {
+        self.pending.pop_front()
+    }
+
+    fn answered(&self, target: ModelTarget, reply: ProviderReply, latency: Duration) -> ModelReply {
+        self.health.mark_healthy(&target);
+        let usage = ModelUsage {
+            latency,
+            ..reply.usage
+        };
+        let span = Span::current();
+        span.record("provider", target.provider.as_str());
+        span.record("model", target.model.as_str());
+        span.record("model_target", tracing::field::display(&target));
+        span.record("prompt_tokens", usage.prompt_tokens);
+        span.record("completion_tokens", usage.completion_tokens);
+        span.record("latency_ms", latency.as_millis() as u64);
+        if self.failures.is_empty() {
+            debug!(model_target = %target, "model target answered");
+        } else {
+            info!(
+                model_target = %target,
+                failed_targets = self.failures.len(),
+                "fallback model target answered"
+            );
         }
-        other => {
-            warn!(provider = %other, "unsupported model provider configured");
-            Err(anyhow!(
-                "Unsupported MODEL_PROVIDER='{}'. Supported providers: ollama.",
-                other
-            ))
+        ModelReply {
+            content: reply.content,
+            target,
+            usage,
         }
     }
+
+    fn failed(&mut self, target: &ModelTarget, err: ModelError) {
+        self.health.mark_failed(target, Instant::now());
+        if !self.pending.is_empty() {
+            warn!(
+                model_target = %target,
+                cooldown_secs = self.cooldown.as_secs(),
+                error = %err,
+                "model target failed; trying next target"
+            );
+        }
+        self.failures.push((target.clone(), err));
+    }
+
+    /// With a single target its error is returned unchanged, keeping the
+    /// actionable message; otherwise every target's failure is listed.
+    fn into_error(mut self) -> ModelError {
+        if self.failures.len() == 1 {
+            let (_, err) = self.failures.remove(0);
+            return err;
+        }
+
+        ModelError::AllTargetsFailed {
+            failures: self.failures,
+        }
+    }
+}
+
+fn unsupported_provider_error(provider: &str, kind: RequestKind) -> ModelError {
+    warn!(provider = %provider, "unsupported model provider configured");
+    ModelError::Unsupported {
+        provider: provider.to_string(),
+        kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{
+        ImageAttachment, Message, TargetChain, TargetHealth, chat, embed, missing_model_target,
+    };
+    use crate::config::{Config, GenerationOptions, ModelTarget};
+    use crate::model_error::{ModelError, RequestKind};
+    use crate::output_schema::OutputFormat;
+    use crate::test_http::{StubRequest, StubResponse, serve, unused_url};
+
+    fn ollama_target(model: &str, base_url: &str) -> ModelTarget {
+        ModelTarget {
+            provider: "ollama".to_string(),
+            model: model.to_string(),
+            base_url: base_url.to_string(),
+            timeout_secs: 1,
+        }
+    }
+
+    /// A config whose primary target uses the unsupported `custom` provider,
+    /// so it fails without any network traffic.
+    fn chain_config(fallbacks: Vec<ModelTarget>) -> Config {
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.model_provider = "custom".to_string();
+        cfg.model_retry.max_attempts = 1;
+        cfg.model_fallbacks = fallbacks;
+        cfg
+    }
+
+    fn serve_one_reply(content: &'static str) -> (String, thread::JoinHandle<Vec<StubRequest>>) {
+        serve(vec![StubResponse::json(
+            200,
+            format!(r#"{{"message":{{"role":"assistant","content":"{content}"}}}}"#),
+        )])
+    }
+
+    fn chain_order(chain: &mut TargetChain<'_>) -> Vec<String> {
+        std::iter::from_fn(|| chain.next_target())
+            .map(|target| target.model)
+            .collect()
+    }
+
+    #[test]
+    fn target_chain_tries_targets_in_cooldown_last() {
+        let cfg = chain_config(vec![
+            ollama_target("second", "http://localhost:1"),
+            ollama_target("third", "http://localhost:2"),
+        ]);
+        let health = TargetHealth::default();
+        health.mark_failed(&cfg.primary_model_target(), Instant::now());
+
+        let mut chain = TargetChain::new(&cfg, &health);
+
+        assert_eq!(
+            chain_order(&mut chain),
+            vec!["second", "third", "qwen2.5:3b"]
+        );
+    }
+
+    #[test]
+    fn target_chain_restores_order_after_cooldown_or_success() {
+        let cfg = chain_config(vec![ollama_target("second", "http://localhost:1")]);
+        let health = TargetHealth::default();
+        let long_ago = Instant::now()
+            .checked_sub(Duration::from_secs(cfg.model_target_cooldown_secs + 1))
+            .expect("clock should allow subtracting the cooldown");
+        health.mark_failed(&cfg.primary_model_target(), long_ago);
+        assert_eq!(
+            chain_order(&mut TargetChain::new(&cfg, &health)),
+            vec!["qwen2.5:3b", "second"]
+        );
+
+        health.mark_failed(&cfg.primary_model_target(), Instant::now());
+        health.mark_healthy(&cfg.primary_model_target());
+        assert_eq!(
+            chain_order(&mut TargetChain::new(&cfg, &health)),
+            vec!["qwen2.5:3b", "second"]
+        );
+    }
+
+    #[test]
+    fn missing_model_target_finds_target_that_returned_not_found() {
+        let fallback = ollama_target("fallback-model", "http://remote:11434");
+        let cfg = chain_config(vec![fallback.clone()]);
+        let not_found = ModelError::ModelNotFound {
+            model: "fallback-model".to_string(),
+            provider: "ollama".to_string(),
+            body: String::new(),
+        };
+
+        assert_eq!(
+            missing_model_target(&not_found, &cfg),
+            Some(fallback.clone())
+        );
+        let embedding_not_found = ModelError::ModelNotFound {
+            model: cfg.embedding_model.clone(),
+            provider: cfg.embedding_provider.clone(),
+            body: String::new(),
+        };
+        assert_eq!(
+            missing_model_target(&embedding_not_found, &cfg),
+            Some(cfg.embedding_model_target())
+        );
+        let all_failed = ModelError::AllTargetsFailed {
+            failures: vec![
+                (
+                    cfg.primary_model_target(),
+                    ModelError::Unsupported {
+                        provider: "custom".to_string(),
+                        kind: RequestKind::Chat,
+                    },
+                ),
+                (fallback.clone(), not_found),
+            ],
+        };
+        assert_eq!(missing_model_target(&all_failed, &cfg), Some(fallback));
+        assert_eq!(
+            missing_model_target(
+                &ModelError::Stream {
+                    message: "boom".to_string()
+                },
+                &cfg
+            ),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_falls_back_to_next_target_and_reports_it() {
+        let (base_url, server) = serve_one_reply("from fallback");
+        let fallback = ollama_target("fallback-model", &base_url);
+        let cfg = chain_config(vec![fallback.clone()]);
+
+        let reply = chat(
+            &Client::new(),
+            &cfg,
+            &[Message::user("hello")],
+            &cfg.generation,
+        )
+        .await
+        .expect("fallback target should answer");
+
+        assert_eq!(reply.content, "from fallback");
+        assert_eq!(reply.target, fallback);
+        server.join().expect("server thread should join");
+    }
+
+    #[tokio::test]
+    async fn embed_splits_texts_into_batches_and_keeps_order() {
+        let (base_url, server) = serve(vec![
+            StubResponse::json(200, r#"{"embeddings":[[1.0],[2.0]]}"#),
+            StubResponse::json(200, r#"{"embeddings":[[3.0]]}"#),
+        ]);
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.embedding_base_url = base_url;
+        cfg.model_retry.max_attempts = 1;
+        cfg.embedding_batch_size = 2;
+        let texts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let vectors = embed(&Client::new(), &cfg, &texts)
+            .await
+            .expect("both batches should succeed");
+
+        assert_eq!(vectors, vec![vec![1.0], vec![2.0], vec![3.0]]);
+        server.join().expect("server thread should join");
+    }
+
+    #[tokio::test]
+    async fn chat_rejects_reply_that_does_not_match_output_format() {
+        let (base_url, server) = serve_one_reply("plain text");
+        let mut cfg = chain_config(Vec::new());
+        cfg.model_provider = "ollama".to_string();
+        cfg.model = "schema-primary".to_string();
+        cfg.model_base_url = base_url;
+        let options = GenerationOptions {
+            output_format: Some(OutputFormat::Schema(serde_json::json!({"type": "object"}))),
+            ..GenerationOptions::default()
+        };
+
+        let err = chat(&Client::new(), &cfg, &[Message::user("hello")], &options)
+            .await
+            .expect_err("plain text should fail validation");
+
+        assert!(
+            matches!(&err, ModelError::InvalidOutput { message } if message.contains("not valid JSON")),
+            "{err}"
+        );
+        server.join().expect("server thread should join");
+    }
+
+    #[tokio::test]
+    async fn chat_lists_every_failed_target_when_chain_is_exhausted() {
+        let dead_url = unused_url();
+        let mut cfg = chain_config(vec![ollama_target("dead-model", &dead_url)]);
+        cfg.model = "exhausted-primary".to_string();
+
+        let err = chat(
+            &Client::new(),
+            &cfg,
+            &[Message::user("hello")],
+            &cfg.generation,
+        )
+        .await
+        .expect_err("every target should fail");
+
+        let ModelError::AllTargetsFailed { failures } = &err else {
+            panic!("expected every target's failure, got: {err}");
+        };
+        assert!(matches!(failures[0].1, ModelError::Unsupported { .. }));
+        assert!(matches!(
+            failures[1].1,
+            ModelError::ConnectionRefused { .. }
+        ));
+        let message = err.to_string();
+        assert!(
+            message.starts_with("All 2 model targets failed"),
+            "{message}"
+        );
+        assert!(message.contains("custom/exhausted-primary@"), "{message}");
+        assert!(
+            message.contains("Unsupported MODEL_PROVIDER='custom'"),
+            "{message}"
+        );
+        assert!(message.contains("ollama/dead-model@"), "{message}");
+    }
+
+    #[test]
+    fn image_attachment_from_path_encodes_file_and_detects_type() {
+        let path = std::env::temp_dir().join(format!("fizz-image-{}.PNG", std::process::id()));
+        std::fs::write(&path, b"\x89PNG data").expect("temp image should be written");
+
+        let image = ImageAttachment::from_path(&path).expect("png should load");
+        std::fs::remove_file(&path).expect("temp image should be removed");
+
+        assert_eq!(image.media_type, "image/png");
+        assert_eq!(image.data, "iVBORyBkYXRh");
+        assert_eq!(image.byte_len(), 9);
+    }
+
+    #[test]
+    fn image_attachment_from_path_rejects_unknown_types() {
+        let err = ImageAttachment::from_path("notes.txt").expect_err("txt is not an image");
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(
+            err.to_string()
+                .contains("unsupported image type 'notes.txt'")
+        );
+    }
 }
@@ -0,0 +1,299 @@
+use reqwest::StatusCode;
+use std::error::Error;
+use std::fmt;
+
+use crate::config::ModelTarget;
+
+/// What a model request was for, which decides the settings an error points
+/// the user at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    Chat,
+    Embedding,
+}
+
+impl RequestKind {
+    fn provider_var(self) -> &'static str {
+        match self {
+            Self::Chat => "MODEL_PROVIDER",
+            Self::Embedding => "EMBEDDING_PROVIDER",
+        }
+    }
+
+    fn base_url_var(self) -> &'static str {
+        match self {
+            Self::Chat => "MODEL_BASE_URL",
+            Self::Embedding => "EMBEDDING_BASE_URL",
+        }
+    }
+
+    fn supported_providers(self) -> &'static str {
+        match self {
+            Self::Chat => "ollama",
+            Self::Embedding => "ollama, openai",
+        }
+    }
+}
+
+/// Why a model request failed.
+///
+/// `Display` renders the actionable message shown to users; callers that need
+/// to react to a failure (retry, fall back, offer to pull a model) match on
+/// the variant instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ModelError {
+    Timeout {
+        api_url: String,
+        timeout_secs: u64,
+    },
+    ConnectionRefused {
+        api_url: String,
+        kind: RequestKind,
+    },
+    Connect {
+        api_url: String,
+        kind: RequestKind,
+    },
+    /// Any other transport failure while calling the provider.
+    Request {
+        api_url: String,
+        message: String,
+    },
+    HttpStatus {
+        code: u16,
+        body: String,
+    },
+    /// The provider does not have the requested model; `body` is the
+    /// provider's raw response.
+    ModelNotFound {
+        model: String,
+        provider: String,
+        body: String,
+    },
+    /// The provider answered, but its response could not be parsed.
+    Decode {
+        context: String,
+        message: String,
+    },
+    /// The provider reported an error in the middle of a streamed response.
+    Stream {
+        message: String,
+    },
+    /// The response did not match the requested output format.
+    InvalidOutput {
+        message: String,
+    },
+    Unsupported {
+        provider: String,
+        kind: RequestKind,
+    },
+    /// Every configured target failed; holds each target's error in the order
+    /// the targets were tried.
+    AllTargetsFailed {
+        failures: Vec<(ModelTarget, ModelError)>,
+    },
+}
+
+impl ModelError {
+    /// Whether trying the same request again may succeed: connection failures,
+    /// timeouts, rate limiting, server errors, and models that are still
+    /// loading.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Timeout { .. } | Self::ConnectionRefused { .. } | Self::Connect { .. } => true,
+            Self::HttpStatus { code, body } => {
+                *code == StatusCode::TOO_MANY_REQUESTS.as_u16()
+                    || (500..600).contains(code)
+                    || is_model_loading(body)
+            }
+            Self::Request { .. }
+            | Self::ModelNotFound { .. }
+            | Self::Decode { .. }
+            | Self::Stream { .. }
+            | Self::InvalidOutput { .. }
+            | Self::Unsupported { .. }
+            | Self::AllTargetsFailed { .. } => false,
+        }
+    }
+}
+
+fn is_model_loading(body: &str) -> bool {
+    let body = body.to_ascii_lowercase();
+    body.contains("model is loading") || body.contains("loading model")
+}
+
+fn fmt_status(f: &mut fmt::Formatter<'_>, code: u16, body: &str) -> fmt::Result {
+    match StatusCode::from_u16(code) {
+        Ok(status) => write!(f, "Model request failed with status {}: {}", status, body),
+        Err(_) => write!(f, "Model request failed with status {}: {}", code, body),
+    }
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout {
+                api_url,
+                timeout_secs,
+            } => write!(
+                f,
+                "Model request timed out after {}s while calling '{}'. \
+                 Increase MODEL_TIMEOUT_SECS or check model responsiveness.",
+                timeout_secs, api_url
+            ),
+            Self::ConnectionRefused { api_url, kind } => write!(
+                f,
+                "Connection refused by model API at '{}'. \
+                 Ensure the model provider is running and {} is correct.",
+                api_url,
+                kind.base_url_var()
+            ),
+            Self::Connect { api_url, kind } => write!(
+                f,
+                "Failed to connect to model API at '{}'. \
+                 Check {} and network connectivity.",
+                api_url,
+                kind.base_url_var()
+            ),
+            Self::Request { api_url, message } => {
+                write!(f, "Failed to call model API at '{}': {}", api_url, message)
+            }
+            Self::HttpStatus { code, body } => fmt_status(f, *code, body),
+            Self::ModelNotFound {
+                model, provider, ..
+            } if provider.eq_ignore_ascii_case("ollama") => write!(
+                f,
+                "Model '{}' is not installed on the model provider. \
+                 Pull it with `ollama pull {}`, or start the REPL to download it.",
+                model, model
+            ),
+            Self::ModelNotFound {
+                model, provider, ..
+            } => write!(
+                f,
+                "Model '{}' is not available from the {} provider. Check the model name.",
+                model, provider
+            ),
+            Self::Decode { context, message } => write!(f, "{}: {}", context, message),
+            Self::Stream { message } => write!(f, "Model stream failed: {}", message),
+            Self::InvalidOutput { message } => write!(
+                f,
+                "Model response did not match the requested output format: {}",
+                message
+            ),
+            Self::Unsupported { provider, kind } => write!(
+                f,
+                "Unsupported {}='{}'. Supported providers: {}.",
+                kind.provider_var(),
+                provider,
+                kind.supported_providers()
+            ),
+            Self::AllTargetsFailed { failures } => {
+                write!(f, "All {} model targets failed:", failures.len())?;
+                for (target, err) in failures {
+                    write!(f, "\n- {}: {}", target, err)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for ModelError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{ModelError, RequestKind};
+
+    fn status(code: u16, body: &str) -> ModelError {
+        ModelError::HttpStatus {
+            code,
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn classifies_retryable_errors() {
+        assert!(status(429, "").is_retryable());
+        assert!(status(503, "").is_retryable());
+        assert!(status(500, "").is_retryable());
+        assert!(status(400, r#"{"error":"model is loading, try again"}"#).is_retryable());
+        assert!(!status(400, "bad request").is_retryable());
+        assert!(
+            ModelError::Timeout {
+                api_url: "http://localhost:11434/api/chat".to_string(),
+                timeout_secs: 5,
+            }
+            .is_retryable()
+        );
+        assert!(
+            !ModelError::ModelNotFound {
+                model: "missing".to_string(),
+                provider: "ollama".to_string(),
+                body: r#"{"error":"model 'missing' not found"}"#.to_string(),
+            }
+            .is_retryable()
+        );
+        assert!(
+            !ModelError::Unsupported {
+                provider: "custom".to_string(),
+                kind: RequestKind::Chat,
+            }
+            .is_retryable()
+        );
+    }
+
+    #[test]
+    fn display_explains_status_failures() {
+        assert_eq!(
+            status(503, "busy").to_string(),
+            "Model request failed with status 503 Service Unavailable: busy"
+        );
+        assert_eq!(
+            ModelError::ModelNotFound {
+                model: "missing".to_string(),
+                provider: "ollama".to_string(),
+                body: "not found".to_string(),
+            }
+            .to_string(),
+            "Model 'missing' is not installed on the model provider. \
+             Pull it with `ollama pull missing`, or start the REPL to download it."
+        );
+        assert_eq!(
+            ModelError::ModelNotFound {
+                model: "missing".to_string(),
+                provider: "openai".to_string(),
+                body: "not found".to_string(),
+            }
+            .to_string(),
+            "Model 'missing' is not available from the openai provider. Check the model name."
+        );
+    }
+
+    #[test]
+    fn display_names_the_settings_of_the_request_kind() {
+        let refused = |kind| ModelError::ConnectionRefused {
+            api_url: "http://localhost:1/v1/embeddings".to_string(),
+            kind,
+        };
+        assert!(
+            refused(RequestKind::Chat)
+                .to_string()
+                .contains("MODEL_BASE_URL")
+        );
+        assert!(
+            refused(RequestKind::Embedding)
+                .to_string()
+                .contains("EMBEDDING_BASE_URL")
+        );
+        assert_eq!(
+            ModelError::Unsupported {
+                provider: "custom".to_string(),
+                kind: RequestKind::Embedding,
+            }
+            .to_string(),
+            "Unsupported EMBEDDING_PROVIDER='custom'. Supported providers: ollama, openai."
+        );
+    }
+}
@@ -1,35 +1,81 @@
-use anyhow::Result;
 use reqwest::Client;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 
-use crate::config::Config;
-use crate::model::{self, Message};
+use crate::config::{Config, GenerationOptions, ModelTarget};
+use crate::model::{self, Message, ModelReply, ModelUsage};
+use crate::model_error::ModelError;
+use crate::output_schema::OutputFormat;
 
 pub struct ModelGatewayRequest {
     pub messages: Vec<Message>,
+    /// Per-request overrides; unset fields fall back to `Config::generation`.
+    pub options: GenerationOptions,
+}
+
+impl ModelGatewayRequest {
+    pub fn new(messages: Vec<Message>) -> Self {
+        Self {
+            messages,
+            options: GenerationOptions::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: GenerationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Requests JSON output; the response content is validated against
+    /// `format` before it is returned.
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.options.output_format = Some(format);
+        self
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ModelGatewayResponse {
     pub content: String,
+    /// The configured target that produced the response.
+    pub target: ModelTarget,
+    /// Tokens and time the request took, as reported by the provider.
+    pub usage: ModelUsage,
 }
 
-pub type ModelGatewayFuture<'a> = Pin<Box<dyn Future<Output = Result<ModelGatewayResponse>> + 'a>>;
+pub type ModelGatewayFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<ModelGatewayResponse, ModelError>> + Send + 'a>>;
 
-pub trait ModelGateway {
+pub type ModelGatewayEmbedFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<Vec<f32>>, ModelError>> + Send + 'a>>;
+
+pub trait ModelGateway: Send + Sync {
     fn chat<'a>(&'a self, request: ModelGatewayRequest) -> ModelGatewayFuture<'a>;
+
+    /// Embeds `texts` with the configured embedding model, returning one
+    /// vector per text in input order.
+    fn embed<'a>(&'a self, texts: Vec<String>) -> ModelGatewayEmbedFuture<'a>;
 }
 
-type ModelChatFuture<'a> = Pin<Box<dyn Future<Output = Result<String>> + 'a>>;
+type ModelChatFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<ModelReply, ModelError>> + Send + 'a>>;
 
-trait ChatBackend {
+trait ChatBackend: Send + Sync {
     fn chat<'a>(
         &'a self,
         client: &'a Client,
         cfg: &'a Config,
         messages: &'a [Message],
+        options: &'a GenerationOptions,
     ) -> ModelChatFuture<'a>;
+
+    fn embed<'a>(
+        &'a self,
+        client: &'a Client,
+        cfg: &'a Config,
+        texts: &'a [String],
+    ) -> ModelGatewayEmbedFuture<'a>;
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -41,19 +87,29 @@ impl ChatBackend for ProviderChatBackend {
         client: &'a Client,
         cfg: &'a Config,
         messages: &'a [Message],
+        options: &'a GenerationOptions,
     ) -> ModelChatFuture<'a> {
-        Box::pin(async move { model::chat(client, cfg, messages).await })
+        Box::pin(async move { model::chat(client, cfg, messages, options).await })
+    }
+
+    fn embed<'a>(
+        &'a self,
+        client: &'a Client,
+        cfg: &'a Config,
+        texts: &'a [String],
+    ) -> ModelGatewayEmbedFuture<'a> {
+        Box::pin(async move { model::embed(client, cfg, texts).await })
     }
 }
 
-pub struct HostModelGateway<'a, B = ProviderChatBackend> {
-    client: &'a Client,
-    cfg: &'a Config,
+pub struct HostModelGateway<B = ProviderChatBackend> {
+    client: Client,
+    cfg: Arc<Config>,
     backend: B,
 }
 
-impl<'a> HostModelGateway<'a, ProviderChatBackend> {
-    pub fn new(client: &'a Client, cfg: &'a Config) -> Self {
+impl HostModelGateway<ProviderChatBackend> {
+    pub fn new(client: Client, cfg: Arc<Config>) -> Self {
         Self {
             client,
             cfg,
@@ -62,8 +118,8 @@ impl<'a> HostModelGateway<'a, ProviderChatBackend> {
     }
 }
 
-impl<'a, B> HostModelGateway<'a, B> {
-    pub fn with_backend(client: &'a Client, cfg: &'a Config, backend: B) -> Self {
+impl<B> HostModelGateway<B> {
+    pub fn with_backend(client: Client, cfg: Arc<Config>, backend: B) -> Self {
         Self {
             client,
             cfg,
@@ -72,31 +128,46 @@ impl<'a, B> HostModelGateway<'a, B> {
     }
 }
 
-impl<'a, B> ModelGateway for HostModelGateway<'a, B>
+impl<B> ModelGateway for HostModelGateway<B>
 where
     B: ChatBackend,
 {
-    fn chat<'b>(&'b self, request: ModelGatewayRequest) -> ModelGatewayFuture<'b> {
+    fn chat<'a>(&'a self, request: ModelGatewayRequest) -> ModelGatewayFuture<'a> {
         Box::pin(async move {
-            let content = self
+            let options = self.cfg.generation.merged_with(&request.options);
+            let reply = self
                 .backend
-                .chat(self.client, self.cfg, &request.messages)
+                .chat(&self.client, &self.cfg, &request.messages, &options)
                 .await?;
-            Ok(ModelGatewayResponse { content })
+            Ok(ModelGatewayResponse {
+                content: reply.content,
+                target: reply.target,
+                usage: reply.usage,
+            })
         })
     }
+
+    fn embed<'a>(&'a self, texts: Vec<String>) -> ModelGatewayEmbedFuture<'a> {
+        Box::pin(async move { self.backend.embed(&self.client, &self.cfg, &texts).await })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use anyhow::anyhow;
-    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex};
 
     use super::{
-        ChatBackend, HostModelGateway, ModelChatFuture, ModelGateway, ModelGatewayRequest,
+        ChatBackend, HostModelGateway, ModelChatFuture, ModelGateway, ModelGatewayEmbedFuture,
+        ModelGatewayRequest,
+    };
+    use crate::config::{
+        AgentMode, Config, DelegationLimits, GenerationOptions, ModelRetryPolicy, ModelRoles,
+        SessionLimits, ToolCallExtraction, ToolPolicy, ToolResourceLimits, ToolRuntime,
+        WorkspaceFsMode,
     };
-    use crate::config::{Config, ToolPolicy, ToolResourceLimits, ToolRuntime, WorkspaceFsMode};
-    use crate::model::Message;
+    use crate::model::{Message, ModelReply, ModelUsage};
+    use crate::model_error::ModelError;
+    use crate::output_schema::OutputFormat;
 
     #[derive(Debug)]
     enum StubOutcome {
@@ -106,21 +177,24 @@ mod tests {
 
     #[derive(Debug)]
     struct StubBackend {
-        calls: RefCell<Vec<Vec<Message>>>,
+        calls: Mutex<Vec<Vec<Message>>>,
+        options: Mutex<Vec<GenerationOptions>>,
         outcome: StubOutcome,
     }
 
     impl StubBackend {
         fn ok(content: impl Into<String>) -> Self {
             Self {
-                calls: RefCell::new(Vec::new()),
+                calls: Mutex::new(Vec::new()),
+                options: Mutex::new(Vec::new()),
                 outcome: StubOutcome::Ok(content.into()),
             }
         }
 
         fn err(message: impl Into<String>) -> Self {
             Self {
-                calls: RefCell::new(Vec::new()),
+                calls: Mutex::new(Vec::new()),
+                options: Mutex::new(Vec::new()),
                 outcome: StubOutcome::Err(message.into()),
             }
         }
@@ -130,25 +204,87 @@ mod tests {
         fn chat<'a>(
             &'a self,
             _client: &'a reqwest::Client,
-            _cfg: &'a Config,
+            cfg: &'a Config,
             messages: &'a [Message],
+            options: &'a GenerationOptions,
         ) -> ModelChatFuture<'a> {
-            self.calls.borrow_mut().push(messages.to_vec());
+            self.calls
+                .lock()
+                .expect("calls lock should not be poisoned")
+                .push(messages.to_vec());
+            self.options
+                .lock()
+                .expect("options lock should not be poisoned")
+                .push(options.clone());
             let result = match &self.outcome {
-                StubOutcome::Ok(content) => Ok(content.clone()),
-                StubOutcome::Err(message) => Err(anyhow!(message.clone())),
+                StubOutcome::Ok(content) => Ok(ModelReply {
+                    content: content.clone(),
+                    target: cfg.primary_model_target(),
+                    usage: ModelUsage {
+                        prompt_tokens: 10,
+                        completion_tokens: 2,
+                        ..ModelUsage::default()
+                    },
+                }),
+                StubOutcome::Err(message) => Err(ModelError::HttpStatus {
+                    code: 500,
+                    body: message.clone(),
+                }),
+            };
+            Box::pin(async move { result })
+        }
+
+        fn embed<'a>(
+            &'a self,
+            _client: &'a reqwest::Client,
+            _cfg: &'a Config,
+            texts: &'a [String],
+        ) -> ModelGatewayEmbedFuture<'a> {
+            let result = match &self.outcome {
+                StubOutcome::Ok(_) => {
+                    Ok(texts.iter().map(|text| vec![text.len() as f32]).collect())
+                }
+                StubOutcome::Err(message) => Err(ModelError::HttpStatus {
+                    code: 500,
+                    body: message.clone(),
+                }),
             };
             Box::pin(async move { result })
         }
     }
 
-    fn test_config() -> Config {
-        Config {
+    fn test_config() -> Arc<Config> {
+        Arc::new(Config {
             model_provider: "ollama".to_string(),
             model: "qwen2.5:3b".to_string(),
             model_base_url: "http://localhost:11434".to_string(),
             system_prompt: "You are a helpful assistant.".to_string(),
             model_timeout_secs: 60,
+            model_retry: ModelRetryPolicy::default(),
+            model_fallbacks: Vec::new(),
+            model_target_cooldown_secs: 30,
+            model_target_health: Arc::default(),
+            model_roles: ModelRoles::default(),
+            generation: GenerationOptions {
+                temperature: Some(0.7),
+                num_ctx: Some(4096),
+                ..GenerationOptions::default()
+            },
+            embedding_model: "nomic-embed-text".to_string(),
+            embedding_provider: "ollama".to_string(),
+            embedding_base_url: "http://localhost:11434".to_string(),
+            embedding_api_key: None,
+            embedding_batch_size: 32,
+            docs_index_path: ".fizz/docs-index.json".into(),
+            docs_chunk_chars: 1_500,
+            memory_path: ".fizz/memory.json".into(),
+            memory_inject_limit: 5,
+            memory_embeddings: false,
+            mcp_config_path: ".fizz/mcp.json".into(),
+            plugin_dir: ".fizz/plugins".into(),
+            server_host: "127.0.0.1".to_string(),
+            server_port: 8080,
+            sessions: SessionLimits::default(),
             tool_runtime: ToolRuntime::Builtin,
             workspace_fs_mode: WorkspaceFsMode::Host,
             tool_policy: ToolPolicy {
@@ -156,16 +292,22 @@ mod tests {
                 resource_limits: ToolResourceLimits {
                     timeout_secs: 30,
                     memory_mb: 256,
+                    max_output_bytes: 1_048_576,
                 },
             },
-        }
+            tool_max_concurrency: 4,
+            tool_max_calls_per_hop: 8,
+            tool_call_extraction: ToolCallExtraction::Strict,
+            delegation: DelegationLimits::default(),
+            agent_mode: AgentMode::React,
+        })
     }
 
     #[tokio::test]
     async fn host_gateway_maps_request_messages_and_response_content() {
         let client = reqwest::Client::new();
         let cfg = test_config();
-        let gateway = HostModelGateway::with_backend(&client, &cfg, StubBackend::ok("hello"));
+        let gateway = HostModelGateway::with_backend(client, cfg, StubBackend::ok("hello"));
         let request_messages = vec![
             Message::system("sys"),
             Message::user("hi"),
@@ -173,14 +315,21 @@ mod tests {
         ];
 
         let response = gateway
-            .chat(ModelGatewayRequest {
-                messages: request_messages.clone(),
-            })
+            .chat(ModelGatewayRequest::new(request_messages.clone()))
             .await
             .expect("gateway chat should succeed");
 
         assert_eq!(response.content, "hello");
-        let calls = gateway.backend.calls.borrow();
+        assert_eq!(response.usage.total_tokens(), 12);
+        assert_eq!(
+            response.target.to_string(),
+            "ollama/qwen2.5:3b@http://localhost:11434"
+        );
+        let calls = gateway
+            .backend
+            .calls
+            .lock()
+            .expect("calls lock should not be poisoned");
         assert_eq!(calls.len(), 1);
         assert_eq!(calls[0].len(), request_messages.len());
         assert_eq!(calls[0][0].role.as_str(), "system");
@@ -188,25 +337,103 @@ mod tests {
         assert_eq!(calls[0][2].role.as_str(), "assistant");
     }
 
+    #[tokio::test]
+    async fn host_gateway_merges_request_options_over_config_defaults() {
+        let client = reqwest::Client::new();
+        let gateway = HostModelGateway::with_backend(client, test_config(), StubBackend::ok("ok"));
+
+        gateway
+            .chat(
+                ModelGatewayRequest::new(vec![Message::user("hi")]).with_options(
+                    GenerationOptions {
+                        temperature: Some(0.0),
+                        seed: Some(42),
+                        ..GenerationOptions::default()
+                    },
+                ),
+            )
+            .await
+            .expect("gateway chat should succeed");
+
+        let options = gateway
+            .backend
+            .options
+            .lock()
+            .expect("options lock should not be poisoned");
+        assert_eq!(
+            options[0],
+            GenerationOptions {
+                temperature: Some(0.0),
+                seed: Some(42),
+                num_ctx: Some(4096),
+                ..GenerationOptions::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn host_gateway_passes_output_format_to_backend() {
+        let client = reqwest::Client::new();
+        let gateway = HostModelGateway::with_backend(client, test_config(), StubBackend::ok("{}"));
+        let schema = serde_json::json!({"type": "object"});
+
+        gateway
+            .chat(
+                ModelGatewayRequest::new(vec![Message::user("hi")])
+                    .with_output_format(OutputFormat::Schema(schema.clone())),
+            )
+            .await
+            .expect("gateway chat should succeed");
+
+        let options = gateway
+            .backend
+            .options
+            .lock()
+            .expect("options lock should not be poisoned");
+        assert_eq!(options[0].output_format, Some(OutputFormat::Schema(schema)));
+        assert_eq!(options[0].temperature, Some(0.7));
+    }
+
+    #[tokio::test]
+    async fn host_gateway_returns_one_embedding_per_text() {
+        let client = reqwest::Client::new();
+        let gateway = HostModelGateway::with_backend(client, test_config(), StubBackend::ok(""));
+
+        let vectors = gateway
+            .embed(vec!["a".to_string(), "abc".to_string()])
+            .await
+            .expect("gateway embed should succeed");
+
+        assert_eq!(vectors, vec![vec![1.0], vec![3.0]]);
+    }
+
     #[tokio::test]
     async fn host_gateway_preserves_backend_errors() {
         let client = reqwest::Client::new();
         let cfg = test_config();
         let gateway =
-            HostModelGateway::with_backend(&client, &cfg, StubBackend::err("backend failure"));
+            HostModelGateway::with_backend(client, cfg, StubBackend::err("backend failure"));
 
         let err = gateway
-            .chat(ModelGatewayRequest {
-                messages: vec![Message::user("ping")],
-            })
+            .chat(ModelGatewayRequest::new(vec![Message::user("ping")]))
             .await
             .expect_err("gateway chat should fail");
 
-        let msg = format!("{err:#}");
-        assert!(
-            msg.contains("backend failure"),
-            "unexpected error message: {msg}"
+        assert_eq!(
+            err,
+            ModelError::HttpStatus {
+                code: 500,
+                body: "backend failure".to_string(),
+            }
+        );
+        assert_eq!(
+            gateway
+                .backend
+                .calls
+                .lock()
+                .expect("calls lock should not be poisoned")
+                .len(),
+            1
         );
-        assert_eq!(gateway.backend.calls.borrow().len(), 1);
     }
 }
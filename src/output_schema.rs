@@ -0,0 +1,346 @@
+use serde_json::{Map, Value};
+use std::error::Error;
+use std::fmt;
+
+/// Constrains the shape of a model response.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    /// Any valid JSON value.
+    Json,
+    /// JSON matching a JSON Schema; see [`validate`] for the supported subset.
+    Schema(Value),
+}
+
+impl OutputFormat {
+    /// Parses `content` as JSON and validates it against the format.
+    pub fn check(&self, content: &str) -> Result<Value, SchemaViolation> {
+        let value: Value = serde_json::from_str(content.trim()).map_err(|err| SchemaViolation {
+            path: String::new(),
+            message: format!("response is not valid JSON: {err}"),
+        })?;
+        if let Self::Schema(schema) = self {
+            validate(&value, schema)?;
+        }
+        Ok(value)
+    }
+}
+
+/// The first place where a value does not match its schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value; empty for the root.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl Error for SchemaViolation {}
+
+/// Validates `value` against a JSON Schema.
+///
+/// Supports the subset used for model output: `type`, `enum`, `const`,
+/// `properties`, `required`, `additionalProperties`, `items`, `minItems`,
+/// `maxItems`, `minLength`, `maxLength`, `minimum`, `maximum`, `allOf`,
+/// `anyOf` and `oneOf`. Other keywords are ignored.
+pub fn validate(value: &Value, schema: &Value) -> Result<(), SchemaViolation> {
+    validate_at(value, schema, "")
+}
+
+fn violation(path: &str, message: impl Into<String>) -> SchemaViolation {
+    SchemaViolation {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str) -> Result<(), SchemaViolation> {
+    let schema = match schema {
+        Value::Bool(true) => return Ok(()),
+        Value::Bool(false) => return Err(violation(path, "no value is allowed here")),
+        Value::Object(schema) => schema,
+        _ => return Ok(()),
+    };
+
+    if let Some(expected) = schema.get("type") {
+        check_type(value, expected, path)?;
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum")
+        && !allowed.contains(value)
+    {
+        return Err(violation(
+            path,
+            format!("{value} is not one of {allowed:?}"),
+        ));
+    }
+    if let Some(expected) = schema.get("const")
+        && value != expected
+    {
+        return Err(violation(path, format!("expected {expected}, got {value}")));
+    }
+
+    match value {
+        Value::Object(object) => check_object(object, schema, path)?,
+        Value::Array(items) => check_array(items, schema, path)?,
+        Value::String(text) => check_string(text, schema, path)?,
+        Value::Number(number) => check_number(number.as_f64().unwrap_or(f64::NAN), schema, path)?,
+        _ => {}
+    }
+
+    check_combinators(value, schema, path)
+}
+
+fn type_matches(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn check_type(value: &Value, expected: &Value, path: &str) -> Result<(), SchemaViolation> {
+    let matches = match expected {
+        Value::String(name) => type_matches(value, name),
+        Value::Array(names) => names
+            .iter()
+            .filter_map(Value::as_str)
+            .any(|name| type_matches(value, name)),
+        _ => true,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(violation(
+            path,
+            format!("expected type {expected}, got {value}"),
+        ))
+    }
+}
+
+fn check_object(
+    object: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Err(violation(
+                    path,
+                    format!("missing required property '{key}'"),
+                ));
+            }
+        }
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, property) in object {
+        let property_path = format!("{path}/{key}");
+        match properties.and_then(|properties| properties.get(key)) {
+            Some(property_schema) => validate_at(property, property_schema, &property_path)?,
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    return Err(violation(path, format!("unexpected property '{key}'")));
+                }
+                Some(additional) => validate_at(property, additional, &property_path)?,
+                None => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+fn check_array(
+    items: &[Value],
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    if let Some(min) = schema.get("minItems").and_then(Value::as_u64)
+        && (items.len() as u64) < min
+    {
+        return Err(violation(path, format!("expected at least {min} items")));
+    }
+    if let Some(max) = schema.get("maxItems").and_then(Value::as_u64)
+        && (items.len() as u64) > max
+    {
+        return Err(violation(path, format!("expected at most {max} items")));
+    }
+    if let Some(item_schema) = schema.get("items") {
+        for (index, item) in items.iter().enumerate() {
+            validate_at(item, item_schema, &format!("{path}/{index}"))?;
+        }
+    }
+    Ok(())
+}
+
+fn check_string(
+    text: &str,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    let length = text.chars().count() as u64;
+    if let Some(min) = schema.get("minLength").and_then(Value::as_u64)
+        && length < min
+    {
+        return Err(violation(
+            path,
+            format!("expected at least {min} characters"),
+        ));
+    }
+    if let Some(max) = schema.get("maxLength").and_then(Value::as_u64)
+        && length > max
+    {
+        return Err(violation(
+            path,
+            format!("expected at most {max} characters"),
+        ));
+    }
+    Ok(())
+}
+
+fn check_number(
+    number: f64,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    if let Some(min) = schema.get("minimum").and_then(Value::as_f64)
+        && number < min
+    {
+        return Err(violation(path, format!("{number} is less than {min}")));
+    }
+    if let Some(max) = schema.get("maximum").and_then(Value::as_f64)
+        && number > max
+    {
+        return Err(violation(path, format!("{number} is greater than {max}")));
+    }
+    Ok(())
+}
+
+fn check_combinators(
+    value: &Value,
+    schema: &Map<String, Value>,
+    path: &str,
+) -> Result<(), SchemaViolation> {
+    if let Some(Value::Array(all)) = schema.get("allOf") {
+        for sub_schema in all {
+            validate_at(value, sub_schema, path)?;
+        }
+    }
+    if let Some(Value::Array(any)) = schema.get("anyOf")
+        && !any
+            .iter()
+            .any(|sub_schema| validate_at(value, sub_schema, path).is_ok())
+    {
+        return Err(violation(path, "value matches none of the anyOf schemas"));
+    }
+    if let Some(Value::Array(one)) = schema.get("oneOf") {
+        let matching = one
+            .iter()
+            .filter(|sub_schema| validate_at(value, sub_schema, path).is_ok())
+            .count();
+        if matching != 1 {
+            return Err(violation(
+                path,
+                format!("value matches {matching} of the oneOf schemas, expected exactly 1"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{OutputFormat, validate};
+
+    fn person_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {
+                "name": {"type": "string", "minLength": 1},
+                "age": {"type": "integer", "minimum": 0},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}, "maxItems": 2}
+            },
+            "additionalProperties": false
+        })
+    }
+
+    #[test]
+    fn validate_accepts_matching_values() {
+        let value = json!({"name": "Ada", "age": 36, "tags": ["a"]});
+        assert_eq!(validate(&value, &person_schema()), Ok(()));
+    }
+
+    #[test]
+    fn validate_reports_path_of_first_violation() {
+        let schema = person_schema();
+        let cases = [
+            (json!({"tags": []}), "", "missing required property 'name'"),
+            (
+                json!({"name": "Ada", "tags": ["c"]}),
+                "/tags/0",
+                "\"c\" is not one of",
+            ),
+            (
+                json!({"name": "Ada", "age": -1, "tags": []}),
+                "/age",
+                "less than 0",
+            ),
+            (
+                json!({"name": "Ada", "tags": [], "extra": true}),
+                "",
+                "unexpected property 'extra'",
+            ),
+            (json!({"name": 3, "tags": []}), "/name", "expected type"),
+        ];
+
+        for (value, path, message) in cases {
+            let err = validate(&value, &schema).expect_err("value should be rejected");
+            assert_eq!(err.path, path, "{value}");
+            assert!(err.message.contains(message), "{value}: {err}");
+        }
+    }
+
+    #[test]
+    fn validate_requires_exactly_one_one_of_match() {
+        let schema = json!({
+            "oneOf": [
+                {"type": "object", "required": ["answer"]},
+                {"type": "object", "required": ["tool_calls"]}
+            ]
+        });
+
+        assert!(validate(&json!({"answer": "hi"}), &schema).is_ok());
+        assert!(validate(&json!({"other": 1}), &schema).is_err());
+        assert!(validate(&json!({"answer": "hi", "tool_calls": []}), &schema).is_err());
+    }
+
+    #[test]
+    fn output_format_check_parses_then_validates() {
+        assert_eq!(OutputFormat::Json.check(" [1, 2] "), Ok(json!([1, 2])));
+        let err = OutputFormat::Json
+            .check("not json")
+            .expect_err("plain text should be rejected");
+        assert!(err.to_string().starts_with("response is not valid JSON"));
+        assert!(
+            OutputFormat::Schema(json!({"type": "object"}))
+                .check("[]")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,743 @@
+//! Tools provided by external executables.
+//!
+//! Each plugin is a directory under `PLUGIN_DIR` holding a `plugin.json`
+//! manifest:
+//!
+//! ```json
+//! {"name": "tickets", "command": "python3", "args": ["tickets.py"],
+//!  "persistent": false,
+//!  "tools": [{"name": "tickets.create", "description": "...", "parameters": {...}}]}
+//! ```
+//!
+//! A call writes one JSON line, `{"id":1,"tool":"tickets.create","arguments":{...}}`,
+//! to the plugin's stdin and reads one JSON line back: `{"id":1,"output":"..."}`
+//! or `{"id":1,"error":"..."}`. Plugins run in their directory. By default a
+//! process is started per call and its stdin is closed after the request;
+//! `"persistent": true` keeps one process running and sends it one request at
+//! a time. The limits in [`ToolResourceLimits`] apply to every call.
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::agent::tools::{
+    ToolCall, ToolExecutionError, ToolFuture, ToolOutput, ToolRunner, ToolSpec,
+};
+use crate::config::ToolResourceLimits;
+
+const MANIFEST_FILE: &str = "plugin.json";
+/// How much of a plugin's stderr is kept for error messages.
+const STDERR_TAIL_BYTES: usize = 2_048;
+
+/// Why a plugin could not be loaded or a call to it failed.
+#[derive(Debug)]
+pub enum PluginError {
+    Manifest {
+        path: PathBuf,
+        message: String,
+    },
+    Spawn {
+        plugin: String,
+        command: String,
+        source: io::Error,
+    },
+    Io {
+        plugin: String,
+        source: io::Error,
+    },
+    Timeout {
+        plugin: String,
+        timeout_secs: u64,
+    },
+    OutputTooLarge {
+        plugin: String,
+        limit_bytes: u64,
+    },
+    /// The process exited without answering.
+    Crashed {
+        plugin: String,
+        status: String,
+        stderr: String,
+    },
+    InvalidResponse {
+        plugin: String,
+        message: String,
+    },
+    /// The plugin answered with an error.
+    ToolFailed {
+        tool: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Manifest { path, message } => {
+                write!(f, "Invalid plugin manifest '{}': {message}", path.display())
+            }
+            Self::Spawn {
+                plugin,
+                command,
+                source,
+            } => write!(f, "Failed to start plugin '{plugin}' ({command}): {source}"),
+            Self::Io { plugin, source } => {
+                write!(f, "Failed to talk to plugin '{plugin}': {source}")
+            }
+            Self::Timeout {
+                plugin,
+                timeout_secs,
+            } => write!(
+                f,
+                "Plugin '{plugin}' did not answer within {timeout_secs}s and was stopped"
+            ),
+            Self::OutputTooLarge {
+                plugin,
+                limit_bytes,
+            } => write!(
+                f,
+                "Plugin '{plugin}' wrote more than {limit_bytes} bytes and was stopped"
+            ),
+            Self::Crashed {
+                plugin,
+                status,
+                stderr,
+            } => {
+                write!(f, "Plugin '{plugin}' exited without answering ({status})")?;
+                if !stderr.is_empty() {
+                    write!(f, "; stderr: {stderr}")?;
+                }
+                Ok(())
+            }
+            Self::InvalidResponse { plugin, message } => {
+                write!(f, "Plugin '{plugin}' sent an invalid response: {message}")
+            }
+            Self::ToolFailed { tool, message } => write!(f, "{tool} failed: {message}"),
+        }
+    }
+}
+
+impl Error for PluginError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Spawn { source, .. } | Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    name: String,
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    persistent: bool,
+    tools: Vec<ManifestTool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestTool {
+    name: String,
+    description: String,
+    #[serde(default)]
+    parameters: Option<Value>,
+}
+
+/// A loaded plugin manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginManifest {
+    pub name: String,
+    /// Directory holding the manifest; the plugin runs there.
+    pub dir: PathBuf,
+    /// Commands containing a `/` are resolved against `dir`; others are
+    /// looked up on `PATH`.
+    pub command: String,
+    pub args: Vec<String>,
+    pub persistent: bool,
+    pub tools: Vec<ToolSpec>,
+}
+
+impl PluginManifest {
+    /// Reads `<dir>/plugin.json`.
+    pub fn load(dir: &Path) -> Result<Self, PluginError> {
+        let path = dir.join(MANIFEST_FILE);
+        let manifest_error = |message: String| PluginError::Manifest {
+            path: path.clone(),
+            message,
+        };
+        let raw = fs::read_to_string(&path).map_err(|err| manifest_error(err.to_string()))?;
+        let file: ManifestFile =
+            serde_json::from_str(&raw).map_err(|err| manifest_error(err.to_string()))?;
+        if file.name.trim().is_empty() {
+            return Err(manifest_error("plugin name is empty".to_string()));
+        }
+        if file.command.trim().is_empty() {
+            return Err(manifest_error("command is empty".to_string()));
+        }
+        if file.tools.is_empty() {
+            return Err(manifest_error("plugin lists no tools".to_string()));
+        }
+        let mut tools: Vec<ToolSpec> = Vec::with_capacity(file.tools.len());
+        for tool in file.tools {
+            if tool.name.trim().is_empty() {
+                return Err(manifest_error("a tool name is empty".to_string()));
+            }
+            if tools.iter().any(|known| known.name == tool.name) {
+                return Err(manifest_error(format!(
+                    "tool '{}' is listed twice",
+                    tool.name
+                )));
+            }
+            let mut spec = ToolSpec::new(tool.name, tool.description);
+            if let Some(parameters) = tool.parameters {
+                spec = spec.with_parameters(parameters);
+            }
+            tools.push(spec);
+        }
+
+        let command = if file.command.contains('/') {
+            dir.join(&file.command).display().to_string()
+        } else {
+            file.command
+        };
+        Ok(Self {
+            name: file.name,
+            dir: dir.to_path_buf(),
+            command,
+            args: file.args,
+            persistent: file.persistent,
+            tools,
+        })
+    }
+}
+
+/// Runs tools provided by plugin executables.
+#[derive(Default)]
+pub struct ProcessRunner {
+    plugins: Vec<Plugin>,
+    /// Tool name to index into `plugins`.
+    routes: HashMap<String, usize>,
+    limits: ToolResourceLimits,
+    next_request_id: AtomicU64,
+}
+
+struct Plugin {
+    manifest: PluginManifest,
+    /// The running process of a persistent plugin, started on first use and
+    /// restarted after a failure.
+    process: tokio::sync::Mutex<Option<PluginProcess>>,
+}
+
+struct PluginProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    stderr: StderrTail,
+}
+
+impl ProcessRunner {
+    pub fn new(limits: ToolResourceLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Loads every plugin directory under `dir`, in name order. A missing
+    /// directory means no plugins. Plugins with a broken manifest, or whose
+    /// tools clash with an earlier plugin's, are logged, returned and left
+    /// out.
+    pub fn discover(dir: &Path, limits: ToolResourceLimits) -> (Self, Vec<PluginError>) {
+        let mut runner = Self::new(limits);
+        let mut errors = Vec::new();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return (runner, errors),
+            Err(err) => {
+                let err = PluginError::Manifest {
+                    path: dir.to_path_buf(),
+                    message: err.to_string(),
+                };
+                warn!(error = %err, "skipping plugins");
+                errors.push(err);
+                return (runner, errors);
+            }
+        };
+        let mut dirs: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.join(MANIFEST_FILE).is_file())
+            .collect();
+        dirs.sort();
+
+        for dir in dirs {
+            match PluginManifest::load(&dir).and_then(|manifest| runner.add(manifest)) {
+                Ok(()) => {}
+                Err(err) => {
+                    warn!(error = %err, "skipping plugin");
+                    errors.push(err);
+                }
+            }
+        }
+        (runner, errors)
+    }
+
+    /// Adds a plugin unless one of its tools is already offered.
+    pub fn add(&mut self, manifest: PluginManifest) -> Result<(), PluginError> {
+        if let Some(tool) = manifest
+            .tools
+            .iter()
+            .find(|tool| self.routes.contains_key(&tool.name))
+        {
+            return Err(PluginError::Manifest {
+                path: manifest.dir.join(MANIFEST_FILE),
+                message: format!("tool '{}' is already offered by another plugin", tool.name),
+            });
+        }
+        info!(
+            plugin = %manifest.name,
+            tool_count = manifest.tools.len(),
+            persistent = manifest.persistent,
+            "loaded plugin"
+        );
+        let index = self.plugins.len();
+        for tool in &manifest.tools {
+            self.routes.insert(tool.name.clone(), index);
+        }
+        self.plugins.push(Plugin {
+            manifest,
+            process: tokio::sync::Mutex::new(None),
+        });
+        Ok(())
+    }
+
+    async fn call(&self, plugin: &Plugin, call: &ToolCall) -> Result<String, PluginError> {
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut request =
+            json!({"id": id, "tool": call.name, "arguments": call.arguments}).to_string();
+        request.push('\n');
+        let name = &plugin.manifest.name;
+        debug!(plugin = %name, tool_name = %call.name, "calling plugin");
+
+        let line = if plugin.manifest.persistent {
+            self.call_persistent(plugin, &request).await?
+        } else {
+            self.call_once(&plugin.manifest, &request).await?
+        };
+        parse_response(name, &call.name, id, &line)
+    }
+
+    /// Starts a process for one request and reads its whole stdout.
+    async fn call_once(
+        &self,
+        manifest: &PluginManifest,
+        request: &str,
+    ) -> Result<String, PluginError> {
+        let timeout = Duration::from_secs(self.limits.timeout_secs);
+        let mut process = spawn(manifest, &self.limits)?;
+        let name = &manifest.name;
+        let exchange = async {
+            write_request(name, &mut process.stdin, request).await?;
+            drop(process.stdin);
+            let mut output = Vec::new();
+            (&mut process.stdout)
+                .take(self.limits.max_output_bytes + 1)
+                .read_to_end(&mut output)
+                .await
+                .map_err(|source| io_error(name, source))?;
+            if output.len() as u64 > self.limits.max_output_bytes {
+                return Err(self.output_too_large(name));
+            }
+            let status = process
+                .child
+                .wait()
+                .await
+                .map_err(|source| io_error(name, source))?;
+            Ok((output, status))
+        };
+        // Dropping the child on timeout or error kills the process.
+        let (output, status) = match tokio::time::timeout(timeout, exchange).await {
+            Ok(result) => result?,
+            Err(_) => return Err(self.timed_out(name)),
+        };
+
+        let output = String::from_utf8_lossy(&output);
+        let line = output.lines().find(|line| !line.trim().is_empty());
+        match line {
+            Some(line) => Ok(line.to_string()),
+            None => Err(crashed(name, status, process.stderr.finish().await)),
+        }
+    }
+
+    /// Sends one request to the plugin's long-lived process, starting it if
+    /// needed. A process that fails a call is stopped and replaced on the
+    /// next call.
+    async fn call_persistent(&self, plugin: &Plugin, request: &str) -> Result<String, PluginError> {
+        let timeout = Duration::from_secs(self.limits.timeout_secs);
+        let name = &plugin.manifest.name;
+        let mut slot = plugin.process.lock().await;
+        let mut process = match slot.take() {
+            Some(process) => process,
+            None => spawn(&plugin.manifest, &self.limits)?,
+        };
+
+        let exchange = async {
+            write_request(name, &mut process.stdin, request).await?;
+            let mut line = Vec::new();
+            (&mut process.stdout)
+                .take(self.limits.max_output_bytes + 1)
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(|source| io_error(name, source))?;
+            if line.len() as u64 > self.limits.max_output_bytes {
+                return Err(self.output_too_large(name));
+            }
+            Ok(line)
+        };
+        let line = match tokio::time::timeout(timeout, exchange).await {
+            Ok(Ok(line)) if line.ends_with(b"\n") => line,
+            Ok(Ok(_)) => {
+                // End of output: the process is gone or going.
+                let status = process.child.wait().await;
+                let stderr = process.stderr.finish().await;
+                return Err(match status {
+                    Ok(status) => crashed(name, status, stderr),
+                    Err(source) => io_error(name, source),
+                });
+            }
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Err(self.timed_out(name)),
+        };
+        *slot = Some(process);
+        Ok(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    fn timed_out(&self, plugin: &str) -> PluginError {
+        PluginError::Timeout {
+            plugin: plugin.to_string(),
+            timeout_secs: self.limits.timeout_secs,
+        }
+    }
+
+    fn output_too_large(&self, plugin: &str) -> PluginError {
+        PluginError::OutputTooLarge {
+            plugin: plugin.to_string(),
+            limit_bytes: self.limits.max_output_bytes,
+        }
+    }
+}
+
+impl ToolRunner for ProcessRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.manifest.tools.iter().cloned())
+            .collect()
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let Some(&index) = self.routes.get(&call.name) else {
+                return Err(ToolExecutionError::new(format!(
+                    "unknown tool '{}'",
+                    call.name
+                )));
+            };
+            self.call(&self.plugins[index], call)
+                .await
+                .map(ToolOutput::new)
+                .map_err(|err| {
+                    if !matches!(err, PluginError::ToolFailed { .. }) {
+                        warn!(error = %err, "plugin call failed");
+                    }
+                    ToolExecutionError::new(err.to_string())
+                })
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PluginResponse {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    output: Option<Value>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+fn parse_response(plugin: &str, tool: &str, id: u64, line: &str) -> Result<String, PluginError> {
+    let invalid = |message: String| PluginError::InvalidResponse {
+        plugin: plugin.to_string(),
+        message,
+    };
+    let response: PluginResponse =
+        serde_json::from_str(line).map_err(|err| invalid(format!("{err} in {line:?}")))?;
+    if response.id.is_some_and(|answered| answered != id) {
+        return Err(invalid(format!(
+            "expected id {id}, got {}",
+            response.id.unwrap_or_default()
+        )));
+    }
+    match (response.output, response.error) {
+        (_, Some(message)) => Err(PluginError::ToolFailed {
+            tool: tool.to_string(),
+            message,
+        }),
+        (Some(Value::String(output)), None) => Ok(output),
+        (Some(output), None) => Ok(output.to_string()),
+        (None, None) => Err(invalid("response has neither output nor error".to_string())),
+    }
+}
+
+fn spawn(
+    manifest: &PluginManifest,
+    limits: &ToolResourceLimits,
+) -> Result<PluginProcess, PluginError> {
+    let mut command = Command::new(&manifest.command);
+    command
+        .args(&manifest.args)
+        .current_dir(&manifest.dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    limit_memory(&mut command, limits.memory_mb);
+    let mut child = command.spawn().map_err(|source| PluginError::Spawn {
+        plugin: manifest.name.clone(),
+        command: manifest.command.clone(),
+        source,
+    })?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+    Ok(PluginProcess {
+        child,
+        stdin,
+        stdout: BufReader::new(stdout),
+        stderr: StderrTail::spawn(manifest.name.clone(), stderr),
+    })
+}
+
+/// Caps the plugin's address space at `memory_mb`; allocations beyond it
+/// fail inside the plugin.
+#[cfg(unix)]
+fn limit_memory(command: &mut Command, memory_mb: u64) {
+    let bytes = memory_mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+    // SAFETY: the hook runs in the forked child before exec and only calls
+    // setrlimit, which is async-signal-safe.
+    unsafe {
+        command.pre_exec(move || {
+            let limit = libc::rlimit {
+                rlim_cur: bytes,
+                rlim_max: bytes,
+            };
+            if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_memory(_command: &mut Command, _memory_mb: u64) {}
+
+async fn write_request(
+    plugin: &str,
+    stdin: &mut ChildStdin,
+    request: &str,
+) -> Result<(), PluginError> {
+    stdin
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|source| io_error(plugin, source))?;
+    stdin
+        .flush()
+        .await
+        .map_err(|source| io_error(plugin, source))
+}
+
+fn io_error(plugin: &str, source: io::Error) -> PluginError {
+    PluginError::Io {
+        plugin: plugin.to_string(),
+        source,
+    }
+}
+
+fn crashed(plugin: &str, status: ExitStatus, stderr: String) -> PluginError {
+    PluginError::Crashed {
+        plugin: plugin.to_string(),
+        status: status.to_string(),
+        stderr,
+    }
+}
+
+/// Logs a plugin's stderr at debug level and keeps its last
+/// [`STDERR_TAIL_BYTES`] for error messages.
+struct StderrTail {
+    tail: Arc<Mutex<String>>,
+    reader: JoinHandle<()>,
+}
+
+impl StderrTail {
+    fn spawn(plugin: String, stderr: ChildStderr) -> Self {
+        let tail = Arc::new(Mutex::new(String::new()));
+        let shared = Arc::clone(&tail);
+        let reader = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!(plugin = %plugin, "{line}");
+                let mut tail = shared
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                if !tail.is_empty() {
+                    tail.push('\n');
+                }
+                tail.push_str(&line);
+                if tail.len() > STDERR_TAIL_BYTES {
+                    let mut cut = tail.len() - STDERR_TAIL_BYTES;
+                    while !tail.is_char_boundary(cut) {
+                        cut += 1;
+                    }
+                    tail.drain(..cut);
+                }
+            }
+        });
+        Self { tail, reader }
+    }
+
+    /// Waits briefly for the rest of the stream, then returns the tail.
+    async fn finish(self) -> String {
+        let _ = tokio::time::timeout(Duration::from_millis(200), self.reader).await;
+        let tail = self
+            .tail
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        tail.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    use super::{PluginError, PluginManifest, ProcessRunner, parse_response};
+    use crate::agent::tools::ToolRunner;
+    use crate::config::ToolResourceLimits;
+    use crate::test_fs::unique_temp_dir;
+
+    fn write_manifest(root: &Path, dir: &str, manifest: &str) {
+        let dir = root.join(dir);
+        fs::create_dir_all(&dir).expect("failed to create plugin directory");
+        fs::write(dir.join("plugin.json"), manifest).expect("failed to write manifest");
+    }
+
+    #[test]
+    fn manifest_load_resolves_relative_commands_against_the_plugin_dir() {
+        let root = unique_temp_dir("manifest");
+        write_manifest(
+            &root,
+            "tickets",
+            r#"{"name": "tickets", "command": "./bin/tickets", "persistent": true,
+                "tools": [{"name": "tickets.create", "description": "creates a ticket.",
+                           "parameters": {"type": "object"}}]}"#,
+        );
+
+        let manifest = PluginManifest::load(&root.join("tickets")).expect("manifest should load");
+        assert_eq!(manifest.name, "tickets");
+        assert_eq!(
+            manifest.command,
+            root.join("tickets")
+                .join("./bin/tickets")
+                .display()
+                .to_string()
+        );
+        assert!(manifest.persistent);
+        assert_eq!(manifest.tools[0].name, "tickets.create");
+        assert!(manifest.tools[0].parameters.is_some());
+
+        fs::remove_dir_all(&root).expect("failed to remove temp directory");
+    }
+
+    #[test]
+    fn discover_skips_broken_and_clashing_plugins() {
+        let root = unique_temp_dir("discover");
+        write_manifest(
+            &root,
+            "a-good",
+            r#"{"name": "good", "command": "python3", "tools": [{"name": "good.run", "description": "runs."}]}"#,
+        );
+        write_manifest(&root, "b-broken", r#"{"name": "broken", "tools": []}"#);
+        write_manifest(
+            &root,
+            "c-clash",
+            r#"{"name": "clash", "command": "python3", "tools": [{"name": "good.run", "description": "again."}]}"#,
+        );
+        fs::create_dir_all(root.join("d-not-a-plugin")).expect("failed to create directory");
+
+        let (runner, errors) = ProcessRunner::discover(&root, ToolResourceLimits::default());
+        let names: Vec<String> = runner.tools().into_iter().map(|tool| tool.name).collect();
+        assert_eq!(names, ["good.run"]);
+        assert_eq!(errors.len(), 2);
+        assert!(
+            errors
+                .iter()
+                .all(|err| matches!(err, PluginError::Manifest { .. }))
+        );
+        assert!(errors[1].to_string().contains("already offered"));
+
+        fs::remove_dir_all(&root).expect("failed to remove temp directory");
+    }
+
+    #[test]
+    fn discover_treats_a_missing_directory_as_no_plugins() {
+        let (runner, errors) = ProcessRunner::discover(
+            &PathBuf::from("/nonexistent/fizz/plugins"),
+            ToolResourceLimits::default(),
+        );
+        assert!(runner.tools().is_empty());
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn parse_response_maps_output_errors_and_mismatched_ids() {
+        assert_eq!(
+            parse_response("p", "p.run", 3, r#"{"id": 3, "output": "done"}"#).expect("output"),
+            "done"
+        );
+        assert_eq!(
+            parse_response("p", "p.run", 3, r#"{"output": {"rows": 2}}"#).expect("output"),
+            r#"{"rows":2}"#
+        );
+
+        let err = parse_response("p", "p.run", 3, r#"{"id": 3, "error": "no such row"}"#)
+            .expect_err("error responses should fail");
+        assert_eq!(err.to_string(), "p.run failed: no such row");
+
+        let err = parse_response("p", "p.run", 3, r#"{"id": 2, "output": "stale"}"#)
+            .expect_err("mismatched ids should fail");
+        assert!(matches!(err, PluginError::InvalidResponse { .. }));
+
+        assert!(parse_response("p", "p.run", 3, "{}").is_err());
+        assert!(parse_response("p", "p.run", 3, "not json").is_err());
+    }
+}
@@ -1,6 +1,12 @@
-use anyhow::anyhow;
+use reqwest::{Client, StatusCode};
+use serde::Serialize;
 use std::error::Error as StdError;
 use std::io::ErrorKind;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::{ApiKey, ModelTarget};
+use crate::model_error::{ModelError, RequestKind};
 
 fn error_chain_has_connection_refused(err: &(dyn StdError + 'static)) -> bool {
     let mut current: Option<&(dyn StdError + 'static)> = Some(err);
@@ -52,54 +58,132 @@ pub(crate) fn model_api_request_error(
     err: reqwest::Error,
     api_url: &str,
     timeout_secs: u64,
-) -> anyhow::Error {
+    kind: RequestKind,
+) -> ModelError {
+    let api_url = api_url.to_string();
     if err.is_timeout() || error_chain_has_timeout(&err) {
-        return anyhow!(
-            "Model request timed out after {}s while calling '{}'. \
-             Increase MODEL_TIMEOUT_SECS or check model responsiveness.",
+        return ModelError::Timeout {
+            api_url,
             timeout_secs,
-            api_url
-        );
+        };
     }
 
     if err.is_connect() {
         if error_chain_has_connection_refused(&err) {
-            return anyhow!(
-                "Connection refused by model API at '{}'. \
-                 Ensure the model provider is running and MODEL_BASE_URL is correct.",
-                api_url
-            );
+            return ModelError::ConnectionRefused { api_url, kind };
         }
+        return ModelError::Connect { api_url, kind };
+    }
 
-        return anyhow!(
-            "Failed to connect to model API at '{}'. \
-             Check MODEL_BASE_URL and network connectivity.",
-            api_url
+    ModelError::Request {
+        api_url,
+        message: err.to_string(),
+    }
+}
+
+/// Posts `body` once, with `bearer` as the token when given, mapping transport
+/// failures and non-success statuses to [`ModelError`]s for
+/// [`with_retry`](crate::providers::retry::with_retry) to classify.
+pub(crate) async fn send_attempt(
+    client: &Client,
+    target: &ModelTarget,
+    api_url: &str,
+    body: &impl Serialize,
+    attempt: u32,
+    bearer: Option<&ApiKey>,
+    kind: RequestKind,
+) -> Result<reqwest::Response, ModelError> {
+    let mut request = client
+        .post(api_url)
+        .timeout(Duration::from_secs(target.timeout_secs))
+        .json(body);
+    if let Some(api_key) = bearer {
+        request = request.bearer_auth(api_key.expose());
+    }
+    let response = request.send().await.map_err(|err| {
+        warn!(
+            provider = %target.provider,
+            api_url = %api_url,
+            model = %target.model,
+            attempt,
+            error = %err,
+            "model request failed"
+        );
+        model_api_request_error(err, api_url, target.timeout_secs, kind)
+    })?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let response_body = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "<failed to read response body>".to_string());
+        warn!(
+            provider = %target.provider,
+            api_url = %api_url,
+            model = %target.model,
+            attempt,
+            status = %status,
+            response_body_len = response_body.len(),
+            "model provider returned non-success status"
         );
+        return Err(model_api_status_error(
+            status,
+            response_body,
+            &target.provider,
+            &target.model,
+        ));
+    }
+
+    Ok(response)
+}
+
+/// Maps a non-success response. A 404 whose body says a model was not found
+/// means the provider does not have the requested model; any other 404 (a
+/// wrong base URL or path, say) stays a plain status error.
+pub(crate) fn model_api_status_error(
+    status: StatusCode,
+    body: String,
+    provider: &str,
+    model: &str,
+) -> ModelError {
+    if status == StatusCode::NOT_FOUND && is_model_not_found_body(&body) {
+        return ModelError::ModelNotFound {
+            model: model.to_string(),
+            provider: provider.to_string(),
+            body,
+        };
     }
 
-    anyhow!("Failed to call model API at '{}': {}", api_url, err)
+    ModelError::HttpStatus {
+        code: status.as_u16(),
+        body,
+    }
+}
+
+/// Ollama answers a missing model with e.g.
+/// `{"error":"model 'qwen2.5:3b' not found"}`, OpenAI with the error code
+/// `model_not_found`.
+fn is_model_not_found_body(body: &str) -> bool {
+    let body = body.to_ascii_lowercase();
+    body.contains("model_not_found")
+        || body
+            .find("model")
+            .is_some_and(|start| body[start..].contains("not found"))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{error_chain_has_timeout, model_api_request_error};
-    use reqwest::Client;
-    use std::net::TcpListener;
+    use super::{error_chain_has_timeout, model_api_request_error, model_api_status_error};
+    use crate::model_error::{ModelError, RequestKind};
+    use crate::test_http::{StubResponse, serve_with, unused_url};
+    use reqwest::{Client, StatusCode};
     use std::thread;
     use std::time::Duration;
 
-    fn free_local_addr() -> std::net::SocketAddr {
-        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
-        let addr = listener.local_addr().expect("address should be available");
-        drop(listener);
-        addr
-    }
-
     #[tokio::test]
     async fn maps_connection_refused_errors_to_actionable_message() {
-        let addr = free_local_addr();
-        let api_url = format!("http://{}/api/chat", addr);
+        let api_url = format!("{}/api/chat", unused_url());
         let client = Client::builder()
             .timeout(Duration::from_millis(300))
             .build()
@@ -110,8 +194,9 @@ mod tests {
             .send()
             .await
             .expect_err("request should fail with connection-refused");
-        let mapped = model_api_request_error(req_err, &api_url, 1);
-        let msg = format!("{mapped:#}");
+        let mapped = model_api_request_error(req_err, &api_url, 1, RequestKind::Chat);
+        assert!(matches!(mapped, ModelError::ConnectionRefused { .. }));
+        let msg = mapped.to_string();
 
         assert!(
             msg.contains("Connection refused by model API"),
@@ -122,14 +207,12 @@ mod tests {
 
     #[tokio::test]
     async fn maps_timeout_errors_to_actionable_message() {
-        let listener = TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
-        let addr = listener.local_addr().expect("address should be available");
-        let server = thread::spawn(move || {
-            let (_stream, _) = listener.accept().expect("accept should succeed");
+        let (base_url, server) = serve_with(1, |_| {
             thread::sleep(Duration::from_secs(1));
+            StubResponse::json(200, "{}")
         });
 
-        let api_url = format!("http://{}/api/chat", addr);
+        let api_url = format!("{base_url}/api/chat");
         let client = Client::builder()
             .timeout(Duration::from_millis(100))
             .build()
@@ -140,8 +223,15 @@ mod tests {
             .send()
             .await
             .expect_err("request should fail with timeout");
-        let mapped = model_api_request_error(req_err, &api_url, 2);
-        let msg = format!("{mapped:#}");
+        let mapped = model_api_request_error(req_err, &api_url, 2, RequestKind::Chat);
+        assert!(matches!(
+            mapped,
+            ModelError::Timeout {
+                timeout_secs: 2,
+                ..
+            }
+        ));
+        let msg = mapped.to_string();
 
         assert!(
             msg.contains("Model request timed out after 2s"),
@@ -155,6 +245,43 @@ mod tests {
         server.join().expect("server thread should join");
     }
 
+    #[test]
+    fn maps_not_found_status_to_model_not_found() {
+        let body = r#"{"error":"model 'missing' not found"}"#.to_string();
+        assert_eq!(
+            model_api_status_error(StatusCode::NOT_FOUND, body.clone(), "ollama", "missing"),
+            ModelError::ModelNotFound {
+                model: "missing".to_string(),
+                provider: "ollama".to_string(),
+                body,
+            }
+        );
+        assert_eq!(
+            model_api_status_error(
+                StatusCode::NOT_FOUND,
+                "404 page not found".to_string(),
+                "ollama",
+                "m"
+            ),
+            ModelError::HttpStatus {
+                code: 404,
+                body: "404 page not found".to_string(),
+            }
+        );
+        assert_eq!(
+            model_api_status_error(
+                StatusCode::BAD_GATEWAY,
+                "upstream".to_string(),
+                "ollama",
+                "m"
+            ),
+            ModelError::HttpStatus {
+                code: 502,
+                body: "upstream".to_string(),
+            }
+        );
+    }
+
     #[test]
     fn detects_timeout_from_error_kind() {
         let err = std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out");
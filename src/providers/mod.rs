@@ -1,2 +1,5 @@
 pub(crate) mod http_errors;
 pub mod ollama;
+pub mod ollama_admin;
+pub mod openai;
+pub(crate) mod retry;
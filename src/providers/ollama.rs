@@ -1,28 +1,94 @@
-use anyhow::{Context, Result, anyhow};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, warn};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::debug;
 
-use crate::config::Config;
-use crate::model::Message;
-use crate::providers::http_errors::model_api_request_error;
+use crate::config::{Config, GenerationOptions, ModelTarget};
+use crate::model::{Message, MessageRole, ModelUsage, ProviderReply};
+use crate::model_error::{ModelError, RequestKind};
+use crate::output_schema::OutputFormat;
+use crate::providers::http_errors::{model_api_request_error, send_attempt};
+use crate::providers::retry::with_retry;
 
 #[derive(Debug, Serialize)]
 struct OllamaChatRequest {
     model: String,
     stream: bool,
     messages: Vec<ChatMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    keep_alive: Option<Value>,
+    /// `"json"` or a JSON Schema the response must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<Value>,
+}
+
+/// Ollama's `options` object; see the Modelfile parameter docs.
+#[derive(Debug, Default, PartialEq, Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_ctx: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
 struct ChatMessage {
     role: String,
     content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+    /// Base64-encoded images, for vision models.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct OllamaChatResponse {
     message: ChatMessageResponse,
+    #[serde(flatten)]
+    metrics: OllamaMetrics,
+}
+
+/// Counts and durations (in nanoseconds) Ollama reports with a finished
+/// response.
+#[derive(Debug, Default, Deserialize)]
+struct OllamaMetrics {
+    #[serde(default)]
+    prompt_eval_count: u64,
+    #[serde(default)]
+    eval_count: u64,
+    #[serde(default)]
+    load_duration: u64,
+    #[serde(default)]
+    prompt_eval_duration: u64,
+    #[serde(default)]
+    eval_duration: u64,
+}
+
+impl OllamaMetrics {
+    fn usage(&self) -> ModelUsage {
+        ModelUsage {
+            prompt_tokens: self.prompt_eval_count,
+            completion_tokens: self.eval_count,
+            load_duration: Duration::from_nanos(self.load_duration),
+            prompt_duration: Duration::from_nanos(self.prompt_eval_duration),
+            completion_duration: Duration::from_nanos(self.eval_duration),
+            latency: Duration::ZERO,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,84 +96,360 @@ struct ChatMessageResponse {
     content: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct OllamaChatStreamChunk {
+    #[serde(default)]
+    message: Option<ChatMessageResponse>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(flatten)]
+    metrics: OllamaMetrics,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
 fn chat_url(base_url: &str) -> String {
     format!("{}/api/chat", base_url.trim_end_matches('/'))
 }
 
+fn embed_url(base_url: &str) -> String {
+    format!("{}/api/embed", base_url.trim_end_matches('/'))
+}
+
+/// Maps generation options to Ollama's wire format, omitting `options` when
+/// nothing is set so the model's Modelfile defaults apply.
+fn to_ollama_options(options: &GenerationOptions) -> (Option<OllamaOptions>, Option<Value>) {
+    let ollama_options = OllamaOptions {
+        temperature: options.temperature,
+        top_p: options.top_p,
+        top_k: options.top_k,
+        seed: options.seed,
+        num_predict: options.max_tokens,
+        stop: options.stop.clone(),
+        num_ctx: options.num_ctx,
+    };
+    let ollama_options = (ollama_options != OllamaOptions::default()).then_some(ollama_options);
+    // Ollama reads a bare number as seconds and a string as a duration.
+    let keep_alive = options.keep_alive.as_deref().map(|keep_alive| {
+        keep_alive
+            .parse::<i64>()
+            .map(Value::from)
+            .unwrap_or_else(|_| Value::from(keep_alive))
+    });
+    (ollama_options, keep_alive)
+}
+
+fn to_ollama_format(format: Option<&OutputFormat>) -> Option<Value> {
+    format.map(|format| match format {
+        OutputFormat::Json => Value::from("json"),
+        OutputFormat::Schema(schema) => schema.clone(),
+    })
+}
+
 fn to_ollama_messages(messages: &[Message]) -> Vec<ChatMessage> {
     messages
         .iter()
         .map(|msg| ChatMessage {
             role: msg.role.as_str().to_string(),
             content: msg.content.clone(),
+            tool_name: match &msg.role {
+                MessageRole::Tool { tool_name, .. } => Some(tool_name.clone()),
+                _ => None,
+            },
+            images: msg.images.iter().map(|image| image.data.clone()).collect(),
         })
         .collect()
 }
 
-pub async fn chat(client: &Client, cfg: &Config, messages: &[Message]) -> Result<String> {
-    let api_url = chat_url(&cfg.model_base_url);
+async fn send_chat_request(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    api_url: &str,
+    messages: &[Message],
+    options: &GenerationOptions,
+    stream: bool,
+) -> Result<reqwest::Response, ModelError> {
+    let (ollama_options, keep_alive) = to_ollama_options(options);
     let body = OllamaChatRequest {
-        model: cfg.model.clone(),
-        stream: false,
+        model: target.model.clone(),
+        stream,
         messages: to_ollama_messages(messages),
+        options: ollama_options,
+        keep_alive,
+        format: to_ollama_format(options.output_format.as_ref()),
     };
+
+    with_retry(&cfg.model_retry, |attempt| {
+        debug!(
+            api_url = %api_url,
+            model = %target.model,
+            message_count = body.messages.len(),
+            stream = body.stream,
+            attempt,
+            "sending ollama chat request"
+        );
+        send_attempt(
+            client,
+            target,
+            api_url,
+            &body,
+            attempt,
+            None,
+            RequestKind::Chat,
+        )
+    })
+    .await
+}
+
+pub async fn chat(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    messages: &[Message],
+    options: &GenerationOptions,
+) -> Result<ProviderReply, ModelError> {
+    let api_url = chat_url(&target.base_url);
+    let response =
+        send_chat_request(client, cfg, target, &api_url, messages, options, false).await?;
+
+    let parsed: OllamaChatResponse = response.json().await.map_err(|err| ModelError::Decode {
+        context: "Failed to parse model chat response".to_string(),
+        message: err.to_string(),
+    })?;
+    let usage = parsed.metrics.usage();
     debug!(
-        api_url = %api_url,
-        model = %cfg.model,
-        message_count = messages.len(),
-        "sending ollama chat request"
+        model = %target.model,
+        response_len = parsed.message.content.len(),
+        prompt_tokens = usage.prompt_tokens,
+        completion_tokens = usage.completion_tokens,
+        "received ollama chat response"
     );
+    Ok(ProviderReply {
+        content: parsed.message.content,
+        usage,
+    })
+}
 
-    let response = client
-        .post(&api_url)
-        .json(&body)
-        .send()
-        .await
-        .map_err(|err| {
-            warn!(
-                api_url = %api_url,
-                model = %cfg.model,
-                error = %err,
-                "ollama request failed"
-            );
-            model_api_request_error(err, &api_url, cfg.model_timeout_secs)
-        })?;
+pub async fn chat_stream(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    messages: &[Message],
+    options: &GenerationOptions,
+    on_delta: &mut (dyn FnMut(&str) + Send),
+) -> Result<ProviderReply, ModelError> {
+    let api_url = chat_url(&target.base_url);
+    let mut response =
+        send_chat_request(client, cfg, target, &api_url, messages, options, true).await?;
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let response_body = response
-            .text()
-            .await
-            .unwrap_or_else(|_| "<failed to read response body>".to_string());
-        warn!(
+    let mut pending = Vec::new();
+    let mut reply = ProviderReply::default();
+    let mut done = false;
+    while !done {
+        let Some(bytes) = response.chunk().await.map_err(|err| {
+            model_api_request_error(err, &api_url, target.timeout_secs, RequestKind::Chat)
+        })?
+        else {
+            break;
+        };
+        pending.extend_from_slice(&bytes);
+
+        while let Some(newline) = pending.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=newline).collect();
+            done = apply_stream_line(&line, &mut reply, on_delta)?;
+            if done {
+                break;
+            }
+        }
+    }
+    if !done {
+        apply_stream_line(&pending, &mut reply, on_delta)?;
+    }
+
+    debug!(
+        model = %target.model,
+        response_len = reply.content.len(),
+        prompt_tokens = reply.usage.prompt_tokens,
+        completion_tokens = reply.usage.completion_tokens,
+        "received ollama chat stream"
+    );
+    Ok(reply)
+}
+
+/// Embeds `texts` in one request to `/api/embed`, returning one vector per
+/// text in input order.
+pub async fn embed(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, ModelError> {
+    let api_url = embed_url(&target.base_url);
+    let body = OllamaEmbedRequest {
+        model: &target.model,
+        input: texts,
+    };
+    let response = with_retry(&cfg.model_retry, |attempt| {
+        debug!(
             api_url = %api_url,
-            model = %cfg.model,
-            status = %status,
-            response_body_len = response_body.len(),
-            "ollama returned non-success status"
+            model = %target.model,
+            text_count = texts.len(),
+            attempt,
+            "sending ollama embed request"
         );
-        return Err(anyhow!(
-            "Model request failed with status {}: {}",
-            status,
-            response_body
-        ));
-    }
+        send_attempt(
+            client,
+            target,
+            &api_url,
+            &body,
+            attempt,
+            None,
+            RequestKind::Embedding,
+        )
+    })
+    .await?;
 
-    let parsed: OllamaChatResponse = response
-        .json()
-        .await
-        .context("Failed to parse model chat response")?;
+    let parsed: OllamaEmbedResponse = response.json().await.map_err(|err| ModelError::Decode {
+        context: "Failed to parse model embed response".to_string(),
+        message: err.to_string(),
+    })?;
+    if parsed.embeddings.len() != texts.len() {
+        return Err(ModelError::Decode {
+            context: "Failed to parse model embed response".to_string(),
+            message: format!(
+                "expected {} embeddings, got {}",
+                texts.len(),
+                parsed.embeddings.len()
+            ),
+        });
+    }
     debug!(
-        model = %cfg.model,
-        response_len = parsed.message.content.len(),
-        "received ollama chat response"
+        model = %target.model,
+        embedding_count = parsed.embeddings.len(),
+        "received ollama embeddings"
     );
-    Ok(parsed.message.content)
+    Ok(parsed.embeddings)
+}
+
+/// Applies one NDJSON line of a streamed chat response and reports whether the
+/// stream is done. The final line carries the usage metrics.
+fn apply_stream_line(
+    line: &[u8],
+    reply: &mut ProviderReply,
+    on_delta: &mut dyn FnMut(&str),
+) -> Result<bool, ModelError> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(false);
+    }
+
+    let chunk: OllamaChatStreamChunk =
+        serde_json::from_slice(line).map_err(|err| ModelError::Decode {
+            context: "Failed to parse model chat stream chunk".to_string(),
+            message: err.to_string(),
+        })?;
+    if let Some(message) = chunk.error {
+        return Err(ModelError::Stream { message });
+    }
+    if let Some(message) = chunk.message
+        && !message.content.is_empty()
+    {
+        reply.content.push_str(&message.content);
+        on_delta(&message.content);
+    }
+    if chunk.done {
+        reply.usage = chunk.metrics.usage();
+    }
+    Ok(chunk.done)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::chat_url;
+    use reqwest::Client;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{
+        apply_stream_line, chat, chat_url, embed, to_ollama_format, to_ollama_messages,
+        to_ollama_options,
+    };
+    use crate::config::{Config, GenerationOptions};
+    use crate::model::{ImageAttachment, Message, ModelUsage, ProviderReply};
+    use crate::model_error::ModelError;
+    use crate::output_schema::OutputFormat;
+    use crate::test_http::{StubResponse, serve};
+
+    fn test_config(model_base_url: String, max_attempts: u32) -> Config {
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.embedding_base_url = model_base_url.clone();
+        cfg.model_base_url = model_base_url;
+        cfg.model_retry.max_attempts = max_attempts;
+        cfg.model_retry.base_delay_ms = 1;
+        cfg.model_retry.max_delay_ms = 2;
+        cfg
+    }
+
+    #[test]
+    fn to_ollama_options_maps_set_fields_only() {
+        assert_eq!(
+            to_ollama_options(&GenerationOptions::default()),
+            (None, None)
+        );
+
+        let (options, keep_alive) = to_ollama_options(&GenerationOptions {
+            temperature: Some(0.0),
+            seed: Some(7),
+            max_tokens: Some(256),
+            stop: vec!["END".to_string()],
+            num_ctx: Some(8192),
+            keep_alive: Some("300".to_string()),
+            ..GenerationOptions::default()
+        });
+
+        assert_eq!(
+            serde_json::to_value(options).expect("options should serialize"),
+            json!({
+                "temperature": 0.0,
+                "seed": 7,
+                "num_predict": 256,
+                "stop": ["END"],
+                "num_ctx": 8192
+            })
+        );
+        assert_eq!(keep_alive, Some(json!(300)));
+        let (_, keep_alive) = to_ollama_options(&GenerationOptions {
+            keep_alive: Some("10m".to_string()),
+            ..GenerationOptions::default()
+        });
+        assert_eq!(keep_alive, Some(json!("10m")));
+    }
+
+    #[test]
+    fn to_ollama_format_sends_json_or_schema() {
+        let schema = json!({"type": "object", "required": ["answer"]});
+
+        assert_eq!(to_ollama_format(None), None);
+        assert_eq!(
+            to_ollama_format(Some(&OutputFormat::Json)),
+            Some(json!("json"))
+        );
+        assert_eq!(
+            to_ollama_format(Some(&OutputFormat::Schema(schema.clone()))),
+            Some(schema)
+        );
+    }
 
     #[test]
     fn chat_url_trims_trailing_slash() {
@@ -116,4 +458,175 @@ mod tests {
             "http://localhost:11434/api/chat"
         );
     }
+
+    #[tokio::test]
+    async fn chat_retries_transient_statuses() {
+        let (base_url, server) = serve(vec![
+            StubResponse::json(503, r#"{"error":"server busy"}"#),
+            StubResponse::json(500, r#"{"error":"model is loading"}"#),
+            StubResponse::json(
+                200,
+                r#"{"message":{"role":"assistant","content":"hi"},"done":true,"prompt_eval_count":12,"eval_count":3,"prompt_eval_duration":4000000,"eval_duration":1500000000}"#,
+            ),
+        ]);
+        let cfg = test_config(base_url, 3);
+
+        let reply = chat(
+            &Client::new(),
+            &cfg,
+            &cfg.primary_model_target(),
+            &[Message::user("hello")],
+            &GenerationOptions::default(),
+        )
+        .await
+        .expect("third attempt should succeed");
+
+        assert_eq!(reply.content, "hi");
+        assert_eq!(reply.usage.prompt_tokens, 12);
+        assert_eq!(reply.usage.completion_tokens, 3);
+        assert_eq!(reply.usage.prompt_duration, Duration::from_millis(4));
+        assert_eq!(reply.usage.completion_tokens_per_sec(), Some(2.0));
+        server.join().expect("server thread should join");
+    }
+
+    #[tokio::test]
+    async fn chat_does_not_retry_missing_model() {
+        let (base_url, server) = serve(vec![StubResponse::json(
+            404,
+            r#"{"error":"model 'missing' not found"}"#,
+        )]);
+        let cfg = test_config(base_url, 3);
+
+        let err = chat(
+            &Client::new(),
+            &cfg,
+            &cfg.primary_model_target(),
+            &[Message::user("hello")],
+            &GenerationOptions::default(),
+        )
+        .await
+        .expect_err("404 should fail without retrying");
+
+        assert_eq!(
+            err,
+            ModelError::ModelNotFound {
+                model: "qwen2.5:3b".to_string(),
+                provider: "ollama".to_string(),
+                body: r#"{"error":"model 'missing' not found"}"#.to_string(),
+            }
+        );
+        server.join().expect("server thread should join");
+    }
+
+    #[tokio::test]
+    async fn embed_returns_vectors_and_checks_their_count() {
+        let (base_url, server) = serve(vec![
+            StubResponse::json(503, r#"{"error":"server busy"}"#),
+            StubResponse::json(
+                200,
+                r#"{"model":"nomic-embed-text","embeddings":[[0.1,0.2],[0.3,0.4]]}"#,
+            ),
+            StubResponse::json(200, r#"{"embeddings":[[0.5]]}"#),
+        ]);
+        let cfg = test_config(base_url, 2);
+        let target = cfg.embedding_model_target();
+        let texts = vec!["a".to_string(), "b".to_string()];
+
+        let vectors = embed(&Client::new(), &cfg, &target, &texts)
+            .await
+            .expect("retry should succeed");
+        let err = embed(&Client::new(), &cfg, &target, &texts)
+            .await
+            .expect_err("short response should fail");
+
+        assert_eq!(vectors, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+        assert!(
+            matches!(&err, ModelError::Decode { message, .. } if message == "expected 2 embeddings, got 1"),
+            "{err}"
+        );
+        server.join().expect("server thread should join");
+    }
+
+    #[test]
+    fn to_ollama_messages_maps_tool_results_to_native_tool_role() {
+        let messages = to_ollama_messages(&[
+            Message::user("what time?"),
+            Message::tool("time.now", "call_1_1_0", "noon"),
+        ]);
+        let json = serde_json::to_value(&messages).expect("messages should serialize");
+
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"role": "user", "content": "what time?"},
+                {"role": "tool", "content": "noon", "tool_name": "time.now"},
+            ])
+        );
+    }
+
+    #[test]
+    fn to_ollama_messages_sends_images_as_base64() {
+        let messages = to_ollama_messages(&[Message::user("what is this?")
+            .with_images(vec![ImageAttachment::new("image/png", b"png-bytes")])]);
+        let json = serde_json::to_value(&messages).expect("messages should serialize");
+
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"role": "user", "content": "what is this?", "images": ["cG5nLWJ5dGVz"]},
+            ])
+        );
+    }
+
+    #[test]
+    fn apply_stream_line_accumulates_deltas_until_done() {
+        let mut reply = ProviderReply::default();
+        let mut deltas = Vec::new();
+        let mut on_delta = |delta: &str| deltas.push(delta.to_string());
+
+        let lines = [
+            r#"{"message":{"role":"assistant","content":"Hel"},"done":false}"#,
+            "",
+            r#"{"message":{"role":"assistant","content":"lo"},"done":false}"#,
+            r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":7,"eval_count":2,"load_duration":5000}"#,
+        ];
+        let done: Vec<bool> = lines
+            .iter()
+            .map(|line| {
+                apply_stream_line(line.as_bytes(), &mut reply, &mut on_delta)
+                    .expect("line should parse")
+            })
+            .collect();
+
+        assert_eq!(done, vec![false, false, false, true]);
+        assert_eq!(reply.content, "Hello");
+        assert_eq!(deltas, vec!["Hel".to_string(), "lo".to_string()]);
+        assert_eq!(
+            reply.usage,
+            ModelUsage {
+                prompt_tokens: 7,
+                completion_tokens: 2,
+                load_duration: Duration::from_micros(5),
+                ..ModelUsage::default()
+            }
+        );
+    }
+
+    #[test]
+    fn apply_stream_line_surfaces_stream_errors() {
+        let mut reply = ProviderReply::default();
+        let err = apply_stream_line(
+            br#"{"error":"model runner crashed"}"#,
+            &mut reply,
+            &mut |_| {},
+        )
+        .expect_err("error chunk should fail");
+
+        assert_eq!(
+            err,
+            ModelError::Stream {
+                message: "model runner crashed".to_string(),
+            }
+        );
+    }
 }
@@ -0,0 +1,377 @@
+use reqwest::{Client, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::model_error::{ModelError, RequestKind};
+use crate::providers::http_errors::{model_api_request_error, model_api_status_error};
+
+/// Upper bound for a model pull; large models take a long time to download,
+/// so the regular model timeout does not apply.
+const PULL_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// A model installed on the Ollama server, as listed by `/api/tags`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct InstalledModel {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+/// Model details reported by `/api/show`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ModelDetails {
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+    pub quantization_level: Option<String>,
+    /// Maximum context window in tokens, when the model metadata declares one.
+    pub context_length: Option<u64>,
+}
+
+/// One progress update streamed by `/api/pull`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+impl PullProgress {
+    /// Completed fraction of the current download, between 0 and 1.
+    pub fn fraction(&self) -> Option<f64> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => {
+                Some((completed as f64 / total as f64).min(1.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<InstalledModel>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModelRequest<'a> {
+    model: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowResponse {
+    #[serde(default)]
+    details: ShowDetails,
+    #[serde(default)]
+    model_info: Map<String, Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ShowDetails {
+    family: Option<String>,
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PullChunk {
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(flatten)]
+    progress: Option<PullProgress>,
+}
+
+/// Client for Ollama's model management endpoints.
+pub struct OllamaAdmin {
+    client: Client,
+    base_url: String,
+    timeout_secs: u64,
+}
+
+impl OllamaAdmin {
+    pub fn new(client: Client, base_url: impl Into<String>, timeout_secs: u64) -> Self {
+        Self {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout_secs,
+        }
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!("{}/api/{}", self.base_url, path)
+    }
+
+    /// Lists installed models (`/api/tags`).
+    pub async fn list_models(&self) -> Result<Vec<InstalledModel>, ModelError> {
+        let api_url = self.api_url("tags");
+        let response = self
+            .client
+            .get(&api_url)
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .send()
+            .await
+            .map_err(|err| {
+                model_api_request_error(err, &api_url, self.timeout_secs, RequestKind::Chat)
+            })?;
+        let tags: TagsResponse = decode_json(check_status(response, None).await?, "tags").await?;
+        debug!(model_count = tags.models.len(), "listed ollama models");
+        Ok(tags.models)
+    }
+
+    /// Whether `model` is installed. A name without a tag matches `:latest`,
+    /// the way Ollama resolves it.
+    pub async fn has_model(&self, model: &str) -> Result<bool, ModelError> {
+        let wanted = with_default_tag(model);
+        Ok(self
+            .list_models()
+            .await?
+            .iter()
+            .any(|installed| with_default_tag(&installed.name) == wanted))
+    }
+
+    /// Fetches details for an installed model (`/api/show`).
+    pub async fn show_model(&self, model: &str) -> Result<ModelDetails, ModelError> {
+        let api_url = self.api_url("show");
+        let response = self
+            .client
+            .post(&api_url)
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .json(&ModelRequest {
+                model,
+                stream: None,
+            })
+            .send()
+            .await
+            .map_err(|err| {
+                model_api_request_error(err, &api_url, self.timeout_secs, RequestKind::Chat)
+            })?;
+        let show: ShowResponse =
+            decode_json(check_status(response, Some(model)).await?, "show").await?;
+
+        Ok(ModelDetails {
+            family: show.details.family,
+            parameter_size: show.details.parameter_size,
+            quantization_level: show.details.quantization_level,
+            context_length: context_length(&show.model_info),
+        })
+    }
+
+    /// Downloads `model` (`/api/pull`), passing each progress update to
+    /// `on_progress` as it streams in.
+    pub async fn pull_model(
+        &self,
+        model: &str,
+        on_progress: &mut (dyn FnMut(&PullProgress) + Send),
+    ) -> Result<(), ModelError> {
+        let api_url = self.api_url("pull");
+        info!(model = %model, "pulling ollama model");
+        let mut response = self
+            .client
+            .post(&api_url)
+            .timeout(PULL_TIMEOUT)
+            .json(&ModelRequest {
+                model,
+                stream: Some(true),
+            })
+            .send()
+            .await
+            .map_err(|err| {
+                model_api_request_error(err, &api_url, PULL_TIMEOUT.as_secs(), RequestKind::Chat)
+            })?;
+        response = check_status(response, Some(model)).await?;
+
+        let mut pending = Vec::new();
+        while let Some(bytes) = response.chunk().await.map_err(|err| {
+            model_api_request_error(err, &api_url, PULL_TIMEOUT.as_secs(), RequestKind::Chat)
+        })? {
+            pending.extend_from_slice(&bytes);
+            while let Some(newline) = pending.iter().position(|byte| *byte == b'\n') {
+                let line: Vec<u8> = pending.drain(..=newline).collect();
+                apply_pull_line(&line, on_progress)?;
+            }
+        }
+        apply_pull_line(&pending, on_progress)?;
+
+        info!(model = %model, "pulled ollama model");
+        Ok(())
+    }
+}
+
+/// Maps a non-success response; for requests about a specific `model`, a 404
+/// means that model is not installed.
+async fn check_status(response: Response, model: Option<&str>) -> Result<Response, ModelError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "<failed to read response body>".to_string());
+    Err(match model {
+        Some(model) => model_api_status_error(status, body, "ollama", model),
+        None => ModelError::HttpStatus {
+            code: status.as_u16(),
+            body,
+        },
+    })
+}
+
+async fn decode_json<T: for<'de> Deserialize<'de>>(
+    response: Response,
+    endpoint: &str,
+) -> Result<T, ModelError> {
+    response.json().await.map_err(|err| ModelError::Decode {
+        context: format!("Failed to parse Ollama {endpoint} response"),
+        message: err.to_string(),
+    })
+}
+
+fn apply_pull_line(
+    line: &[u8],
+    on_progress: &mut (dyn FnMut(&PullProgress) + Send),
+) -> Result<(), ModelError> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(());
+    }
+
+    let chunk: PullChunk = serde_json::from_slice(line).map_err(|err| ModelError::Decode {
+        context: "Failed to parse Ollama pull progress".to_string(),
+        message: err.to_string(),
+    })?;
+    if let Some(message) = chunk.error {
+        return Err(ModelError::Stream { message });
+    }
+    if let Some(progress) = chunk.progress {
+        on_progress(&progress);
+    }
+    Ok(())
+}
+
+/// Only the part after the last `/` can carry a tag, so a registry host with
+/// a port (`host:5000/model`) does not count as one.
+fn with_default_tag(model: &str) -> String {
+    let name = model.rsplit('/').next().unwrap_or(model);
+    if name.contains(':') {
+        model.to_string()
+    } else {
+        format!("{model}:latest")
+    }
+}
+
+/// Ollama reports the context window as `<architecture>.context_length`.
+fn context_length(model_info: &Map<String, Value>) -> Option<u64> {
+    let architecture = model_info.get("general.architecture")?.as_str()?;
+    model_info
+        .get(&format!("{architecture}.context_length"))?
+        .as_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use serde_json::json;
+
+    use super::{OllamaAdmin, PullProgress, apply_pull_line, context_length, with_default_tag};
+    use crate::model_error::ModelError;
+    use crate::test_http::{StubResponse, serve};
+
+    #[test]
+    fn with_default_tag_appends_latest_to_untagged_names() {
+        assert_eq!(with_default_tag("llama3"), "llama3:latest");
+        assert_eq!(with_default_tag("qwen2.5:3b"), "qwen2.5:3b");
+        assert_eq!(
+            with_default_tag("registry.local:5000/llama3"),
+            "registry.local:5000/llama3:latest"
+        );
+        assert_eq!(
+            with_default_tag("registry.local:5000/llama3:8b"),
+            "registry.local:5000/llama3:8b"
+        );
+    }
+
+    #[tokio::test]
+    async fn has_model_matches_installed_names_with_default_tag() {
+        let tags = r#"{"models":[{"name":"llama3:latest","size":42},{"name":"qwen2.5:3b"}]}"#;
+        let (base_url, server) = serve(vec![
+            StubResponse::json(200, tags),
+            StubResponse::json(200, tags),
+        ]);
+        let admin = OllamaAdmin::new(Client::new(), format!("{base_url}/"), 1);
+
+        assert!(admin.has_model("llama3").await.expect("tags should load"));
+        assert!(!admin.has_model("mistral").await.expect("tags should load"));
+        server.join().expect("server thread should join");
+    }
+
+    #[tokio::test]
+    async fn show_model_reads_details_and_context_length() {
+        let (base_url, server) = serve(vec![StubResponse::json(
+            200,
+            r#"{"details":{"family":"qwen2","parameter_size":"3.1B","quantization_level":"Q4_K_M"},"model_info":{"general.architecture":"qwen2","qwen2.context_length":32768}}"#,
+        )]);
+        let admin = OllamaAdmin::new(Client::new(), base_url, 1);
+
+        let details = admin
+            .show_model("qwen2.5:3b")
+            .await
+            .expect("show should succeed");
+
+        assert_eq!(details.family.as_deref(), Some("qwen2"));
+        assert_eq!(details.quantization_level.as_deref(), Some("Q4_K_M"));
+        assert_eq!(details.context_length, Some(32768));
+        let requests = server.join().expect("server thread should join");
+        assert!(requests[0].head.starts_with("POST /api/show "));
+        assert!(requests[0].body.contains(r#""model":"qwen2.5:3b""#));
+    }
+
+    #[test]
+    fn context_length_reads_architecture_specific_key() {
+        let info = json!({
+            "general.architecture": "qwen2",
+            "qwen2.context_length": 32768,
+            "llama.context_length": 8192
+        });
+
+        assert_eq!(
+            context_length(info.as_object().expect("object")),
+            Some(32768)
+        );
+        assert_eq!(context_length(json!({}).as_object().expect("object")), None);
+    }
+
+    #[test]
+    fn apply_pull_line_reports_progress_and_errors() {
+        let mut updates = Vec::new();
+        let mut on_progress = |progress: &PullProgress| updates.push(progress.clone());
+
+        apply_pull_line(
+            br#"{"status":"pulling abc","digest":"sha256:abc","total":200,"completed":50}"#,
+            &mut on_progress,
+        )
+        .expect("progress line should parse");
+        apply_pull_line(b"  \n", &mut on_progress).expect("blank line should be skipped");
+        let err = apply_pull_line(
+            br#"{"error":"pull model manifest: file does not exist"}"#,
+            &mut on_progress,
+        )
+        .expect_err("error line should fail");
+
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].status, "pulling abc");
+        assert_eq!(updates[0].fraction(), Some(0.25));
+        assert_eq!(
+            err,
+            ModelError::Stream {
+                message: "pull model manifest: file does not exist".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! OpenAI-compatible endpoints. Only embeddings are implemented; chat is not.
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::{Config, ModelTarget};
+use crate::model_error::{ModelError, RequestKind};
+use crate::providers::http_errors::send_attempt;
+use crate::providers::retry::with_retry;
+
+#[derive(Debug, Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+fn embeddings_url(base_url: &str) -> String {
+    format!("{}/v1/embeddings", base_url.trim_end_matches('/'))
+}
+
+/// Embeds `texts` with `/v1/embeddings`, sending `EMBEDDING_API_KEY` as a
+/// bearer token when set.
+pub async fn embed(
+    client: &Client,
+    cfg: &Config,
+    target: &ModelTarget,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, ModelError> {
+    let api_url = embeddings_url(&target.base_url);
+    let body = EmbeddingsRequest {
+        model: &target.model,
+        input: texts,
+    };
+    let response = with_retry(&cfg.model_retry, |attempt| {
+        debug!(
+            api_url = %api_url,
+            model = %target.model,
+            text_count = texts.len(),
+            attempt,
+            "sending openai embeddings request"
+        );
+        send_attempt(
+            client,
+            target,
+            &api_url,
+            &body,
+            attempt,
+            cfg.embedding_api_key.as_ref(),
+            RequestKind::Embedding,
+        )
+    })
+    .await?;
+
+    let parsed: EmbeddingsResponse = response.json().await.map_err(|err| ModelError::Decode {
+        context: "Failed to parse model embed response".to_string(),
+        message: err.to_string(),
+    })?;
+    let embeddings = in_input_order(parsed.data, texts.len())?;
+    debug!(
+        model = %target.model,
+        embedding_count = embeddings.len(),
+        "received openai embeddings"
+    );
+    Ok(embeddings)
+}
+
+/// Orders the returned vectors by their `index`, requiring exactly one per
+/// input text.
+fn in_input_order(
+    mut data: Vec<EmbeddingData>,
+    expected: usize,
+) -> Result<Vec<Vec<f32>>, ModelError> {
+    let decode_error = |message: String| ModelError::Decode {
+        context: "Failed to parse model embed response".to_string(),
+        message,
+    };
+    if data.len() != expected {
+        return Err(decode_error(format!(
+            "expected {expected} embeddings, got {}",
+            data.len()
+        )));
+    }
+    data.sort_by_key(|item| item.index);
+    if data
+        .iter()
+        .enumerate()
+        .any(|(position, item)| item.index != position)
+    {
+        return Err(decode_error(
+            "embedding indexes do not match the input".to_string(),
+        ));
+    }
+    Ok(data.into_iter().map(|item| item.embedding).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+
+    use super::embed;
+    use crate::config::{ApiKey, Config};
+    use crate::model_error::ModelError;
+    use crate::test_http::{StubResponse, serve};
+
+    fn test_config(base_url: String) -> Config {
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.embedding_provider = "openai".to_string();
+        cfg.embedding_base_url = base_url;
+        cfg.embedding_model = "text-embedding-3-small".to_string();
+        cfg.embedding_api_key = Some(ApiKey::new("sk-test"));
+        cfg.model_retry.max_attempts = 1;
+        cfg
+    }
+
+    #[tokio::test]
+    async fn embed_sends_the_key_and_orders_vectors_by_index() {
+        let (base_url, server) = serve(vec![StubResponse::json(
+            200,
+            r#"{"object":"list","data":[{"object":"embedding","index":1,"embedding":[0.3,0.4]},{"object":"embedding","index":0,"embedding":[0.1,0.2]}]}"#,
+        )]);
+        let cfg = test_config(base_url);
+        let texts = vec!["a".to_string(), "b".to_string()];
+
+        let vectors = embed(&Client::new(), &cfg, &cfg.embedding_model_target(), &texts)
+            .await
+            .expect("embed should succeed");
+
+        assert_eq!(vectors, vec![vec![0.1, 0.2], vec![0.3, 0.4]]);
+        let requests = server.join().expect("server thread should join");
+        let head = &requests[0].head;
+        assert!(head.starts_with("POST /v1/embeddings "), "{head}");
+        assert!(
+            head.to_ascii_lowercase()
+                .contains("authorization: bearer sk-test"),
+            "{head}"
+        );
+        assert!(
+            requests[0]
+                .body
+                .contains(r#""model":"text-embedding-3-small""#)
+        );
+    }
+
+    #[tokio::test]
+    async fn embed_maps_an_unknown_model_to_model_not_found() {
+        let (base_url, server) = serve(vec![StubResponse::json(
+            404,
+            r#"{"error":{"message":"The model does not exist","type":"invalid_request_error","code":"model_not_found"}}"#,
+        )]);
+        let cfg = test_config(base_url);
+
+        let err = embed(
+            &Client::new(),
+            &cfg,
+            &cfg.embedding_model_target(),
+            &["a".to_string()],
+        )
+        .await
+        .expect_err("unknown model should fail");
+
+        assert!(
+            matches!(&err, ModelError::ModelNotFound { model, .. } if model == "text-embedding-3-small"),
+            "{err}"
+        );
+        assert!(!err.to_string().contains("ollama pull"), "{err}");
+        server.join().expect("server thread should join");
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::time::Duration;
+use tracing::{Span, debug, warn};
+
+use crate::config::ModelRetryPolicy;
+use crate::model_error::ModelError;
+
+/// Runs `attempt` until it succeeds, fails with an error that is not
+/// [`ModelError::is_retryable`], or the policy's attempts are used up. The
+/// last error is returned unchanged, so its actionable message reaches the
+/// caller.
+///
+/// The attempt count is recorded on the current span's `attempts` field when
+/// the span declares one.
+pub(crate) async fn with_retry<T, F, Fut>(
+    policy: &ModelRetryPolicy,
+    mut attempt: F,
+) -> Result<T, ModelError>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, ModelError>>,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt_number = 1;
+    loop {
+        Span::current().record("attempts", attempt_number);
+        debug!(
+            attempt = attempt_number,
+            max_attempts, "model request attempt"
+        );
+
+        let err = match attempt(attempt_number).await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        if !err.is_retryable() || attempt_number >= max_attempts {
+            return Err(err);
+        }
+
+        let delay = backoff_delay(policy, attempt_number);
+        warn!(
+            attempt = attempt_number,
+            max_attempts,
+            delay_ms = delay.as_millis() as u64,
+            error = %err,
+            "retrying transient model request failure"
+        );
+        tokio::time::sleep(delay).await;
+        attempt_number += 1;
+    }
+}
+
+/// Exponential backoff with jitter: the delay after attempt `n` is drawn from
+/// the upper half of `min(max_delay, base_delay * 2^(n-1))`.
+fn backoff_delay(policy: &ModelRetryPolicy, attempt_number: u32) -> Duration {
+    let exponent = attempt_number.saturating_sub(1).min(32);
+    let ceiling = policy
+        .base_delay_ms
+        .saturating_mul(1u64 << exponent)
+        .min(policy.max_delay_ms);
+    let half = ceiling / 2;
+    let jitter = if half == 0 {
+        0
+    } else {
+        random_u64() % (half + 1)
+    };
+    Duration::from_millis(ceiling - half + jitter)
+}
+
+fn random_u64() -> u64 {
+    // Each `RandomState` is seeded differently, which is plenty for jitter.
+    RandomState::new().hash_one(0u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::Duration;
+
+    use super::{backoff_delay, with_retry};
+    use crate::config::ModelRetryPolicy;
+    use crate::model_error::ModelError;
+
+    fn unavailable(body: impl Into<String>) -> ModelError {
+        ModelError::HttpStatus {
+            code: 503,
+            body: body.into(),
+        }
+    }
+
+    fn fast_policy(max_attempts: u32) -> ModelRetryPolicy {
+        ModelRetryPolicy {
+            max_attempts,
+            base_delay_ms: 1,
+            max_delay_ms: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn with_retry_retries_retryable_errors_until_success() {
+        let calls = Cell::new(0);
+
+        let value = with_retry(&fast_policy(3), |attempt| {
+            calls.set(calls.get() + 1);
+            async move {
+                if attempt < 3 {
+                    Err(unavailable("busy"))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await
+        .expect("third attempt should succeed");
+
+        assert_eq!(value, 3);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn with_retry_returns_last_error_when_attempts_run_out() {
+        let calls = Cell::new(0);
+
+        let err = with_retry(&fast_policy(2), |attempt| {
+            calls.set(calls.get() + 1);
+            async move { Err::<(), _>(unavailable(format!("attempt {attempt}"))) }
+        })
+        .await
+        .expect_err("all attempts should fail");
+
+        assert_eq!(calls.get(), 2);
+        assert_eq!(err, unavailable("attempt 2"));
+    }
+
+    #[tokio::test]
+    async fn with_retry_does_not_retry_fatal_errors() {
+        let calls = Cell::new(0);
+
+        let err = with_retry(&fast_policy(5), |_| {
+            calls.set(calls.get() + 1);
+            async {
+                Err::<(), _>(ModelError::ModelNotFound {
+                    model: "missing".to_string(),
+                    provider: "ollama".to_string(),
+                    body: "not found".to_string(),
+                })
+            }
+        })
+        .await
+        .expect_err("fatal error should fail immediately");
+
+        assert_eq!(calls.get(), 1);
+        assert!(matches!(err, ModelError::ModelNotFound { .. }));
+    }
+
+    #[test]
+    fn backoff_delay_grows_exponentially_within_jitter_bounds() {
+        let policy = ModelRetryPolicy {
+            max_attempts: 10,
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+        };
+
+        for _ in 0..50 {
+            let first = backoff_delay(&policy, 1);
+            assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+            let third = backoff_delay(&policy, 3);
+            assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+            let capped = backoff_delay(&policy, 30);
+            assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_millis(1_000));
+        }
+    }
+}
@@ -1,31 +1,59 @@
 use anyhow::{Context, Result};
 use reqwest::Client;
 use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
-use crate::agent::Agent;
-use crate::config::Config;
-use crate::model::Message;
+use crate::agent::events::{AgentEvent, AgentEventKind};
+use crate::agent::plan::StepStatus;
+use crate::agent::{Agent, TurnCancelled, TurnResult};
+use crate::config::{Config, ModelTarget};
+use crate::memory::{self, MemoryStore};
+use crate::model::{self, ImageAttachment, Message, MessageRole, RoleUsage, UsageTotals};
+use crate::model_error::ModelError;
+use crate::model_gateway::HostModelGateway;
+use crate::providers::ollama_admin::{ModelDetails, OllamaAdmin, PullProgress};
+use crate::retrieval::{self, IndexReport};
 
-pub async fn run_repl(client: &Client, cfg: &Config) -> Result<()> {
-    let mut agent = Agent::new(client, cfg);
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+const PULL_PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Token of the turn currently in flight, if any, shared with the SIGINT
+/// handler.
+type ActiveTurn = Arc<Mutex<Option<CancellationToken>>>;
+
+pub async fn run_repl(client: Client, cfg: Arc<Config>) -> Result<()> {
+    let model = cfg.model.clone();
+    let mut agent = crate::AgentFactory::new(client.clone(), Arc::clone(&cfg))
+        .await?
+        .build();
+    let gateway = HostModelGateway::new(client.clone(), Arc::clone(&cfg));
+    let mut events = agent.subscribe();
+    let active_turn = ActiveTurn::default();
+    spawn_interrupt_handler(active_turn.clone());
 
     println!("fizz agent harness");
-    println!("model: {}", cfg.model);
+    println!("model: {}", model);
     println!(
-        "type a prompt, '/history' to inspect memory, '/reset' to clear memory, or 'exit' to quit"
+        "type a prompt, '/attach <path>' to add an image, '/index <dir>' to index documents, '/memory' to manage long-term memory, '/history' to inspect memory, '/plan' to show the current plan, '/reset' to clear memory, '/usage' for token usage, or 'exit' to quit"
     );
+    println!("press Ctrl-C once to cancel a running turn, twice to exit");
+
+    let primary = cfg.primary_model_target();
+    match model::is_model_installed(&client, &primary).await {
+        Ok(true) => print_model_details(&client, &primary, cfg.generation.num_ctx).await,
+        Ok(false) => offer_model_pull(&client, &primary, &active_turn).await?,
+        Err(err) => warn!(error = %err, "could not check whether the model is installed"),
+    }
 
+    let mut last_turn_usage = None;
     loop {
-        print!("> ");
-        io::stdout().flush().context("Failed to flush stdout")?;
-
-        let mut input = String::new();
-        let read = io::stdin()
-            .read_line(&mut input)
-            .context("Failed to read stdin")?;
-        if read == 0 {
+        let Some(input) = read_line("> ")? else {
             break;
-        }
+        };
 
         let prompt = input.trim();
         if prompt.is_empty() {
@@ -43,14 +71,380 @@ pub async fn run_repl(client: &Client, cfg: &Config) -> Result<()> {
             print_history(agent.history());
             continue;
         }
+        if prompt.eq_ignore_ascii_case("/plan") {
+            match agent.plan() {
+                Some(plan) => println!("{plan}\n"),
+                None => println!("no plan yet; set AGENT_MODE=plan to plan turns\n"),
+            }
+            continue;
+        }
+        if let Some(path) = command_argument(prompt, "/attach") {
+            attach_image(&mut agent, path);
+            continue;
+        }
+        if let Some(argument) = command_argument(prompt, "/memory") {
+            if let Some(store) = agent.memory() {
+                manage_memory(store, argument);
+            }
+            continue;
+        }
+        if let Some(dir) = command_argument(prompt, "/index") {
+            index_docs(&gateway, &cfg, dir, &active_turn).await;
+            continue;
+        }
+        if prompt.eq_ignore_ascii_case("/usage") {
+            print_usage(last_turn_usage.as_ref(), agent.session_role_usage(), &cfg);
+            continue;
+        }
+
+        let cancel = CancellationToken::new();
+        set_active_turn(&active_turn, Some(cancel.clone()));
+        let result = run_turn_with_events(&mut agent, &mut events, prompt, &cancel).await;
+        set_active_turn(&active_turn, None);
+
+        match result {
+            Ok(turn) => last_turn_usage = Some(turn.role_usage),
+            Err(err) if err.is::<TurnCancelled>() => {}
+            Err(err) => {
+                let Some(missing) = err
+                    .downcast_ref::<ModelError>()
+                    .and_then(|model_err| model::missing_model_target(model_err, &cfg))
+                else {
+                    return Err(err);
+                };
+                offer_model_pull(&client, &missing, &active_turn).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_line(prompt: &str) -> Result<Option<String>> {
+    print!("{prompt}");
+    io::stdout().flush().context("Failed to flush stdout")?;
+
+    let mut input = String::new();
+    let read = io::stdin()
+        .read_line(&mut input)
+        .context("Failed to read stdin")?;
+    Ok((read > 0).then_some(input))
+}
+
+/// Prints what the provider reports about the target's model, warning when
+/// `MODEL_NUM_CTX` asks for a larger context than the model supports.
+async fn print_model_details(client: &Client, target: &ModelTarget, num_ctx: Option<u32>) {
+    match model::model_details(client, target).await {
+        Ok(details) => println!("{}", format_model_details(&details, num_ctx)),
+        Err(err) => warn!(error = %err, "could not fetch model details"),
+    }
+}
+
+fn format_model_details(details: &ModelDetails, num_ctx: Option<u32>) -> String {
+    let unknown = || "unknown".to_string();
+    let mut text = format!(
+        "family: {}, quantization: {}, context length: {}",
+        details.family.clone().unwrap_or_else(unknown),
+        details.quantization_level.clone().unwrap_or_else(unknown),
+        details
+            .context_length
+            .map(|tokens| format!("{tokens} tokens"))
+            .unwrap_or_else(unknown)
+    );
+    if let (Some(num_ctx), Some(context_length)) = (num_ctx, details.context_length)
+        && u64::from(num_ctx) > context_length
+    {
+        text.push_str(&format!(
+            "\nwarning: MODEL_NUM_CTX={num_ctx} exceeds the model's context length of {context_length} tokens"
+        ));
+    }
+    text
+}
 
-        let answer = agent.run_turn(prompt).await?;
-        println!("{}\n", answer.trim());
+/// Explains that the target's model is missing and, for Ollama targets, offers
+/// to pull it. A failed or cancelled pull leaves the REPL running.
+async fn offer_model_pull(
+    client: &Client,
+    target: &ModelTarget,
+    active_turn: &ActiveTurn,
+) -> Result<()> {
+    println!(
+        "model '{}' is not installed at {}",
+        target.model, target.base_url
+    );
+    if !target.provider.eq_ignore_ascii_case("ollama") {
+        println!();
+        return Ok(());
+    }
+
+    let answer = read_line("pull it now? [y/N] ")?.unwrap_or_default();
+    if !matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes") {
+        println!(
+            "skipped; run `ollama pull {}` to install it\n",
+            target.model
+        );
+        return Ok(());
     }
 
+    let admin = OllamaAdmin::new(client.clone(), &target.base_url, target.timeout_secs);
+    let cancel = CancellationToken::new();
+    set_active_turn(active_turn, Some(cancel.clone()));
+    let mut on_progress = |progress: &PullProgress| {
+        print!("\r{:<72}", format_pull_progress(progress));
+        let _ = io::stdout().flush();
+    };
+    let result = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        result = admin.pull_model(&target.model, &mut on_progress) => Some(result),
+    };
+    set_active_turn(active_turn, None);
+
+    match result {
+        Some(Ok(())) => println!("\npulled '{}'\n", target.model),
+        Some(Err(err)) => println!("\npull failed: {err}\n"),
+        None => println!("\npull cancelled\n"),
+    }
     Ok(())
 }
 
+fn format_pull_progress(progress: &PullProgress) -> String {
+    let Some(fraction) = progress.fraction() else {
+        return progress.status.clone();
+    };
+
+    let filled = (fraction * PULL_PROGRESS_BAR_WIDTH as f64).round() as usize;
+    format!(
+        "{} [{}{}] {:>3}%",
+        progress.status,
+        "#".repeat(filled),
+        "-".repeat(PULL_PROGRESS_BAR_WIDTH - filled),
+        (fraction * 100.0).floor() as u32
+    )
+}
+
+/// Routes SIGINT: the first Ctrl-C cancels the running turn, and a Ctrl-C
+/// with no cancellable turn (at the prompt, or a second press while a
+/// cancelled turn unwinds) exits the process.
+fn spawn_interrupt_handler(active_turn: ActiveTurn) {
+    tokio::spawn(async move {
+        loop {
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                warn!(error = %err, "failed to listen for ctrl-c");
+                return;
+            }
+
+            match set_active_turn(&active_turn, None) {
+                Some(cancel) => cancel.cancel(),
+                None => {
+                    println!();
+                    std::process::exit(INTERRUPTED_EXIT_CODE);
+                }
+            }
+        }
+    });
+}
+
+fn set_active_turn(
+    active_turn: &ActiveTurn,
+    cancel: Option<CancellationToken>,
+) -> Option<CancellationToken> {
+    let mut slot = active_turn
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    std::mem::replace(&mut *slot, cancel)
+}
+
+async fn run_turn_with_events(
+    agent: &mut Agent,
+    events: &mut UnboundedReceiver<AgentEvent>,
+    prompt: &str,
+    cancel: &CancellationToken,
+) -> Result<TurnResult> {
+    let turn = agent.run_turn_cancellable(prompt, cancel);
+    tokio::pin!(turn);
+
+    let result = loop {
+        tokio::select! {
+            result = &mut turn => break result,
+            Some(event) = events.recv() => render_event(&event),
+        }
+    };
+    while let Ok(event) = events.try_recv() {
+        render_event(&event);
+    }
+
+    result
+}
+
+fn render_event(event: &AgentEvent) {
+    match &event.kind {
+        AgentEventKind::ToolCallParsed { tool_name, .. } => {
+            println!("  [tool] calling {tool_name}");
+        }
+        AgentEventKind::ToolDenied {
+            tool_name, reason, ..
+        } => {
+            println!("  [tool] {tool_name} denied: {reason}");
+        }
+        AgentEventKind::ToolResult {
+            tool_name,
+            is_error: true,
+            output,
+            ..
+        } => {
+            println!("  [tool] {tool_name} failed: {output}");
+        }
+        AgentEventKind::PlanCreated { plan } => {
+            println!("  [plan]\n{}", indent_lines(&plan.to_string(), "    "));
+        }
+        AgentEventKind::PlanStepStarted {
+            step_index,
+            description,
+        } => {
+            println!("  [plan] step {}: {description}", step_index + 1);
+        }
+        AgentEventKind::PlanStepFinished {
+            step_index,
+            status: StepStatus::Failed,
+            result,
+        } => {
+            println!("  [plan] step {} failed: {result}", step_index + 1);
+        }
+        AgentEventKind::PlanStepFinished {
+            step_index,
+            status: StepStatus::Skipped,
+            result,
+        } => {
+            println!("  [plan] step {} skipped: {result}", step_index + 1);
+        }
+        AgentEventKind::PlanRevised { plan } => {
+            println!(
+                "  [plan] revised\n{}",
+                indent_lines(&plan.to_string(), "    ")
+            );
+        }
+        AgentEventKind::HistoryTrimmed {
+            dropped_messages, ..
+        } => {
+            println!("  [history] trimmed {dropped_messages} older messages");
+        }
+        AgentEventKind::TurnFinished { answer, .. } => {
+            println!("{}\n", answer.trim());
+        }
+        AgentEventKind::TurnCancelled => {
+            println!("\nturn cancelled\n");
+        }
+        _ => {}
+    }
+}
+
+fn indent_lines(text: &str, indent: &str) -> String {
+    text.lines()
+        .map(|line| format!("{indent}{line}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the argument of `command` when `input` invokes it, e.g. `Some("")`
+/// for a bare `/attach`.
+fn command_argument<'a>(input: &'a str, command: &str) -> Option<&'a str> {
+    let (name, argument) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+    name.eq_ignore_ascii_case(command).then(|| argument.trim())
+}
+
+fn attach_image(agent: &mut Agent, path: &str) {
+    if path.is_empty() {
+        println!("usage: /attach <path to png, jpg, gif or webp>\n");
+        return;
+    }
+
+    match ImageAttachment::from_path(path) {
+        Ok(image) => {
+            println!(
+                "attached {path} ({}, {} bytes); it will be sent with your next prompt\n",
+                image.media_type,
+                image.byte_len()
+            );
+            agent.attach_image(image);
+        }
+        Err(err) => println!("could not attach '{path}': {err}\n"),
+    }
+}
+
+/// Indexes `dir` for `docs.search`. Ctrl-C cancels the pass and leaves the
+/// index as it was.
+async fn index_docs(gateway: &HostModelGateway, cfg: &Config, dir: &str, active_turn: &ActiveTurn) {
+    if dir.is_empty() {
+        println!("usage: /index <dir>\n");
+        return;
+    }
+
+    println!("indexing {dir} with {}...", cfg.embedding_model);
+    let cancel = CancellationToken::new();
+    set_active_turn(active_turn, Some(cancel.clone()));
+    let result = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        result = retrieval::index_directory(gateway, cfg, Path::new(dir)) => Some(result),
+    };
+    set_active_turn(active_turn, None);
+
+    match result {
+        Some(Ok(report)) => println!("{}\n", format_index_report(dir, &report)),
+        Some(Err(err)) => println!("indexing failed: {err}\n"),
+        None => println!("indexing cancelled\n"),
+    }
+}
+
+fn format_index_report(dir: &str, report: &IndexReport) -> String {
+    let mut line = format!(
+        "indexed {dir}: {} new or changed file{} ({} chunks), {} unchanged, {} removed, {} skipped; {} chunks in the index",
+        report.indexed_files,
+        if report.indexed_files == 1 { "" } else { "s" },
+        report.chunks_embedded,
+        report.unchanged_files,
+        report.removed_files,
+        report.skipped_files,
+        report.total_chunks
+    );
+    if report.rebuilt {
+        line.push_str(" (rebuilt for a new embedding model)");
+    }
+    line
+}
+
+/// `/memory` lists memories, `/memory forget <id>` deletes one and
+/// `/memory clear` deletes all of them.
+fn manage_memory(store: &MemoryStore, argument: &str) {
+    let (action, rest) = argument
+        .split_once(char::is_whitespace)
+        .map_or((argument, ""), |(action, rest)| (action, rest.trim()));
+    match action.to_ascii_lowercase().as_str() {
+        "" => {
+            let memories = store.list();
+            if memories.is_empty() {
+                println!("(no memories in {})\n", store.path().display());
+            } else {
+                println!("{}\n", memory::format_memories(&memories));
+            }
+        }
+        "forget" => match rest.trim_start_matches('#').parse::<u64>() {
+            Ok(id) => match store.forget(id) {
+                Ok(true) => println!("forgot memory #{id}\n"),
+                Ok(false) => println!("no memory with id {id}\n"),
+                Err(err) => println!("could not forget memory: {err}\n"),
+            },
+            Err(_) => println!("usage: /memory forget <id>\n"),
+        },
+        "clear" => match store.clear() {
+            Ok(count) => println!("forgot {count} memories\n"),
+            Err(err) => println!("could not clear memories: {err}\n"),
+        },
+        _ => println!("usage: /memory [forget <id> | clear]\n"),
+    }
+}
+
 fn print_history(history: &[Message]) {
     if history.is_empty() {
         println!("(history is empty)\n");
@@ -58,7 +452,166 @@ fn print_history(history: &[Message]) {
     }
 
     for (idx, msg) in history.iter().enumerate() {
-        println!("[{}] {}: {}", idx, msg.role.as_str(), msg.content);
+        match &msg.role {
+            MessageRole::Tool { tool_name, .. } => {
+                println!("[{}] tool({}): {}", idx, tool_name, msg.content);
+            }
+            role => println!("[{}] {}: {}", idx, role.as_str(), msg.content),
+        }
+        if !msg.images.is_empty() {
+            println!("    ({} image(s) attached)", msg.images.len());
+        }
     }
     println!();
 }
+
+/// With model roles configured, each total is followed by one line per role
+/// that made calls.
+fn print_usage(last_turn: Option<&RoleUsage>, session: &RoleUsage, cfg: &Config) {
+    match last_turn {
+        Some(usage) => print_role_usage("last turn", usage, cfg),
+        None => println!("last turn: (no completed turn yet)"),
+    }
+    print_role_usage("session", session, cfg);
+    println!();
+}
+
+fn print_role_usage(label: &str, usage: &RoleUsage, cfg: &Config) {
+    println!("{}", format_usage(label, &usage.total()));
+    if cfg.model_roles.is_empty() {
+        return;
+    }
+    for (role, totals) in usage.iter() {
+        let label = format!(
+            "  {} ({})",
+            role.as_str(),
+            cfg.role_model_target(role).model
+        );
+        println!("{}", format_usage(&label, totals));
+    }
+}
+
+fn format_usage(label: &str, totals: &UsageTotals) -> String {
+    let usage = &totals.usage;
+    let mut line = format!(
+        "{label}: {} model call{}, {} prompt + {} completion tokens, {:.2}s",
+        totals.model_calls,
+        if totals.model_calls == 1 { "" } else { "s" },
+        usage.prompt_tokens,
+        usage.completion_tokens,
+        usage.latency.as_secs_f64()
+    );
+    if let Some(rate) = usage.completion_tokens_per_sec() {
+        line.push_str(&format!(" ({rate:.1} tokens/s)"));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        command_argument, format_index_report, format_model_details, format_pull_progress,
+        format_usage,
+    };
+    use crate::model::{ModelUsage, UsageTotals};
+    use crate::providers::ollama_admin::{ModelDetails, PullProgress};
+    use crate::retrieval::IndexReport;
+
+    fn progress(status: &str, completed: Option<u64>, total: Option<u64>) -> PullProgress {
+        PullProgress {
+            status: status.to_string(),
+            total,
+            completed,
+        }
+    }
+
+    #[test]
+    fn command_argument_matches_command_name_only() {
+        assert_eq!(
+            command_argument("/attach  shot.png ", "/attach"),
+            Some("shot.png")
+        );
+        assert_eq!(command_argument("/ATTACH", "/attach"), Some(""));
+        assert_eq!(command_argument("/attachment x", "/attach"), None);
+        assert_eq!(command_argument("describe /attach", "/attach"), None);
+    }
+
+    #[test]
+    fn format_usage_reports_tokens_latency_and_rate() {
+        let totals = UsageTotals {
+            model_calls: 2,
+            usage: ModelUsage {
+                prompt_tokens: 812,
+                completion_tokens: 45,
+                completion_duration: Duration::from_secs(3),
+                latency: Duration::from_millis(3_210),
+                ..ModelUsage::default()
+            },
+        };
+
+        assert_eq!(
+            format_usage("last turn", &totals),
+            "last turn: 2 model calls, 812 prompt + 45 completion tokens, 3.21s (15.0 tokens/s)"
+        );
+        assert_eq!(
+            format_usage("session", &UsageTotals::default()),
+            "session: 0 model calls, 0 prompt + 0 completion tokens, 0.00s"
+        );
+    }
+
+    #[test]
+    fn format_index_report_summarizes_the_pass() {
+        let report = IndexReport {
+            indexed_files: 1,
+            unchanged_files: 4,
+            removed_files: 2,
+            skipped_files: 0,
+            chunks_embedded: 3,
+            total_chunks: 20,
+            rebuilt: true,
+        };
+
+        assert_eq!(
+            format_index_report("docs", &report),
+            "indexed docs: 1 new or changed file (3 chunks), 4 unchanged, 2 removed, 0 skipped; 20 chunks in the index (rebuilt for a new embedding model)"
+        );
+    }
+
+    #[test]
+    fn format_model_details_warns_when_num_ctx_exceeds_the_context_length() {
+        let details = ModelDetails {
+            family: Some("qwen2".to_string()),
+            parameter_size: Some("3.1B".to_string()),
+            quantization_level: Some("Q4_K_M".to_string()),
+            context_length: Some(32768),
+        };
+
+        assert_eq!(
+            format_model_details(&details, Some(8192)),
+            "family: qwen2, quantization: Q4_K_M, context length: 32768 tokens"
+        );
+        assert_eq!(
+            format_model_details(&details, Some(65536)),
+            "family: qwen2, quantization: Q4_K_M, context length: 32768 tokens\n\
+             warning: MODEL_NUM_CTX=65536 exceeds the model's context length of 32768 tokens"
+        );
+        assert_eq!(
+            format_model_details(&ModelDetails::default(), Some(65536)),
+            "family: unknown, quantization: unknown, context length: unknown"
+        );
+    }
+
+    #[test]
+    fn format_pull_progress_draws_bar_for_downloads() {
+        assert_eq!(
+            format_pull_progress(&progress("pulling 6a0746a1ec1a", Some(50), Some(100))),
+            "pulling 6a0746a1ec1a [###############---------------]  50%"
+        );
+        assert_eq!(
+            format_pull_progress(&progress("pulling manifest", None, None)),
+            "pulling manifest"
+        );
+    }
+}
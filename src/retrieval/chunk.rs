@@ -0,0 +1,134 @@
+/// A run of whole lines from a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    /// First line of the chunk, counting from 1.
+    pub start_line: usize,
+    /// Last line of the chunk, inclusive.
+    pub end_line: usize,
+    pub text: String,
+}
+
+/// Splits `text` into chunks of at most `max_chars` characters.
+///
+/// Chunks end at line boundaries, preferring a blank line once a chunk is at
+/// least half full so paragraphs stay together. A single line longer than
+/// `max_chars` is split on character boundaries. Chunks holding only
+/// whitespace are dropped.
+pub fn chunk_text(text: &str, max_chars: usize) -> Vec<TextChunk> {
+    let max_chars = max_chars.max(1);
+    let mut chunks = Vec::new();
+    let mut current = ChunkBuilder::default();
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line_chars = line.chars().count();
+
+        if line_chars > max_chars {
+            current.flush_into(&mut chunks);
+            for piece in split_chars(line, max_chars) {
+                chunks.push(TextChunk {
+                    start_line: line_number,
+                    end_line: line_number,
+                    text: piece,
+                });
+            }
+            continue;
+        }
+
+        if current.chars + line_chars + 1 > max_chars {
+            current.flush_into(&mut chunks);
+        }
+        current.push(line_number, line, line_chars);
+        if line.trim().is_empty() && current.chars * 2 >= max_chars {
+            current.flush_into(&mut chunks);
+        }
+    }
+    current.flush_into(&mut chunks);
+    chunks
+}
+
+#[derive(Default)]
+struct ChunkBuilder {
+    start_line: usize,
+    end_line: usize,
+    text: String,
+    chars: usize,
+}
+
+impl ChunkBuilder {
+    fn push(&mut self, line_number: usize, line: &str, line_chars: usize) {
+        if self.text.is_empty() {
+            self.start_line = line_number;
+        } else {
+            self.text.push('\n');
+            self.chars += 1;
+        }
+        self.text.push_str(line);
+        self.chars += line_chars;
+        self.end_line = line_number;
+    }
+
+    fn flush_into(&mut self, chunks: &mut Vec<TextChunk>) {
+        let builder = std::mem::take(self);
+        if builder.text.trim().is_empty() {
+            return;
+        }
+        chunks.push(TextChunk {
+            start_line: builder.start_line,
+            end_line: builder.end_line,
+            text: builder.text.trim_end().to_string(),
+        });
+    }
+}
+
+fn split_chars(line: &str, max_chars: usize) -> Vec<String> {
+    let chars: Vec<char> = line.chars().collect();
+    chars
+        .chunks(max_chars)
+        .map(|piece| piece.iter().collect::<String>())
+        .filter(|piece| !piece.trim().is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TextChunk, chunk_text};
+
+    #[test]
+    fn chunk_text_keeps_short_text_in_one_chunk() {
+        assert_eq!(
+            chunk_text("# Title\n\nBody text.\n", 100),
+            vec![TextChunk {
+                start_line: 1,
+                end_line: 3,
+                text: "# Title\n\nBody text.".to_string(),
+            }]
+        );
+        assert!(chunk_text("\n  \n", 100).is_empty());
+    }
+
+    #[test]
+    fn chunk_text_breaks_at_paragraphs_and_size_limit() {
+        let text = "aaaa\nbbbb\n\ncccc\ndddd\neeee";
+
+        let chunks = chunk_text(text, 12);
+
+        let spans: Vec<(usize, usize, &str)> = chunks
+            .iter()
+            .map(|chunk| (chunk.start_line, chunk.end_line, chunk.text.as_str()))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(1, 3, "aaaa\nbbbb"), (4, 5, "cccc\ndddd"), (6, 6, "eeee")]
+        );
+    }
+
+    #[test]
+    fn chunk_text_splits_overlong_lines() {
+        let chunks = chunk_text("short\néééééééééé\nend", 4);
+
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(texts, vec!["shor", "t", "éééé", "éééé", "éé", "end"]);
+        assert!(chunks[2..5].iter().all(|chunk| chunk.start_line == 2));
+    }
+}
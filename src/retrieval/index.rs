@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::RetrievalError;
+use crate::atomic_write::write_atomic;
+
+/// Bumped when the on-disk layout changes; older indexes are rebuilt.
+pub const INDEX_VERSION: u32 = 1;
+
+/// Vectors and metadata for every indexed document, kept in one JSON file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocsIndex {
+    pub version: u32,
+    /// Model that produced the vectors; vectors from different models are
+    /// not comparable, so a change of model re-embeds everything.
+    pub embedding_model: String,
+    /// Keyed by the document's canonical path.
+    pub documents: BTreeMap<String, IndexedDocument>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedDocument {
+    /// Modification time in milliseconds since the unix epoch.
+    pub modified_ms: u64,
+    pub len: u64,
+    /// FNV-1a hash of the contents.
+    pub hash: String,
+    pub chunks: Vec<IndexedChunk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedChunk {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    pub embedding: Vec<f32>,
+}
+
+impl DocsIndex {
+    pub fn new(embedding_model: impl Into<String>) -> Self {
+        Self {
+            version: INDEX_VERSION,
+            embedding_model: embedding_model.into(),
+            documents: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the index at `path`; a missing file is an empty index.
+    pub fn load(path: &Path, embedding_model: &str) -> Result<Self, RetrievalError> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::new(embedding_model));
+            }
+            Err(err) => return Err(RetrievalError::io(path, err)),
+        };
+        serde_json::from_slice(&bytes).map_err(|err| RetrievalError::CorruptIndex {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })
+    }
+
+    /// Writes the index to a temporary file next to `path` and renames it into
+    /// place, so readers never see a partial index.
+    pub fn save(&self, path: &Path) -> Result<(), RetrievalError> {
+        let json = serde_json::to_vec(self).map_err(|err| RetrievalError::CorruptIndex {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        write_atomic(path, &json).map_err(|err| RetrievalError::io(&err.path, err.source))
+    }
+
+    /// Whether the vectors can be compared with ones from `embedding_model`.
+    pub fn is_compatible(&self, embedding_model: &str) -> bool {
+        self.version == INDEX_VERSION && self.embedding_model == embedding_model
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.documents
+            .values()
+            .map(|document| document.chunks.len())
+            .sum()
+    }
+}
+
+/// A stable 64-bit FNV-1a hash, hex encoded. Used to skip re-embedding files
+/// whose modification time changed but whose contents did not.
+pub fn content_hash(bytes: &[u8]) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let hash = bytes.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    });
+    format!("{hash:016x}")
+}
+
+/// Cosine similarity of two vectors; 0 when either is empty, zero, or the
+/// lengths differ.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let mut dot = 0.0f32;
+    let mut norm_a = 0.0f32;
+    let mut norm_b = 0.0f32;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DocsIndex, INDEX_VERSION, content_hash, cosine_similarity};
+
+    #[test]
+    fn content_hash_is_stable_fnv1a() {
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+        assert_eq!(content_hash(b"a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn cosine_similarity_handles_mismatched_and_zero_vectors() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn index_requires_same_version_and_model() {
+        let mut index = DocsIndex::new("nomic-embed-text");
+        assert!(index.is_compatible("nomic-embed-text"));
+        assert!(!index.is_compatible("mxbai-embed-large"));
+        index.version = INDEX_VERSION + 1;
+        assert!(!index.is_compatible("nomic-embed-text"));
+    }
+}
@@ -0,0 +1,671 @@
+mod chunk;
+mod index;
+
+use serde_json::json;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tracing::{debug, info, warn};
+
+use crate::agent::tools::{
+    ToolCall, ToolExecutionError, ToolFuture, ToolOutput, ToolRunner, ToolSpec,
+};
+use crate::config::Config;
+use crate::model_error::ModelError;
+use crate::model_gateway::ModelGateway;
+pub use chunk::{TextChunk, chunk_text};
+use index::content_hash;
+pub(crate) use index::cosine_similarity;
+pub use index::{DocsIndex, IndexedChunk, IndexedDocument};
+
+/// File extensions picked up by [`index_directory`]; matched case-insensitively.
+const INDEXED_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "rst", "adoc", "rs", "py", "js", "ts", "tsx", "go", "java", "kt", "c",
+    "h", "cc", "cpp", "hpp", "cs", "rb", "sh", "toml", "yaml", "yml", "json", "sql",
+];
+/// Directories never descended into, besides hidden ones.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+/// Larger files are skipped; they are usually generated or data dumps.
+const MAX_DOCUMENT_BYTES: u64 = 1024 * 1024;
+const DEFAULT_SEARCH_LIMIT: usize = 4;
+const MAX_SEARCH_LIMIT: usize = 10;
+
+/// Why indexing or searching documents failed.
+#[derive(Debug)]
+pub enum RetrievalError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    NotADirectory {
+        path: PathBuf,
+    },
+    CorruptIndex {
+        path: PathBuf,
+        message: String,
+    },
+    /// The index was built with another embedding model or index version.
+    IncompatibleIndex {
+        embedding_model: String,
+    },
+    EmptyIndex,
+    Embed(ModelError),
+}
+
+impl RetrievalError {
+    fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for RetrievalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(f, "Failed to access '{}': {source}", path.display())
+            }
+            Self::NotADirectory { path } => write!(f, "'{}' is not a directory", path.display()),
+            Self::CorruptIndex { path, message } => write!(
+                f,
+                "Docs index '{}' is unreadable ({message}); delete it and index again",
+                path.display()
+            ),
+            Self::IncompatibleIndex { embedding_model } => write!(
+                f,
+                "Docs index was built with embedding model '{embedding_model}'; run /index again to rebuild it"
+            ),
+            Self::EmptyIndex => write!(f, "No documents are indexed yet; run /index <dir> first"),
+            Self::Embed(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for RetrievalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Embed(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ModelError> for RetrievalError {
+    fn from(err: ModelError) -> Self {
+        Self::Embed(err)
+    }
+}
+
+/// What one [`index_directory`] pass did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexReport {
+    /// New or changed files that were chunked and embedded.
+    pub indexed_files: usize,
+    pub unchanged_files: usize,
+    /// Previously indexed files under the directory that no longer exist.
+    pub removed_files: usize,
+    /// Files that were too large, unreadable or not UTF-8.
+    pub skipped_files: usize,
+    pub chunks_embedded: usize,
+    /// Chunks in the whole index after the pass.
+    pub total_chunks: usize,
+    /// The existing index was discarded because it was built with another
+    /// embedding model or index version.
+    pub rebuilt: bool,
+}
+
+/// A chunk that matched a search, best matches first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub text: String,
+    /// Cosine similarity between the query and the chunk.
+    pub score: f32,
+}
+
+struct PendingDocument {
+    key: String,
+    modified_ms: u64,
+    len: u64,
+    hash: String,
+    chunks: Vec<TextChunk>,
+}
+
+/// Indexes the supported files under `dir` into `Config::docs_index_path`.
+///
+/// Files whose size and modification time match the index are skipped
+/// without being read; files whose contents hash the same are skipped
+/// without being embedded. Indexed files under `dir` that no longer exist
+/// are dropped, as are files that are now skipped. The index is only written
+/// once every new chunk is embedded.
+pub async fn index_directory(
+    gateway: &dyn ModelGateway,
+    cfg: &Config,
+    dir: &Path,
+) -> Result<IndexReport, RetrievalError> {
+    let root = fs::canonicalize(dir).map_err(|err| RetrievalError::io(dir, err))?;
+    if !root.is_dir() {
+        return Err(RetrievalError::NotADirectory { path: root });
+    }
+
+    let mut report = IndexReport::default();
+    let mut index = DocsIndex::load(&cfg.docs_index_path, &cfg.embedding_model)?;
+    if !index.is_compatible(&cfg.embedding_model) {
+        index = DocsIndex::new(&cfg.embedding_model);
+        report.rebuilt = true;
+    }
+
+    let mut seen = HashSet::new();
+    let mut pending = Vec::new();
+    for path in collect_files(&root)? {
+        let key = path.to_string_lossy().into_owned();
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                warn!(path = %key, error = %err, "skipping unreadable document");
+                report.skipped_files += 1;
+                continue;
+            }
+        };
+        if metadata.len() > MAX_DOCUMENT_BYTES {
+            debug!(path = %key, len = metadata.len(), "skipping large document");
+            report.skipped_files += 1;
+            continue;
+        }
+
+        let modified_ms = modified_ms(&metadata);
+        let len = metadata.len();
+        if let Some(existing) = index.documents.get(&key)
+            && existing.modified_ms == modified_ms
+            && existing.len == len
+        {
+            seen.insert(key);
+            report.unchanged_files += 1;
+            continue;
+        }
+
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                warn!(path = %key, error = %err, "skipping unreadable document");
+                report.skipped_files += 1;
+                continue;
+            }
+        };
+        let hash = content_hash(&bytes);
+        if let Some(existing) = index.documents.get_mut(&key)
+            && existing.hash == hash
+        {
+            existing.modified_ms = modified_ms;
+            existing.len = len;
+            seen.insert(key);
+            report.unchanged_files += 1;
+            continue;
+        }
+        let Ok(text) = String::from_utf8(bytes) else {
+            debug!(path = %key, "skipping document that is not UTF-8");
+            report.skipped_files += 1;
+            continue;
+        };
+
+        seen.insert(key.clone());
+        pending.push(PendingDocument {
+            key,
+            modified_ms,
+            len,
+            hash,
+            chunks: chunk_text(&text, cfg.docs_chunk_chars),
+        });
+    }
+
+    let texts: Vec<String> = pending
+        .iter()
+        .flat_map(|document| document.chunks.iter().map(|chunk| chunk.text.clone()))
+        .collect();
+    let mut vectors = if texts.is_empty() {
+        Vec::new()
+    } else {
+        gateway.embed(texts).await?
+    }
+    .into_iter();
+
+    for document in pending {
+        let chunks: Vec<IndexedChunk> = document
+            .chunks
+            .into_iter()
+            .map(|chunk| IndexedChunk {
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                text: chunk.text,
+                embedding: vectors.next().unwrap_or_default(),
+            })
+            .collect();
+        report.indexed_files += 1;
+        report.chunks_embedded += chunks.len();
+        index.documents.insert(
+            document.key,
+            IndexedDocument {
+                modified_ms: document.modified_ms,
+                len: document.len,
+                hash: document.hash,
+                chunks,
+            },
+        );
+    }
+
+    let before = index.documents.len();
+    index
+        .documents
+        .retain(|key, _| seen.contains(key) || !Path::new(key).starts_with(&root));
+    report.removed_files = before - index.documents.len();
+
+    index.save(&cfg.docs_index_path)?;
+    report.total_chunks = index.chunk_count();
+    info!(
+        dir = %root.display(),
+        indexed_files = report.indexed_files,
+        unchanged_files = report.unchanged_files,
+        removed_files = report.removed_files,
+        skipped_files = report.skipped_files,
+        chunks_embedded = report.chunks_embedded,
+        "indexed documents"
+    );
+    Ok(report)
+}
+
+/// Returns the `limit` chunks most similar to `query`.
+pub async fn search(
+    gateway: &dyn ModelGateway,
+    cfg: &Config,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<SearchHit>, RetrievalError> {
+    let index = DocsIndex::load(&cfg.docs_index_path, &cfg.embedding_model)?;
+    if !index.is_compatible(&cfg.embedding_model) {
+        return Err(RetrievalError::IncompatibleIndex {
+            embedding_model: index.embedding_model,
+        });
+    }
+    if index.chunk_count() == 0 {
+        return Err(RetrievalError::EmptyIndex);
+    }
+
+    let query_vector = gateway
+        .embed(vec![query.to_string()])
+        .await?
+        .pop()
+        .unwrap_or_default();
+    let mut hits: Vec<SearchHit> = index
+        .documents
+        .iter()
+        .flat_map(|(path, document)| {
+            document.chunks.iter().map(|chunk| SearchHit {
+                path: path.clone(),
+                start_line: chunk.start_line,
+                end_line: chunk.end_line,
+                text: chunk.text.clone(),
+                score: cosine_similarity(&query_vector, &chunk.embedding),
+            })
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+    hits.truncate(limit);
+    debug!(
+        query_len = query.len(),
+        hit_count = hits.len(),
+        "searched documents"
+    );
+    Ok(hits)
+}
+
+/// Renders hits as the `docs.search` tool output.
+pub fn format_hits(hits: &[SearchHit]) -> String {
+    if hits.is_empty() {
+        return "No matching documents.".to_string();
+    }
+    hits.iter()
+        .enumerate()
+        .map(|(rank, hit)| {
+            format!(
+                "[{}] {}:{}-{} (score {:.2})\n{}",
+                rank + 1,
+                hit.path,
+                hit.start_line,
+                hit.end_line,
+                hit.score,
+                hit.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Offers the `docs.search` tool over the index at `Config::docs_index_path`.
+/// The index is read on every call, so documents indexed while an agent is
+/// running are searchable right away.
+pub struct DocsSearchRunner {
+    gateway: Arc<dyn ModelGateway>,
+    cfg: Arc<Config>,
+}
+
+impl DocsSearchRunner {
+    pub fn new(gateway: Arc<dyn ModelGateway>, cfg: Arc<Config>) -> Self {
+        Self { gateway, cfg }
+    }
+}
+
+impl ToolRunner for DocsSearchRunner {
+    fn tools(&self) -> Vec<ToolSpec> {
+        vec![
+            ToolSpec::new(
+                "docs.search",
+                "searches the locally indexed documents and returns the most relevant passages with their file and line numbers.",
+            )
+            .with_parameters(json!({
+                "type": "object",
+                "required": ["query"],
+                "properties": {
+                    "query": {"type": "string"},
+                    "limit": {"type": "integer", "minimum": 1, "maximum": MAX_SEARCH_LIMIT}
+                }
+            })),
+        ]
+    }
+
+    fn execute<'a>(&'a self, call: &'a ToolCall) -> ToolFuture<'a> {
+        Box::pin(async move {
+            let query = call.required_str("query")?;
+            let limit = call
+                .arguments
+                .get("limit")
+                .and_then(|limit| limit.as_u64())
+                .map_or(DEFAULT_SEARCH_LIMIT, |limit| limit as usize)
+                .clamp(1, MAX_SEARCH_LIMIT);
+            let hits = search(self.gateway.as_ref(), &self.cfg, query, limit)
+                .await
+                .map_err(|err| ToolExecutionError::new(err.to_string()))?;
+            Ok(ToolOutput::new(format_hits(&hits)))
+        })
+    }
+}
+
+/// Lists indexable files under `root`, sorted. Hidden entries, build output
+/// directories and symlinks are skipped.
+fn collect_files(root: &Path) -> Result<Vec<PathBuf>, RetrievalError> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if dir == root => return Err(RetrievalError::io(&dir, err)),
+            Err(err) => {
+                warn!(dir = %dir.display(), error = %err, "skipping unreadable directory");
+                continue;
+            }
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() && !SKIPPED_DIRS.contains(&name.as_ref()) {
+                dirs.push(entry.path());
+            } else if file_type.is_file() && is_indexed_extension(&entry.path()) {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_indexed_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| {
+            INDEXED_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        })
+}
+
+fn modified_ms(metadata: &Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since_epoch| since_epoch.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    use super::{DocsIndex, DocsSearchRunner, RetrievalError, index_directory, search};
+    use crate::agent::tools::{ToolCall, ToolRunner};
+    use crate::config::Config;
+    use crate::model_error::{ModelError, RequestKind};
+    use crate::model_gateway::{
+        ModelGateway, ModelGatewayEmbedFuture, ModelGatewayFuture, ModelGatewayRequest,
+    };
+    use crate::test_fs::unique_temp_dir;
+
+    const VOCABULARY: [&str; 3] = ["retry", "image", "schema"];
+
+    /// Embeds a text as its counts of [`VOCABULARY`] words and records the
+    /// texts of every embed request.
+    #[derive(Default)]
+    struct KeywordGateway {
+        requests: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl KeywordGateway {
+        fn embedded_texts(&self) -> usize {
+            self.requests
+                .lock()
+                .expect("requests lock should not be poisoned")
+                .iter()
+                .map(Vec::len)
+                .sum()
+        }
+    }
+
+    impl ModelGateway for KeywordGateway {
+        fn chat<'a>(&'a self, _request: ModelGatewayRequest) -> ModelGatewayFuture<'a> {
+            Box::pin(async {
+                Err(ModelError::Unsupported {
+                    provider: "keyword".to_string(),
+                    kind: RequestKind::Chat,
+                })
+            })
+        }
+
+        fn embed<'a>(&'a self, texts: Vec<String>) -> ModelGatewayEmbedFuture<'a> {
+            let vectors = texts
+                .iter()
+                .map(|text| {
+                    let text = text.to_lowercase();
+                    VOCABULARY
+                        .iter()
+                        .map(|word| text.matches(word).count() as f32)
+                        .collect()
+                })
+                .collect();
+            self.requests
+                .lock()
+                .expect("requests lock should not be poisoned")
+                .push(texts);
+            Box::pin(async move { Ok(vectors) })
+        }
+    }
+
+    fn test_config(root: &std::path::Path) -> Config {
+        let mut cfg = Config::from_env_with(|_| None);
+        cfg.docs_index_path = root.join("index").join("docs.json");
+        cfg.docs_chunk_chars = 200;
+        cfg
+    }
+
+    #[tokio::test]
+    async fn index_directory_reindexes_only_changed_files() {
+        let root = unique_temp_dir("incremental");
+        let docs = root.join("docs");
+        fs::create_dir_all(docs.join(".git")).expect("create docs");
+        fs::write(docs.join("retry.md"), "Retry with backoff.").expect("write retry.md");
+        fs::write(docs.join("image.txt"), "Image attachments.").expect("write image.txt");
+        fs::write(docs.join("photo.png"), [0u8, 1, 2]).expect("write photo.png");
+        fs::write(docs.join(".git").join("HEAD.md"), "hidden").expect("write hidden");
+        let cfg = test_config(&root);
+        let gateway = KeywordGateway::default();
+
+        let first = index_directory(&gateway, &cfg, &docs)
+            .await
+            .expect("first pass should succeed");
+        assert_eq!((first.indexed_files, first.chunks_embedded), (2, 2));
+        assert_eq!(gateway.embedded_texts(), 2);
+
+        let second = index_directory(&gateway, &cfg, &docs)
+            .await
+            .expect("second pass should succeed");
+        assert_eq!((second.indexed_files, second.unchanged_files), (0, 2));
+        assert_eq!(gateway.embedded_texts(), 2);
+
+        fs::write(docs.join("retry.md"), "Retry, then retry again.").expect("rewrite");
+        fs::remove_file(docs.join("image.txt")).expect("remove image.txt");
+        fs::write(docs.join("schema.md"), "Schema output.").expect("write schema.md");
+
+        let third = index_directory(&gateway, &cfg, &docs)
+            .await
+            .expect("third pass should succeed");
+        assert_eq!(
+            (third.indexed_files, third.removed_files, third.total_chunks),
+            (2, 1, 2)
+        );
+        assert_eq!(gateway.embedded_texts(), 4);
+
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn index_directory_skips_embedding_when_only_mtime_changes() {
+        let root = unique_temp_dir("mtime");
+        let docs = root.join("docs");
+        fs::create_dir_all(&docs).expect("create docs");
+        fs::write(docs.join("retry.md"), "Retry with backoff.").expect("write retry.md");
+        let cfg = test_config(&root);
+        let gateway = KeywordGateway::default();
+        index_directory(&gateway, &cfg, &docs)
+            .await
+            .expect("first pass should succeed");
+
+        fs::File::options()
+            .write(true)
+            .open(docs.join("retry.md"))
+            .expect("open retry.md")
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .expect("set mtime");
+        let report = index_directory(&gateway, &cfg, &docs)
+            .await
+            .expect("second pass should succeed");
+
+        assert_eq!((report.indexed_files, report.unchanged_files), (0, 1));
+        assert_eq!(gateway.embedded_texts(), 1);
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn index_directory_rebuilds_index_from_another_embedding_model() {
+        let root = unique_temp_dir("rebuild");
+        let docs = root.join("docs");
+        fs::create_dir_all(&docs).expect("create docs");
+        fs::write(docs.join("retry.md"), "Retry with backoff.").expect("write retry.md");
+        let mut cfg = test_config(&root);
+        DocsIndex::new("old-model")
+            .save(&cfg.docs_index_path)
+            .expect("save old index");
+        cfg.embedding_model = "new-model".to_string();
+
+        let report = index_directory(&KeywordGateway::default(), &cfg, &docs)
+            .await
+            .expect("indexing should succeed");
+
+        assert!(report.rebuilt);
+        assert_eq!(report.indexed_files, 1);
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn docs_search_tool_returns_best_matching_passages() {
+        let root = unique_temp_dir("search");
+        let docs = root.join("docs");
+        fs::create_dir_all(&docs).expect("create docs");
+        fs::write(docs.join("retry.md"), "Retry with backoff.").expect("write retry.md");
+        fs::write(docs.join("image.md"), "Image input.\nImage output.").expect("write image.md");
+        let cfg = Arc::new(test_config(&root));
+        let gateway = Arc::new(KeywordGateway::default());
+        index_directory(gateway.as_ref(), &cfg, &docs)
+            .await
+            .expect("indexing should succeed");
+
+        let hits = search(gateway.as_ref(), &cfg, "how do images work?", 1)
+            .await
+            .expect("search should succeed");
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.ends_with("image.md"));
+        assert_eq!((hits[0].start_line, hits[0].end_line), (1, 2));
+
+        let runner = DocsSearchRunner::new(gateway, Arc::clone(&cfg));
+        let call = ToolCall::new("docs.search").with_arguments(
+            serde_json::json!({"query": "retry policy", "limit": 1})
+                .as_object()
+                .cloned()
+                .expect("arguments are an object"),
+        );
+        let output = runner.execute(&call).await.expect("tool should succeed");
+        assert!(output.content.starts_with("[1] "));
+        assert!(
+            output
+                .content
+                .contains("retry.md:1-1 (score 1.00)\nRetry with backoff.")
+        );
+
+        let missing = runner
+            .execute(&ToolCall::new("docs.search"))
+            .await
+            .expect_err("query is required");
+        assert_eq!(
+            missing.to_string(),
+            "docs.search requires a non-empty string argument 'query'"
+        );
+        fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn search_reports_missing_index() {
+        let root = unique_temp_dir("empty");
+        let cfg = test_config(&root);
+
+        let err = search(&KeywordGateway::default(), &cfg, "retry", 4)
+            .await
+            .expect_err("an empty index cannot be searched");
+
+        assert!(matches!(err, RetrievalError::EmptyIndex));
+        fs::remove_dir_all(root).ok();
+    }
+}
@@ -0,0 +1,75 @@
+use axum::Json;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+use crate::agent::TurnCancelled;
+use crate::model_error::ModelError;
+
+/// An error response. Both APIs use the OpenAI error format,
+/// `{"error":{"message":...,"type":...}}`.
+#[derive(Debug, PartialEq)]
+pub(super) struct ApiError {
+    pub(super) status: StatusCode,
+    pub(super) kind: &'static str,
+    pub(super) message: String,
+}
+
+impl ApiError {
+    pub(super) fn new(status: StatusCode, kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub(super) fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request_error", message)
+    }
+
+    pub(super) fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub(super) fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "conflict", message)
+    }
+
+    /// Maps a failed turn: model failures are upstream errors, a turn cut
+    /// short by shutdown is unavailability, anything else is internal.
+    pub(super) fn from_turn_error(err: &anyhow::Error) -> Self {
+        if err.downcast_ref::<ModelError>().is_some() {
+            Self::new(StatusCode::BAD_GATEWAY, "model_error", err.to_string())
+        } else if err.is::<TurnCancelled>() {
+            Self::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server_shutting_down",
+                "the server is shutting down",
+            )
+        } else {
+            Self::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "server_error",
+                format!("{err:#}"),
+            )
+        }
+    }
+
+    pub(super) fn body(&self) -> serde_json::Value {
+        json!({
+            "error": {
+                "message": self.message,
+                "type": self.kind,
+                "param": null,
+                "code": null,
+            }
+        })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
@@ -0,0 +1,105 @@
+//! HTTP APIs for running agent turns, started by `fizz serve`.
+//!
+//! The OpenAI-compatible API under `/v1` keeps no conversation state: every
+//! request gets a fresh [`Agent`](crate::agent::Agent) seeded with the
+//! messages the client sent. The native API under `/api/sessions` keeps an
+//! agent per session and streams its events. Long-term memory is shared
+//! through the one memory store.
+
+mod error;
+mod openai;
+mod sessions;
+
+use anyhow::{Context, Result};
+use axum::Router;
+use axum::routing::{get, post};
+use reqwest::Client;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+
+use crate::AgentFactory;
+use crate::config::Config;
+use sessions::SessionStore;
+
+/// Binds `SERVER_HOST:SERVER_PORT` and serves until `shutdown` fires.
+pub async fn serve(client: Client, cfg: Arc<Config>, shutdown: CancellationToken) -> Result<()> {
+    let addr = cfg.server_addr();
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind {addr}"))?;
+    serve_on(listener, client, cfg, shutdown).await
+}
+
+/// Serves on an already bound listener until `shutdown` fires.
+///
+/// Shutdown stops accepting connections, cancels the turns still running and
+/// waits for their responses to finish.
+pub async fn serve_on(
+    listener: TcpListener,
+    client: Client,
+    cfg: Arc<Config>,
+    shutdown: CancellationToken,
+) -> Result<()> {
+    let sessions = Arc::new(SessionStore::new(cfg.sessions.clone()));
+    let state = ServerState {
+        agents: Arc::new(AgentFactory::new(client, cfg).await?),
+        sessions,
+        shutdown: shutdown.clone(),
+    };
+    let addr = listener
+        .local_addr()
+        .context("Failed to read the server address")?;
+    info!(%addr, "serving http api");
+
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await
+        .context("HTTP server failed")?;
+    info!("http api stopped");
+    Ok(())
+}
+
+#[derive(Clone)]
+struct ServerState {
+    agents: Arc<AgentFactory>,
+    sessions: Arc<SessionStore>,
+    /// Parent of every turn's cancellation token.
+    shutdown: CancellationToken,
+}
+
+fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/v1/models", get(openai::list_models))
+        .route("/v1/chat/completions", post(openai::chat_completions))
+        .route(
+            "/api/sessions",
+            post(sessions::create_session).get(sessions::list_sessions),
+        )
+        .route(
+            "/api/sessions/{id}",
+            get(sessions::get_session).delete(sessions::delete_session),
+        )
+        .route("/api/sessions/{id}/turns", post(sessions::post_turn))
+        .route("/api/sessions/{id}/cancel", post(sessions::cancel_turn))
+        .route("/api/sessions/{id}/events", get(sessions::stream_events))
+        .route("/api/sessions/{id}/history", get(sessions::get_history))
+        .route(
+            "/api/sessions/{id}/approvals",
+            get(sessions::list_approvals),
+        )
+        .route(
+            "/api/sessions/{id}/approvals/{call_id}",
+            post(sessions::decide_approval),
+        )
+        .route(
+            "/api/sessions/{id}/workspace/diff",
+            get(sessions::workspace_unavailable),
+        )
+        .route(
+            "/api/sessions/{id}/workspace/commit",
+            post(sessions::workspace_unavailable),
+        )
+        .with_state(state)
+}
@@ -0,0 +1,680 @@
+//! The OpenAI-compatible endpoints: `/v1/models` and `/v1/chat/completions`.
+//!
+//! Requests carry the whole conversation; the last message must come from the
+//! user and is run as a turn. fizz runs its own tools, so client `tools` and
+//! `tool` messages are not supported.
+
+use axum::Json;
+use axum::extract::State;
+use axum::extract::rejection::JsonRejection;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use super::ServerState;
+use super::error::ApiError;
+use crate::agent::TurnResult;
+use crate::agent::events::{AgentEvent, AgentEventKind};
+use crate::config::{GenerationOptions, ToolCallExtraction};
+use crate::model::{ImageAttachment, Message, ModelUsage};
+
+/// The one model the API offers: the agent, whatever model it runs on.
+const MODEL_ID: &str = "fizz";
+
+static NEXT_COMPLETION_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ChatCompletionRequest {
+    messages: Vec<RequestMessage>,
+    #[serde(default)]
+    stream: bool,
+    #[serde(default)]
+    stream_options: Option<StreamOptions>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    seed: Option<u64>,
+    max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    stop: Option<StopSequences>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct StreamOptions {
+    #[serde(default)]
+    include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum StopSequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestMessage {
+    role: String,
+    #[serde(default)]
+    content: Option<MessageContent>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageUrl {
+    url: String,
+}
+
+/// A request turned into agent input: the earlier messages, and the text and
+/// images of the final user message.
+#[derive(Debug, PartialEq)]
+struct TurnInput {
+    conversation: Vec<Message>,
+    user_input: String,
+    images: Vec<ImageAttachment>,
+}
+
+impl ChatCompletionRequest {
+    fn generation_overrides(&self) -> GenerationOptions {
+        GenerationOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            seed: self.seed,
+            max_tokens: self.max_completion_tokens.or(self.max_tokens),
+            stop: match &self.stop {
+                Some(StopSequences::One(stop)) => vec![stop.clone()],
+                Some(StopSequences::Many(stops)) => stops.clone(),
+                None => Vec::new(),
+            },
+            ..GenerationOptions::default()
+        }
+    }
+
+    fn include_usage(&self) -> bool {
+        self.stream_options
+            .as_ref()
+            .is_some_and(|options| options.include_usage)
+    }
+
+    fn turn_input(&self) -> Result<TurnInput, ApiError> {
+        let Some((last, earlier)) = self.messages.split_last() else {
+            return Err(ApiError::invalid_request("messages must not be empty"));
+        };
+        if last.role != "user" {
+            return Err(ApiError::invalid_request(
+                "the last message must have role 'user'",
+            ));
+        }
+
+        let conversation = earlier
+            .iter()
+            .map(convert_message)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (user_input, images) = message_parts(last)?;
+        Ok(TurnInput {
+            conversation,
+            user_input,
+            images,
+        })
+    }
+}
+
+fn convert_message(message: &RequestMessage) -> Result<Message, ApiError> {
+    let (content, images) = message_parts(message)?;
+    match message.role.as_str() {
+        "system" | "developer" => Ok(Message::system(content)),
+        "user" => Ok(Message::user(content).with_images(images)),
+        "assistant" => Ok(Message::assistant(content)),
+        "tool" | "function" => Err(ApiError::invalid_request(
+            "tool messages are not supported; fizz runs its own tools",
+        )),
+        other => Err(ApiError::invalid_request(format!(
+            "unsupported message role '{other}'"
+        ))),
+    }
+}
+
+/// Splits a message into its text, with text parts joined by newlines, and
+/// its images.
+fn message_parts(message: &RequestMessage) -> Result<(String, Vec<ImageAttachment>), ApiError> {
+    match &message.content {
+        None => Ok((String::new(), Vec::new())),
+        Some(MessageContent::Text(text)) => Ok((text.clone(), Vec::new())),
+        Some(MessageContent::Parts(parts)) => {
+            let mut texts = Vec::new();
+            let mut images = Vec::new();
+            for part in parts {
+                match part {
+                    ContentPart::Text { text } => texts.push(text.as_str()),
+                    ContentPart::ImageUrl { image_url } => {
+                        images.push(image_from_data_url(&image_url.url)?)
+                    }
+                }
+            }
+            Ok((texts.join("\n"), images))
+        }
+    }
+}
+
+/// Images must be inline `data:<type>;base64,...` URLs; fizz does not fetch
+/// remote images.
+pub(super) fn image_from_data_url(url: &str) -> Result<ImageAttachment, ApiError> {
+    let invalid = || ApiError::invalid_request("image_url must be a base64 data: URL");
+    let rest = url.strip_prefix("data:").ok_or_else(invalid)?;
+    let (media_type, data) = rest.split_once(";base64,").ok_or_else(invalid)?;
+    if !media_type.starts_with("image/") {
+        return Err(invalid());
+    }
+    let bytes = BASE64.decode(data.trim()).map_err(|_| invalid())?;
+    Ok(ImageAttachment::new(media_type, &bytes))
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ModelList {
+    object: &'static str,
+    data: Vec<ModelObject>,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ModelObject {
+    id: &'static str,
+    object: &'static str,
+    created: i64,
+    owned_by: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletion {
+    id: String,
+    object: &'static str,
+    created: i64,
+    model: &'static str,
+    choices: Vec<CompletionChoice>,
+    usage: Usage,
+}
+
+#[derive(Debug, Serialize)]
+struct CompletionChoice {
+    index: u32,
+    message: AssistantMessage,
+    finish_reason: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct AssistantMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatCompletionChunk {
+    id: String,
+    object: &'static str,
+    created: i64,
+    model: &'static str,
+    choices: Vec<ChunkChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChunkChoice {
+    index: u32,
+    delta: Delta,
+    finish_reason: Option<&'static str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Delta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Usage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+}
+
+impl From<&ModelUsage> for Usage {
+    fn from(usage: &ModelUsage) -> Self {
+        Self {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens(),
+        }
+    }
+}
+
+pub(super) async fn list_models() -> Json<ModelList> {
+    Json(ModelList {
+        object: "list",
+        data: vec![ModelObject {
+            id: MODEL_ID,
+            object: "model",
+            created: 0,
+            owned_by: "fizz",
+        }],
+    })
+}
+
+pub(super) async fn chat_completions(
+    State(state): State<ServerState>,
+    request: Result<Json<ChatCompletionRequest>, JsonRejection>,
+) -> Response {
+    let request = match request {
+        Ok(Json(request)) => request,
+        Err(rejection) => return ApiError::invalid_request(rejection.body_text()).into_response(),
+    };
+    let input = match request.turn_input() {
+        Ok(input) => input,
+        Err(err) => return err.into_response(),
+    };
+
+    let mut cfg = state.agents.config().as_ref().clone();
+    cfg.generation = cfg.generation.merged_with(&request.generation_overrides());
+    let tool_call_extraction = cfg.tool_call_extraction;
+    let mut agent = state.agents.build_with_config(Arc::new(cfg));
+    agent.load_conversation(input.conversation);
+    for image in input.images {
+        agent.attach_image(image);
+    }
+    debug!(
+        stream = request.stream,
+        message_count = request.messages.len(),
+        "received chat completion request"
+    );
+
+    let completion_id = next_completion_id();
+    let cancel = state.shutdown.child_token();
+    if !request.stream {
+        return match agent.run_turn_cancellable(&input.user_input, &cancel).await {
+            Ok(result) => Json(completion(completion_id, &result)).into_response(),
+            Err(err) => {
+                warn!(error = %format!("{err:#}"), "chat completion failed");
+                ApiError::from_turn_error(&err).into_response()
+            }
+        };
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let mut events = agent.subscribe();
+    let include_usage = request.include_usage();
+    tokio::spawn(async move {
+        let mut chunks = ChunkStream::new(completion_id, tool_call_extraction);
+        let turn = agent.run_turn_cancellable(&input.user_input, &cancel);
+        tokio::pin!(turn);
+
+        let result = loop {
+            tokio::select! {
+                result = &mut turn => break result,
+                Some(event) = events.recv() => send_all(&sender, chunks.on_event(&event)),
+                _ = sender.closed(), if !cancel.is_cancelled() => {
+                    debug!("client disconnected; cancelling turn");
+                    cancel.cancel();
+                }
+            }
+        };
+        while let Ok(event) = events.try_recv() {
+            send_all(&sender, chunks.on_event(&event));
+        }
+
+        match result {
+            Ok(result) => {
+                send_all(&sender, chunks.finish(&result, include_usage));
+            }
+            Err(err) => {
+                warn!(error = %format!("{err:#}"), "streamed chat completion failed");
+                let _ = sender.send(
+                    Event::default().data(ApiError::from_turn_error(&err).body().to_string()),
+                );
+            }
+        }
+        let _ = sender.send(Event::default().data("[DONE]"));
+    });
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok::<_, Infallible>(event), receiver))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn send_all(sender: &mpsc::UnboundedSender<Event>, chunks: Vec<ChatCompletionChunk>) {
+    for chunk in chunks {
+        match Event::default().json_data(&chunk) {
+            Ok(event) => {
+                let _ = sender.send(event);
+            }
+            Err(err) => warn!(error = %err, "failed to encode completion chunk"),
+        }
+    }
+}
+
+fn completion(id: String, result: &TurnResult) -> ChatCompletion {
+    ChatCompletion {
+        id,
+        object: "chat.completion",
+        created: chrono::Utc::now().timestamp(),
+        model: MODEL_ID,
+        choices: vec![CompletionChoice {
+            index: 0,
+            message: AssistantMessage {
+                role: "assistant",
+                content: result.answer.clone(),
+            },
+            finish_reason: "stop",
+        }],
+        usage: Usage::from(&result.usage.usage),
+    }
+}
+
+fn next_completion_id() -> String {
+    format!(
+        "chatcmpl-{:x}{:04x}",
+        chrono::Utc::now().timestamp_millis(),
+        NEXT_COMPLETION_ID.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// How the current model response is being relayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayMode {
+    /// Only whitespace has arrived so far.
+    Undecided,
+    /// The response cannot be a tool call; deltas are forwarded as they come.
+    Live,
+    /// The response may be a tool call; the answer is sent once the turn
+    /// finishes.
+    Held,
+}
+
+/// Turns agent events into completion chunks.
+///
+/// Only answer text reaches the client: tool-call JSON and tool results stay
+/// inside the turn. A response is streamed live only when it provably is not
+/// a tool call, which is the case in strict extraction once its first
+/// character is not `{`; every other answer arrives in one chunk when the
+/// turn finishes.
+struct ChunkStream {
+    id: String,
+    created: i64,
+    tool_call_extraction: ToolCallExtraction,
+    mode: RelayMode,
+    pending: String,
+    role_sent: bool,
+}
+
+impl ChunkStream {
+    fn new(id: String, tool_call_extraction: ToolCallExtraction) -> Self {
+        Self {
+            id,
+            created: chrono::Utc::now().timestamp(),
+            tool_call_extraction,
+            mode: RelayMode::Undecided,
+            pending: String::new(),
+            role_sent: false,
+        }
+    }
+
+    fn on_event(&mut self, event: &AgentEvent) -> Vec<ChatCompletionChunk> {
+        match &event.kind {
+            AgentEventKind::ModelRequestSent { .. } => {
+                self.mode = RelayMode::Undecided;
+                self.pending.clear();
+                Vec::new()
+            }
+            AgentEventKind::ModelDelta { content } => self.on_delta(content),
+            AgentEventKind::TurnFinished { answer, .. } if self.mode != RelayMode::Live => {
+                vec![self.content_chunk(answer.clone())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn on_delta(&mut self, content: &str) -> Vec<ChatCompletionChunk> {
+        match self.mode {
+            RelayMode::Live => vec![self.content_chunk(content.to_string())],
+            RelayMode::Held => Vec::new(),
+            RelayMode::Undecided => {
+                self.pending.push_str(content);
+                let text = self.pending.trim_start();
+                if text.is_empty() {
+                    return Vec::new();
+                }
+                if self.tool_call_extraction == ToolCallExtraction::Strict && !text.starts_with('{')
+                {
+                    self.mode = RelayMode::Live;
+                    let text = text.to_string();
+                    self.pending.clear();
+                    vec![self.content_chunk(text)]
+                } else {
+                    self.mode = RelayMode::Held;
+                    Vec::new()
+                }
+            }
+        }
+    }
+
+    /// The closing chunk, plus a usage-only chunk when the client asked for
+    /// one.
+    fn finish(&mut self, result: &TurnResult, include_usage: bool) -> Vec<ChatCompletionChunk> {
+        let mut chunks = vec![self.chunk(Delta::default(), Some("stop"))];
+        if include_usage {
+            chunks.push(ChatCompletionChunk {
+                choices: Vec::new(),
+                usage: Some(Usage::from(&result.usage.usage)),
+                ..self.chunk(Delta::default(), None)
+            });
+        }
+        chunks
+    }
+
+    fn content_chunk(&mut self, content: String) -> ChatCompletionChunk {
+        let role = (!self.role_sent).then_some("assistant");
+        self.role_sent = true;
+        self.chunk(
+            Delta {
+                role,
+                content: Some(content),
+            },
+            None,
+        )
+    }
+
+    fn chunk(&self, delta: Delta, finish_reason: Option<&'static str>) -> ChatCompletionChunk {
+        ChatCompletionChunk {
+            id: self.id.clone(),
+            object: "chat.completion.chunk",
+            created: self.created,
+            model: MODEL_ID,
+            choices: vec![ChunkChoice {
+                index: 0,
+                delta,
+                finish_reason,
+            }],
+            usage: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    use super::{ChatCompletionRequest, ChunkStream, TurnInput};
+    use crate::agent::events::{AgentEvent, AgentEventKind};
+    use crate::config::ToolCallExtraction;
+    use crate::model::{ImageAttachment, Message};
+
+    fn request(body: serde_json::Value) -> ChatCompletionRequest {
+        serde_json::from_value(body).expect("request should deserialize")
+    }
+
+    fn event(kind: AgentEventKind) -> AgentEvent {
+        AgentEvent { turn_id: 1, kind }
+    }
+
+    fn delta(content: &str) -> AgentEvent {
+        event(AgentEventKind::ModelDelta {
+            content: content.to_string(),
+        })
+    }
+
+    fn contents(chunks: &[super::ChatCompletionChunk]) -> Vec<&str> {
+        chunks
+            .iter()
+            .filter_map(|chunk| chunk.choices[0].delta.content.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn turn_input_splits_the_last_user_message_from_the_conversation() {
+        let request = request(json!({
+            "model": "anything",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "what is"},
+                    {"type": "text", "text": "this?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw=="}}
+                ]}
+            ]
+        }));
+
+        assert_eq!(
+            request.turn_input().expect("input should convert"),
+            TurnInput {
+                conversation: vec![
+                    Message::system("Be brief."),
+                    Message::user("hi"),
+                    Message::assistant("hello"),
+                ],
+                user_input: "what is\nthis?".to_string(),
+                images: vec![ImageAttachment::new("image/png", b"\x89PNG")],
+            }
+        );
+    }
+
+    #[test]
+    fn turn_input_rejects_unsupported_conversations() {
+        let cases = [
+            (json!([]), "messages must not be empty"),
+            (
+                json!([{"role": "assistant", "content": "hi"}]),
+                "the last message must have role 'user'",
+            ),
+            (
+                json!([{"role": "tool", "content": "42"}, {"role": "user", "content": "and?"}]),
+                "tool messages are not supported; fizz runs its own tools",
+            ),
+            (
+                json!([{"role": "user", "content": [
+                    {"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}
+                ]}]),
+                "image_url must be a base64 data: URL",
+            ),
+        ];
+
+        for (messages, message) in cases {
+            let err = request(json!({ "messages": messages }))
+                .turn_input()
+                .expect_err("input should be rejected");
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+            assert_eq!(err.message, message);
+        }
+    }
+
+    #[test]
+    fn generation_overrides_take_request_options() {
+        let overrides = request(json!({
+            "messages": [],
+            "temperature": 0.1,
+            "max_tokens": 10,
+            "max_completion_tokens": 20,
+            "stop": "END"
+        }))
+        .generation_overrides();
+
+        assert_eq!(overrides.temperature, Some(0.1));
+        assert_eq!(overrides.max_tokens, Some(20));
+        assert_eq!(overrides.stop, vec!["END".to_string()]);
+    }
+
+    #[test]
+    fn chunk_stream_relays_plain_answers_live_in_strict_mode() {
+        let mut chunks = ChunkStream::new("id".to_string(), ToolCallExtraction::Strict);
+
+        let mut sent = chunks.on_event(&event(AgentEventKind::ModelRequestSent {
+            message_count: 2,
+        }));
+        for part in ["  ", "Hel", "lo"] {
+            sent.extend(chunks.on_event(&delta(part)));
+        }
+        sent.extend(chunks.on_event(&event(AgentEventKind::TurnFinished {
+            answer: "  Hello".to_string(),
+            tool_hops: 0,
+        })));
+
+        assert_eq!(contents(&sent), vec!["Hel", "lo"]);
+        assert_eq!(sent[0].choices[0].delta.role, Some("assistant"));
+        assert_eq!(sent[1].choices[0].delta.role, None);
+    }
+
+    #[test]
+    fn chunk_stream_holds_back_possible_tool_calls() {
+        let mut chunks = ChunkStream::new("id".to_string(), ToolCallExtraction::Strict);
+
+        let mut sent = chunks.on_event(&event(AgentEventKind::ModelRequestSent {
+            message_count: 2,
+        }));
+        sent.extend(chunks.on_event(&delta(r#"{"tool_call":"#)));
+        sent.extend(chunks.on_event(&delta(r#"{"name":"time.now"}}"#)));
+        sent.extend(chunks.on_event(&event(AgentEventKind::ModelRequestSent {
+            message_count: 4,
+        })));
+        sent.extend(chunks.on_event(&delta("It is noon.")));
+        sent.extend(chunks.on_event(&event(AgentEventKind::TurnFinished {
+            answer: "It is noon.".to_string(),
+            tool_hops: 1,
+        })));
+        assert_eq!(contents(&sent), vec!["It is noon."]);
+
+        let mut lenient = ChunkStream::new("id".to_string(), ToolCallExtraction::Lenient);
+        let mut sent = lenient.on_event(&delta("Sure: ```json"));
+        sent.extend(lenient.on_event(&event(AgentEventKind::TurnFinished {
+            answer: "Sure.".to_string(),
+            tool_hops: 0,
+        })));
+        assert_eq!(contents(&sent), vec!["Sure."]);
+    }
+}
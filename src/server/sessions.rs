@@ -0,0 +1,697 @@
+//! The native session API under `/api/sessions`.
+//!
+//! A session is an agent that lives on the server between requests. Turns run
+//! in the background; clients follow them through the session's event stream
+//! and answer tool approval requests through the approvals endpoints.
+
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, oneshot};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+use super::ServerState;
+use super::error::ApiError;
+use super::openai::image_from_data_url;
+use crate::agent::Agent;
+use crate::agent::events::AgentEvent;
+use crate::agent::tools::{ToolApproval, ToolApprovalFuture, ToolApprover, ToolCall};
+use crate::config::SessionLimits;
+use crate::model::{Message, MessageRole, UsageTotals};
+
+/// Events buffered per session for slow stream readers; a reader that falls
+/// further behind skips the oldest ones.
+const SESSION_EVENT_BUFFER: usize = 256;
+
+/// The server's sessions, by id. Idle sessions are removed whenever the
+/// store is used, and at most `max_sessions` are kept.
+pub(super) struct SessionStore {
+    sessions: Mutex<HashMap<String, Arc<Session>>>,
+    next_id: AtomicU64,
+    limits: SessionLimits,
+}
+
+impl SessionStore {
+    pub(super) fn new(limits: SessionLimits) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            limits,
+        }
+    }
+
+    /// Locks the sessions after removing the idle ones.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<Session>>> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let idle_ttl = Duration::from_secs(self.limits.idle_ttl_secs);
+        sessions.retain(|id, session| {
+            if !session.is_idle(idle_ttl) {
+                return true;
+            }
+            session.close();
+            info!(session_id = %id, "removed idle session");
+            false
+        });
+        sessions
+    }
+
+    fn approval_timeout(&self) -> Duration {
+        Duration::from_secs(self.limits.approval_timeout_secs)
+    }
+
+    fn insert(&self, session: Session) -> Result<Arc<Session>, ApiError> {
+        let mut sessions = self.lock();
+        if sessions.len() >= self.limits.max_sessions {
+            return Err(ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "session_limit_reached",
+                format!(
+                    "the server keeps at most {} sessions; delete one first",
+                    self.limits.max_sessions
+                ),
+            ));
+        }
+        let session = Arc::new(session);
+        sessions.insert(session.id.clone(), Arc::clone(&session));
+        Ok(session)
+    }
+
+    fn get(&self, id: &str) -> Result<Arc<Session>, ApiError> {
+        let session = self
+            .lock()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ApiError::not_found(format!("no session '{id}'")))?;
+        session.touch();
+        Ok(session)
+    }
+
+    fn remove(&self, id: &str) -> Option<Arc<Session>> {
+        self.lock().remove(id)
+    }
+
+    fn list(&self) -> Vec<Arc<Session>> {
+        let mut sessions: Vec<Arc<Session>> = self.lock().values().cloned().collect();
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        sessions
+    }
+
+    fn next_id(&self) -> String {
+        format!(
+            "sess_{:x}{:04x}",
+            chrono::Utc::now().timestamp_millis(),
+            self.next_id.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+}
+
+struct Session {
+    id: String,
+    created_at: String,
+    agent: tokio::sync::Mutex<Agent>,
+    events: broadcast::Sender<AgentEvent>,
+    approvals: Arc<PendingApprovals>,
+    /// Cancels the running turn, if any.
+    running_turn: Mutex<Option<CancellationToken>>,
+    /// Fires when the session is deleted, expires or the server shuts down;
+    /// ends the event streams and every turn.
+    closed: CancellationToken,
+    /// When a request last used the session or its last turn ended.
+    last_active: Mutex<Instant>,
+}
+
+impl Session {
+    fn running_turn(&self) -> MutexGuard<'_, Option<CancellationToken>> {
+        self.running_turn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn touch(&self) {
+        *self
+            .last_active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Instant::now();
+    }
+
+    fn is_idle(&self, idle_ttl: Duration) -> bool {
+        let last_active = *self
+            .last_active
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.running_turn().is_none() && last_active.elapsed() >= idle_ttl
+    }
+
+    fn summary(&self) -> Value {
+        json!({
+            "id": self.id,
+            "created_at": self.created_at,
+            "turn_running": self.running_turn().is_some(),
+            "pending_approvals": self.approvals.list().len(),
+        })
+    }
+
+    fn close(&self) {
+        self.closed.cancel();
+        self.approvals.deny_all("the session was closed");
+    }
+}
+
+/// Tool calls waiting for a client's decision, keyed by call id. A call
+/// without a decision after `timeout` is denied.
+struct PendingApprovals {
+    pending: Mutex<HashMap<String, PendingApproval>>,
+    timeout: Duration,
+}
+
+struct PendingApproval {
+    tool_name: String,
+    arguments: Map<String, Value>,
+    decision: oneshot::Sender<ToolApproval>,
+}
+
+#[derive(Debug, Serialize)]
+struct PendingApprovalView {
+    call_id: String,
+    tool_name: String,
+    arguments: Map<String, Value>,
+}
+
+impl PendingApprovals {
+    fn new(timeout: Duration) -> Self {
+        Self {
+            pending: Mutex::new(HashMap::new()),
+            timeout,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, PendingApproval>> {
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn list(&self) -> Vec<PendingApprovalView> {
+        let mut views: Vec<PendingApprovalView> = self
+            .lock()
+            .iter()
+            .map(|(call_id, pending)| PendingApprovalView {
+                call_id: call_id.clone(),
+                tool_name: pending.tool_name.clone(),
+                arguments: pending.arguments.clone(),
+            })
+            .collect();
+        views.sort_by(|a, b| a.call_id.cmp(&b.call_id));
+        views
+    }
+
+    /// Delivers `approval` to the waiting call; false when no call with that
+    /// id is waiting.
+    fn decide(&self, call_id: &str, approval: ToolApproval) -> bool {
+        match self.lock().remove(call_id) {
+            Some(pending) => pending.decision.send(approval).is_ok(),
+            None => false,
+        }
+    }
+
+    fn deny_all(&self, reason: &str) {
+        for (_, pending) in self.lock().drain() {
+            let _ = pending.decision.send(ToolApproval::Denied {
+                reason: reason.to_string(),
+            });
+        }
+    }
+}
+
+/// Removes a call's entry when its review ends, including when the turn is
+/// cancelled while the call waits.
+struct PendingGuard<'a> {
+    approvals: &'a PendingApprovals,
+    call_id: &'a str,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.approvals.lock().remove(self.call_id);
+    }
+}
+
+impl ToolApprover for PendingApprovals {
+    fn review<'a>(&'a self, call: &'a ToolCall) -> ToolApprovalFuture<'a> {
+        let (sender, receiver) = oneshot::channel();
+        self.lock().insert(
+            call.id.clone(),
+            PendingApproval {
+                tool_name: call.name.clone(),
+                arguments: call.arguments.clone(),
+                decision: sender,
+            },
+        );
+        let guard = PendingGuard {
+            approvals: self,
+            call_id: &call.id,
+        };
+        let timeout = self.timeout;
+        Box::pin(async move {
+            let _guard = guard;
+            match tokio::time::timeout(timeout, receiver).await {
+                Ok(Ok(approval)) => approval,
+                Ok(Err(_)) => ToolApproval::Denied {
+                    reason: "the session was closed".to_string(),
+                },
+                Err(_) => ToolApproval::Denied {
+                    reason: format!("no approval decision within {} seconds", timeout.as_secs()),
+                },
+            }
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct CreateSessionRequest {
+    /// Whether tool calls wait for approval; defaults to true.
+    #[serde(default)]
+    require_approval: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct TurnRequest {
+    input: String,
+    /// Images as base64 `data:` URLs.
+    #[serde(default)]
+    images: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct ApprovalRequest {
+    approve: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+pub(super) async fn create_session(State(state): State<ServerState>, body: Bytes) -> Response {
+    let request: CreateSessionRequest = if body.is_empty() {
+        CreateSessionRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(request) => request,
+            Err(err) => return ApiError::invalid_request(err.to_string()).into_response(),
+        }
+    };
+
+    let approvals = Arc::new(PendingApprovals::new(state.sessions.approval_timeout()));
+    let mut agent = state.agents.build();
+    if request.require_approval.unwrap_or(true) {
+        agent = agent.with_tool_approver(Arc::clone(&approvals) as Arc<dyn ToolApprover>);
+    }
+    let (events, _) = broadcast::channel(SESSION_EVENT_BUFFER);
+    let mut agent_events = agent.subscribe();
+    let forward = events.clone();
+    // Ends when the session, and with it the agent, is dropped.
+    tokio::spawn(async move {
+        while let Some(event) = agent_events.recv().await {
+            let _ = forward.send(event);
+        }
+    });
+
+    let session = match state.sessions.insert(Session {
+        id: state.sessions.next_id(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        agent: tokio::sync::Mutex::new(agent),
+        events,
+        approvals,
+        running_turn: Mutex::new(None),
+        closed: state.shutdown.child_token(),
+        last_active: Mutex::new(Instant::now()),
+    }) {
+        Ok(session) => session,
+        Err(err) => return err.into_response(),
+    };
+    info!(session_id = %session.id, "created session");
+    (StatusCode::CREATED, Json(session.summary())).into_response()
+}
+
+pub(super) async fn list_sessions(State(state): State<ServerState>) -> Json<Value> {
+    let sessions: Vec<Value> = state
+        .sessions
+        .list()
+        .iter()
+        .map(|session| session.summary())
+        .collect();
+    Json(json!({ "sessions": sessions }))
+}
+
+pub(super) async fn get_session(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    Ok(Json(state.sessions.get(&id)?.summary()))
+}
+
+pub(super) async fn delete_session(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let session = state
+        .sessions
+        .remove(&id)
+        .ok_or_else(|| ApiError::not_found(format!("no session '{id}'")))?;
+    session.close();
+    info!(session_id = %id, "deleted session");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Starts a turn in the background and answers `202 Accepted`; the turn's
+/// progress and answer arrive on the event stream.
+pub(super) async fn post_turn(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+    Json(request): Json<TurnRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let session = state.sessions.get(&id)?;
+    if request.input.trim().is_empty() {
+        return Err(ApiError::invalid_request("input must not be empty"));
+    }
+    let images = request
+        .images
+        .iter()
+        .map(|url| image_from_data_url(url))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cancel = session.closed.child_token();
+    {
+        let mut running_turn = session.running_turn();
+        if running_turn.is_some() {
+            return Err(ApiError::conflict("a turn is already running"));
+        }
+        *running_turn = Some(cancel.clone());
+    }
+
+    let turn_session = Arc::clone(&session);
+    tokio::spawn(async move {
+        let session = turn_session;
+        let mut agent = session.agent.lock().await;
+        for image in images {
+            agent.attach_image(image);
+        }
+        // Failures reach clients as `turn_failed` events.
+        if let Err(err) = agent.run_turn_cancellable(&request.input, &cancel).await {
+            debug!(session_id = %session.id, error = %format!("{err:#}"), "session turn ended early");
+        }
+        drop(agent);
+        session.touch();
+        session.running_turn().take();
+    });
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({ "session_id": session.id, "status": "running" })),
+    ))
+}
+
+pub(super) async fn cancel_turn(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let session = state.sessions.get(&id)?;
+    match session.running_turn().as_ref() {
+        Some(cancel) => {
+            cancel.cancel();
+            Ok(StatusCode::ACCEPTED)
+        }
+        None => Err(ApiError::conflict("no turn is running")),
+    }
+}
+
+/// Streams the session's agent events as server-sent events named after
+/// [`AgentEventKind::as_str`](crate::agent::events::AgentEventKind::as_str).
+/// Only events emitted after the client connects are sent.
+pub(super) async fn stream_events(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let session = state.sessions.get(&id)?;
+    let receiver = session.events.subscribe();
+    let closed = session.closed.clone();
+    drop(session);
+
+    let events = stream::unfold((receiver, closed), |(mut receiver, closed)| async move {
+        loop {
+            let received = tokio::select! {
+                _ = closed.cancelled() => return None,
+                received = receiver.recv() => received,
+            };
+            match received {
+                Ok(event) => {
+                    let sse = Event::default().event(event.kind.as_str());
+                    let sse = match sse.json_data(&event) {
+                        Ok(sse) => sse,
+                        Err(err) => {
+                            warn!(error = %err, "failed to encode agent event");
+                            continue;
+                        }
+                    };
+                    return Some((Ok::<_, Infallible>(sse), (receiver, closed)));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "event stream reader fell behind");
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response())
+}
+
+pub(super) async fn list_approvals(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let session = state.sessions.get(&id)?;
+    Ok(Json(json!({ "approvals": session.approvals.list() })))
+}
+
+pub(super) async fn decide_approval(
+    State(state): State<ServerState>,
+    Path((id, call_id)): Path<(String, String)>,
+    Json(request): Json<ApprovalRequest>,
+) -> Result<StatusCode, ApiError> {
+    let session = state.sessions.get(&id)?;
+    let approval = if request.approve {
+        ToolApproval::Approved
+    } else {
+        ToolApproval::Denied {
+            reason: request
+                .reason
+                .filter(|reason| !reason.trim().is_empty())
+                .unwrap_or_else(|| "the user denied the call".to_string()),
+        }
+    };
+    if session.approvals.decide(&call_id, approval) {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ApiError::not_found(format!(
+            "no tool call '{call_id}' is waiting for approval"
+        )))
+    }
+}
+
+/// The full history sent to the model, like the REPL's `/history`.
+pub(super) async fn get_history(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let session = state.sessions.get(&id)?;
+    let Ok(agent) = session.agent.try_lock() else {
+        return Err(ApiError::conflict(
+            "a turn is running; fetch the history after it finishes",
+        ));
+    };
+    let messages: Vec<Value> = agent.history().iter().map(history_entry).collect();
+    let role_usage: Map<String, Value> = agent
+        .session_role_usage()
+        .iter()
+        .map(|(role, totals)| (role.as_str().to_string(), usage_entry(totals)))
+        .collect();
+    Ok(Json(json!({
+        "messages": messages,
+        "usage": usage_entry(&agent.session_usage()),
+        "role_usage": role_usage,
+    })))
+}
+
+fn usage_entry(totals: &UsageTotals) -> Value {
+    json!({
+        "model_calls": totals.model_calls,
+        "prompt_tokens": totals.usage.prompt_tokens,
+        "completion_tokens": totals.usage.completion_tokens,
+    })
+}
+
+fn history_entry(message: &Message) -> Value {
+    let mut entry = json!({
+        "role": message.role.as_str(),
+        "content": message.content,
+        "image_count": message.images.len(),
+    });
+    if let MessageRole::Tool { tool_name, call_id } = &message.role {
+        entry["tool_name"] = json!(tool_name);
+        entry["call_id"] = json!(call_id);
+    }
+    entry
+}
+
+/// Workspace diff and commit need a workspace filesystem backend, which
+/// fizz does not have yet: `WORKSPACE_FS_MODE` is accepted but tools run on
+/// the host filesystem. The routes exist so clients get a clear answer.
+pub(super) async fn workspace_unavailable(
+    State(state): State<ServerState>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    state.sessions.get(&id)?;
+    Err(ApiError::new(
+        StatusCode::NOT_IMPLEMENTED,
+        "not_implemented",
+        format!(
+            "workspace diff and commit are not available: no workspace filesystem backend is implemented (WORKSPACE_FS_MODE={})",
+            state.agents.config().workspace_fs_mode.as_str()
+        ),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tokio::sync::broadcast;
+    use tokio_util::sync::CancellationToken;
+
+    use super::{PendingApprovals, Session, SessionStore};
+    use crate::agent::Agent;
+    use crate::agent::tools::{ToolApproval, ToolApprover, ToolCall};
+    use crate::config::{Config, SessionLimits};
+
+    fn approvals() -> PendingApprovals {
+        PendingApprovals::new(Duration::from_secs(60))
+    }
+
+    fn session(id: &str, last_active: Instant) -> Session {
+        let cfg = Arc::new(Config::from_env_with(|_| None));
+        Session {
+            id: id.to_string(),
+            created_at: String::new(),
+            agent: tokio::sync::Mutex::new(Agent::new(reqwest::Client::new(), cfg)),
+            events: broadcast::channel(1).0,
+            approvals: Arc::new(approvals()),
+            running_turn: Mutex::new(None),
+            closed: CancellationToken::new(),
+            last_active: Mutex::new(last_active),
+        }
+    }
+
+    fn call(id: &str) -> ToolCall {
+        let mut call = ToolCall::new("time.now");
+        call.id = id.to_string();
+        call
+    }
+
+    #[tokio::test]
+    async fn pending_approvals_deliver_decisions_to_waiting_calls() {
+        let approvals = Arc::new(approvals());
+        let waiting = Arc::clone(&approvals);
+        let review = tokio::spawn(async move { waiting.review(&call("call_1_1_0")).await });
+        while approvals.list().is_empty() {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(approvals.list()[0].call_id, "call_1_1_0");
+        assert!(!approvals.decide("call_9_9_9", ToolApproval::Approved));
+        assert!(approvals.decide("call_1_1_0", ToolApproval::Approved));
+        assert_eq!(
+            review.await.expect("review should not panic"),
+            ToolApproval::Approved
+        );
+        assert!(approvals.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropped_reviews_leave_no_pending_entry() {
+        let approvals = approvals();
+        let call = call("call_1_1_0");
+
+        let review = approvals.review(&call);
+        assert_eq!(approvals.list().len(), 1);
+        drop(review);
+
+        assert!(approvals.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn undecided_approvals_are_denied_after_the_timeout() {
+        let approvals = PendingApprovals::new(Duration::from_millis(10));
+
+        let approval = approvals.review(&call("call_1_1_0")).await;
+
+        assert_eq!(
+            approval,
+            ToolApproval::Denied {
+                reason: "no approval decision within 0 seconds".to_string()
+            }
+        );
+        assert!(approvals.list().is_empty());
+    }
+
+    #[tokio::test]
+    async fn session_store_removes_idle_sessions_and_caps_the_rest() {
+        let store = SessionStore::new(SessionLimits {
+            max_sessions: 2,
+            idle_ttl_secs: 60,
+            approval_timeout_secs: 60,
+        });
+        let long_ago = Instant::now()
+            .checked_sub(Duration::from_secs(61))
+            .expect("clock should allow subtracting the idle ttl");
+        let idle = store
+            .insert(session("sess_idle", long_ago))
+            .expect("first session fits");
+        store
+            .insert(session("sess_a", Instant::now()))
+            .expect("idle session makes room");
+        store
+            .insert(session("sess_b", Instant::now()))
+            .expect("second active session fits");
+
+        assert!(idle.closed.is_cancelled());
+        let err = store
+            .insert(session("sess_c", Instant::now()))
+            .err()
+            .expect("a third session exceeds the cap");
+        assert_eq!(err.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            store
+                .list()
+                .iter()
+                .map(|session| session.id.as_str())
+                .collect::<Vec<_>>(),
+            ["sess_a", "sess_b"]
+        );
+    }
+}
@@ -0,0 +1,20 @@
+//! Scratch directories for tests.
+//!
+//! Integration tests include this file with `#[path]`, so it must not depend
+//! on anything else in the crate.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Creates an empty directory under the system temp dir whose name starts
+/// with `fizz-<suffix>` and is unique to this call.
+pub(crate) fn unique_temp_dir(suffix: &str) -> PathBuf {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock should be after unix epoch")
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("fizz-{suffix}-{stamp}-{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("failed to create temp directory");
+    dir
+}
@@ -0,0 +1,152 @@
+//! A blocking HTTP/1.1 stub server for tests. Each connection carries one
+//! request and gets one response before the server closes it.
+//!
+//! Integration tests include this file with `#[path]`, so it must not depend
+//! on anything else in the crate.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+/// A request the stub received.
+#[derive(Debug, Clone)]
+pub(crate) struct StubRequest {
+    /// The request line and headers, without the blank line that ends them.
+    pub(crate) head: String,
+    pub(crate) body: String,
+}
+
+/// A response the stub sends back.
+#[derive(Debug, Clone)]
+pub(crate) struct StubResponse {
+    pub(crate) status: u16,
+    pub(crate) content_type: &'static str,
+    pub(crate) body: String,
+}
+
+impl StubResponse {
+    pub(crate) fn json(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.into(),
+        }
+    }
+
+    pub(crate) fn ndjson(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/x-ndjson",
+            body: body.into(),
+        }
+    }
+}
+
+/// Serves `responses` in order, one connection each, and returns the base
+/// URL. Joining the handle yields the requests that were received.
+pub(crate) fn serve(
+    responses: Vec<StubResponse>,
+) -> (String, thread::JoinHandle<Vec<StubRequest>>) {
+    let connections = responses.len();
+    let mut responses = responses.into_iter();
+    serve_with(connections, move |_| {
+        responses.next().expect("a response per connection")
+    })
+}
+
+/// Answers `connections` connections with `handler`, then stops. Returns the
+/// base URL; joining the handle yields the requests that were received.
+pub(crate) fn serve_with(
+    connections: usize,
+    mut handler: impl FnMut(&StubRequest) -> StubResponse + Send + 'static,
+) -> (String, thread::JoinHandle<Vec<StubRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
+    let base_url = base_url(&listener);
+    let server = thread::spawn(move || {
+        let mut requests = Vec::with_capacity(connections);
+        for _ in 0..connections {
+            let (mut stream, _) = listener.accept().expect("accept should succeed");
+            let request = read_request(&mut stream);
+            write_response(&mut stream, &handler(&request));
+            requests.push(request);
+        }
+        requests
+    });
+    (base_url, server)
+}
+
+/// Answers every connection with `handler` until the process exits, and
+/// returns the base URL.
+pub(crate) fn serve_forever(
+    mut handler: impl FnMut(&StubRequest) -> StubResponse + Send + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
+    let base_url = base_url(&listener);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                return;
+            };
+            let request = read_request(&mut stream);
+            write_response(&mut stream, &handler(&request));
+        }
+    });
+    base_url
+}
+
+/// A base URL nothing listens on, so connecting to it is refused.
+pub(crate) fn unused_url() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind should succeed");
+    base_url(&listener)
+}
+
+fn base_url(listener: &TcpListener) -> String {
+    format!(
+        "http://{}",
+        listener.local_addr().expect("address should be available")
+    )
+}
+
+fn read_request(stream: &mut TcpStream) -> StubRequest {
+    let mut reader = BufReader::new(stream);
+    let mut head = String::new();
+    let mut content_length = 0usize;
+    loop {
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .expect("request header should be readable");
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().expect("content-length is a number");
+        }
+        head.push_str(line);
+        head.push_str("\r\n");
+    }
+    let mut body = vec![0u8; content_length];
+    reader
+        .read_exact(&mut body)
+        .expect("request body should be readable");
+    StubRequest {
+        head,
+        body: String::from_utf8(body).expect("request body should be UTF-8"),
+    }
+}
+
+/// Writes `response`, ignoring failures: a client that timed out may have
+/// closed the connection already.
+fn write_response(stream: &mut TcpStream, response: &StubResponse) {
+    let raw = format!(
+        "HTTP/1.1 {} Status\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    let _ = stream.write_all(raw.as_bytes());
+}
@@ -0,0 +1,12 @@
+"""A persistent plugin for the plugin integration tests."""
+
+import json
+import sys
+
+count = 0
+for line in sys.stdin:
+    request = json.loads(line)
+    if request["tool"] == "counter.exit":
+        sys.exit(1)
+    count += 1
+    print(json.dumps({"id": request["id"], "output": str(count)}), flush=True)
@@ -0,0 +1,26 @@
+"""A one-shot plugin for the plugin integration tests."""
+
+import json
+import sys
+import time
+
+request = json.loads(sys.stdin.readline())
+tool = request["tool"]
+reply = {"id": request["id"]}
+
+if tool == "echo.say":
+    reply["output"] = request["arguments"]["text"]
+elif tool == "echo.fail":
+    reply["error"] = "the text was rude"
+elif tool == "echo.crash":
+    print("something broke", file=sys.stderr)
+    sys.exit(3)
+elif tool == "echo.flood":
+    reply["output"] = "x" * 100_000
+elif tool == "echo.hang":
+    time.sleep(60)
+elif tool == "echo.hog":
+    hog = bytearray(512 * 1024 * 1024)
+    reply["output"] = str(len(hog))
+
+print(json.dumps(reply), flush=True)
@@ -0,0 +1,90 @@
+"""A minimal MCP server over stdio for the MCP integration tests.
+
+Tools: `echo` (pings the client before answering), `fail` (a tool error),
+`broken` (a JSON-RPC error) and `slow` (never answers). `tools/list` is split
+over two pages.
+"""
+
+import json
+import sys
+
+
+TOOLS = [
+    {
+        "name": "echo",
+        "description": "Echoes its text.",
+        "inputSchema": {
+            "type": "object",
+            "properties": {"text": {"type": "string"}},
+            "required": ["text"],
+        },
+    },
+    {"name": "fail", "inputSchema": {"type": "object"}},
+    {"name": "broken", "description": "Always errors.", "inputSchema": {"type": "object"}},
+    {"name": "slow", "description": "Never answers.", "inputSchema": {"type": "object"}},
+]
+
+
+def send(message):
+    sys.stdout.write(json.dumps(message) + "\n")
+    sys.stdout.flush()
+
+
+def read():
+    line = sys.stdin.readline()
+    if not line:
+        sys.exit(0)
+    return json.loads(line)
+
+
+def call_tool(request_id, params):
+    name = params["name"]
+    arguments = params.get("arguments", {})
+    if name == "echo":
+        send({"jsonrpc": "2.0", "id": "ping-1", "method": "ping"})
+        pong = read()
+        assert pong == {"jsonrpc": "2.0", "id": "ping-1", "result": {}}, pong
+        text = arguments["text"]
+        send({"jsonrpc": "2.0", "id": request_id, "result": {
+            "content": [{"type": "text", "text": f"echo: {text}"}],
+        }})
+    elif name == "fail":
+        send({"jsonrpc": "2.0", "id": request_id, "result": {
+            "content": [{"type": "text", "text": "ticket not found"}],
+            "isError": True,
+        }})
+    elif name == "broken":
+        send({"jsonrpc": "2.0", "id": request_id,
+              "error": {"code": -32602, "message": "bad arguments"}})
+    elif name == "slow":
+        pass
+
+
+def main():
+    print("stub mcp server starting", file=sys.stderr)
+    while True:
+        message = read()
+        method = message.get("method")
+        request_id = message.get("id")
+        if method == "initialize":
+            assert message["params"]["clientInfo"]["name"] == "fizz"
+            send({"jsonrpc": "2.0", "id": request_id, "result": {
+                "protocolVersion": message["params"]["protocolVersion"],
+                "capabilities": {"tools": {}},
+                "serverInfo": {"name": "stub", "version": "0.1.0"},
+            }})
+        elif method == "tools/list":
+            if message["params"].get("cursor") == "page-2":
+                send({"jsonrpc": "2.0", "id": request_id, "result": {"tools": TOOLS[2:]}})
+            else:
+                send({"jsonrpc": "2.0", "id": request_id,
+                      "result": {"tools": TOOLS[:2], "nextCursor": "page-2"}})
+        elif method == "tools/call":
+            call_tool(request_id, message["params"])
+        elif request_id is not None:
+            send({"jsonrpc": "2.0", "id": request_id,
+                  "error": {"code": -32601, "message": f"unknown method {method}"}})
+
+
+if __name__ == "__main__":
+    main()
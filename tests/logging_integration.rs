@@ -1,8 +1,11 @@
+#[path = "../src/test_fs.rs"]
+mod test_fs;
+
 use serde_json::Value;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
-use std::time::{SystemTime, UNIX_EPOCH};
+use test_fs::unique_temp_dir;
 
 fn run_with_logging_env(
     log_output: &str,
@@ -25,19 +28,6 @@ fn run_with_logging_env(
     cmd.output().expect("failed to run fizz binary")
 }
 
-fn unique_temp_dir(suffix: &str) -> PathBuf {
-    let stamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("system clock should be after unix epoch")
-        .as_nanos();
-    let dir = std::env::temp_dir().join(format!(
-        "fizz-logging-{suffix}-{stamp}-{}",
-        std::process::id()
-    ));
-    fs::create_dir_all(&dir).expect("failed to create temp directory");
-    dir
-}
-
 fn find_rotated_log_file(dir: &Path, base_file_name: &str) -> PathBuf {
     let expected_prefix = format!("{base_file_name}.");
     let mut matches: Vec<PathBuf> = fs::read_dir(dir)
@@ -0,0 +1,152 @@
+use fizz::agent::tools::{ToolCall, ToolRunner};
+use fizz::mcp::{McpError, McpRunner, McpServerConfig};
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn stub_server(name: &str) -> McpServerConfig {
+    McpServerConfig {
+        name: name.to_string(),
+        command: "python3".to_string(),
+        args: vec![format!(
+            "{}/tests/fixtures/stub_mcp_server.py",
+            env!("CARGO_MANIFEST_DIR")
+        )],
+        env: BTreeMap::new(),
+    }
+}
+
+fn call(name: &str, arguments: Value) -> ToolCall {
+    let Value::Object(arguments) = arguments else {
+        panic!("arguments should be an object");
+    };
+    ToolCall::new(name).with_arguments(arguments)
+}
+
+#[tokio::test]
+async fn runner_lists_tools_from_every_page_with_their_schemas() {
+    let (runner, errors) = McpRunner::connect(&[stub_server("stub")], Duration::from_secs(5)).await;
+    assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+
+    let tools = runner.tools();
+    let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["stub.echo", "stub.fail", "stub.broken", "stub.slow"]
+    );
+    assert_eq!(tools[0].description, "Echoes its text.");
+    assert_eq!(
+        tools[0].parameters.as_ref().expect("echo has a schema")["required"],
+        json!(["text"])
+    );
+    assert_eq!(tools[1].description, "'fail' tool from MCP server 'stub'.");
+    assert_eq!(tools[1].parameters, None);
+}
+
+#[tokio::test]
+async fn runner_forwards_calls_and_maps_results_and_errors() {
+    let (runner, _) = McpRunner::connect(&[stub_server("stub")], Duration::from_secs(1)).await;
+
+    let output = runner
+        .execute(&call("stub.echo", json!({"text": "hi"})))
+        .await
+        .expect("echo should succeed");
+    assert_eq!(output.content, "echo: hi");
+
+    let err = runner
+        .execute(&call("stub.fail", json!({})))
+        .await
+        .expect_err("tool errors should fail");
+    assert_eq!(err.to_string(), "stub.fail failed: ticket not found");
+
+    let err = runner
+        .execute(&call("stub.broken", json!({})))
+        .await
+        .expect_err("JSON-RPC errors should fail");
+    assert_eq!(
+        err.to_string(),
+        "MCP server 'stub' returned error -32602: bad arguments"
+    );
+
+    let err = runner
+        .execute(&call("stub.slow", json!({})))
+        .await
+        .expect_err("unanswered calls should time out");
+    assert_eq!(
+        err.to_string(),
+        "MCP server 'stub' did not answer tools/call within 1s"
+    );
+
+    // The server is still usable after a timed-out call.
+    let output = runner
+        .execute(&call("stub.echo", json!({"text": "again"})))
+        .await
+        .expect("echo should succeed after a timeout");
+    assert_eq!(output.content, "echo: again");
+}
+
+#[tokio::test]
+async fn runner_skips_servers_that_fail_to_start() {
+    let missing = McpServerConfig {
+        name: "missing".to_string(),
+        command: "/nonexistent/fizz-mcp-server".to_string(),
+        args: Vec::new(),
+        env: BTreeMap::new(),
+    };
+    let (runner, errors) =
+        McpRunner::connect(&[missing, stub_server("stub")], Duration::from_secs(5)).await;
+
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], McpError::Spawn { server, .. } if server == "missing"));
+    assert_eq!(runner.tools().len(), 4);
+}
+
+#[tokio::test]
+async fn fizz_mcp_serve_offers_its_local_tools_to_mcp_clients() {
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock should be after unix epoch")
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!("fizz-mcp-{stamp}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create temp directory");
+    let fizz = McpServerConfig {
+        name: "fizz".to_string(),
+        command: env!("CARGO_BIN_EXE_fizz").to_string(),
+        args: vec!["mcp-serve".to_string()],
+        env: BTreeMap::from([
+            (
+                "MEMORY_PATH".to_string(),
+                dir.join("memory.json").display().to_string(),
+            ),
+            ("LOG_OUTPUT".to_string(), "stderr".to_string()),
+        ]),
+    };
+
+    let (runner, errors) = McpRunner::connect(&[fizz], Duration::from_secs(10)).await;
+    assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+    let names: Vec<String> = runner.tools().into_iter().map(|tool| tool.name).collect();
+    assert!(names.contains(&"fizz.time.now".to_string()), "{names:?}");
+    assert!(names.contains(&"fizz.memory.save".to_string()), "{names:?}");
+
+    let output = runner
+        .execute(&call(
+            "fizz.memory.save",
+            json!({"text": "builds run offline"}),
+        ))
+        .await
+        .expect("memory.save should succeed");
+    assert_eq!(output.content, "Saved memory #1.");
+    let memories = std::fs::read_to_string(dir.join("memory.json")).expect("memory file");
+    assert!(memories.contains("builds run offline"));
+
+    let err = runner
+        .execute(&call("fizz.memory.save", json!({})))
+        .await
+        .expect_err("missing arguments should fail");
+    assert!(
+        err.to_string().starts_with("fizz.memory.save failed:"),
+        "{err}"
+    );
+
+    std::fs::remove_dir_all(&dir).expect("failed to remove temp directory");
+}
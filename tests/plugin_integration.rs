@@ -0,0 +1,112 @@
+use fizz::agent::tools::{ToolCall, ToolRunner};
+use fizz::config::ToolResourceLimits;
+use fizz::plugins::ProcessRunner;
+use serde_json::{Value, json};
+use std::path::PathBuf;
+
+fn fixture_runner(limits: ToolResourceLimits) -> ProcessRunner {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/plugins");
+    let (runner, errors) = ProcessRunner::discover(&dir, limits);
+    assert!(errors.is_empty(), "unexpected errors: {errors:?}");
+    runner
+}
+
+fn limits() -> ToolResourceLimits {
+    ToolResourceLimits {
+        timeout_secs: 2,
+        memory_mb: 128,
+        max_output_bytes: 4_096,
+    }
+}
+
+fn call(name: &str, arguments: Value) -> ToolCall {
+    let Value::Object(arguments) = arguments else {
+        panic!("arguments should be an object");
+    };
+    ToolCall::new(name).with_arguments(arguments)
+}
+
+async fn run(runner: &ProcessRunner, name: &str) -> Result<String, String> {
+    runner
+        .execute(&call(name, json!({})))
+        .await
+        .map(|output| output.content)
+        .map_err(|err| err.to_string())
+}
+
+#[tokio::test]
+async fn one_shot_plugins_answer_and_report_tool_errors() {
+    let runner = fixture_runner(limits());
+    let names: Vec<String> = runner.tools().into_iter().map(|tool| tool.name).collect();
+    assert_eq!(names.len(), 8);
+    assert!(names.contains(&"echo.say".to_string()));
+
+    let output = runner
+        .execute(&call("echo.say", json!({"text": "hello"})))
+        .await
+        .expect("echo.say should succeed");
+    assert_eq!(output.content, "hello");
+
+    assert_eq!(
+        run(&runner, "echo.fail").await,
+        Err("echo.fail failed: the text was rude".to_string())
+    );
+}
+
+#[tokio::test]
+async fn one_shot_plugin_crashes_become_errors_with_stderr() {
+    let runner = fixture_runner(limits());
+
+    let err = run(&runner, "echo.crash")
+        .await
+        .expect_err("crash should fail");
+    assert_eq!(
+        err,
+        "Plugin 'echo' exited without answering (exit status: 3); stderr: something broke"
+    );
+}
+
+#[tokio::test]
+async fn plugins_are_stopped_when_they_exceed_limits() {
+    let runner = fixture_runner(limits());
+
+    assert_eq!(
+        run(&runner, "echo.flood").await,
+        Err("Plugin 'echo' wrote more than 4096 bytes and was stopped".to_string())
+    );
+    assert_eq!(
+        run(&runner, "echo.hang").await,
+        Err("Plugin 'echo' did not answer within 2s and was stopped".to_string())
+    );
+}
+
+/// The memory limit is an address-space rlimit, so it only exists on Unix.
+#[cfg(unix)]
+#[tokio::test]
+async fn plugins_fail_when_they_exceed_the_memory_limit() {
+    let runner = fixture_runner(limits());
+
+    let err = run(&runner, "echo.hog").await.expect_err("hog should fail");
+    assert!(
+        err.starts_with("Plugin 'echo' exited without answering") && err.contains("MemoryError"),
+        "{err}"
+    );
+}
+
+#[tokio::test]
+async fn persistent_plugins_keep_their_process_and_restart_after_a_crash() {
+    let runner = fixture_runner(limits());
+
+    assert_eq!(run(&runner, "counter.next").await, Ok("1".to_string()));
+    assert_eq!(run(&runner, "counter.next").await, Ok("2".to_string()));
+
+    let err = run(&runner, "counter.exit")
+        .await
+        .expect_err("exit should fail");
+    assert!(
+        err.starts_with("Plugin 'counter' exited without answering (exit status: 1)"),
+        "{err}"
+    );
+
+    assert_eq!(run(&runner, "counter.next").await, Ok("1".to_string()));
+}
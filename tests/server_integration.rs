@@ -0,0 +1,442 @@
+#[path = "../src/test_fs.rs"]
+mod test_fs;
+#[allow(dead_code)]
+#[path = "../src/test_http.rs"]
+mod test_http;
+
+use fizz::config::Config;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use test_fs::unique_temp_dir;
+use test_http::{StubResponse, serve_forever};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// A stand-in for Ollama's streaming `/api/chat`: it asks for `time.now`
+/// until the conversation holds a tool result, then answers. Input starting
+/// with `delegate:` is handed to a sub-agent instead.
+fn spawn_stub_ollama() -> String {
+    serve_forever(|request| {
+        let request: Value = serde_json::from_str(&request.body).expect("request should be JSON");
+        let messages = request["messages"]
+            .as_array()
+            .expect("request should have messages");
+        let has_tool_result = messages.iter().any(|message| message["role"] == "tool");
+        let delegates = messages.iter().any(|message| {
+            message["role"] == "user"
+                && message["content"]
+                    .as_str()
+                    .is_some_and(|content| content.starts_with("delegate:"))
+        });
+        let deltas: &[&str] = if has_tool_result {
+            &["It is ", "noon."]
+        } else if delegates {
+            &[
+                r#"{"tool_call":"#,
+                r#"{"name":"agent.delegate","arguments":{"task":"what time is it?"}}}"#,
+            ]
+        } else {
+            &[r#"{"tool_call":"#, r#"{"name":"time.now"}}"#]
+        };
+
+        let mut body = String::new();
+        for delta in deltas {
+            body.push_str(
+                &json!({"message": {"role": "assistant", "content": delta}, "done": false})
+                    .to_string(),
+            );
+            body.push('\n');
+        }
+        body.push_str(
+            &json!({
+                "message": {"role": "assistant", "content": ""},
+                "done": true,
+                "prompt_eval_count": 10,
+                "eval_count": 4
+            })
+            .to_string(),
+        );
+        body.push('\n');
+        StubResponse::ndjson(body)
+    })
+}
+
+struct TestServer {
+    base_url: String,
+    shutdown: CancellationToken,
+    handle: JoinHandle<anyhow::Result<()>>,
+}
+
+impl TestServer {
+    /// `model` names the test's scratch directory, so it must differ between
+    /// tests.
+    async fn start(model: &str) -> Self {
+        let dir = unique_temp_dir(model);
+        let mut cfg = Config::from_env();
+        cfg.model_provider = "ollama".to_string();
+        cfg.model = model.to_string();
+        cfg.model_base_url = spawn_stub_ollama();
+        cfg.model_fallbacks = Vec::new();
+        cfg.model_retry.max_attempts = 1;
+        cfg.tool_call_extraction = fizz::config::ToolCallExtraction::Strict;
+        cfg.memory_path = dir.join("memory.json");
+        cfg.docs_index_path = dir.join("docs-index.json");
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind should succeed");
+        let base_url = format!(
+            "http://{}",
+            listener.local_addr().expect("address should be available")
+        );
+        let shutdown = CancellationToken::new();
+        let handle = tokio::spawn(fizz::server::serve_on(
+            listener,
+            reqwest::Client::new(),
+            Arc::new(cfg),
+            shutdown.clone(),
+        ));
+        Self {
+            base_url,
+            shutdown,
+            handle,
+        }
+    }
+
+    async fn stop(self) {
+        self.shutdown.cancel();
+        self.handle
+            .await
+            .expect("server task should not panic")
+            .expect("server should shut down cleanly");
+    }
+}
+
+#[tokio::test]
+async fn chat_completions_runs_a_turn_with_tools() {
+    let server = TestServer::start("server-plain").await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url))
+        .json(&json!({
+            "model": "fizz",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "what time is it?"}
+            ]
+        }))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.expect("body should be JSON");
+
+    assert_eq!(body["object"], "chat.completion");
+    assert_eq!(body["model"], "fizz");
+    assert_eq!(body["choices"][0]["message"]["role"], "assistant");
+    assert_eq!(body["choices"][0]["message"]["content"], "It is noon.");
+    assert_eq!(body["choices"][0]["finish_reason"], "stop");
+    assert_eq!(body["usage"]["prompt_tokens"], 20);
+    assert_eq!(body["usage"]["completion_tokens"], 8);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn chat_completions_streams_only_the_answer() {
+    let server = TestServer::start("server-stream").await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", server.base_url))
+        .json(&json!({
+            "messages": [{"role": "user", "content": "what time is it?"}],
+            "stream": true,
+            "stream_options": {"include_usage": true}
+        }))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 200);
+    assert!(
+        response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/event-stream"))
+    );
+    let body = response.text().await.expect("stream should be readable");
+
+    let data: Vec<&str> = body
+        .lines()
+        .filter_map(|line| line.strip_prefix("data: "))
+        .collect();
+    assert_eq!(data.last(), Some(&"[DONE]"));
+    let chunks: Vec<Value> = data[..data.len() - 1]
+        .iter()
+        .map(|chunk| serde_json::from_str(chunk).expect("chunk should be JSON"))
+        .collect();
+    let content: String = chunks
+        .iter()
+        .filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(content, "It is noon.");
+    assert!(!body.contains("tool_call"), "tool call leaked: {body}");
+    assert!(
+        chunks
+            .iter()
+            .all(|chunk| chunk["object"] == "chat.completion.chunk")
+    );
+    assert!(
+        chunks
+            .iter()
+            .any(|chunk| chunk["choices"][0]["finish_reason"] == "stop")
+    );
+    assert_eq!(
+        chunks.last().expect("usage chunk")["usage"]["total_tokens"],
+        28
+    );
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn models_and_errors_use_the_openai_format() {
+    let server = TestServer::start("server-models").await;
+    let client = reqwest::Client::new();
+
+    let models: Value = client
+        .get(format!("{}/v1/models", server.base_url))
+        .send()
+        .await
+        .expect("request should succeed")
+        .json()
+        .await
+        .expect("body should be JSON");
+    assert_eq!(models["object"], "list");
+    assert_eq!(models["data"][0]["id"], "fizz");
+
+    let response = client
+        .post(format!("{}/v1/chat/completions", server.base_url))
+        .json(&json!({"messages": [{"role": "assistant", "content": "hi"}]}))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.expect("body should be JSON");
+    assert_eq!(body["error"]["type"], "invalid_request_error");
+
+    server.stop().await;
+}
+
+/// Polls the session's approvals until a call to `tool_name` waits, and
+/// returns its id.
+async fn wait_for_approval(client: &reqwest::Client, session_url: &str, tool_name: &str) -> String {
+    loop {
+        let approvals: Value = client
+            .get(format!("{session_url}/approvals"))
+            .send()
+            .await
+            .expect("request should succeed")
+            .json()
+            .await
+            .expect("body should be JSON");
+        let waiting = approvals["approvals"]
+            .as_array()
+            .expect("approvals list")
+            .iter()
+            .find(|approval| approval["tool_name"] == tool_name)
+            .and_then(|approval| approval["call_id"].as_str());
+        if let Some(call_id) = waiting {
+            return call_id.to_string();
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+async fn approve(client: &reqwest::Client, session_url: &str, call_id: &str) {
+    let response = client
+        .post(format!("{session_url}/approvals/{call_id}"))
+        .json(&json!({"approve": true}))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 204);
+}
+
+/// Reads server-sent events until one named `until` arrives, returning the
+/// event names and their JSON payloads.
+async fn read_events(mut response: reqwest::Response, until: &str) -> Vec<(String, Value)> {
+    let mut buffer = String::new();
+    let mut events = Vec::new();
+    while let Some(bytes) = response.chunk().await.expect("stream should be readable") {
+        buffer.push_str(&String::from_utf8_lossy(&bytes));
+        while let Some(end) = buffer.find("\n\n") {
+            let block: String = buffer.drain(..end + 2).collect();
+            let name = block
+                .lines()
+                .find_map(|line| line.strip_prefix("event: "))
+                .unwrap_or_default()
+                .to_string();
+            let Some(data) = block.lines().find_map(|line| line.strip_prefix("data: ")) else {
+                continue;
+            };
+            let data: Value = serde_json::from_str(data).expect("event data should be JSON");
+            let done = name == until;
+            events.push((name, data));
+            if done {
+                return events;
+            }
+        }
+    }
+    panic!("stream ended before '{until}': {events:?}");
+}
+
+#[tokio::test]
+async fn sessions_stream_events_and_wait_for_tool_approval() {
+    let server = TestServer::start("server-sessions").await;
+    let client = reqwest::Client::new();
+    let api = format!("{}/api/sessions", server.base_url);
+
+    let response = client
+        .post(&api)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 201);
+    let session: Value = response.json().await.expect("body should be JSON");
+    let id = session["id"].as_str().expect("session id").to_string();
+    let session_url = format!("{api}/{id}");
+
+    let events = client
+        .get(format!("{session_url}/events"))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(events.status(), 200);
+    let events = tokio::spawn(read_events(events, "turn_finished"));
+
+    let response = client
+        .post(format!("{session_url}/turns"))
+        .json(&json!({"input": "what time is it?"}))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 202);
+
+    let call_id = wait_for_approval(&client, &session_url, "time.now").await;
+    approve(&client, &session_url, &call_id).await;
+
+    let events = events.await.expect("event reader should not panic");
+    let names: Vec<&str> = events.iter().map(|(name, _)| name.as_str()).collect();
+    for expected in [
+        "turn_started",
+        "tool_call_parsed",
+        "tool_approval_requested",
+        "tool_approved",
+        "tool_result",
+    ] {
+        assert!(names.contains(&expected), "missing {expected}: {names:?}");
+    }
+    let (_, finished) = events.last().expect("turn_finished event");
+    assert_eq!(finished["type"], "turn_finished");
+    assert_eq!(finished["answer"], "It is noon.");
+
+    let mut history: Value = Value::Null;
+    for _ in 0..100 {
+        let response = client
+            .get(format!("{session_url}/history"))
+            .send()
+            .await
+            .expect("request should succeed");
+        if response.status() == 200 {
+            history = response.json().await.expect("body should be JSON");
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let messages = history["messages"].as_array().expect("history messages");
+    assert!(
+        messages
+            .iter()
+            .any(|message| { message["role"] == "tool" && message["tool_name"] == "time.now" })
+    );
+    assert_eq!(
+        messages.last().expect("final answer")["content"],
+        "It is noon."
+    );
+    assert_eq!(history["role_usage"]["router"]["model_calls"], 1);
+    assert_eq!(history["role_usage"]["tool_step"]["model_calls"], 1);
+
+    let response = client
+        .get(format!("{session_url}/workspace/diff"))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 501);
+
+    let response = client
+        .delete(&session_url)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 204);
+    let response = client
+        .get(&session_url)
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 404);
+
+    server.stop().await;
+}
+
+#[tokio::test]
+async fn sessions_review_the_tool_calls_of_sub_agents() {
+    let server = TestServer::start("server-delegate").await;
+    let client = reqwest::Client::new();
+    let api = format!("{}/api/sessions", server.base_url);
+
+    let session: Value = client
+        .post(&api)
+        .send()
+        .await
+        .expect("request should succeed")
+        .json()
+        .await
+        .expect("body should be JSON");
+    let session_url = format!("{api}/{}", session["id"].as_str().expect("session id"));
+    let events = client
+        .get(format!("{session_url}/events"))
+        .send()
+        .await
+        .expect("request should succeed");
+    let events = tokio::spawn(read_events(events, "turn_finished"));
+
+    let response = client
+        .post(format!("{session_url}/turns"))
+        .json(&json!({"input": "delegate: what time is it?"}))
+        .send()
+        .await
+        .expect("request should succeed");
+    assert_eq!(response.status(), 202);
+
+    let delegate_id = wait_for_approval(&client, &session_url, "agent.delegate").await;
+    approve(&client, &session_url, &delegate_id).await;
+    let child_id = wait_for_approval(&client, &session_url, "time.now").await;
+    assert_eq!(child_id, format!("{delegate_id}.call_1_1_0"));
+    approve(&client, &session_url, &child_id).await;
+
+    let events = events.await.expect("event reader should not panic");
+    assert!(events.iter().any(|(name, data)| {
+        name == "tool_approval_requested" && data["call_id"] == child_id.as_str()
+    }));
+    assert!(
+        events
+            .iter()
+            .any(|(name, data)| name == "tool_result" && data["tool_name"] == "time.now")
+    );
+    let (_, finished) = events.last().expect("turn_finished event");
+    assert_eq!(finished["answer"], "It is noon.");
+
+    server.stop().await;
+}